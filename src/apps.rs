@@ -0,0 +1,388 @@
+//! Hosting several apps in one process. Apps are declared in a manifest
+//! passed in with `--apps`, and requests are routed to them by host name
+//! and path prefix:
+//!
+//! ```toml
+//! [[apps]]
+//! name = "blog"
+//! hosts = ["blog.example.com", "*.blog.example.com"]
+//! dir = "apps/blog"
+//!
+//! [[apps]]
+//! name = "api"
+//! hosts = ["example.com"]
+//! path_prefix = "/api"
+//! dir = "apps/api"
+//! mode = "cloudflare"
+//! max_js_threads = 4
+//! ```
+//!
+//! Each app gets its own pool of JS threads. The manifest is checked for
+//! changes periodically, and apps are started, restarted or shut down to
+//! match it without restarting the process.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use parking_lot::RwLock;
+use serde::Deserialize;
+
+use crate::{
+    request_handlers::{
+        cloudflare::{
+            bindings::BindingsConfig, wrangler::WranglerConfig, CloudflareRequestHandler,
+        },
+        wintercg::WinterCGRequestHandler,
+        UserCode,
+    },
+    runners::single::SingleRunner,
+    server::BoxedDynRunner,
+    HandlerName,
+};
+
+const DEFAULT_MAIN: &str = "index.js";
+
+#[derive(Deserialize)]
+struct AppsManifest {
+    apps: Vec<AppConfig>,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct AppConfig {
+    name: String,
+
+    /// Host names the app is served on. `*` matches any part of a name,
+    /// e.g. `*.example.com`. The app is served on all hosts if left out.
+    #[serde(default)]
+    hosts: Vec<String>,
+
+    /// Only requests whose path is or starts with this prefix are routed to
+    /// the app. The prefix is not stripped from the request URL.
+    path_prefix: Option<String>,
+
+    /// Directory of the app, relative to the manifest.
+    dir: PathBuf,
+
+    /// The app's entry point, relative to its directory. Defaults to the
+    /// `main` entry of the app's wrangler config, then to `index.js`.
+    main: Option<PathBuf>,
+
+    /// Defaults to Cloudflare mode if the app has a wrangler config, and
+    /// WinterCG mode otherwise.
+    mode: Option<HandlerName>,
+
+    #[serde(default)]
+    script: bool,
+
+    /// Path to a bindings file, relative to the app's directory. Can't be
+    /// used if the app has a wrangler config.
+    bindings: Option<PathBuf>,
+
+    /// Defaults to the value of `--max-js-threads`.
+    max_js_threads: Option<usize>,
+}
+
+struct MountedApp {
+    config: AppConfig,
+    runner: BoxedDynRunner,
+}
+
+struct State {
+    manifest_modified: Option<SystemTime>,
+    apps: Vec<MountedApp>,
+}
+
+struct Inner {
+    manifest_path: PathBuf,
+    default_max_js_threads: usize,
+    state: RwLock<State>,
+}
+
+#[derive(Clone)]
+pub struct Apps {
+    inner: Arc<Inner>,
+}
+
+impl Apps {
+    pub fn load(manifest_path: PathBuf, default_max_js_threads: usize) -> Result<Self> {
+        let manifest_modified = modified_time(&manifest_path);
+        let apps = start_apps(&manifest_path, default_max_js_threads, &mut vec![])?;
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                manifest_path,
+                default_max_js_threads,
+                state: RwLock::new(State {
+                    manifest_modified,
+                    apps,
+                }),
+            }),
+        })
+    }
+
+    /// Finds the app to route a request to. Apps with an exact host match
+    /// take precedence over wildcard matches, which take precedence over
+    /// apps served on all hosts. Among those, the longest matching path
+    /// prefix wins, and then the app declared first.
+    pub fn route(&self, host: &str, path: &str) -> Option<BoxedDynRunner> {
+        let host = host.to_ascii_lowercase();
+        let state = self.inner.state.read();
+
+        let mut best: Option<((u8, usize), &MountedApp)> = None;
+        for app in &state.apps {
+            let Some(host_rank) = host_rank(&app.config.hosts, &host) else {
+                continue;
+            };
+            let Some(prefix_len) = prefix_len(app.config.path_prefix.as_deref(), path) else {
+                continue;
+            };
+
+            let rank = (host_rank, prefix_len);
+            if best.map(|(best_rank, _)| rank > best_rank).unwrap_or(true) {
+                best = Some((rank, app));
+            }
+        }
+
+        best.map(|(_, app)| app.runner.clone())
+    }
+
+    /// Re-reads the manifest if it changed since it was last read. Apps
+    /// whose config changed are restarted, and removed apps are shut down
+    /// once their in-flight requests finish.
+    pub fn reload_if_changed(&self) -> Result<()> {
+        let modified = modified_time(&self.inner.manifest_path);
+        if modified == self.inner.state.read().manifest_modified {
+            return Ok(());
+        }
+
+        let mut previous = {
+            let state = self.inner.state.read();
+            state
+                .apps
+                .iter()
+                .map(|app| MountedApp {
+                    config: app.config.clone(),
+                    runner: app.runner.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+        let apps = start_apps(
+            &self.inner.manifest_path,
+            self.inner.default_max_js_threads,
+            &mut previous,
+        );
+
+        let mut state = self.inner.state.write();
+        state.manifest_modified = modified;
+        let apps = apps?;
+        tracing::info!(
+            path = %self.inner.manifest_path.display(),
+            "Reloaded apps manifest, now serving {} apps",
+            apps.len()
+        );
+        state.apps = apps;
+        drop(state);
+
+        // Whatever is left over from the previous manifest was removed or
+        // replaced
+        for app in previous {
+            tracing::info!(app = app.config.name, "Shutting down app");
+            tokio::spawn(async move { app.runner.shutdown(None).await });
+        }
+
+        Ok(())
+    }
+
+    /// Periodically checks the manifest for changes. Polling is used since
+    /// file watching APIs are not available under WASIX.
+    pub fn watch(&self, interval: Duration) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if let Err(e) = this.reload_if_changed() {
+                    tracing::error!(
+                        error = format!("{e:#}"),
+                        "Failed to reload apps manifest, keeping the previous apps"
+                    );
+                }
+            }
+        });
+    }
+
+    pub async fn shutdown(&self, timeout: Option<Duration>) {
+        let runners = self
+            .inner
+            .state
+            .read()
+            .apps
+            .iter()
+            .map(|app| app.runner.clone())
+            .collect::<Vec<_>>();
+        futures::future::join_all(runners.iter().map(|runner| runner.shutdown(timeout))).await;
+    }
+}
+
+/// Starts the apps declared in the manifest. Running apps from `previous`
+/// whose config didn't change are reused, and removed from it.
+fn start_apps(
+    manifest_path: &Path,
+    default_max_js_threads: usize,
+    previous: &mut Vec<MountedApp>,
+) -> Result<Vec<MountedApp>> {
+    let file_content = std::fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read apps manifest {}", manifest_path.display()))?;
+    let manifest = if manifest_path
+        .extension()
+        .map(|e| e == "json")
+        .unwrap_or(false)
+    {
+        serde_json::from_str::<AppsManifest>(&file_content).map_err(anyhow::Error::from)
+    } else {
+        toml::from_str::<AppsManifest>(&file_content).map_err(anyhow::Error::from)
+    }
+    .with_context(|| format!("Failed to parse apps manifest {}", manifest_path.display()))?;
+
+    for (i, app) in manifest.apps.iter().enumerate() {
+        if manifest.apps[..i].iter().any(|a| a.name == app.name) {
+            bail!("App {} is declared more than once", app.name);
+        }
+    }
+
+    let base_dir = manifest_path.parent().unwrap_or(Path::new("."));
+    let mut apps = vec![];
+    for config in manifest.apps {
+        if let Some(i) = previous.iter().position(|app| app.config == config) {
+            apps.push(previous.remove(i));
+            continue;
+        }
+
+        // Runners only spawn their threads once they receive a request, so
+        // nothing needs to be cleaned up if a later app fails to start
+        let runner = start_app(&config, base_dir, default_max_js_threads)
+            .with_context(|| format!("Failed to start app {}", config.name))?;
+        tracing::info!(app = config.name, "Starting app");
+        apps.push(MountedApp { config, runner });
+    }
+
+    Ok(apps)
+}
+
+fn start_app(
+    config: &AppConfig,
+    base_dir: &Path,
+    default_max_js_threads: usize,
+) -> Result<BoxedDynRunner> {
+    let dir = base_dir.join(&config.dir);
+    let wrangler_config = WranglerConfig::discover(&dir)
+        .map(|path| WranglerConfig::try_parse(path, None))
+        .transpose()?;
+
+    let main = match (&config.main, &wrangler_config) {
+        (Some(main), _) => dir.join(main),
+        (
+            None,
+            Some(WranglerConfig {
+                main: Some(main), ..
+            }),
+        ) => main.clone(),
+        (None, _) => dir.join(DEFAULT_MAIN),
+    };
+    let user_code = UserCode::from_path(&main, config.script)?;
+
+    let max_js_threads = config.max_js_threads.unwrap_or(default_max_js_threads);
+    if max_js_threads == 0 {
+        bail!("max_js_threads must be at least 1");
+    }
+
+    let mode = match (&config.mode, &wrangler_config) {
+        (Some(mode), _) => mode.clone(),
+        (None, Some(_)) => HandlerName::Cloudflare,
+        (None, None) => HandlerName::WinterCG,
+    };
+
+    Ok(match mode {
+        HandlerName::Cloudflare => {
+            let bindings_config = match (&config.bindings, wrangler_config) {
+                (Some(_), Some(_)) => {
+                    bail!("`bindings` cannot be used when the app has a wrangler config")
+                }
+                (Some(path), None) => BindingsConfig::try_parse(dir.join(path))?,
+                (None, Some(wrangler_config)) => wrangler_config.bindings,
+                (None, None) => BindingsConfig::default(),
+            };
+            if !bindings_config.durable_objects.bindings.is_empty() {
+                bail!("Durable Objects are not supported when hosting multiple apps");
+            }
+            if !bindings_config.services.is_empty() {
+                tracing::warn!(
+                    app = config.name,
+                    "Service bindings are only available with --workers, ignoring"
+                );
+            }
+
+            Box::new(SingleRunner::new_request_handler(
+                CloudflareRequestHandler::new(bindings_config),
+                max_js_threads,
+                user_code,
+            ))
+        }
+        HandlerName::WinterCG => {
+            if config.bindings.is_some() {
+                tracing::warn!(
+                    app = config.name,
+                    "Bindings are only supported in Cloudflare mode, ignoring"
+                );
+            }
+            Box::new(SingleRunner::new_request_handler(
+                WinterCGRequestHandler,
+                max_js_threads,
+                user_code,
+            ))
+        }
+    })
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn host_rank(patterns: &[String], host: &str) -> Option<u8> {
+    if patterns.is_empty() {
+        return Some(0);
+    }
+
+    patterns
+        .iter()
+        .filter_map(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+            if pattern == host {
+                Some(2)
+            } else if glob_match::glob_match(&pattern, host) {
+                Some(1)
+            } else {
+                None
+            }
+        })
+        .max()
+}
+
+/// Returns the length of the prefix if the path matches it. Prefixes only
+/// match whole path segments, so `/api` matches `/api/users` but not
+/// `/apis`.
+fn prefix_len(prefix: Option<&str>, path: &str) -> Option<usize> {
+    let Some(prefix) = prefix.map(|p| p.trim_end_matches('/')) else {
+        return Some(0);
+    };
+
+    let rest = path.strip_prefix(prefix)?;
+    if rest.is_empty() || rest.starts_with('/') {
+        Some(prefix.len())
+    } else {
+        None
+    }
+}
@@ -1,5 +1,8 @@
 mod subtle;
 
+pub(crate) use subtle::crypto_key::{CryptoKey, KeyAlgorithm, KeyMaterial, KeyType, KeyUsage};
+pub use subtle::node::CryptoModule;
+
 use ion::{
     conversions::ToValue, function_spec, ClassDefinition, Context, Error, ErrorKind, Object, Result,
 };
@@ -58,8 +61,7 @@ pub fn define(cx: &Context, global: &ion::Object) -> bool {
     crypto.set(cx, "subtle", &subtle.as_value(cx))
         && global.set(cx, "crypto", &ion::Value::object(cx, &crypto))
         && subtle::define(cx, subtle)
+        && subtle::digest_stream::define(cx, &crypto)
         && subtle::crypto_key::CryptoKey::init_class(cx, global).0
-        && subtle::crypto_key::KeyAlgorithm::init_class(cx, global).0
-        && subtle::algorithm::hmac::HmacKeyAlgorithm::init_class(cx, global).0
         && unsafe { crypto.define_methods(cx, METHODS) }
 }
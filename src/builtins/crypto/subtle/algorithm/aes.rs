@@ -0,0 +1,494 @@
+use aes::cipher::{
+    block_padding::Pkcs7, consts::U16, generic_array::GenericArray, BlockDecryptMut, BlockEncrypt,
+    BlockEncryptMut, BlockSizeUser, KeyInit, KeyIvInit,
+};
+use ghash::{universal_hash::UniversalHash, GHash};
+use ion::{
+    conversions::{ConversionBehavior, FromValue, ToValue},
+    typedarray::ArrayBuffer,
+    Context, Error, ErrorKind, Object,
+};
+
+use crate::{
+    builtins::crypto::subtle::{
+        crypto_key::{
+            generate_random_key, CryptoKey, GeneratedKey, KeyAlgorithm, KeyFormat, KeyMaterial,
+            KeyType, KeyUsage,
+        },
+        jwk::{self, JsonWebKey},
+        timing_safe_eq, BufferSource, HeapKeyData,
+    },
+    ion_err, ion_mk_err,
+};
+
+use super::CryptoAlgorithm;
+
+const USAGES: &[KeyUsage] = &[
+    KeyUsage::Encrypt,
+    KeyUsage::Decrypt,
+    KeyUsage::WrapKey,
+    KeyUsage::UnwrapKey,
+];
+const KW_USAGES: &[KeyUsage] = &[KeyUsage::WrapKey, KeyUsage::UnwrapKey];
+
+/// Runs `$body` with `$cipher` bound to the AES variant matching the length
+/// of `$key`.
+macro_rules! with_aes_cipher {
+    ($key:expr, $cipher:ident => $body:expr) => {
+        match $key.len() {
+            16 => {
+                type $cipher = aes::Aes128;
+                $body
+            }
+            24 => {
+                type $cipher = aes::Aes192;
+                $body
+            }
+            32 => {
+                type $cipher = aes::Aes256;
+                $body
+            }
+            _ => ion_err!("AES key length must be 128, 192 or 256 bits", Normal),
+        }
+    };
+}
+
+// The standard has two separate dictionaries for key generation and key
+// derivation, but they're the exact same, so we use one.
+#[derive(FromValue)]
+pub struct AesKeyGenParams {
+    #[ion(convert = ConversionBehavior::EnforceRange, strict)]
+    length: u16,
+}
+
+#[derive(FromValue)]
+pub struct AesGcmParams<'cx> {
+    iv: BufferSource<'cx>,
+    #[ion(name = "additionalData")]
+    additional_data: Option<BufferSource<'cx>>,
+    #[ion(name = "tagLength", convert = ConversionBehavior::EnforceRange, strict)]
+    tag_length: Option<u8>,
+}
+
+#[derive(FromValue)]
+pub struct AesCbcParams<'cx> {
+    iv: BufferSource<'cx>,
+}
+
+#[derive(FromValue)]
+pub struct AesCtrParams<'cx> {
+    counter: BufferSource<'cx>,
+    #[ion(convert = ConversionBehavior::EnforceRange, strict)]
+    length: u8,
+}
+
+pub enum Aes {
+    Gcm,
+    Cbc,
+    Ctr,
+    Kw,
+}
+
+impl Aes {
+    fn jwk_suffix(&self) -> &'static str {
+        match self {
+            Self::Gcm => "GCM",
+            Self::Cbc => "CBC",
+            Self::Ctr => "CTR",
+            Self::Kw => "KW",
+        }
+    }
+
+    fn usages(&self) -> &'static [KeyUsage] {
+        match self {
+            Self::Kw => KW_USAGES,
+            _ => USAGES,
+        }
+    }
+
+    fn key_data<'k>(&self, key: &'k CryptoKey) -> ion::Result<&'k [u8]> {
+        match (&key.algorithm, &key.key) {
+            (KeyAlgorithm::Aes { .. }, KeyMaterial::Secret(key_data)) => Ok(key_data.as_slice()),
+            _ => ion_err!("The provided key is not an AES key", Type),
+        }
+    }
+
+    fn new_key(
+        &self,
+        cx: &Context,
+        key_data: Vec<u8>,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<CryptoKey> {
+        let algorithm = KeyAlgorithm::Aes {
+            name: self.name(),
+            length: key_data.len() as u16 * 8,
+        };
+        CryptoKey::new(
+            cx,
+            extractable,
+            algorithm,
+            KeyType::Secret,
+            usages,
+            KeyMaterial::Secret(key_data),
+        )
+    }
+
+    fn crypt(
+        &self,
+        cx: &Context,
+        params: &Object,
+        key: &[u8],
+        data: Vec<u8>,
+        encrypt: bool,
+    ) -> ion::Result<Vec<u8>> {
+        match self {
+            Self::Gcm => {
+                let params = AesGcmParams::from_value(cx, &params.as_value(cx), false, ())?;
+                let iv = params.iv.to_owned();
+                let tag_length = params.tag_length.unwrap_or(128);
+                if ![32, 64, 96, 104, 112, 120, 128].contains(&tag_length) {
+                    ion_err!(
+                        "tagLength must be one of 32, 64, 96, 104, 112, 120 or 128",
+                        Normal
+                    );
+                }
+                let aad = params
+                    .additional_data
+                    .map(|aad| aad.to_owned())
+                    .unwrap_or_default();
+
+                with_aes_cipher!(key, C => gcm::<C>(
+                    key,
+                    &iv,
+                    &aad,
+                    data,
+                    tag_length as usize / 8,
+                    encrypt
+                ))
+            }
+
+            Self::Cbc => {
+                let params = AesCbcParams::from_value(cx, &params.as_value(cx), false, ())?;
+                let iv = params.iv.to_owned();
+                if iv.len() != 16 {
+                    ion_err!("iv must be 16 bytes long", Normal);
+                }
+
+                with_aes_cipher!(key, C => {
+                    if encrypt {
+                        Ok(cbc::Encryptor::<C>::new_from_slices(key, &iv)
+                            .map_err(|_| ion_mk_err!("Invalid key or iv", Normal))?
+                            .encrypt_padded_vec_mut::<Pkcs7>(&data))
+                    } else {
+                        cbc::Decryptor::<C>::new_from_slices(key, &iv)
+                            .map_err(|_| ion_mk_err!("Invalid key or iv", Normal))?
+                            .decrypt_padded_vec_mut::<Pkcs7>(&data)
+                            .map_err(|_| ion_mk_err!("Decryption failed", Normal))
+                    }
+                })
+            }
+
+            // Encryption and decryption are the same operation in CTR mode
+            Self::Ctr => {
+                let params = AesCtrParams::from_value(cx, &params.as_value(cx), false, ())?;
+                let counter = params.counter.to_owned();
+                let Ok(counter) = <[u8; 16]>::try_from(counter) else {
+                    ion_err!("counter must be 16 bytes long", Normal);
+                };
+                if params.length == 0 || params.length > 128 {
+                    ion_err!("length must be between 1 and 128", Normal);
+                }
+
+                with_aes_cipher!(key, C => ctr::<C>(key, counter, params.length, data))
+            }
+
+            // AES-KW can only be used through wrapKey and unwrapKey
+            Self::Kw => ion_err!("Operation not supported by the specified algorithm", Normal),
+        }
+    }
+}
+
+impl CryptoAlgorithm for Aes {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Gcm => "AES-GCM",
+            Self::Cbc => "AES-CBC",
+            Self::Ctr => "AES-CTR",
+            Self::Kw => "AES-KW",
+        }
+    }
+
+    fn encrypt<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        key: &CryptoKey,
+        data: Vec<u8>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        let key_data = self.key_data(key)?;
+        let result = self.crypt(cx, params, key_data, data, true)?;
+        ArrayBuffer::copy_from_bytes(cx, &result)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn decrypt<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        key: &CryptoKey,
+        data: Vec<u8>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        let key_data = self.key_data(key)?;
+        let result = self.crypt(cx, params, key_data, data, false)?;
+        ArrayBuffer::copy_from_bytes(cx, &result)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn wrap_key<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        wrapping_key: &CryptoKey,
+        key: Vec<u8>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        let Self::Kw = self else {
+            return self.encrypt(cx, params, wrapping_key, key);
+        };
+
+        let key_data = self.key_data(wrapping_key)?;
+        if key.len() < 16 || key.len() % 8 != 0 {
+            ion_err!(
+                "AES-KW can only wrap keys that are a multiple of 64 bits and at least 128 bits long",
+                Normal
+            );
+        }
+        let result = with_aes_cipher!(key_data, C => aes_kw::Kek::<C>::new(GenericArray::from_slice(key_data))
+            .wrap_vec(&key)
+            .map_err(|_| ion_mk_err!("Wrapping the key failed", Normal))?);
+        ArrayBuffer::copy_from_bytes(cx, &result)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn unwrap_key<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        unwrapping_key: &CryptoKey,
+        wrapped_key: Vec<u8>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        let Self::Kw = self else {
+            return self.decrypt(cx, params, unwrapping_key, wrapped_key);
+        };
+
+        let key_data = self.key_data(unwrapping_key)?;
+        let result = with_aes_cipher!(key_data, C => aes_kw::Kek::<C>::new(GenericArray::from_slice(key_data))
+            .unwrap_vec(&wrapped_key)
+            .map_err(|_| ion_mk_err!("Unwrapping the key failed", Normal))?);
+        ArrayBuffer::copy_from_bytes(cx, &result)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn generate_key(
+        &self,
+        cx: &Context,
+        params: &Object,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<GeneratedKey> {
+        if usages.iter().any(|u| !self.usages().contains(u)) {
+            ion_err!("Invalid key usage specified", Syntax);
+        }
+
+        let length = self.get_key_length(cx, params)?;
+        let key_data = generate_random_key(length / 8, &mut rand::thread_rng());
+        Ok(GeneratedKey::Key(self.new_key(
+            cx,
+            key_data,
+            extractable,
+            usages,
+        )?))
+    }
+
+    fn import_key(
+        &self,
+        cx: &Context,
+        _params: &Object,
+        format: KeyFormat,
+        key_data: HeapKeyData,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<CryptoKey> {
+        if usages.iter().any(|u| !self.usages().contains(u)) {
+            ion_err!("Invalid key usage specified", Syntax);
+        }
+
+        let key_bytes = match (format, key_data) {
+            (KeyFormat::Raw, HeapKeyData::Buffer(buffer)) => buffer,
+            (KeyFormat::Jwk, HeapKeyData::Jwk(jwk)) => {
+                if jwk.kty != "oct" {
+                    ion_err!("kty member of JWK key must be 'oct'", Normal);
+                }
+                let key_bytes = jwk::decode_member("k", jwk.k.as_ref())?;
+
+                if let Some(ref alg) = jwk.alg {
+                    let expected = format!("A{}{}", key_bytes.len() * 8, self.jwk_suffix());
+                    if *alg != expected {
+                        ion_err!(format!("alg field of JWK must be {expected}"), Normal);
+                    }
+                }
+                jwk.validate_usages("enc", &usages, extractable)?;
+
+                key_bytes
+            }
+            _ => ion_err!("Unsupported key format", Normal),
+        };
+
+        if ![16, 24, 32].contains(&key_bytes.len()) {
+            ion_err!("AES key length must be 128, 192 or 256 bits", Normal);
+        }
+
+        self.new_key(cx, key_bytes, extractable, usages)
+    }
+
+    fn export_key<'cx>(
+        &self,
+        cx: &'cx Context,
+        format: KeyFormat,
+        key: &CryptoKey,
+    ) -> ion::Result<ion::Value<'cx>> {
+        let key_data = self.key_data(key)?;
+
+        match format {
+            KeyFormat::Raw => {
+                let ab = ArrayBuffer::copy_from_bytes(cx, key_data)
+                    .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))?;
+                Ok(ab.as_value(cx))
+            }
+
+            KeyFormat::Jwk => {
+                let jwk = JsonWebKey {
+                    kty: "oct".to_string(),
+                    k: Some(jwk::encode_member(key_data)),
+                    alg: Some(format!("A{}{}", key_data.len() * 8, self.jwk_suffix())),
+                    key_ops: Some(key.usages.iter().map(|u| u.as_ref().to_string()).collect()),
+                    ext: Some(key.extractable),
+                    ..Default::default()
+                };
+                Ok(jwk.as_value(cx))
+            }
+
+            _ => ion_err!("Unsupported key format", Normal),
+        }
+    }
+
+    fn get_key_length(&self, cx: &Context, params: &Object) -> ion::Result<usize> {
+        let params = AesKeyGenParams::from_value(cx, &params.as_value(cx), false, ())?;
+        if ![128, 192, 256].contains(&params.length) {
+            ion_err!("AES key length must be 128, 192 or 256 bits", Normal);
+        }
+        Ok(params.length as usize)
+    }
+}
+
+/// GCM as specified in NIST SP 800-38D, which allows IVs of any length and
+/// tags as short as 32 bits. `data` ends with the tag when decrypting.
+fn gcm<C>(
+    key: &[u8],
+    iv: &[u8],
+    aad: &[u8],
+    data: Vec<u8>,
+    tag_length: usize,
+    encrypt: bool,
+) -> ion::Result<Vec<u8>>
+where
+    C: BlockEncrypt + BlockSizeUser<BlockSize = U16> + KeyInit,
+{
+    let cipher = C::new(GenericArray::from_slice(key));
+    let mut hash_key = GenericArray::default();
+    cipher.encrypt_block(&mut hash_key);
+
+    let ghash = |a: &[u8], b: &[u8]| {
+        let mut lengths = [0u8; 16];
+        lengths[..8].copy_from_slice(&(a.len() as u64 * 8).to_be_bytes());
+        lengths[8..].copy_from_slice(&(b.len() as u64 * 8).to_be_bytes());
+
+        let mut ghash = GHash::new(&hash_key);
+        ghash.update_padded(a);
+        ghash.update_padded(b);
+        ghash.update_padded(&lengths);
+        ghash.finalize()
+    };
+
+    let mut j0 = [0u8; 16];
+    if iv.len() == 12 {
+        j0[..12].copy_from_slice(iv);
+        j0[15] = 1;
+    } else {
+        j0.copy_from_slice(&ghash(&[], iv));
+    }
+    let mut counter = j0;
+    let low = u32::from_be_bytes(counter[12..].try_into().unwrap()).wrapping_add(1);
+    counter[12..].copy_from_slice(&low.to_be_bytes());
+
+    let compute_tag = |ciphertext: &[u8]| {
+        let mut tag = GenericArray::clone_from_slice(&j0);
+        cipher.encrypt_block(&mut tag);
+        tag.iter_mut()
+            .zip(ghash(aad, ciphertext))
+            .for_each(|(byte, hash_byte)| *byte ^= hash_byte);
+        tag[..tag_length].to_vec()
+    };
+
+    if encrypt {
+        let mut ciphertext = ctr::<C>(key, counter, 32, data)?;
+        let tag = compute_tag(&ciphertext);
+        ciphertext.extend_from_slice(&tag);
+        Ok(ciphertext)
+    } else {
+        let Some(split) = data.len().checked_sub(tag_length) else {
+            ion_err!("Decryption failed", Normal);
+        };
+        let (ciphertext, tag) = data.split_at(split);
+        if !timing_safe_eq(&compute_tag(ciphertext), tag) {
+            ion_err!("Decryption failed", Normal);
+        }
+        ctr::<C>(key, counter, 32, ciphertext.to_vec())
+    }
+}
+
+/// CTR mode where only the rightmost `length` bits of the counter block
+/// are incremented, as required by the Web Crypto API.
+fn ctr<C>(key: &[u8], counter: [u8; 16], length: u8, mut data: Vec<u8>) -> ion::Result<Vec<u8>>
+where
+    C: BlockEncrypt + KeyInit,
+{
+    let mask = if length == 128 {
+        u128::MAX
+    } else {
+        (1u128 << length) - 1
+    };
+
+    let blocks = data.len().div_ceil(16) as u128;
+    if length < 128 && blocks > 1u128 << length {
+        ion_err!(
+            "The counter would wrap around for this amount of data",
+            Normal
+        );
+    }
+
+    let cipher = C::new(GenericArray::from_slice(key));
+    let mut counter = u128::from_be_bytes(counter);
+    for chunk in data.chunks_mut(16) {
+        let mut block = GenericArray::clone_from_slice(&counter.to_be_bytes());
+        cipher.encrypt_block(&mut block);
+        chunk
+            .iter_mut()
+            .zip(block.iter())
+            .for_each(|(byte, key_byte)| *byte ^= key_byte);
+
+        counter = (counter & !mask) | (counter.wrapping_add(1) & mask);
+    }
+
+    Ok(data)
+}
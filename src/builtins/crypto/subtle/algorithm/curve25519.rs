@@ -0,0 +1,467 @@
+use ed25519_dalek::{Signer, Verifier};
+use ion::{
+    conversions::{FromValue, ToValue},
+    typedarray::ArrayBuffer,
+    ClassDefinition, Context, Error, ErrorKind, Object,
+};
+use pkcs8::{
+    der::{
+        asn1::{BitStringRef, OctetStringRef},
+        Decode, Encode,
+    },
+    spki::{AlgorithmIdentifierRef, SubjectPublicKeyInfoRef},
+    ObjectIdentifier, PrivateKeyInfo,
+};
+
+use crate::{
+    builtins::crypto::subtle::{
+        crypto_key::{
+            generate_random_key, CryptoKey, GeneratedKey, KeyAlgorithm, KeyFormat, KeyMaterial,
+            KeyType, KeyUsage,
+        },
+        jwk::{self, JsonWebKey},
+        HeapKeyData,
+    },
+    ion_err, ion_mk_err,
+};
+
+use super::{
+    ec::{truncate_bits, EcdhKeyDeriveParams},
+    CryptoAlgorithm,
+};
+
+const KEY_LENGTH: usize = 32;
+
+// From RFC 8410
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+const X25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.110");
+
+#[derive(Clone)]
+pub struct OkpKey {
+    private: Option<[u8; KEY_LENGTH]>,
+    public: [u8; KEY_LENGTH],
+}
+
+impl OkpKey {
+    pub(crate) fn public_only(&self) -> Self {
+        Self {
+            private: None,
+            public: self.public,
+        }
+    }
+}
+
+pub enum Curve25519 {
+    Ed25519,
+    X25519,
+}
+
+impl Curve25519 {
+    fn algorithm_identifier(&self) -> AlgorithmIdentifierRef<'static> {
+        AlgorithmIdentifierRef {
+            oid: match self {
+                Self::Ed25519 => ED25519_OID,
+                Self::X25519 => X25519_OID,
+            },
+            // RFC 8410 requires the parameters to be absent
+            parameters: None,
+        }
+    }
+
+    fn public_usages(&self) -> &'static [KeyUsage] {
+        match self {
+            Self::Ed25519 => &[KeyUsage::Verify],
+            Self::X25519 => &[],
+        }
+    }
+
+    fn private_usages(&self) -> &'static [KeyUsage] {
+        match self {
+            Self::Ed25519 => &[KeyUsage::Sign],
+            Self::X25519 => &[KeyUsage::DeriveKey, KeyUsage::DeriveBits],
+        }
+    }
+
+    fn public_key(&self, private: &[u8; KEY_LENGTH]) -> [u8; KEY_LENGTH] {
+        match self {
+            Self::Ed25519 => ed25519_dalek::SigningKey::from_bytes(private)
+                .verifying_key()
+                .to_bytes(),
+            Self::X25519 => {
+                let secret = x25519_dalek::StaticSecret::from(*private);
+                x25519_dalek::PublicKey::from(&secret).to_bytes()
+            }
+        }
+    }
+
+    fn private_key(&self, private: &[u8]) -> ion::Result<OkpKey> {
+        let Ok(private) = <[u8; KEY_LENGTH]>::try_from(private) else {
+            ion_err!(
+                format!("{} private keys must be 32 bytes long", self.name()),
+                Normal
+            );
+        };
+        Ok(OkpKey {
+            public: self.public_key(&private),
+            private: Some(private),
+        })
+    }
+
+    fn public_only_key(&self, public: &[u8]) -> ion::Result<OkpKey> {
+        let Ok(public) = <[u8; KEY_LENGTH]>::try_from(public) else {
+            ion_err!(
+                format!("{} public keys must be 32 bytes long", self.name()),
+                Normal
+            );
+        };
+        if let Self::Ed25519 = self {
+            ed25519_dalek::VerifyingKey::from_bytes(&public)
+                .map_err(|_| ion_mk_err!("Invalid Ed25519 public key", Normal))?;
+        }
+        Ok(OkpKey {
+            private: None,
+            public,
+        })
+    }
+
+    fn okp_key<'k>(&self, key: &'k CryptoKey) -> ion::Result<&'k OkpKey> {
+        match &key.key {
+            KeyMaterial::Okp(okp_key) if key.algorithm.name() == self.name() => Ok(okp_key),
+            _ => ion_err!(
+                format!("The provided key is not an {} key", self.name()),
+                Type
+            ),
+        }
+    }
+
+    fn new_key(
+        &self,
+        cx: &Context,
+        key: OkpKey,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<CryptoKey> {
+        let key_type = match key.private {
+            Some(_) => KeyType::Private,
+            None => KeyType::Public,
+        };
+        CryptoKey::new(
+            cx,
+            extractable,
+            KeyAlgorithm::Basic { name: self.name() },
+            key_type,
+            usages,
+            KeyMaterial::Okp(key),
+        )
+    }
+
+    fn import_jwk(&self, jwk: &JsonWebKey) -> ion::Result<OkpKey> {
+        if jwk.kty != "OKP" {
+            ion_err!("kty member of JWK key must be 'OKP'", Normal);
+        }
+        if jwk.crv.as_deref() != Some(self.name()) {
+            ion_err!(
+                format!("crv member of JWK key must be '{}'", self.name()),
+                Normal
+            );
+        }
+        if let (Self::Ed25519, Some(alg)) = (self, &jwk.alg) {
+            if alg != "EdDSA" && alg != "Ed25519" {
+                ion_err!("alg field of JWK must be EdDSA", Normal);
+            }
+        }
+
+        let x = jwk::decode_member("x", jwk.x.as_ref())?;
+        if jwk.d.is_none() {
+            return self.public_only_key(&x);
+        }
+
+        let key = self.private_key(&jwk::decode_member("d", jwk.d.as_ref())?)?;
+        if key.public[..] != x[..] {
+            ion_err!(
+                "The public and private parts of the JWK don't match",
+                Normal
+            );
+        }
+        Ok(key)
+    }
+
+    fn check_algorithm(&self, algorithm: &AlgorithmIdentifierRef) -> ion::Result<()> {
+        if *algorithm != self.algorithm_identifier() {
+            ion_err!(
+                format!("The key data is not an {} key", self.name()),
+                Normal
+            );
+        }
+        Ok(())
+    }
+
+    fn import_spki(&self, der: &[u8]) -> ion::Result<OkpKey> {
+        let spki = SubjectPublicKeyInfoRef::from_der(der)
+            .map_err(|e| ion_mk_err!(format!("Invalid SPKI data: {e}"), Normal))?;
+        self.check_algorithm(&spki.algorithm)?;
+        let Some(public) = spki.subject_public_key.as_bytes() else {
+            ion_err!("Invalid SPKI data: the public key has unused bits", Normal);
+        };
+        self.public_only_key(public)
+    }
+
+    /// Accepts both version 1 (RFC 5208) and version 2 (RFC 5958)
+    /// structures. If a version 2 structure has a public key, it must match
+    /// the private key.
+    fn import_pkcs8(&self, der: &[u8]) -> ion::Result<OkpKey> {
+        let info = PrivateKeyInfo::from_der(der)
+            .map_err(|e| ion_mk_err!(format!("Invalid PKCS#8 data: {e}"), Normal))?;
+        self.check_algorithm(&info.algorithm)?;
+
+        // The private key is itself wrapped in an OCTET STRING
+        let private = OctetStringRef::from_der(info.private_key)
+            .map_err(|e| ion_mk_err!(format!("Invalid PKCS#8 data: {e}"), Normal))?;
+        let key = self.private_key(private.as_bytes())?;
+
+        if let Some(public) = info.public_key {
+            if public != key.public {
+                ion_err!(
+                    "The public key in the PKCS#8 data doesn't match the private key",
+                    Normal
+                );
+            }
+        }
+        Ok(key)
+    }
+
+    fn export_spki(&self, public: &[u8]) -> ion::Result<Vec<u8>> {
+        let encode = || {
+            SubjectPublicKeyInfoRef {
+                algorithm: self.algorithm_identifier(),
+                subject_public_key: BitStringRef::from_bytes(public)?,
+            }
+            .to_der()
+        };
+        encode().map_err(|e| ion_mk_err!(format!("Failed to encode key: {e}"), Normal))
+    }
+
+    fn export_pkcs8(&self, private: &[u8]) -> ion::Result<Vec<u8>> {
+        let encode = || {
+            let private = OctetStringRef::new(private)?.to_der()?;
+            PrivateKeyInfo::new(self.algorithm_identifier(), &private).to_der()
+        };
+        encode().map_err(|e| ion_mk_err!(format!("Failed to encode key: {e}"), Normal))
+    }
+}
+
+impl CryptoAlgorithm for Curve25519 {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Ed25519 => "Ed25519",
+            Self::X25519 => "X25519",
+        }
+    }
+
+    fn sign<'cx>(
+        &self,
+        cx: &'cx Context,
+        _params: &Object,
+        key: &CryptoKey,
+        data: Vec<u8>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        if !matches!(self, Self::Ed25519) {
+            ion_err!("Operation not supported by the specified algorithm", Normal);
+        }
+
+        let okp_key = self.okp_key(key)?;
+        let Some(ref private) = okp_key.private else {
+            ion_err!("Signing requires a private key", Normal);
+        };
+
+        let signature = ed25519_dalek::SigningKey::from_bytes(private).sign(&data);
+        ArrayBuffer::copy_from_bytes(cx, &signature.to_bytes())
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn verify(
+        &self,
+        cx: &Context,
+        _params: &Object,
+        key: &CryptoKey,
+        signature: Vec<u8>,
+        data: Vec<u8>,
+    ) -> ion::Result<bool> {
+        if !matches!(self, Self::Ed25519) {
+            ion_err!("Operation not supported by the specified algorithm", Normal);
+        }
+
+        let okp_key = self.okp_key(key)?;
+        if okp_key.private.is_some() {
+            ion_err!("Verification requires a public key", Normal);
+        }
+
+        let verifying_key = ed25519_dalek::VerifyingKey::from_bytes(&okp_key.public)
+            .map_err(|_| ion_mk_err!("Invalid Ed25519 public key", Normal))?;
+        Ok(match ed25519_dalek::Signature::from_slice(&signature) {
+            Ok(signature) => verifying_key.verify(&data, &signature).is_ok(),
+            Err(_) => false,
+        })
+    }
+
+    fn derive_bits<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        base_key: &CryptoKey,
+        length: Option<usize>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        if !matches!(self, Self::X25519) {
+            ion_err!("Operation not supported by the specified algorithm", Normal);
+        }
+
+        let Some(private) = self.okp_key(base_key)?.private else {
+            ion_err!("baseKey must be a private key", Normal);
+        };
+
+        let params = EcdhKeyDeriveParams::from_value(cx, &params.as_value(cx), false, ())?;
+        if !CryptoKey::instance_of(cx, &params.public) {
+            ion_err!("public must be a CryptoKey", Type);
+        }
+        let public_key = CryptoKey::get_private(cx, &params.public).unwrap();
+        if !matches!(public_key.key_type, KeyType::Public) {
+            ion_err!("public must be a public key", Normal);
+        }
+        let Ok(public_okp_key) = self.okp_key(public_key) else {
+            ion_err!("public must be an X25519 key", Normal);
+        };
+
+        let shared_secret = x25519_dalek::StaticSecret::from(private)
+            .diffie_hellman(&x25519_dalek::PublicKey::from(public_okp_key.public));
+        if !shared_secret.was_contributory() {
+            ion_err!("The shared secret is all zeroes", Normal);
+        }
+
+        let mut secret = shared_secret.to_bytes().to_vec();
+        if let Some(length) = length {
+            if length > secret.len() * 8 {
+                ion_err!("length is too long for X25519", Normal);
+            }
+            truncate_bits(&mut secret, length);
+        }
+
+        ArrayBuffer::copy_from_bytes(cx, &secret)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn generate_key(
+        &self,
+        cx: &Context,
+        _params: &Object,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<GeneratedKey> {
+        let public_usages = self.public_usages();
+        let private_usages = self.private_usages();
+        if usages
+            .iter()
+            .any(|u| !public_usages.contains(u) && !private_usages.contains(u))
+        {
+            ion_err!("Invalid key usage specified", Syntax);
+        }
+
+        let private = generate_random_key(KEY_LENGTH, &mut rand::thread_rng());
+        let key = self.private_key(&private)?;
+
+        let filter_usages = |allowed: &[KeyUsage]| {
+            usages
+                .iter()
+                .filter(|u| allowed.contains(u))
+                .copied()
+                .collect()
+        };
+
+        Ok(GeneratedKey::Pair {
+            // Public keys are always extractable
+            public_key: self.new_key(
+                cx,
+                OkpKey {
+                    private: None,
+                    public: key.public,
+                },
+                true,
+                filter_usages(public_usages),
+            )?,
+            private_key: self.new_key(cx, key, extractable, filter_usages(private_usages))?,
+        })
+    }
+
+    fn import_key(
+        &self,
+        cx: &Context,
+        _params: &Object,
+        format: KeyFormat,
+        key_data: HeapKeyData,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<CryptoKey> {
+        let key = match (format, key_data) {
+            (KeyFormat::Raw, HeapKeyData::Buffer(buffer)) => self.public_only_key(&buffer)?,
+            (KeyFormat::Spki, HeapKeyData::Buffer(buffer)) => self.import_spki(&buffer)?,
+            (KeyFormat::Pkcs8, HeapKeyData::Buffer(buffer)) => self.import_pkcs8(&buffer)?,
+            (KeyFormat::Jwk, HeapKeyData::Jwk(jwk)) => {
+                let expected_use = match self {
+                    Self::Ed25519 => "sig",
+                    Self::X25519 => "enc",
+                };
+                jwk.validate_usages(expected_use, &usages, extractable)?;
+                self.import_jwk(&jwk)?
+            }
+            _ => ion_err!("Unsupported key format", Normal),
+        };
+
+        let allowed_usages = match key.private {
+            Some(_) => self.private_usages(),
+            None => self.public_usages(),
+        };
+        if usages.iter().any(|u| !allowed_usages.contains(u)) {
+            ion_err!("Invalid key usage specified", Syntax);
+        }
+
+        self.new_key(cx, key, extractable, usages)
+    }
+
+    fn export_key<'cx>(
+        &self,
+        cx: &'cx Context,
+        format: KeyFormat,
+        key: &CryptoKey,
+    ) -> ion::Result<ion::Value<'cx>> {
+        let okp_key = self.okp_key(key)?;
+
+        let bytes = match (format, &okp_key.private) {
+            (KeyFormat::Raw, None) => okp_key.public.to_vec(),
+            (KeyFormat::Spki, None) => self.export_spki(&okp_key.public)?,
+            (KeyFormat::Pkcs8, Some(private)) => self.export_pkcs8(private)?,
+            (KeyFormat::Raw | KeyFormat::Spki, Some(_)) => {
+                ion_err!("Only public keys can be exported in this format", Normal)
+            }
+            (KeyFormat::Pkcs8, None) => {
+                ion_err!("Only private keys can be exported as PKCS#8", Normal)
+            }
+
+            (KeyFormat::Jwk, private) => {
+                let jwk = JsonWebKey {
+                    kty: "OKP".to_string(),
+                    crv: Some(self.name().to_string()),
+                    alg: matches!(self, Self::Ed25519).then(|| "EdDSA".to_string()),
+                    key_ops: Some(key.usages.iter().map(|u| u.as_ref().to_string()).collect()),
+                    ext: Some(key.extractable),
+                    x: Some(jwk::encode_member(&okp_key.public)),
+                    d: private.as_ref().map(|d| jwk::encode_member(d)),
+                    ..Default::default()
+                };
+                return Ok(jwk.as_value(cx));
+            }
+        };
+
+        let ab = ArrayBuffer::copy_from_bytes(cx, &bytes)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))?;
+        Ok(ab.as_value(cx))
+    }
+}
@@ -0,0 +1,545 @@
+use ion::{
+    conversions::{FromValue, ToValue},
+    typedarray::ArrayBuffer,
+    ClassDefinition, Context, Error, ErrorKind, Object,
+};
+use p256::{
+    ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier},
+    elliptic_curve::sec1::ToEncodedPoint,
+    pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey},
+};
+
+use crate::{
+    builtins::crypto::subtle::{
+        crypto_key::{
+            CryptoKey, GeneratedKey, KeyAlgorithm, KeyFormat, KeyMaterial, KeyType, KeyUsage,
+        },
+        jwk::{self, JsonWebKey},
+        AlgorithmIdentifier, HeapKeyData,
+    },
+    ion_err, ion_mk_err,
+};
+
+use super::{sha::Sha, CryptoAlgorithm};
+
+/// Runs `$body` with `$curve` bound to the crate implementing a curve.
+macro_rules! with_curve {
+    ($named_curve:expr, $curve:ident => $body:expr) => {
+        match $named_curve {
+            NamedCurve::P256 => {
+                use p256 as $curve;
+                $body
+            }
+            NamedCurve::P384 => {
+                use p384 as $curve;
+                $body
+            }
+            NamedCurve::P521 => {
+                use p521 as $curve;
+                $body
+            }
+        }
+    };
+}
+
+#[derive(FromValue)]
+pub struct EcKeyParams {
+    #[ion(name = "namedCurve")]
+    named_curve: String,
+}
+
+#[derive(FromValue)]
+pub struct EcdsaParams<'cx> {
+    hash: AlgorithmIdentifier<'cx>,
+}
+
+// Also used by X25519
+#[derive(FromValue)]
+pub struct EcdhKeyDeriveParams<'cx> {
+    pub(super) public: Object<'cx>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NamedCurve {
+    P256,
+    P384,
+    P521,
+}
+
+impl NamedCurve {
+    fn from_name(name: &str) -> ion::Result<Self> {
+        match name {
+            "P-256" => Ok(Self::P256),
+            "P-384" => Ok(Self::P384),
+            "P-521" => Ok(Self::P521),
+            _ => ion_err!("Unsupported named curve", Normal),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::P256 => "P-256",
+            Self::P384 => "P-384",
+            Self::P521 => "P-521",
+        }
+    }
+
+    /// The length of coordinates and private keys, in bytes.
+    fn field_size(&self) -> usize {
+        match self {
+            Self::P256 => 32,
+            Self::P384 => 48,
+            Self::P521 => 66,
+        }
+    }
+}
+
+/// Keys are kept in a curve-independent encoding: the private scalar if
+/// there is one, and the public point in uncompressed SEC1 format.
+#[derive(Clone)]
+pub struct EcKey {
+    private: Option<Vec<u8>>,
+    public: Vec<u8>,
+}
+
+impl EcKey {
+    fn generate(curve: NamedCurve) -> Self {
+        with_curve!(curve, c => {
+            let secret = c::SecretKey::random(&mut rand::thread_rng());
+            Self {
+                private: Some(secret.to_bytes().to_vec()),
+                public: secret.public_key().to_encoded_point(false).as_bytes().to_vec(),
+            }
+        })
+    }
+
+    fn from_private_bytes(curve: NamedCurve, bytes: &[u8]) -> ion::Result<Self> {
+        with_curve!(curve, c => {
+            let secret = c::SecretKey::from_slice(bytes)
+                .map_err(|_| ion_mk_err!("Invalid EC private key", Normal))?;
+            Ok(Self {
+                private: Some(secret.to_bytes().to_vec()),
+                public: secret.public_key().to_encoded_point(false).as_bytes().to_vec(),
+            })
+        })
+    }
+
+    fn from_public_bytes(curve: NamedCurve, bytes: &[u8]) -> ion::Result<Self> {
+        with_curve!(curve, c => {
+            let public = c::PublicKey::from_sec1_bytes(bytes)
+                .map_err(|_| ion_mk_err!("Invalid EC public key", Normal))?;
+            Ok(Self {
+                private: None,
+                public: public.to_encoded_point(false).as_bytes().to_vec(),
+            })
+        })
+    }
+
+    pub(crate) fn public_only(&self) -> Self {
+        Self {
+            private: None,
+            public: self.public.clone(),
+        }
+    }
+}
+
+pub enum Ec {
+    Ecdsa,
+    Ecdh,
+}
+
+impl Ec {
+    fn public_usages(&self) -> &'static [KeyUsage] {
+        match self {
+            Self::Ecdsa => &[KeyUsage::Verify],
+            Self::Ecdh => &[],
+        }
+    }
+
+    fn private_usages(&self) -> &'static [KeyUsage] {
+        match self {
+            Self::Ecdsa => &[KeyUsage::Sign],
+            Self::Ecdh => &[KeyUsage::DeriveKey, KeyUsage::DeriveBits],
+        }
+    }
+
+    /// The curve and EC key of a `CryptoKey`.
+    fn key_parts<'k>(&self, key: &'k CryptoKey) -> ion::Result<(NamedCurve, &'k EcKey)> {
+        match (&key.algorithm, &key.key) {
+            (KeyAlgorithm::Ec { named_curve, .. }, KeyMaterial::Ec(ec_key)) => {
+                Ok((*named_curve, ec_key))
+            }
+            _ => ion_err!("The provided key is not an EC key", Type),
+        }
+    }
+
+    fn new_key(
+        &self,
+        cx: &Context,
+        named_curve: NamedCurve,
+        key: EcKey,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<CryptoKey> {
+        let key_type = match key.private {
+            Some(_) => KeyType::Private,
+            None => KeyType::Public,
+        };
+        let algorithm = KeyAlgorithm::Ec {
+            name: self.name(),
+            named_curve,
+        };
+        CryptoKey::new(
+            cx,
+            extractable,
+            algorithm,
+            key_type,
+            usages,
+            KeyMaterial::Ec(key),
+        )
+    }
+
+    fn import_jwk(&self, named_curve: NamedCurve, jwk: &JsonWebKey) -> ion::Result<EcKey> {
+        if jwk.kty != "EC" {
+            ion_err!("kty member of JWK key must be 'EC'", Normal);
+        }
+        if jwk.crv.as_deref() != Some(named_curve.name()) {
+            ion_err!(
+                format!("crv member of JWK key must be '{}'", named_curve.name()),
+                Normal
+            );
+        }
+        if let (Self::Ecdsa, Some(alg)) = (self, &jwk.alg) {
+            let expected = jwk_alg(named_curve);
+            if alg != expected {
+                ion_err!(format!("alg field of JWK must be {expected}"), Normal);
+            }
+        }
+
+        if jwk.d.is_some() {
+            let d = jwk::decode_member("d", jwk.d.as_ref())?;
+            let key = EcKey::from_private_bytes(named_curve, &d)?;
+
+            // Make sure the public key matches the private one
+            let public = EcKey::from_public_bytes(named_curve, &jwk_point(named_curve, jwk)?)?;
+            if public.public != key.public {
+                ion_err!(
+                    "The public and private parts of the JWK don't match",
+                    Normal
+                );
+            }
+            Ok(key)
+        } else {
+            EcKey::from_public_bytes(named_curve, &jwk_point(named_curve, jwk)?)
+        }
+    }
+}
+
+impl CryptoAlgorithm for Ec {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Ecdsa => "ECDSA",
+            Self::Ecdh => "ECDH",
+        }
+    }
+
+    fn sign<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        key: &CryptoKey,
+        data: Vec<u8>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        if !matches!(self, Self::Ecdsa) {
+            ion_err!("Operation not supported by the specified algorithm", Normal);
+        }
+
+        let (named_curve, ec_key) = self.key_parts(key)?;
+        let Some(ref private) = ec_key.private else {
+            ion_err!("Signing requires a private key", Normal);
+        };
+
+        let params = EcdsaParams::from_value(cx, &params.as_value(cx), false, ())?;
+        let hashed = Sha::from_identifier(cx, &params.hash)?.hash(&data);
+
+        let signature = with_curve!(named_curve, c => {
+            let signing_key = c::ecdsa::SigningKey::from_slice(private)
+                .map_err(|_| ion_mk_err!("Invalid EC private key", Normal))?;
+            let signature: c::ecdsa::Signature = signing_key
+                .sign_prehash(&hashed)
+                .map_err(|e| ion_mk_err!(format!("Signing failed: {e}"), Normal))?;
+            signature.to_bytes().to_vec()
+        });
+
+        ArrayBuffer::copy_from_bytes(cx, &signature)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn verify(
+        &self,
+        cx: &Context,
+        params: &Object,
+        key: &CryptoKey,
+        signature: Vec<u8>,
+        data: Vec<u8>,
+    ) -> ion::Result<bool> {
+        if !matches!(self, Self::Ecdsa) {
+            ion_err!("Operation not supported by the specified algorithm", Normal);
+        }
+
+        let (named_curve, ec_key) = self.key_parts(key)?;
+        if ec_key.private.is_some() {
+            ion_err!("Verification requires a public key", Normal);
+        }
+
+        let params = EcdsaParams::from_value(cx, &params.as_value(cx), false, ())?;
+        let hashed = Sha::from_identifier(cx, &params.hash)?.hash(&data);
+
+        Ok(with_curve!(named_curve, c => {
+            let verifying_key = c::ecdsa::VerifyingKey::from_sec1_bytes(&ec_key.public)
+                .map_err(|_| ion_mk_err!("Invalid EC public key", Normal))?;
+            match c::ecdsa::Signature::from_slice(&signature) {
+                Ok(signature) => verifying_key.verify_prehash(&hashed, &signature).is_ok(),
+                Err(_) => false,
+            }
+        }))
+    }
+
+    fn derive_bits<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        base_key: &CryptoKey,
+        length: Option<usize>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        if !matches!(self, Self::Ecdh) {
+            ion_err!("Operation not supported by the specified algorithm", Normal);
+        }
+
+        let (named_curve, ec_key) = self.key_parts(base_key)?;
+        let Some(ref private) = ec_key.private else {
+            ion_err!("baseKey must be a private key", Normal);
+        };
+
+        let params = EcdhKeyDeriveParams::from_value(cx, &params.as_value(cx), false, ())?;
+        if !CryptoKey::instance_of(cx, &params.public) {
+            ion_err!("public must be a CryptoKey", Type);
+        }
+        let public_key = CryptoKey::get_private(cx, &params.public).unwrap();
+        if !matches!(public_key.key_type, KeyType::Public) {
+            ion_err!("public must be a public key", Normal);
+        }
+        let (public_curve, public_ec_key) = self.key_parts(public_key)?;
+        if public_key.algorithm.name() != base_key.algorithm.name() || public_curve != named_curve {
+            ion_err!(
+                "public must be an ECDH key on the same curve as baseKey",
+                Normal
+            );
+        }
+
+        let mut secret = with_curve!(named_curve, c => {
+            let secret = c::SecretKey::from_slice(private)
+                .map_err(|_| ion_mk_err!("Invalid EC private key", Normal))?;
+            let public = c::PublicKey::from_sec1_bytes(&public_ec_key.public)
+                .map_err(|_| ion_mk_err!("Invalid EC public key", Normal))?;
+            c::ecdh::diffie_hellman(secret.to_nonzero_scalar(), public.as_affine())
+                .raw_secret_bytes()
+                .to_vec()
+        });
+
+        if let Some(length) = length {
+            if length > secret.len() * 8 {
+                ion_err!("length is too long for the curve", Normal);
+            }
+            truncate_bits(&mut secret, length);
+        }
+
+        ArrayBuffer::copy_from_bytes(cx, &secret)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn generate_key(
+        &self,
+        cx: &Context,
+        params: &Object,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<GeneratedKey> {
+        let public_usages = self.public_usages();
+        let private_usages = self.private_usages();
+        if usages
+            .iter()
+            .any(|u| !public_usages.contains(u) && !private_usages.contains(u))
+        {
+            ion_err!("Invalid key usage specified", Syntax);
+        }
+
+        let params = EcKeyParams::from_value(cx, &params.as_value(cx), false, ())?;
+        let named_curve = NamedCurve::from_name(&params.named_curve)?;
+        let key = EcKey::generate(named_curve);
+
+        let filter_usages = |allowed: &[KeyUsage]| {
+            usages
+                .iter()
+                .filter(|u| allowed.contains(u))
+                .copied()
+                .collect()
+        };
+
+        Ok(GeneratedKey::Pair {
+            // Public keys are always extractable
+            public_key: self.new_key(
+                cx,
+                named_curve,
+                key.public_only(),
+                true,
+                filter_usages(public_usages),
+            )?,
+            private_key: self.new_key(
+                cx,
+                named_curve,
+                key,
+                extractable,
+                filter_usages(private_usages),
+            )?,
+        })
+    }
+
+    fn import_key(
+        &self,
+        cx: &Context,
+        params: &Object,
+        format: KeyFormat,
+        key_data: HeapKeyData,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<CryptoKey> {
+        let params = EcKeyParams::from_value(cx, &params.as_value(cx), false, ())?;
+        let named_curve = NamedCurve::from_name(&params.named_curve)?;
+
+        let key = match (format, key_data) {
+            (KeyFormat::Raw, HeapKeyData::Buffer(buffer)) => {
+                EcKey::from_public_bytes(named_curve, &buffer)?
+            }
+            (KeyFormat::Spki, HeapKeyData::Buffer(buffer)) => with_curve!(named_curve, c => {
+                let public = c::PublicKey::from_public_key_der(&buffer)
+                    .map_err(|e| ion_mk_err!(format!("Invalid SPKI data: {e}"), Normal))?;
+                EcKey::from_public_bytes(named_curve, public.to_encoded_point(false).as_bytes())?
+            }),
+            (KeyFormat::Pkcs8, HeapKeyData::Buffer(buffer)) => with_curve!(named_curve, c => {
+                let secret = c::SecretKey::from_pkcs8_der(&buffer)
+                    .map_err(|e| ion_mk_err!(format!("Invalid PKCS#8 data: {e}"), Normal))?;
+                EcKey::from_private_bytes(named_curve, &secret.to_bytes())?
+            }),
+            (KeyFormat::Jwk, HeapKeyData::Jwk(jwk)) => {
+                let expected_use = match self {
+                    Self::Ecdsa => "sig",
+                    Self::Ecdh => "enc",
+                };
+                jwk.validate_usages(expected_use, &usages, extractable)?;
+                self.import_jwk(named_curve, &jwk)?
+            }
+            _ => ion_err!("Unsupported key format", Normal),
+        };
+
+        let allowed_usages = match key.private {
+            Some(_) => self.private_usages(),
+            None => self.public_usages(),
+        };
+        if usages.iter().any(|u| !allowed_usages.contains(u)) {
+            ion_err!("Invalid key usage specified", Syntax);
+        }
+
+        self.new_key(cx, named_curve, key, extractable, usages)
+    }
+
+    fn export_key<'cx>(
+        &self,
+        cx: &'cx Context,
+        format: KeyFormat,
+        key: &CryptoKey,
+    ) -> ion::Result<ion::Value<'cx>> {
+        let (named_curve, ec_key) = self.key_parts(key)?;
+
+        let bytes = match (format, &ec_key.private) {
+            (KeyFormat::Raw, None) => ec_key.public.clone(),
+            (KeyFormat::Spki, None) => with_curve!(named_curve, c => {
+                c::PublicKey::from_sec1_bytes(&ec_key.public)
+                    .map_err(|_| ion_mk_err!("Invalid EC public key", Normal))?
+                    .to_public_key_der()
+                    .map_err(|e| ion_mk_err!(format!("Failed to encode key: {e}"), Normal))?
+                    .as_bytes()
+                    .to_vec()
+            }),
+            (KeyFormat::Pkcs8, Some(private)) => with_curve!(named_curve, c => {
+                c::SecretKey::from_slice(private)
+                    .map_err(|_| ion_mk_err!("Invalid EC private key", Normal))?
+                    .to_pkcs8_der()
+                    .map_err(|e| ion_mk_err!(format!("Failed to encode key: {e}"), Normal))?
+                    .as_bytes()
+                    .to_vec()
+            }),
+            (KeyFormat::Raw | KeyFormat::Spki, Some(_)) => {
+                ion_err!("Only public keys can be exported in this format", Normal)
+            }
+            (KeyFormat::Pkcs8, None) => {
+                ion_err!("Only private keys can be exported as PKCS#8", Normal)
+            }
+
+            (KeyFormat::Jwk, private) => {
+                // Uncompressed points are 0x04 followed by the coordinates
+                let (x, y) = ec_key.public[1..].split_at(named_curve.field_size());
+                let jwk = JsonWebKey {
+                    kty: "EC".to_string(),
+                    crv: Some(named_curve.name().to_string()),
+                    alg: matches!(self, Self::Ecdsa).then(|| jwk_alg(named_curve).to_string()),
+                    key_ops: Some(key.usages.iter().map(|u| u.as_ref().to_string()).collect()),
+                    ext: Some(key.extractable),
+                    x: Some(jwk::encode_member(x)),
+                    y: Some(jwk::encode_member(y)),
+                    d: private.as_deref().map(jwk::encode_member),
+                    ..Default::default()
+                };
+                return Ok(jwk.as_value(cx));
+            }
+        };
+
+        let ab = ArrayBuffer::copy_from_bytes(cx, &bytes)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))?;
+        Ok(ab.as_value(cx))
+    }
+}
+
+fn jwk_alg(named_curve: NamedCurve) -> &'static str {
+    match named_curve {
+        NamedCurve::P256 => "ES256",
+        NamedCurve::P384 => "ES384",
+        NamedCurve::P521 => "ES512",
+    }
+}
+
+/// The uncompressed SEC1 point for the `x` and `y` members of a JWK.
+fn jwk_point(named_curve: NamedCurve, jwk: &JsonWebKey) -> ion::Result<Vec<u8>> {
+    let x = jwk::decode_member("x", jwk.x.as_ref())?;
+    let y = jwk::decode_member("y", jwk.y.as_ref())?;
+    if x.len() != named_curve.field_size() || y.len() != named_curve.field_size() {
+        ion_err!("Invalid length of EC coordinates in JWK", Normal);
+    }
+
+    let mut point = Vec::with_capacity(1 + x.len() + y.len());
+    point.push(0x04);
+    point.extend(x);
+    point.extend(y);
+    Ok(point)
+}
+
+/// Keeps the first `length` bits of `bytes`, zeroing out the rest of the
+/// last byte.
+pub(super) fn truncate_bits(bytes: &mut Vec<u8>, length: usize) {
+    bytes.truncate(length.div_ceil(8));
+    if length % 8 != 0 {
+        if let Some(last) = bytes.last_mut() {
+            *last &= 0xff << (8 - length % 8);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use ion::{
+    conversions::{ConversionBehavior, FromValue, ToValue},
+    typedarray::ArrayBuffer,
+    Context, Error, ErrorKind, Object,
+};
+
+use crate::{
+    builtins::crypto::subtle::{
+        crypto_key::{CryptoKey, KeyAlgorithm, KeyFormat, KeyMaterial, KeyType, KeyUsage},
+        AlgorithmIdentifier, BufferSource, HeapKeyData,
+    },
+    ion_err, ion_mk_err,
+};
+
+use super::{
+    sha::{with_sha, Sha},
+    CryptoAlgorithm,
+};
+
+#[derive(FromValue)]
+pub struct Pbkdf2Params<'cx> {
+    salt: BufferSource<'cx>,
+    #[ion(convert = ConversionBehavior::EnforceRange, strict)]
+    iterations: u32,
+    hash: AlgorithmIdentifier<'cx>,
+}
+
+#[derive(FromValue)]
+pub struct HkdfParams<'cx> {
+    hash: AlgorithmIdentifier<'cx>,
+    salt: BufferSource<'cx>,
+    info: BufferSource<'cx>,
+}
+
+pub enum Kdf {
+    Pbkdf2,
+    Hkdf,
+}
+
+impl CryptoAlgorithm for Kdf {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Pbkdf2 => "PBKDF2",
+            Self::Hkdf => "HKDF",
+        }
+    }
+
+    fn derive_bits<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        base_key: &CryptoKey,
+        length: Option<usize>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        let key_data = match &base_key.key {
+            KeyMaterial::Secret(key_data) if base_key.algorithm.name() == self.name() => key_data,
+            _ => ion_err!(
+                format!("The provided key is not a {} key", self.name()),
+                Type
+            ),
+        };
+
+        let Some(length) = length else {
+            ion_err!(format!("{} requires a length", self.name()), Normal);
+        };
+        if length == 0 || length % 8 != 0 {
+            ion_err!("length must be a non-zero multiple of 8", Normal);
+        }
+        let mut output = vec![0u8; length / 8];
+
+        match self {
+            Self::Pbkdf2 => {
+                let params = Pbkdf2Params::from_value(cx, &params.as_value(cx), false, ())?;
+                let hash = Sha::from_identifier(cx, &params.hash)?;
+                if params.iterations == 0 {
+                    ion_err!("iterations must be greater than zero", Normal);
+                }
+
+                let salt = params.salt.to_owned();
+                with_sha!(hash, D => pbkdf2::pbkdf2_hmac::<D>(
+                    key_data,
+                    &salt,
+                    params.iterations,
+                    &mut output,
+                ));
+            }
+            Self::Hkdf => {
+                let params = HkdfParams::from_value(cx, &params.as_value(cx), false, ())?;
+                let hash = Sha::from_identifier(cx, &params.hash)?;
+
+                let salt = params.salt.to_owned();
+                let info = params.info.to_owned();
+                with_sha!(hash, D => hkdf::Hkdf::<D>::new(Some(&salt), key_data)
+                    .expand(&info, &mut output)
+                    .map_err(|_| ion_mk_err!("length is too long for HKDF", Normal))?);
+            }
+        }
+
+        ArrayBuffer::copy_from_bytes(cx, &output)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn import_key(
+        &self,
+        cx: &Context,
+        _params: &Object,
+        format: KeyFormat,
+        key_data: HeapKeyData,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<CryptoKey> {
+        if usages
+            .iter()
+            .any(|u| !matches!(u, KeyUsage::DeriveKey | KeyUsage::DeriveBits))
+        {
+            ion_err!(
+                "Invalid key usage specified; only 'deriveKey' and 'deriveBits' are allowed",
+                Syntax
+            );
+        }
+
+        let (KeyFormat::Raw, HeapKeyData::Buffer(key_data)) = (format, key_data) else {
+            ion_err!(
+                format!("{} keys can only be imported in raw format", self.name()),
+                Normal
+            );
+        };
+
+        if extractable {
+            ion_err!(
+                format!("{} keys cannot be extractable", self.name()),
+                Syntax
+            );
+        }
+
+        CryptoKey::new(
+            cx,
+            false,
+            KeyAlgorithm::Basic { name: self.name() },
+            KeyType::Secret,
+            usages,
+            KeyMaterial::Secret(key_data),
+        )
+    }
+}
@@ -1,6 +1,6 @@
 use ion::{typedarray::ArrayBuffer, Context, Error, ErrorKind};
 
-use super::CryptoAlgorithm;
+use super::{CryptoAlgorithm, Hasher};
 
 pub struct Md5;
 
@@ -19,4 +19,18 @@ impl CryptoAlgorithm for Md5 {
         ArrayBuffer::copy_from_bytes(cx, &data[..])
             .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
     }
+
+    fn hasher(&self) -> ion::Result<Box<dyn Hasher>> {
+        Ok(Box::new(md5::Context::new()))
+    }
+}
+
+impl Hasher for md5::Context {
+    fn update(&mut self, data: &[u8]) {
+        self.consume(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.compute().0.to_vec()
+    }
 }
@@ -1,14 +1,27 @@
+pub mod aes;
+pub mod curve25519;
+pub mod ec;
 pub mod hmac;
+pub mod kdf;
 pub mod md5;
+pub mod rsa;
 pub mod sha;
 
 use ion::{typedarray::ArrayBuffer, Context, Object, Value};
 
 use super::{
-    crypto_key::{CryptoKey, KeyFormat, KeyUsage},
+    crypto_key::{CryptoKey, GeneratedKey, KeyFormat, KeyUsage},
     HeapKeyData,
 };
 
+/// An in-progress hash computation, used to hash data that arrives in
+/// chunks without buffering all of it.
+pub trait Hasher {
+    fn update(&mut self, data: &[u8]);
+
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+}
+
 #[allow(unused_variables)]
 pub trait CryptoAlgorithm {
     fn name(&self) -> &'static str;
@@ -85,12 +98,19 @@ pub trait CryptoAlgorithm {
         ))
     }
 
+    fn hasher(&self) -> ion::Result<Box<dyn Hasher>> {
+        Err(ion::Error::new(
+            "Operation not supported by the specified algorithm",
+            ion::ErrorKind::Normal,
+        ))
+    }
+
     fn derive_bits<'cx>(
         &self,
         cx: &'cx Context,
         params: &Object,
-        base_key: CryptoKey,
-        length: usize,
+        base_key: &CryptoKey,
+        length: Option<usize>,
     ) -> ion::Result<ArrayBuffer<'cx>> {
         Err(ion::Error::new(
             "Operation not supported by the specified algorithm",
@@ -98,35 +118,26 @@ pub trait CryptoAlgorithm {
         ))
     }
 
+    // Algorithms with a dedicated key wrapping operation (AES-KW) override
+    // these, everything else wraps keys by encrypting them.
     fn wrap_key<'cx>(
         &self,
         cx: &'cx Context,
         params: &Object,
-        format: KeyFormat,
-        key: &CryptoKey,
-        wrapping_key: CryptoKey,
+        wrapping_key: &CryptoKey,
+        key: Vec<u8>,
     ) -> ion::Result<ArrayBuffer<'cx>> {
-        Err(ion::Error::new(
-            "Operation not supported by the specified algorithm",
-            ion::ErrorKind::Normal,
-        ))
+        self.encrypt(cx, params, wrapping_key, key)
     }
 
-    #[allow(clippy::too_many_arguments)]
     fn unwrap_key<'cx>(
         &self,
-        _cx: &'cx Context,
-        _params: &Object,
-        _format: KeyFormat,
-        _wrapped_key: Vec<u8>,
-        _unwrapping_key: &CryptoKey,
-        _extractable: bool,
-        _usages: Vec<KeyUsage>,
+        cx: &'cx Context,
+        params: &Object,
+        unwrapping_key: &CryptoKey,
+        wrapped_key: Vec<u8>,
     ) -> ion::Result<ArrayBuffer<'cx>> {
-        Err(ion::Error::new(
-            "Operation not supported by the specified algorithm",
-            ion::ErrorKind::Normal,
-        ))
+        self.decrypt(cx, params, unwrapping_key, wrapped_key)
     }
 
     fn generate_key(
@@ -135,7 +146,7 @@ pub trait CryptoAlgorithm {
         params: &Object,
         extractable: bool,
         usages: Vec<KeyUsage>,
-    ) -> ion::Result<CryptoKey> {
+    ) -> ion::Result<GeneratedKey> {
         Err(ion::Error::new(
             "Operation not supported by the specified algorithm",
             ion::ErrorKind::Normal,
@@ -0,0 +1,545 @@
+use ion::{
+    conversions::{ConversionBehavior, FromValue, ToValue},
+    typedarray::ArrayBuffer,
+    Context, Error, ErrorKind, Object,
+};
+use rsa::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey},
+    traits::{PrivateKeyParts, PublicKeyParts},
+    BigUint, Oaep, Pkcs1v15Sign, Pss, RsaPrivateKey, RsaPublicKey,
+};
+
+use crate::{
+    builtins::crypto::subtle::{
+        crypto_key::{
+            CryptoKey, GeneratedKey, KeyAlgorithm, KeyFormat, KeyMaterial, KeyType, KeyUsage,
+        },
+        jwk::{self, JsonWebKey},
+        AlgorithmIdentifier, BufferSource, HeapKeyData,
+    },
+    ion_err, ion_mk_err,
+};
+
+use super::{
+    sha::{with_sha, Sha},
+    CryptoAlgorithm,
+};
+
+#[derive(FromValue)]
+pub struct RsaHashedKeyGenParams<'cx> {
+    #[ion(name = "modulusLength", convert = ConversionBehavior::EnforceRange, strict)]
+    modulus_length: u32,
+    #[ion(name = "publicExponent")]
+    public_exponent: BufferSource<'cx>,
+    hash: AlgorithmIdentifier<'cx>,
+}
+
+#[derive(FromValue)]
+pub struct RsaHashedImportParams<'cx> {
+    hash: AlgorithmIdentifier<'cx>,
+}
+
+#[derive(FromValue)]
+pub struct RsaPssParams {
+    #[ion(name = "saltLength", convert = ConversionBehavior::EnforceRange, strict)]
+    salt_length: u32,
+}
+
+#[derive(FromValue)]
+pub struct RsaOaepParams<'cx> {
+    label: Option<BufferSource<'cx>>,
+}
+
+#[derive(Clone)]
+pub enum RsaKey {
+    Private(RsaPrivateKey),
+    Public(RsaPublicKey),
+}
+
+impl RsaKey {
+    fn to_public_key(&self) -> RsaPublicKey {
+        match self {
+            Self::Private(key) => key.to_public_key(),
+            Self::Public(key) => key.clone(),
+        }
+    }
+
+    pub(crate) fn public_only(&self) -> Self {
+        Self::Public(self.to_public_key())
+    }
+}
+
+pub enum Rsa {
+    Pkcs1v15,
+    Pss,
+    Oaep,
+}
+
+impl Rsa {
+    fn public_usages(&self) -> &'static [KeyUsage] {
+        match self {
+            Self::Pkcs1v15 | Self::Pss => &[KeyUsage::Verify],
+            Self::Oaep => &[KeyUsage::Encrypt, KeyUsage::WrapKey],
+        }
+    }
+
+    fn private_usages(&self) -> &'static [KeyUsage] {
+        match self {
+            Self::Pkcs1v15 | Self::Pss => &[KeyUsage::Sign],
+            Self::Oaep => &[KeyUsage::Decrypt, KeyUsage::UnwrapKey],
+        }
+    }
+
+    fn jwk_use(&self) -> &'static str {
+        match self {
+            Self::Pkcs1v15 | Self::Pss => "sig",
+            Self::Oaep => "enc",
+        }
+    }
+
+    fn jwk_alg(&self, hash: Sha) -> String {
+        match self {
+            Self::Pkcs1v15 => format!("RS{}", hash.jwk_suffix()),
+            Self::Pss => format!("PS{}", hash.jwk_suffix()),
+            Self::Oaep if hash == Sha::Sha1 => "RSA-OAEP".to_string(),
+            Self::Oaep => format!("RSA-OAEP-{}", hash.jwk_suffix()),
+        }
+    }
+
+    /// The hash function and RSA key of a `CryptoKey`.
+    fn key_parts<'k>(&self, key: &'k CryptoKey) -> ion::Result<(Sha, &'k RsaKey)> {
+        match (&key.algorithm, &key.key) {
+            (KeyAlgorithm::RsaHashed { hash, .. }, KeyMaterial::Rsa(rsa_key)) => {
+                Ok((*hash, rsa_key))
+            }
+            _ => ion_err!("The provided key is not an RSA key", Type),
+        }
+    }
+
+    fn new_key(
+        &self,
+        cx: &Context,
+        hash: Sha,
+        key: RsaKey,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<CryptoKey> {
+        let key_type = match key {
+            RsaKey::Private(_) => KeyType::Private,
+            RsaKey::Public(_) => KeyType::Public,
+        };
+        let public_key = key.to_public_key();
+        let algorithm = KeyAlgorithm::RsaHashed {
+            name: self.name(),
+            modulus_length: public_key.n().bits() as u32,
+            public_exponent: public_key.e().to_bytes_be(),
+            hash,
+        };
+        CryptoKey::new(
+            cx,
+            extractable,
+            algorithm,
+            key_type,
+            usages,
+            KeyMaterial::Rsa(key),
+        )
+    }
+
+    fn import_jwk(&self, jwk: &JsonWebKey) -> ion::Result<RsaKey> {
+        if jwk.kty != "RSA" {
+            ion_err!("kty member of JWK key must be 'RSA'", Normal);
+        }
+
+        let decode = |name: &str, value: Option<&String>| {
+            jwk::decode_member(name, value).map(|bytes| BigUint::from_bytes_be(&bytes))
+        };
+        let n = decode("n", jwk.n.as_ref())?;
+        let e = decode("e", jwk.e.as_ref())?;
+
+        if jwk.d.is_none() {
+            let key = RsaPublicKey::new(n, e)
+                .map_err(|e| ion_mk_err!(format!("Invalid RSA public key: {e}"), Normal))?;
+            return Ok(RsaKey::Public(key));
+        }
+
+        if jwk.oth.is_some() {
+            ion_err!(
+                "RSA keys with more than two primes are not supported",
+                Normal
+            );
+        }
+        let d = decode("d", jwk.d.as_ref())?;
+        let p = decode("p", jwk.p.as_ref())?;
+        let q = decode("q", jwk.q.as_ref())?;
+        let key = RsaPrivateKey::from_components(n, e, d, vec![p, q])
+            .map_err(|e| ion_mk_err!(format!("Invalid RSA private key: {e}"), Normal))?;
+        key.validate()
+            .map_err(|e| ion_mk_err!(format!("Invalid RSA private key: {e}"), Normal))?;
+        Ok(RsaKey::Private(key))
+    }
+}
+
+impl CryptoAlgorithm for Rsa {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Pkcs1v15 => "RSASSA-PKCS1-v1_5",
+            Self::Pss => "RSA-PSS",
+            Self::Oaep => "RSA-OAEP",
+        }
+    }
+
+    fn encrypt<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        key: &CryptoKey,
+        data: Vec<u8>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        if !matches!(self, Self::Oaep) {
+            ion_err!("Operation not supported by the specified algorithm", Normal);
+        }
+
+        let (hash, rsa_key) = self.key_parts(key)?;
+        let RsaKey::Public(public_key) = rsa_key else {
+            ion_err!("Encryption requires a public key", Normal);
+        };
+
+        let label = oaep_label(cx, params)?;
+        let mut rng = rand::thread_rng();
+        let result = with_sha!(hash, D => match label {
+            Some(label) => public_key.encrypt(&mut rng, Oaep::new_with_label::<D, _>(label), &data),
+            None => public_key.encrypt(&mut rng, Oaep::new::<D>(), &data),
+        })
+        .map_err(|e| ion_mk_err!(format!("Encryption failed: {e}"), Normal))?;
+
+        ArrayBuffer::copy_from_bytes(cx, &result)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn decrypt<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        key: &CryptoKey,
+        data: Vec<u8>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        if !matches!(self, Self::Oaep) {
+            ion_err!("Operation not supported by the specified algorithm", Normal);
+        }
+
+        let (hash, rsa_key) = self.key_parts(key)?;
+        let RsaKey::Private(private_key) = rsa_key else {
+            ion_err!("Decryption requires a private key", Normal);
+        };
+
+        let label = oaep_label(cx, params)?;
+        let result = with_sha!(hash, D => match label {
+            Some(label) => private_key.decrypt(Oaep::new_with_label::<D, _>(label), &data),
+            None => private_key.decrypt(Oaep::new::<D>(), &data),
+        })
+        .map_err(|_| ion_mk_err!("Decryption failed", Normal))?;
+
+        ArrayBuffer::copy_from_bytes(cx, &result)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn sign<'cx>(
+        &self,
+        cx: &'cx Context,
+        params: &Object,
+        key: &CryptoKey,
+        data: Vec<u8>,
+    ) -> ion::Result<ArrayBuffer<'cx>> {
+        let (hash, rsa_key) = self.key_parts(key)?;
+        let RsaKey::Private(private_key) = rsa_key else {
+            ion_err!("Signing requires a private key", Normal);
+        };
+
+        let hashed = hash.hash(&data);
+        let signature = match self {
+            Self::Pkcs1v15 => {
+                with_sha!(hash, D => private_key.sign(Pkcs1v15Sign::new::<D>(), &hashed))
+            }
+            Self::Pss => {
+                let params = RsaPssParams::from_value(cx, &params.as_value(cx), false, ())?;
+                let salt_length = params.salt_length as usize;
+                with_sha!(hash, D => private_key.sign_with_rng(
+                    &mut rand::thread_rng(),
+                    Pss::new_with_salt::<D>(salt_length),
+                    &hashed,
+                ))
+            }
+            Self::Oaep => ion_err!("Operation not supported by the specified algorithm", Normal),
+        }
+        .map_err(|e| ion_mk_err!(format!("Signing failed: {e}"), Normal))?;
+
+        ArrayBuffer::copy_from_bytes(cx, &signature)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))
+    }
+
+    fn verify(
+        &self,
+        cx: &Context,
+        params: &Object,
+        key: &CryptoKey,
+        signature: Vec<u8>,
+        data: Vec<u8>,
+    ) -> ion::Result<bool> {
+        let (hash, rsa_key) = self.key_parts(key)?;
+        let RsaKey::Public(public_key) = rsa_key else {
+            ion_err!("Verification requires a public key", Normal);
+        };
+
+        let hashed = hash.hash(&data);
+        let result = match self {
+            Self::Pkcs1v15 => with_sha!(hash, D => public_key.verify(
+                Pkcs1v15Sign::new::<D>(),
+                &hashed,
+                &signature,
+            )),
+            Self::Pss => {
+                let params = RsaPssParams::from_value(cx, &params.as_value(cx), false, ())?;
+                let salt_length = params.salt_length as usize;
+                with_sha!(hash, D => public_key.verify(
+                    Pss::new_with_salt::<D>(salt_length),
+                    &hashed,
+                    &signature,
+                ))
+            }
+            Self::Oaep => ion_err!("Operation not supported by the specified algorithm", Normal),
+        };
+        Ok(result.is_ok())
+    }
+
+    fn generate_key(
+        &self,
+        cx: &Context,
+        params: &Object,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<GeneratedKey> {
+        let public_usages = self.public_usages();
+        let private_usages = self.private_usages();
+        if usages
+            .iter()
+            .any(|u| !public_usages.contains(u) && !private_usages.contains(u))
+        {
+            ion_err!("Invalid key usage specified", Syntax);
+        }
+
+        let params = RsaHashedKeyGenParams::from_value(cx, &params.as_value(cx), false, ())?;
+        let hash = Sha::from_identifier(cx, &params.hash)?;
+        if params.modulus_length < 1024 || params.modulus_length > 16384 {
+            ion_err!("modulusLength must be between 1024 and 16384", Normal);
+        }
+        let public_exponent = BigUint::from_bytes_be(&params.public_exponent.to_owned());
+
+        let private_key = RsaPrivateKey::new_with_exp(
+            &mut rand::thread_rng(),
+            params.modulus_length as usize,
+            &public_exponent,
+        )
+        .map_err(|e| ion_mk_err!(format!("Failed to generate RSA key: {e}"), Normal))?;
+        let public_key = private_key.to_public_key();
+
+        let filter_usages = |allowed: &[KeyUsage]| {
+            usages
+                .iter()
+                .filter(|u| allowed.contains(u))
+                .copied()
+                .collect()
+        };
+
+        Ok(GeneratedKey::Pair {
+            // Public keys are always extractable
+            public_key: self.new_key(
+                cx,
+                hash,
+                RsaKey::Public(public_key),
+                true,
+                filter_usages(public_usages),
+            )?,
+            private_key: self.new_key(
+                cx,
+                hash,
+                RsaKey::Private(private_key),
+                extractable,
+                filter_usages(private_usages),
+            )?,
+        })
+    }
+
+    fn import_key(
+        &self,
+        cx: &Context,
+        params: &Object,
+        format: KeyFormat,
+        key_data: HeapKeyData,
+        extractable: bool,
+        usages: Vec<KeyUsage>,
+    ) -> ion::Result<CryptoKey> {
+        let params = RsaHashedImportParams::from_value(cx, &params.as_value(cx), false, ())?;
+        let hash = Sha::from_identifier(cx, &params.hash)?;
+
+        let key = match (format, key_data) {
+            (KeyFormat::Spki, HeapKeyData::Buffer(buffer)) => RsaKey::Public(
+                RsaPublicKey::from_public_key_der(&buffer)
+                    .map_err(|e| ion_mk_err!(format!("Invalid SPKI data: {e}"), Normal))?,
+            ),
+            (KeyFormat::Pkcs8, HeapKeyData::Buffer(buffer)) => RsaKey::Private(
+                RsaPrivateKey::from_pkcs8_der(&buffer)
+                    .map_err(|e| ion_mk_err!(format!("Invalid PKCS#8 data: {e}"), Normal))?,
+            ),
+            (KeyFormat::Jwk, HeapKeyData::Jwk(jwk)) => {
+                if let Some(ref alg) = jwk.alg {
+                    let expected = self.jwk_alg(hash);
+                    if *alg != expected {
+                        ion_err!(format!("alg field of JWK must be {expected}"), Normal);
+                    }
+                }
+                jwk.validate_usages(self.jwk_use(), &usages, extractable)?;
+                self.import_jwk(&jwk)?
+            }
+            _ => ion_err!("Unsupported key format", Normal),
+        };
+
+        let allowed_usages = match key {
+            RsaKey::Private(_) => self.private_usages(),
+            RsaKey::Public(_) => self.public_usages(),
+        };
+        if usages.iter().any(|u| !allowed_usages.contains(u)) {
+            ion_err!("Invalid key usage specified", Syntax);
+        }
+
+        self.new_key(cx, hash, key, extractable, usages)
+    }
+
+    fn export_key<'cx>(
+        &self,
+        cx: &'cx Context,
+        format: KeyFormat,
+        key: &CryptoKey,
+    ) -> ion::Result<ion::Value<'cx>> {
+        let (hash, rsa_key) = self.key_parts(key)?;
+
+        let der = match (format, rsa_key) {
+            (KeyFormat::Spki, RsaKey::Public(public_key)) => public_key
+                .to_public_key_der()
+                .map_err(|e| ion_mk_err!(format!("Failed to encode key: {e}"), Normal))?
+                .as_bytes()
+                .to_vec(),
+            (KeyFormat::Pkcs8, RsaKey::Private(private_key)) => private_key
+                .to_pkcs8_der()
+                .map_err(|e| ion_mk_err!(format!("Failed to encode key: {e}"), Normal))?
+                .as_bytes()
+                .to_vec(),
+            (KeyFormat::Spki, _) => ion_err!("Only public keys can be exported as SPKI", Normal),
+            (KeyFormat::Pkcs8, _) => {
+                ion_err!("Only private keys can be exported as PKCS#8", Normal)
+            }
+
+            (KeyFormat::Jwk, key_data) => {
+                let public_key = key_data.to_public_key();
+                let mut jwk = JsonWebKey {
+                    kty: "RSA".to_string(),
+                    alg: Some(self.jwk_alg(hash)),
+                    key_ops: Some(key.usages.iter().map(|u| u.as_ref().to_string()).collect()),
+                    ext: Some(key.extractable),
+                    n: Some(jwk::encode_member(&public_key.n().to_bytes_be())),
+                    e: Some(jwk::encode_member(&public_key.e().to_bytes_be())),
+                    ..Default::default()
+                };
+
+                if let RsaKey::Private(private_key) = key_data {
+                    let [p, q] = private_key.primes() else {
+                        ion_err!(
+                            "RSA keys with more than two primes are not supported",
+                            Normal
+                        );
+                    };
+                    let encode =
+                        |n: Option<&BigUint>| n.map(|n| jwk::encode_member(&n.to_bytes_be()));
+                    jwk.d = encode(Some(private_key.d()));
+                    jwk.p = encode(Some(p));
+                    jwk.q = encode(Some(q));
+                    jwk.dp = encode(private_key.dp());
+                    jwk.dq = encode(private_key.dq());
+                    jwk.qi = private_key
+                        .qinv()
+                        .map(|qi| jwk::encode_member(&qi.to_bytes_be().1));
+                }
+
+                return Ok(jwk.as_value(cx));
+            }
+
+            (KeyFormat::Raw, _) => ion_err!("Unsupported key format", Normal),
+        };
+
+        let ab = ArrayBuffer::copy_from_bytes(cx, &der)
+            .ok_or_else(|| Error::new("Failed to allocate array", ErrorKind::Normal))?;
+        Ok(ab.as_value(cx))
+    }
+}
+
+fn oaep_label(cx: &Context, params: &Object) -> ion::Result<Option<String>> {
+    let params = RsaOaepParams::from_value(cx, &params.as_value(cx), false, ())?;
+    params
+        .label
+        .map(|label| String::from_utf8(label.to_owned()))
+        .transpose()
+        .map_err(|_| ion_mk_err!("Only UTF-8 labels are supported for RSA-OAEP", Normal))
+}
+
+/// Recovers the salt length of an RSA-PSS signature, following the
+/// EMSA-PSS verification steps from RFC 8017 up to the point where the salt
+/// is found. Used by `node:crypto`, which lets callers verify signatures
+/// without knowing the salt length. The signature still has to be verified
+/// with the length that's returned.
+pub(crate) fn pss_salt_length(key: &CryptoKey, signature: &[u8]) -> ion::Result<Option<usize>> {
+    let (hash, rsa_key) = Rsa::Pss.key_parts(key)?;
+    let public_key = rsa_key.to_public_key();
+    let n = public_key.n();
+    if signature.len() != public_key.size() {
+        return Ok(None);
+    }
+    let s = BigUint::from_bytes_be(signature);
+    if &s >= n {
+        return Ok(None);
+    }
+
+    let em_bits = n.bits() - 1;
+    let em_len = em_bits.div_ceil(8);
+    let m = s.modpow(public_key.e(), n).to_bytes_be();
+    let h_len = hash.hash(&[]).len();
+    if m.len() > em_len || em_len < h_len + 2 {
+        return Ok(None);
+    }
+    let mut em = vec![0; em_len - m.len()];
+    em.extend_from_slice(&m);
+    if em[em_len - 1] != 0xbc {
+        return Ok(None);
+    }
+
+    let (masked_db, h) = em[..em_len - 1].split_at(em_len - h_len - 1);
+    let mut db = mgf1(hash, h, masked_db.len());
+    for (byte, masked) in db.iter_mut().zip(masked_db) {
+        *byte ^= masked;
+    }
+    db[0] &= 0xff >> (8 * em_len - em_bits);
+
+    Ok(match db.iter().position(|&byte| byte != 0) {
+        Some(start) if db[start] == 0x01 => Some(db.len() - start - 1),
+        _ => None,
+    })
+}
+
+fn mgf1(hash: Sha, seed: &[u8], length: usize) -> Vec<u8> {
+    let mut mask = Vec::with_capacity(length);
+    let mut counter = 0u32;
+    while mask.len() < length {
+        mask.extend(hash.hash(&[seed, &counter.to_be_bytes()].concat()));
+        counter += 1;
+    }
+    mask.truncate(length);
+    mask
+}
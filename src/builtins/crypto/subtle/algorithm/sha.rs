@@ -1,8 +1,37 @@
 use ion::{typedarray::ArrayBuffer, Context, Error, ErrorKind};
 use sha2::Digest;
 
-use super::CryptoAlgorithm;
+use crate::{builtins::crypto::subtle::AlgorithmIdentifier, ion_err};
 
+use super::{CryptoAlgorithm, Hasher};
+
+/// Runs `$body` with `$digest` bound to the hash function for a `Sha`.
+macro_rules! with_sha {
+    ($sha:expr, $digest:ident => $body:expr) => {
+        match $sha {
+            $crate::builtins::crypto::subtle::algorithm::sha::Sha::Sha1 => {
+                type $digest = sha1::Sha1;
+                $body
+            }
+            $crate::builtins::crypto::subtle::algorithm::sha::Sha::Sha256 => {
+                type $digest = sha2::Sha256;
+                $body
+            }
+            $crate::builtins::crypto::subtle::algorithm::sha::Sha::Sha384 => {
+                type $digest = sha2::Sha384;
+                $body
+            }
+            $crate::builtins::crypto::subtle::algorithm::sha::Sha::Sha512 => {
+                type $digest = sha2::Sha512;
+                $body
+            }
+        }
+    };
+}
+
+pub(crate) use with_sha;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Sha {
     Sha1,
     Sha256,
@@ -10,6 +39,33 @@ pub enum Sha {
     Sha512,
 }
 
+impl Sha {
+    /// Parses the `hash` member of an algorithm's parameters.
+    pub fn from_identifier(cx: &Context, id: &AlgorithmIdentifier) -> ion::Result<Self> {
+        match id.get_algorithm_name(cx)?.to_ascii_lowercase().as_str() {
+            "sha-1" => Ok(Self::Sha1),
+            "sha-256" => Ok(Self::Sha256),
+            "sha-384" => Ok(Self::Sha384),
+            "sha-512" => Ok(Self::Sha512),
+            _ => ion_err!("Unsupported hash algorithm", Type),
+        }
+    }
+
+    /// The suffix of JWK `alg` names using this hash, e.g. `256` in `RS256`.
+    pub fn jwk_suffix(&self) -> &'static str {
+        match self {
+            Self::Sha1 => "1",
+            Self::Sha256 => "256",
+            Self::Sha384 => "384",
+            Self::Sha512 => "512",
+        }
+    }
+
+    pub fn hash(&self, data: &[u8]) -> Vec<u8> {
+        with_sha!(self, D => D::digest(data).to_vec())
+    }
+}
+
 impl CryptoAlgorithm for Sha {
     fn name(&self) -> &'static str {
         match self {
@@ -62,12 +118,28 @@ impl CryptoAlgorithm for Sha {
         }
     }
 
+    fn hasher(&self) -> ion::Result<Box<dyn Hasher>> {
+        Ok(with_sha!(self, D => Box::new(DigestHasher(D::new())) as Box<dyn Hasher>))
+    }
+
+    // The block size of the hash function, which is the default length of
+    // HMAC keys
     fn get_key_length(&self, _cx: &Context, _params: &ion::Object) -> ion::Result<usize> {
         match self {
-            Self::Sha1 => Ok(20),
-            Self::Sha256 => Ok(64),
-            Self::Sha384 => Ok(48),
-            Self::Sha512 => Ok(512),
+            Self::Sha1 | Self::Sha256 => Ok(512),
+            Self::Sha384 | Self::Sha512 => Ok(1024),
         }
     }
 }
+
+struct DigestHasher<D>(D);
+
+impl<D: Digest> Hasher for DigestHasher<D> {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(&mut self.0, data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finalize().to_vec()
+    }
+}
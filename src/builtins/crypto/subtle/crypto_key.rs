@@ -1,13 +1,22 @@
 use ion::{
     class::Reflector,
     conversions::{FromValue, ToValue},
-    Context, Error, ErrorKind, Heap, Result, Value,
+    typedarray::Uint8Array,
+    Context, Error, ErrorKind, Heap, Object, Result, Value,
 };
 use mozjs_sys::jsapi::JSObject;
 use rand_core::CryptoRngCore;
 use strum::{AsRefStr, EnumString};
 
-use crate::{enum_value, ion_err};
+use crate::{enum_value, ion_mk_err};
+
+use super::algorithm::{
+    curve25519::OkpKey,
+    ec::{EcKey, NamedCurve},
+    rsa::RsaKey,
+    sha::Sha,
+    CryptoAlgorithm,
+};
 
 #[derive(EnumString, AsRefStr, Clone, Copy)]
 #[strum(serialize_all = "camelCase")]
@@ -19,7 +28,7 @@ pub enum KeyType {
 
 enum_value!(KeyType);
 
-#[derive(EnumString, AsRefStr, Clone, Copy)]
+#[derive(EnumString, AsRefStr, Clone, Copy, PartialEq, Eq)]
 #[strum(serialize_all = "camelCase")]
 pub enum KeyUsage {
     Encrypt,
@@ -45,33 +54,147 @@ pub enum KeyFormat {
 
 enum_value!(KeyFormat);
 
+/// The secret or public key a `CryptoKey` wraps. Each algorithm only
+/// creates, and only accepts, keys of a single kind.
+#[derive(Clone)]
+pub enum KeyMaterial {
+    Secret(Vec<u8>),
+    Rsa(RsaKey),
+    Ec(EcKey),
+    Okp(OkpKey),
+}
+
+impl KeyMaterial {
+    /// The public half of an asymmetric key, which is the key itself for
+    /// public keys.
+    pub fn public_only(&self) -> Option<Self> {
+        match self {
+            Self::Secret(_) => None,
+            Self::Rsa(key) => Some(Self::Rsa(key.public_only())),
+            Self::Ec(key) => Some(Self::Ec(key.public_only())),
+            Self::Okp(key) => Some(Self::Okp(key.public_only())),
+        }
+    }
+}
+
+/// The parameters of a key's algorithm, reflected to JS as the matching
+/// `KeyAlgorithm` dictionary from the spec.
+#[derive(Clone)]
+pub enum KeyAlgorithm {
+    /// Algorithms with no parameters beyond their name
+    Basic {
+        name: &'static str,
+    },
+    Aes {
+        name: &'static str,
+        length: u16,
+    },
+    Hmac {
+        hash: Sha,
+        length: u32,
+    },
+    RsaHashed {
+        name: &'static str,
+        modulus_length: u32,
+        public_exponent: Vec<u8>,
+        hash: Sha,
+    },
+    Ec {
+        name: &'static str,
+        named_curve: NamedCurve,
+    },
+}
+
+impl KeyAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Basic { name }
+            | Self::Aes { name, .. }
+            | Self::RsaHashed { name, .. }
+            | Self::Ec { name, .. } => name,
+            Self::Hmac { .. } => "HMAC",
+        }
+    }
+
+    fn to_object<'cx>(&self, cx: &'cx Context) -> Result<Object<'cx>> {
+        let hash_object = |hash: &Sha| {
+            let object = Object::new(cx);
+            object.set_as(cx, "name", &hash.name());
+            object
+        };
+
+        let object = Object::new(cx);
+        object.set_as(cx, "name", &self.name());
+        match self {
+            Self::Basic { .. } => {}
+            Self::Aes { length, .. } => {
+                object.set_as(cx, "length", length);
+            }
+            Self::Hmac { hash, length } => {
+                object.set_as(cx, "hash", &hash_object(hash));
+                object.set_as(cx, "length", length);
+            }
+            Self::RsaHashed {
+                modulus_length,
+                public_exponent,
+                hash,
+                ..
+            } => {
+                let public_exponent = Uint8Array::copy_from_bytes(cx, public_exponent)
+                    .ok_or_else(|| ion_mk_err!("Failed to allocate array", Normal))?;
+                object.set_as(cx, "modulusLength", modulus_length);
+                object.set_as(cx, "publicExponent", &public_exponent.get());
+                object.set_as(cx, "hash", &hash_object(hash));
+            }
+            Self::Ec { named_curve, .. } => {
+                object.set_as(cx, "namedCurve", &named_curve.name());
+            }
+        }
+        Ok(object)
+    }
+}
+
 #[js_class]
 pub struct CryptoKey {
     pub reflector: Reflector,
     pub extractable: bool,
-    pub algorithm: Heap<*mut JSObject>, // KeyAlgorithm
+
+    #[trace(no_trace)]
+    pub algorithm: KeyAlgorithm,
+
+    // The dictionary returned by the algorithm getter, created once so
+    // that the same object is returned on every access
+    algorithm_object: Heap<*mut JSObject>,
 
     #[trace(no_trace)]
     pub key_type: KeyType,
 
     #[trace(no_trace)]
     pub usages: Vec<KeyUsage>,
+
+    #[trace(no_trace)]
+    pub key: KeyMaterial,
 }
 
 impl CryptoKey {
     pub fn new(
+        cx: &Context,
         extractable: bool,
-        algorithm: Heap<*mut JSObject>,
+        algorithm: KeyAlgorithm,
         key_type: KeyType,
         usages: Vec<KeyUsage>,
-    ) -> Self {
-        Self {
+        key: KeyMaterial,
+    ) -> Result<Self> {
+        let algorithm_object = Heap::new((*algorithm.to_object(cx)?).get());
+        Ok(Self {
             reflector: Default::default(),
             extractable,
             algorithm,
+            algorithm_object,
             key_type,
             usages,
-        }
+            key,
+        })
     }
 
     pub fn set_extractable(&mut self, extractable: bool) {
@@ -106,7 +229,7 @@ impl CryptoKey {
 
     #[ion(get)]
     pub fn get_algorithm(&self) -> *mut JSObject {
-        self.algorithm.get()
+        self.algorithm_object.get()
     }
 
     #[ion(get)]
@@ -117,24 +240,14 @@ impl CryptoKey {
     }
 }
 
-#[js_class]
-pub struct KeyAlgorithm {
-    pub reflector: Reflector,
-
-    pub name: &'static str,
-}
-
-#[js_class]
-impl KeyAlgorithm {
-    #[ion(constructor)]
-    pub fn constructor() -> Result<KeyAlgorithm> {
-        ion_err!("This type cannot be constructed", Type);
-    }
-
-    #[ion(get)]
-    pub fn get_name(&self) -> &'static str {
-        self.name
-    }
+/// The result of generating a key: a secret key, or a public/private key
+/// pair for asymmetric algorithms.
+pub enum GeneratedKey {
+    Key(CryptoKey),
+    Pair {
+        public_key: CryptoKey,
+        private_key: CryptoKey,
+    },
 }
 
 pub(crate) fn generate_random_key(length: usize, rng: &mut dyn CryptoRngCore) -> Vec<u8> {
@@ -0,0 +1,151 @@
+//! `crypto.DigestStream`, a Cloudflare extension: a `WritableStream` that
+//! hashes everything written to it and resolves its `digest` promise once
+//! it's closed. The stream itself is defined in JS, with the hashing done
+//! by a native `DigestContext`.
+
+use anyhow::Context as _;
+use ion::{
+    class::Reflector, function_spec, typedarray::ArrayBuffer, ClassDefinition, Context, Function,
+    Object, Result, Value,
+};
+use mozjs_sys::jsapi::{JSFunctionSpec, JSObject};
+
+use crate::{ion_err, ion_mk_err, sm_utils::error_report_option_to_anyhow_error};
+
+use super::{algorithm::Hasher, AlgorithmIdentifier, BufferSource};
+
+const DIGEST_STREAM_SCRIPT: &str = r#"
+(function (createHasher) {
+    return class DigestStream extends WritableStream {
+        #digest;
+
+        constructor(algorithm) {
+            const hasher = createHasher(algorithm);
+            let resolveDigest, rejectDigest;
+            const digest = new Promise((resolve, reject) => {
+                resolveDigest = resolve;
+                rejectDigest = reject;
+            });
+
+            super({
+                write(chunk) {
+                    try {
+                        hasher.update(chunk);
+                    } catch (e) {
+                        rejectDigest(e);
+                        throw e;
+                    }
+                },
+                close() {
+                    resolveDigest(hasher.digest());
+                },
+                abort(reason) {
+                    rejectDigest(reason);
+                },
+            });
+
+            this.#digest = digest;
+        }
+
+        get digest() {
+            return this.#digest;
+        }
+    };
+})
+"#;
+
+#[js_class]
+pub struct DigestContext {
+    reflector: Reflector,
+
+    // Taken when the digest is computed
+    #[trace(no_trace)]
+    hasher: Option<Box<dyn Hasher>>,
+}
+
+#[js_class]
+impl DigestContext {
+    #[ion(constructor)]
+    pub fn constructor() -> Result<DigestContext> {
+        ion_err!("Cannot construct this type", Type);
+    }
+
+    pub fn update(&mut self, data: BufferSource) -> Result<()> {
+        let Some(hasher) = self.hasher.as_mut() else {
+            ion_err!("The digest was already computed", Normal);
+        };
+        unsafe {
+            match &data {
+                BufferSource::ArrayBuffer(buf, _) => hasher.update(buf.as_slice()),
+                BufferSource::ArrayBufferView(buf, _) => hasher.update(buf.as_slice()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn digest(&mut self, cx: &Context) -> Result<*mut JSObject> {
+        let Some(hasher) = self.hasher.take() else {
+            ion_err!("The digest was already computed", Normal);
+        };
+        let ab = ArrayBuffer::copy_from_bytes(cx, &hasher.finalize())
+            .ok_or_else(|| ion_mk_err!("Failed to allocate array", Normal))?;
+        Ok(ab.get())
+    }
+}
+
+impl DigestContext {
+    pub(super) fn from_hasher(cx: &Context, hasher: Box<dyn Hasher>) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Reflector::default(),
+                hasher: Some(hasher),
+            }),
+        )
+    }
+}
+
+#[js_fn]
+fn create_hasher<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+) -> Result<*mut JSObject> {
+    let hasher = algorithm.get_algorithm(cx)?.hasher()?;
+    Ok(DigestContext::from_hasher(cx, hasher))
+}
+
+static CREATE_HASHER: JSFunctionSpec = function_spec!(create_hasher, "createHasher", 1);
+
+pub fn define(cx: &Context, crypto: &Object) -> bool {
+    // DigestContext is only created through createHasher, so its
+    // constructor goes on a throwaway object instead of the global.
+    if !DigestContext::init_class(cx, &Object::new(cx)).0 {
+        return false;
+    }
+
+    let result = crate::sm_utils::evaluate_script(cx, DIGEST_STREAM_SCRIPT, "digest_stream.js")
+        .and_then(|setup| {
+            let setup = Function::from_object(cx, &setup.to_object(cx))
+                .context("DigestStream script did not evaluate to a function")?;
+            let create_hasher = Function::from_spec(cx, &CREATE_HASHER);
+            setup
+                .call(
+                    cx,
+                    crypto,
+                    &[Value::object(cx, &create_hasher.to_object(cx))],
+                )
+                .map_err(|e| error_report_option_to_anyhow_error(cx, e))
+        });
+
+    match result {
+        Ok(class) if class.handle().is_object() => crypto.set(cx, "DigestStream", &class),
+        Ok(_) => {
+            tracing::error!("Internal error: DigestStream script did not return a class");
+            false
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to set up DigestStream");
+            false
+        }
+    }
+}
@@ -1,5 +1,10 @@
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
 use ion::conversions::FromValue;
 
+use crate::{ion_err, ion_mk_err};
+
+use super::crypto_key::KeyUsage;
+
 #[derive(FromValue, ToValue)]
 pub struct RsaOtherPrimesInfo {
     pub r: String,
@@ -45,3 +50,66 @@ impl<'cx> FromValue<'cx> for Box<JsonWebKey> {
         Ok(Box::new(jwk))
     }
 }
+
+impl JsonWebKey {
+    /// Checks the `use`, `key_ops` and `ext` members of an imported key
+    /// against the requested usages and extractability.
+    pub fn validate_usages(
+        &self,
+        expected_use: &str,
+        usages: &[KeyUsage],
+        extractable: bool,
+    ) -> ion::Result<()> {
+        if let Some(r#use) = &self.r#use {
+            if r#use != expected_use && !usages.is_empty() {
+                ion_err!(
+                    format!("use member of JWK key must be '{expected_use}'"),
+                    Normal
+                );
+            }
+        }
+
+        if let Some(key_ops) = &self.key_ops {
+            if usages
+                .iter()
+                .any(|u| !key_ops.iter().any(|o| o.as_str() == u.as_ref()))
+            {
+                ion_err!(
+                    "key_ops member of JWK must include all specified usages",
+                    Normal
+                );
+            }
+        }
+
+        if self.ext == Some(false) && extractable {
+            ion_err!(
+                "ext member of JWK must be true when extractable is specified as true",
+                Normal
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Decodes a base64url-encoded member of a JWK.
+pub fn decode_member(name: &str, value: Option<&String>) -> ion::Result<Vec<u8>> {
+    let Some(value) = value else {
+        ion_err!(
+            format!("Mandatory member {name} of JWK not specified"),
+            Normal
+        );
+    };
+    BASE64_URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| {
+            ion_mk_err!(
+                format!("Invalid base64 data in {name} field of JWK"),
+                Normal
+            )
+        })
+}
+
+pub fn encode_member(data: &[u8]) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(data)
+}
@@ -1,12 +1,15 @@
 pub(super) mod algorithm;
 pub(super) mod crypto_key;
+pub(super) mod digest_stream;
 pub(super) mod jwk;
+pub(super) mod node;
 
 use std::{borrow::Cow, marker::PhantomData};
 
 use ion::{
-    class::NativeObject, conversions::ToValue, function_spec, ClassDefinition, Context, Object,
-    Promise, TracedHeap,
+    class::NativeObject,
+    conversions::{FromValue, ToValue},
+    function_spec, ClassDefinition, Context, Object, Promise, TracedHeap,
 };
 use mozjs_sys::jsapi::JSFunctionSpec;
 use runtime::promise::future_to_promise;
@@ -15,13 +18,14 @@ use strum::ParseError;
 use algorithm::{md5::Md5, sha::Sha, CryptoAlgorithm};
 
 use crate::{
-    builtins::crypto::subtle::crypto_key::{CryptoKey, KeyAlgorithm},
+    builtins::crypto::subtle::crypto_key::CryptoKey,
     ion_err,
+    sm_utils::{json_parse, json_stringify},
 };
 
 use self::{
-    algorithm::hmac::Hmac,
-    crypto_key::{KeyFormat, KeyType, KeyUsage},
+    algorithm::{aes::Aes, curve25519::Curve25519, ec::Ec, hmac::Hmac, kdf::Kdf, rsa::Rsa},
+    crypto_key::{GeneratedKey, KeyFormat, KeyType, KeyUsage},
     jwk::JsonWebKey,
 };
 
@@ -104,6 +108,19 @@ impl<'cx> AlgorithmIdentifier<'cx> {
             "sha-512" => Ok(Box::new(Sha::Sha512)),
             "md5" => Ok(Box::new(Md5)),
             "hmac" => Ok(Box::new(Hmac)),
+            "aes-gcm" => Ok(Box::new(Aes::Gcm)),
+            "aes-cbc" => Ok(Box::new(Aes::Cbc)),
+            "aes-ctr" => Ok(Box::new(Aes::Ctr)),
+            "aes-kw" => Ok(Box::new(Aes::Kw)),
+            "rsassa-pkcs1-v1_5" => Ok(Box::new(Rsa::Pkcs1v15)),
+            "rsa-pss" => Ok(Box::new(Rsa::Pss)),
+            "rsa-oaep" => Ok(Box::new(Rsa::Oaep)),
+            "ecdsa" => Ok(Box::new(Ec::Ecdsa)),
+            "ecdh" => Ok(Box::new(Ec::Ecdh)),
+            "ed25519" => Ok(Box::new(Curve25519::Ed25519)),
+            "x25519" => Ok(Box::new(Curve25519::X25519)),
+            "pbkdf2" => Ok(Box::new(Kdf::Pbkdf2)),
+            "hkdf" => Ok(Box::new(Kdf::Hkdf)),
 
             _ => Err(ion::Error::new(
                 "Unknown algorithm identifier",
@@ -129,8 +146,27 @@ impl<'cx> ToValue<'cx> for AlgorithmIdentifier<'cx> {
     }
 }
 
+/// Checks that a key can be used for an operation with the given algorithm.
+fn check_key(alg: &dyn CryptoAlgorithm, key: &CryptoKey, usage: KeyUsage) -> ion::Result<()> {
+    if alg.name().to_ascii_lowercase() != key.algorithm.name().to_ascii_lowercase() {
+        ion_err!(
+            "Provided key does not correspond to specified algorithm",
+            Normal
+        );
+    }
+
+    if !key.usages.contains(&usage) {
+        ion_err!(
+            format!("Key does not support the '{}' operation", usage.as_ref()),
+            Normal
+        );
+    }
+
+    Ok(())
+}
+
 #[js_fn]
-fn sign<'cx>(
+fn encrypt<'cx>(
     cx: &'cx Context,
     algorithm: AlgorithmIdentifier<'cx>,
     key: &CryptoKey,
@@ -145,19 +181,51 @@ fn sign<'cx>(
         future_to_promise(cx, move |cx| async move {
             let key = CryptoKey::get_private(&cx, &key.root(&cx).into()).unwrap();
             let alg = alg?;
+            check_key(alg.as_ref(), key, KeyUsage::Encrypt)?;
+            Ok(alg.encrypt(&cx, &params.root(&cx).into(), key, data)?.get())
+        })
+    }
+}
 
-            let key_alg = KeyAlgorithm::get_private(&cx, &key.algorithm.root(&cx).into()).unwrap();
-            if alg.name().to_ascii_lowercase() != key_alg.name.to_ascii_lowercase() {
-                ion_err!(
-                    "Provided key does not correspond to specified algorithm",
-                    Normal
-                );
-            }
+#[js_fn]
+fn decrypt<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+    key: &CryptoKey,
+    data: BufferSource,
+) -> Option<Promise> {
+    unsafe {
+        let key = TracedHeap::new(key.reflector().get());
+        let alg = algorithm.get_algorithm(cx);
+        let params = TracedHeap::from_local(&algorithm.to_params(cx));
+        let data = data.to_owned();
 
-            if !key.usages.iter().any(|u| matches!(u, KeyUsage::Sign)) {
-                ion_err!("Key does not support the 'sign' operation", Normal);
-            }
+        future_to_promise(cx, move |cx| async move {
+            let key = CryptoKey::get_private(&cx, &key.root(&cx).into()).unwrap();
+            let alg = alg?;
+            check_key(alg.as_ref(), key, KeyUsage::Decrypt)?;
+            Ok(alg.decrypt(&cx, &params.root(&cx).into(), key, data)?.get())
+        })
+    }
+}
 
+#[js_fn]
+fn sign<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+    key: &CryptoKey,
+    data: BufferSource,
+) -> Option<Promise> {
+    unsafe {
+        let key = TracedHeap::new(key.reflector().get());
+        let alg = algorithm.get_algorithm(cx);
+        let params = TracedHeap::from_local(&algorithm.to_params(cx));
+        let data = data.to_owned();
+
+        future_to_promise(cx, move |cx| async move {
+            let key = CryptoKey::get_private(&cx, &key.root(&cx).into()).unwrap();
+            let alg = alg?;
+            check_key(alg.as_ref(), key, KeyUsage::Sign)?;
             Ok(alg.sign(&cx, &params.root(&cx).into(), key, data)?.get())
         })
     }
@@ -181,19 +249,7 @@ fn verify<'cx>(
         future_to_promise(cx, move |cx| async move {
             let key = CryptoKey::get_private(&cx, &key.root(&cx).into()).unwrap();
             let alg = alg?;
-
-            let key_alg = KeyAlgorithm::get_private(&cx, &key.algorithm.root(&cx).into()).unwrap();
-            if alg.name().to_ascii_lowercase() != key_alg.name.to_ascii_lowercase() {
-                ion_err!(
-                    "Provided key does not correspond to specified algorithm",
-                    Normal
-                );
-            }
-
-            if !key.usages.iter().any(|u| matches!(u, KeyUsage::Verify)) {
-                ion_err!("Key does not support the 'verify' operation", Normal);
-            }
-
+            check_key(alg.as_ref(), key, KeyUsage::Verify)?;
             alg.verify(&cx, &params.root(&cx).into(), key, signature, data)
         })
     }
@@ -217,6 +273,99 @@ fn digest<'cx>(
     }
 }
 
+/// Compares two byte strings in time that only depends on their length,
+/// so secrets like signatures can be checked without leaking where they
+/// differ.
+pub(crate) fn timing_safe_eq(a: &[u8], b: &[u8]) -> bool {
+    use ::subtle::ConstantTimeEq;
+    a.ct_eq(b).into()
+}
+
+#[js_fn]
+fn timing_safe_equal(a: BufferSource, b: BufferSource) -> ion::Result<bool> {
+    let (a, b) = (a.to_owned(), b.to_owned());
+    if a.len() != b.len() {
+        ion_err!("Input buffers must have the same byte length", Type);
+    }
+    Ok(timing_safe_eq(&a, &b))
+}
+
+#[js_fn]
+fn derive_bits<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+    base_key: &CryptoKey,
+    length: Option<u32>,
+) -> Option<Promise> {
+    unsafe {
+        let base_key = TracedHeap::new(base_key.reflector().get());
+        let alg = algorithm.get_algorithm(cx);
+        let params = TracedHeap::from_local(&algorithm.to_params(cx));
+
+        future_to_promise(cx, move |cx| async move {
+            let base_key = CryptoKey::get_private(&cx, &base_key.root(&cx).into()).unwrap();
+            let alg = alg?;
+            check_key(alg.as_ref(), base_key, KeyUsage::DeriveBits)?;
+            let bits = alg.derive_bits(
+                &cx,
+                &params.root(&cx).into(),
+                base_key,
+                length.map(|l| l as usize),
+            )?;
+            Ok(bits.get())
+        })
+    }
+}
+
+#[js_fn]
+fn derive_key<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+    base_key: &CryptoKey,
+    derived_key_type: AlgorithmIdentifier<'cx>,
+    extractable: bool,
+    key_usages: Vec<KeyUsage>,
+) -> Option<Promise> {
+    unsafe {
+        let base_key = TracedHeap::new(base_key.reflector().get());
+        let alg = algorithm.get_algorithm(cx);
+        let params = TracedHeap::from_local(&algorithm.to_params(cx));
+        let derived_alg = derived_key_type.get_algorithm(cx);
+        let derived_params = TracedHeap::from_local(&derived_key_type.to_params(cx));
+
+        future_to_promise(cx, move |cx| async move {
+            let base_key = CryptoKey::get_private(&cx, &base_key.root(&cx).into()).unwrap();
+            let alg = alg?;
+            let derived_alg = derived_alg?;
+            check_key(alg.as_ref(), base_key, KeyUsage::DeriveKey)?;
+
+            let derived_params = derived_params.root(&cx).into();
+            let length = derived_alg.get_key_length(&cx, &derived_params)?;
+            let bits = alg.derive_bits(&cx, &params.root(&cx).into(), base_key, Some(length))?;
+            let bits = bits.as_slice().to_vec();
+
+            let no_usages = key_usages.is_empty();
+            let key = derived_alg.import_key(
+                &cx,
+                &derived_params,
+                KeyFormat::Raw,
+                HeapKeyData::Buffer(bits),
+                extractable,
+                key_usages,
+            )?;
+
+            if matches!(key.get_type(), KeyType::Private | KeyType::Secret) && no_usages {
+                ion_err!(
+                    "Private and secret keys must have a non-empty usages list.",
+                    Syntax
+                );
+            }
+
+            Ok(CryptoKey::new_object(&cx, Box::new(key)))
+        })
+    }
+}
+
 #[js_fn]
 fn generate_key<'cx>(
     cx: &'cx Context,
@@ -232,16 +381,33 @@ fn generate_key<'cx>(
             let alg = alg?;
             let key = alg.generate_key(&cx, &params.root(&cx).into(), extractable, key_usages)?;
 
-            if matches!(key.key_type, KeyType::Secret | KeyType::Private) && key.usages.is_empty() {
+            let secret_or_private_key = match key {
+                GeneratedKey::Key(ref key) => key,
+                GeneratedKey::Pair {
+                    ref private_key, ..
+                } => private_key,
+            };
+            if secret_or_private_key.usages.is_empty() {
                 ion_err!(
                     "Usages must be specified for secret and private keys",
                     Syntax
                 );
             }
 
-            // TODO: handle CryptoKeyPair
-
-            Ok(CryptoKey::new_object(&cx, Box::new(key)))
+            match key {
+                GeneratedKey::Key(key) => Ok(CryptoKey::new_object(&cx, Box::new(key))),
+                GeneratedKey::Pair {
+                    public_key,
+                    private_key,
+                } => {
+                    let pair = Object::new(&cx);
+                    let public_key = CryptoKey::new_object(&cx, Box::new(public_key));
+                    let private_key = CryptoKey::new_object(&cx, Box::new(private_key));
+                    pair.set_as(&cx, "publicKey", &public_key);
+                    pair.set_as(&cx, "privateKey", &private_key);
+                    Ok((*pair).get())
+                }
+            }
         })
     }
 }
@@ -310,9 +476,8 @@ fn export_key(cx: &Context, key_format: KeyFormat, key: &CryptoKey) -> Option<Pr
         let key_heap = TracedHeap::new(key.reflector().get());
         future_to_promise(cx, move |cx| async move {
             let key = CryptoKey::get_private(&cx, &key_heap.root(&cx).into()).unwrap();
-            let alg_obj = key.algorithm.root(&cx).into();
-            let alg = KeyAlgorithm::get_private(&cx, &alg_obj).unwrap();
-            let alg = AlgorithmIdentifier::String(alg.name.to_string()).get_algorithm(&cx)?;
+            let alg =
+                AlgorithmIdentifier::String(key.algorithm.name().to_string()).get_algorithm(&cx)?;
             if !key.extractable {
                 ion_err!("Key cannot be exported", Normal);
             }
@@ -321,13 +486,121 @@ fn export_key(cx: &Context, key_format: KeyFormat, key: &CryptoKey) -> Option<Pr
     }
 }
 
+#[js_fn]
+fn wrap_key<'cx>(
+    cx: &'cx Context,
+    key_format: KeyFormat,
+    key: &CryptoKey,
+    wrapping_key: &CryptoKey,
+    wrap_algorithm: AlgorithmIdentifier<'cx>,
+) -> Option<Promise> {
+    unsafe {
+        let key = TracedHeap::new(key.reflector().get());
+        let wrapping_key = TracedHeap::new(wrapping_key.reflector().get());
+        let alg = wrap_algorithm.get_algorithm(cx);
+        let params = TracedHeap::from_local(&wrap_algorithm.to_params(cx));
+
+        future_to_promise(cx, move |cx| async move {
+            let key = CryptoKey::get_private(&cx, &key.root(&cx).into()).unwrap();
+            let wrapping_key = CryptoKey::get_private(&cx, &wrapping_key.root(&cx).into()).unwrap();
+            let alg = alg?;
+            check_key(alg.as_ref(), wrapping_key, KeyUsage::WrapKey)?;
+
+            let key_alg =
+                AlgorithmIdentifier::String(key.algorithm.name().to_string()).get_algorithm(&cx)?;
+            if !key.extractable {
+                ion_err!("Key cannot be exported", Normal);
+            }
+
+            let exported = key_alg.export_key(&cx, key_format, key)?;
+            let bytes = match key_format {
+                KeyFormat::Jwk => json_stringify(&cx, exported)?.into_bytes(),
+                _ => BufferSource::from_value(&cx, &exported, true, ())?.to_owned(),
+            };
+
+            let wrapped = alg.wrap_key(&cx, &params.root(&cx).into(), wrapping_key, bytes)?;
+            Ok(wrapped.get())
+        })
+    }
+}
+
+#[js_fn]
+#[allow(clippy::too_many_arguments)]
+fn unwrap_key<'cx>(
+    cx: &'cx Context,
+    key_format: KeyFormat,
+    wrapped_key: BufferSource,
+    unwrapping_key: &CryptoKey,
+    unwrap_algorithm: AlgorithmIdentifier<'cx>,
+    unwrapped_key_algorithm: AlgorithmIdentifier<'cx>,
+    extractable: bool,
+    key_usages: Vec<KeyUsage>,
+) -> Option<Promise> {
+    unsafe {
+        let wrapped_key = wrapped_key.to_owned();
+        let unwrapping_key = TracedHeap::new(unwrapping_key.reflector().get());
+        let alg = unwrap_algorithm.get_algorithm(cx);
+        let params = TracedHeap::from_local(&unwrap_algorithm.to_params(cx));
+        let unwrapped_alg = unwrapped_key_algorithm.get_algorithm(cx);
+        let unwrapped_params = TracedHeap::from_local(&unwrapped_key_algorithm.to_params(cx));
+
+        future_to_promise(cx, move |cx| async move {
+            let unwrapping_key =
+                CryptoKey::get_private(&cx, &unwrapping_key.root(&cx).into()).unwrap();
+            let alg = alg?;
+            let unwrapped_alg = unwrapped_alg?;
+            check_key(alg.as_ref(), unwrapping_key, KeyUsage::UnwrapKey)?;
+
+            let bytes =
+                alg.unwrap_key(&cx, &params.root(&cx).into(), unwrapping_key, wrapped_key)?;
+            let bytes = bytes.as_slice().to_vec();
+            let key_data = match key_format {
+                KeyFormat::Jwk => {
+                    let Ok(json) = String::from_utf8(bytes) else {
+                        ion_err!("The unwrapped key is not a valid JWK", Normal);
+                    };
+                    let jwk = json_parse(&cx, &json)?;
+                    HeapKeyData::Jwk(Box::<JsonWebKey>::from_value(&cx, &jwk, false, ())?)
+                }
+                _ => HeapKeyData::Buffer(bytes),
+            };
+
+            let no_usages = key_usages.is_empty();
+            let key = unwrapped_alg.import_key(
+                &cx,
+                &unwrapped_params.root(&cx).into(),
+                key_format,
+                key_data,
+                extractable,
+                key_usages,
+            )?;
+
+            if matches!(key.get_type(), KeyType::Private | KeyType::Secret) && no_usages {
+                ion_err!(
+                    "Private and secret keys must have a non-empty usages list.",
+                    Syntax
+                );
+            }
+
+            Ok(CryptoKey::new_object(&cx, Box::new(key)))
+        })
+    }
+}
+
 const METHODS: &[JSFunctionSpec] = &[
+    function_spec!(encrypt, 3),
+    function_spec!(decrypt, 3),
     function_spec!(digest, 2),
     function_spec!(sign, 3),
     function_spec!(verify, 4),
+    function_spec!(derive_bits, "deriveBits", 3),
+    function_spec!(derive_key, "deriveKey", 5),
     function_spec!(generate_key, "generateKey", 3),
     function_spec!(import_key, "importKey", 5),
     function_spec!(export_key, "exportKey", 2),
+    function_spec!(wrap_key, "wrapKey", 4),
+    function_spec!(unwrap_key, "unwrapKey", 7),
+    function_spec!(timing_safe_equal, "timingSafeEqual", 2),
     JSFunctionSpec::ZERO,
 ];
 
@@ -0,0 +1,14 @@
+export const createHash = ________winterjs_crypto_Internal______.createHash;
+export const createHmac = ________winterjs_crypto_Internal______.createHmac;
+export const importKey = ________winterjs_crypto_Internal______.importKey;
+export const exportKey = ________winterjs_crypto_Internal______.exportKey;
+export const encrypt = ________winterjs_crypto_Internal______.encrypt;
+export const decrypt = ________winterjs_crypto_Internal______.decrypt;
+export const sign = ________winterjs_crypto_Internal______.sign;
+export const verify = ________winterjs_crypto_Internal______.verify;
+export const deriveBits = ________winterjs_crypto_Internal______.deriveBits;
+export const parseKey = ________winterjs_crypto_Internal______.parseKey;
+export const publicKey = ________winterjs_crypto_Internal______.publicKey;
+export const pssSaltLength = ________winterjs_crypto_Internal______.pssSaltLength;
+
+export default Object.freeze(________winterjs_crypto_Internal______);
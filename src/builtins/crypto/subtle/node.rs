@@ -0,0 +1,320 @@
+//! Synchronous access to the `crypto.subtle` algorithms, for use by the
+//! `node:crypto` module. Node's crypto APIs are mostly synchronous, so they
+//! can't be built on top of the promise-based `crypto.subtle` methods.
+
+use ion::{
+    function_spec, typedarray::ArrayBuffer, ClassDefinition, Context, Object, Result, Value,
+};
+use mozjs_sys::jsapi::{JSFunctionSpec, JSObject};
+use pkcs8::{
+    der::{
+        asn1::{AnyRef, BitStringRef},
+        Decode, Encode,
+    },
+    spki::{AlgorithmIdentifierRef, SubjectPublicKeyInfoRef},
+    ObjectIdentifier, PrivateKeyInfo,
+};
+use runtime::module::NativeModule;
+use sec1::EcPrivateKey;
+
+use crate::{ion_err, ion_mk_err};
+
+use super::{
+    algorithm::{hmac, rsa, sha::Sha},
+    check_key,
+    crypto_key::{CryptoKey, KeyFormat, KeyType, KeyUsage},
+    digest_stream::DigestContext,
+    AlgorithmIdentifier, BufferSource, KeyData,
+};
+
+const RSA_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const EC_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+const EC_CURVES: &[(ObjectIdentifier, &str)] = &[
+    (ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7"), "P-256"),
+    (ObjectIdentifier::new_unwrap("1.3.132.0.34"), "P-384"),
+    (ObjectIdentifier::new_unwrap("1.3.132.0.35"), "P-521"),
+];
+
+// PKCS#1 keys don't carry an AlgorithmIdentifier, so this one is added when
+// they're wrapped. rsaEncryption requires NULL parameters.
+const RSA_ALGORITHM: AlgorithmIdentifierRef<'static> = AlgorithmIdentifierRef {
+    oid: RSA_OID,
+    parameters: Some(AnyRef::NULL),
+};
+
+#[js_fn]
+fn create_hash<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+) -> Result<*mut JSObject> {
+    let hasher = algorithm.get_algorithm(cx)?.hasher()?;
+    Ok(DigestContext::from_hasher(cx, hasher))
+}
+
+#[js_fn]
+fn create_hmac<'cx>(
+    cx: &'cx Context,
+    hash: AlgorithmIdentifier<'cx>,
+    key: BufferSource,
+) -> Result<*mut JSObject> {
+    let hash = Sha::from_identifier(cx, &hash)?;
+    Ok(DigestContext::from_hasher(
+        cx,
+        hmac::hasher(hash, &key.to_owned()),
+    ))
+}
+
+#[js_fn]
+fn import_key<'cx>(
+    cx: &'cx Context,
+    key_format: KeyFormat,
+    key_data: KeyData,
+    algorithm: AlgorithmIdentifier<'cx>,
+    extractable: bool,
+    key_usages: Vec<KeyUsage>,
+) -> Result<*mut JSObject> {
+    let alg = algorithm.get_algorithm(cx)?;
+    let key = alg.import_key(
+        cx,
+        &algorithm.to_params(cx),
+        key_format,
+        key_data.into_heap(),
+        extractable,
+        key_usages,
+    )?;
+    Ok(CryptoKey::new_object(cx, Box::new(key)))
+}
+
+#[js_fn]
+fn export_key<'cx>(cx: &'cx Context, key_format: KeyFormat, key: &CryptoKey) -> Result<Value<'cx>> {
+    let alg = AlgorithmIdentifier::String(key.algorithm.name().to_string()).get_algorithm(cx)?;
+    alg.export_key(cx, key_format, key)
+}
+
+#[js_fn]
+fn encrypt<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+    key: &CryptoKey,
+    data: BufferSource,
+) -> Result<*mut JSObject> {
+    let alg = algorithm.get_algorithm(cx)?;
+    check_key(alg.as_ref(), key, KeyUsage::Encrypt)?;
+    Ok(alg
+        .encrypt(cx, &algorithm.to_params(cx), key, data.to_owned())?
+        .get())
+}
+
+#[js_fn]
+fn decrypt<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+    key: &CryptoKey,
+    data: BufferSource,
+) -> Result<*mut JSObject> {
+    let alg = algorithm.get_algorithm(cx)?;
+    check_key(alg.as_ref(), key, KeyUsage::Decrypt)?;
+    Ok(alg
+        .decrypt(cx, &algorithm.to_params(cx), key, data.to_owned())?
+        .get())
+}
+
+#[js_fn]
+fn sign<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+    key: &CryptoKey,
+    data: BufferSource,
+) -> Result<*mut JSObject> {
+    let alg = algorithm.get_algorithm(cx)?;
+    check_key(alg.as_ref(), key, KeyUsage::Sign)?;
+    Ok(alg
+        .sign(cx, &algorithm.to_params(cx), key, data.to_owned())?
+        .get())
+}
+
+#[js_fn]
+fn verify<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+    key: &CryptoKey,
+    signature: BufferSource,
+    data: BufferSource,
+) -> Result<bool> {
+    let alg = algorithm.get_algorithm(cx)?;
+    check_key(alg.as_ref(), key, KeyUsage::Verify)?;
+    alg.verify(
+        cx,
+        &algorithm.to_params(cx),
+        key,
+        signature.to_owned(),
+        data.to_owned(),
+    )
+}
+
+#[js_fn]
+fn derive_bits<'cx>(
+    cx: &'cx Context,
+    algorithm: AlgorithmIdentifier<'cx>,
+    base_key: &CryptoKey,
+    length: u32,
+) -> Result<*mut JSObject> {
+    let alg = algorithm.get_algorithm(cx)?;
+    check_key(alg.as_ref(), base_key, KeyUsage::DeriveBits)?;
+    Ok(alg
+        .derive_bits(
+            cx,
+            &algorithm.to_params(cx),
+            base_key,
+            Some(length as usize),
+        )?
+        .get())
+}
+
+/// The name, and curve for EC keys, of the algorithm in a key's
+/// AlgorithmIdentifier. RSA keys can be used with more than one algorithm,
+/// so the caller picks the actual one.
+fn key_algorithm(
+    algorithm: &AlgorithmIdentifierRef,
+) -> Result<(&'static str, Option<&'static str>)> {
+    if algorithm.oid == RSA_OID {
+        Ok(("RSA", None))
+    } else if algorithm.oid == ED25519_OID {
+        Ok(("Ed25519", None))
+    } else if algorithm.oid == EC_OID {
+        let curve = algorithm.parameters_oid().ok().and_then(|oid| {
+            EC_CURVES
+                .iter()
+                .find(|(curve, _)| *curve == oid)
+                .map(|(_, name)| *name)
+        });
+        match curve {
+            Some(curve) => Ok(("ECDSA", Some(curve))),
+            None => ion_err!("Unsupported EC curve", Normal),
+        }
+    } else {
+        ion_err!("Unsupported key type", Normal)
+    }
+}
+
+/// Turns the DER data from a PEM block into PKCS#8 or SPKI data for
+/// `importKey`, wrapping PKCS#1 and SEC1 keys on the way, and reads the
+/// key's algorithm from it.
+#[js_fn]
+fn parse_key(cx: &Context, label: String, der: BufferSource) -> Result<*mut JSObject> {
+    let der = der.to_owned();
+    let invalid = |e: pkcs8::der::Error| ion_mk_err!(format!("Invalid {label} data: {e}"), Normal);
+
+    let (format, der) = match label.as_str() {
+        "PRIVATE KEY" => (KeyFormat::Pkcs8, der),
+        "PUBLIC KEY" => (KeyFormat::Spki, der),
+        "RSA PRIVATE KEY" => (
+            KeyFormat::Pkcs8,
+            PrivateKeyInfo::new(RSA_ALGORITHM, &der)
+                .to_der()
+                .map_err(invalid)?,
+        ),
+        "RSA PUBLIC KEY" => {
+            let spki = SubjectPublicKeyInfoRef {
+                algorithm: RSA_ALGORITHM,
+                subject_public_key: BitStringRef::from_bytes(&der).map_err(invalid)?,
+            };
+            (KeyFormat::Spki, spki.to_der().map_err(invalid)?)
+        }
+        "EC PRIVATE KEY" => {
+            let key = EcPrivateKey::from_der(&der).map_err(invalid)?;
+            let Some(curve) = key.parameters.and_then(|p| p.named_curve()) else {
+                ion_err!("EC private keys must specify a named curve", Normal);
+            };
+            let algorithm = AlgorithmIdentifierRef {
+                oid: EC_OID,
+                parameters: Some(AnyRef::from(&curve)),
+            };
+            (
+                KeyFormat::Pkcs8,
+                PrivateKeyInfo::new(algorithm, &der)
+                    .to_der()
+                    .map_err(invalid)?,
+            )
+        }
+        _ => ion_err!(format!("Unsupported PEM key type: {label}"), Normal),
+    };
+
+    let (name, named_curve) = match format {
+        KeyFormat::Spki => key_algorithm(
+            &SubjectPublicKeyInfoRef::from_der(&der)
+                .map_err(invalid)?
+                .algorithm,
+        )?,
+        _ => key_algorithm(&PrivateKeyInfo::from_der(&der).map_err(invalid)?.algorithm)?,
+    };
+
+    let algorithm = Object::new(cx);
+    algorithm.set_as(cx, "name", &name);
+    if let Some(named_curve) = named_curve {
+        algorithm.set_as(cx, "namedCurve", &named_curve);
+    }
+    let der = ArrayBuffer::copy_from_bytes(cx, &der)
+        .ok_or_else(|| ion_mk_err!("Failed to allocate array", Normal))?;
+
+    let result = Object::new(cx);
+    result.set_as(cx, "format", &format.as_ref());
+    result.set_as(cx, "der", &der.get());
+    result.set_as(cx, "algorithm", &algorithm);
+    Ok((*result).get())
+}
+
+/// Creates a key for verifying signatures from a private key, which Node
+/// accepts in place of the public key.
+#[js_fn]
+fn public_key(cx: &Context, key: &CryptoKey) -> Result<*mut JSObject> {
+    let Some(public) = key.key.public_only() else {
+        ion_err!("Expected a public or private key", Type);
+    };
+    let key = CryptoKey::new(
+        cx,
+        true,
+        key.algorithm.clone(),
+        KeyType::Public,
+        vec![KeyUsage::Verify],
+        public,
+    )?;
+    Ok(CryptoKey::new_object(cx, Box::new(key)))
+}
+
+#[js_fn]
+fn pss_salt_length(key: &CryptoKey, signature: BufferSource) -> Result<Option<u32>> {
+    Ok(rsa::pss_salt_length(key, &signature.to_owned())?.map(|length| length as u32))
+}
+
+const METHODS: &[JSFunctionSpec] = &[
+    function_spec!(create_hash, "createHash", 1),
+    function_spec!(create_hmac, "createHmac", 2),
+    function_spec!(import_key, "importKey", 5),
+    function_spec!(export_key, "exportKey", 2),
+    function_spec!(encrypt, 3),
+    function_spec!(decrypt, 3),
+    function_spec!(sign, 3),
+    function_spec!(verify, 4),
+    function_spec!(derive_bits, "deriveBits", 3),
+    function_spec!(parse_key, "parseKey", 2),
+    function_spec!(public_key, "publicKey", 1),
+    function_spec!(pss_salt_length, "pssSaltLength", 2),
+    JSFunctionSpec::ZERO,
+];
+
+#[derive(Default)]
+pub struct CryptoModule;
+
+impl NativeModule for CryptoModule {
+    const NAME: &'static str = "__winterjs_crypto_";
+
+    const SOURCE: &'static str = include_str!("node.js");
+
+    fn module(cx: &Context) -> Option<Object> {
+        let ret = Object::new(cx);
+        unsafe { ret.define_methods(cx, METHODS) }.then_some(ret)
+    }
+}
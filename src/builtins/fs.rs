@@ -0,0 +1,359 @@
+//! The `fs` global. Each function checks the read and write permissions for
+//! the paths it's given before touching the filesystem, so the checks can't
+//! be sidestepped from JS. Functions without the `Sync` suffix run on the
+//! blocking thread pool and return promises.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use ion::{
+    conversions::ToValue, function_spec, typedarray::Uint8Array, Context, Object, Promise, Value,
+};
+use mozjs::jsval::JSVal;
+use mozjs_sys::jsapi::JSFunctionSpec;
+use runtime::promise::future_to_promise;
+
+use crate::{
+    ion_mk_err,
+    permissions::{check_read, check_write},
+};
+
+/// Converts the result of a filesystem operation into a JS value.
+type ToJs<T> = fn(&Context, T) -> ion::Result<JSVal>;
+
+fn io_error(action: &str, path: &Path, e: io::Error) -> ion::Error {
+    ion_mk_err!(
+        format!("Failed to {action} {}: {e}", path.display()),
+        Normal
+    )
+}
+
+fn bytes_to_js(cx: &Context, bytes: Vec<u8>) -> ion::Result<JSVal> {
+    Ok(Uint8Array::copy_from_bytes(cx, &bytes)
+        .ok_or_else(|| ion_mk_err!("Failed to allocate array", Normal))?
+        .get())
+}
+
+fn string_to_js(cx: &Context, string: String) -> ion::Result<JSVal> {
+    Ok(string.as_value(cx).get())
+}
+
+fn strings_to_js(cx: &Context, strings: Vec<String>) -> ion::Result<JSVal> {
+    Ok(strings.as_value(cx).get())
+}
+
+fn done_to_js(cx: &Context, (): ()) -> ion::Result<JSVal> {
+    Ok(true.as_value(cx).get())
+}
+
+fn read_dir(path: &Path) -> io::Result<Vec<String>> {
+    std::fs::read_dir(path)?
+        .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+        .collect()
+}
+
+#[cfg(unix)]
+fn soft_link(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(not(unix))]
+fn soft_link(_original: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Symbolic links are not supported on this platform",
+    ))
+}
+
+/// An operation on one or two paths, which have already passed their
+/// permission checks.
+struct Op<T> {
+    action: &'static str,
+    path: PathBuf,
+    run: Box<dyn FnOnce() -> io::Result<T> + Send>,
+}
+
+impl<T: Send + 'static> Op<T> {
+    fn new(
+        action: &'static str,
+        path: &str,
+        checks: ion::Result<()>,
+        run: impl FnOnce(&Path) -> io::Result<T> + Send + 'static,
+    ) -> ion::Result<Self> {
+        checks?;
+        let path = PathBuf::from(path);
+        let target = path.clone();
+        Ok(Self {
+            action,
+            path,
+            run: Box::new(move || run(&target)),
+        })
+    }
+
+    fn run_sync(op: ion::Result<Self>, cx: &Context, to_js: ToJs<T>) -> ion::Result<JSVal> {
+        let op = op?;
+        let result = (op.run)().map_err(|e| io_error(op.action, &op.path, e))?;
+        to_js(cx, result)
+    }
+
+    fn run_async(op: ion::Result<Self>, cx: &Context, to_js: ToJs<T>) -> Option<Promise> {
+        let op = match op {
+            Ok(op) => op,
+            Err(e) => return Some(Promise::rejected(cx, e)),
+        };
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, result) = cx.await_native(tokio::task::spawn_blocking(op.run)).await;
+                let result = result
+                    .map_err(|e| ion_mk_err!(format!("Background task failed: {e}"), Normal))?
+                    .map_err(|e| io_error(op.action, &op.path, e))?;
+                to_js(&cx, result)
+            })
+        }
+    }
+}
+
+fn read_binary_op(path: &str) -> ion::Result<Op<Vec<u8>>> {
+    Op::new("read", path, check_read(Path::new(path)), |p| {
+        std::fs::read(p)
+    })
+}
+
+fn read_string_op(path: &str) -> ion::Result<Op<String>> {
+    Op::new("read", path, check_read(Path::new(path)), |p| {
+        std::fs::read_to_string(p)
+    })
+}
+
+fn read_dir_op(path: &str) -> ion::Result<Op<Vec<String>>> {
+    Op::new("read directory", path, check_read(Path::new(path)), |p| {
+        read_dir(p)
+    })
+}
+
+fn write_op(path: &str, contents: String) -> ion::Result<Op<()>> {
+    Op::new("write", path, check_write(Path::new(path)), move |p| {
+        std::fs::write(p, contents)
+    })
+}
+
+fn create_dir_op(path: &str, recursive: bool) -> ion::Result<Op<()>> {
+    Op::new("create", path, check_write(Path::new(path)), move |p| {
+        if recursive {
+            std::fs::create_dir_all(p)
+        } else {
+            std::fs::create_dir(p)
+        }
+    })
+}
+
+fn remove_file_op(path: &str) -> ion::Result<Op<()>> {
+    Op::new("remove", path, check_write(Path::new(path)), |p| {
+        std::fs::remove_file(p)
+    })
+}
+
+fn remove_dir_op(path: &str, recursive: bool) -> ion::Result<Op<()>> {
+    Op::new("remove", path, check_write(Path::new(path)), move |p| {
+        if recursive {
+            std::fs::remove_dir_all(p)
+        } else {
+            std::fs::remove_dir(p)
+        }
+    })
+}
+
+fn copy_op(from: &str, to: String) -> ion::Result<Op<()>> {
+    let checks = check_read(Path::new(from)).and_then(|()| check_write(Path::new(&to)));
+    Op::new("copy", from, checks, move |p| {
+        std::fs::copy(p, to).map(|_| ())
+    })
+}
+
+fn rename_op(from: &str, to: String) -> ion::Result<Op<()>> {
+    let checks = check_write(Path::new(from)).and_then(|()| check_write(Path::new(&to)));
+    Op::new("rename", from, checks, move |p| std::fs::rename(p, to))
+}
+
+fn soft_link_op(original: &str, link: String) -> ion::Result<Op<()>> {
+    let checks = check_read(Path::new(original)).and_then(|()| check_write(Path::new(&link)));
+    Op::new("link to", original, checks, move |p| {
+        soft_link(p, Path::new(&link))
+    })
+}
+
+fn hard_link_op(original: &str, link: String) -> ion::Result<Op<()>> {
+    let checks = check_read(Path::new(original)).and_then(|()| check_write(Path::new(&link)));
+    Op::new("link to", original, checks, move |p| {
+        std::fs::hard_link(p, link)
+    })
+}
+
+#[js_fn]
+fn read_binary(cx: &Context, path: String) -> Option<Promise> {
+    Op::run_async(read_binary_op(&path), cx, bytes_to_js)
+}
+
+#[js_fn]
+fn read_binary_sync(cx: &Context, path: String) -> ion::Result<JSVal> {
+    Op::run_sync(read_binary_op(&path), cx, bytes_to_js)
+}
+
+#[js_fn]
+fn read_string(cx: &Context, path: String) -> Option<Promise> {
+    Op::run_async(read_string_op(&path), cx, string_to_js)
+}
+
+#[js_fn]
+fn read_string_sync(cx: &Context, path: String) -> ion::Result<JSVal> {
+    Op::run_sync(read_string_op(&path), cx, string_to_js)
+}
+
+#[js_fn]
+fn read_dir_fn(cx: &Context, path: String) -> Option<Promise> {
+    Op::run_async(read_dir_op(&path), cx, strings_to_js)
+}
+
+#[js_fn]
+fn read_dir_sync(cx: &Context, path: String) -> ion::Result<JSVal> {
+    Op::run_sync(read_dir_op(&path), cx, strings_to_js)
+}
+
+#[js_fn]
+fn write(cx: &Context, path: String, contents: String) -> Option<Promise> {
+    Op::run_async(write_op(&path, contents), cx, done_to_js)
+}
+
+#[js_fn]
+fn write_sync(cx: &Context, path: String, contents: String) -> ion::Result<JSVal> {
+    Op::run_sync(write_op(&path, contents), cx, done_to_js)
+}
+
+#[js_fn]
+fn create_dir(cx: &Context, path: String) -> Option<Promise> {
+    Op::run_async(create_dir_op(&path, false), cx, done_to_js)
+}
+
+#[js_fn]
+fn create_dir_sync(cx: &Context, path: String) -> ion::Result<JSVal> {
+    Op::run_sync(create_dir_op(&path, false), cx, done_to_js)
+}
+
+#[js_fn]
+fn create_dir_recursive(cx: &Context, path: String) -> Option<Promise> {
+    Op::run_async(create_dir_op(&path, true), cx, done_to_js)
+}
+
+#[js_fn]
+fn create_dir_recursive_sync(cx: &Context, path: String) -> ion::Result<JSVal> {
+    Op::run_sync(create_dir_op(&path, true), cx, done_to_js)
+}
+
+#[js_fn]
+fn remove_file(cx: &Context, path: String) -> Option<Promise> {
+    Op::run_async(remove_file_op(&path), cx, done_to_js)
+}
+
+#[js_fn]
+fn remove_file_sync(cx: &Context, path: String) -> ion::Result<JSVal> {
+    Op::run_sync(remove_file_op(&path), cx, done_to_js)
+}
+
+#[js_fn]
+fn remove_dir(cx: &Context, path: String) -> Option<Promise> {
+    Op::run_async(remove_dir_op(&path, false), cx, done_to_js)
+}
+
+#[js_fn]
+fn remove_dir_sync(cx: &Context, path: String) -> ion::Result<JSVal> {
+    Op::run_sync(remove_dir_op(&path, false), cx, done_to_js)
+}
+
+#[js_fn]
+fn remove_dir_recursive(cx: &Context, path: String) -> Option<Promise> {
+    Op::run_async(remove_dir_op(&path, true), cx, done_to_js)
+}
+
+#[js_fn]
+fn remove_dir_recursive_sync(cx: &Context, path: String) -> ion::Result<JSVal> {
+    Op::run_sync(remove_dir_op(&path, true), cx, done_to_js)
+}
+
+#[js_fn]
+fn copy(cx: &Context, from: String, to: String) -> Option<Promise> {
+    Op::run_async(copy_op(&from, to), cx, done_to_js)
+}
+
+#[js_fn]
+fn copy_sync(cx: &Context, from: String, to: String) -> ion::Result<JSVal> {
+    Op::run_sync(copy_op(&from, to), cx, done_to_js)
+}
+
+#[js_fn]
+fn rename(cx: &Context, from: String, to: String) -> Option<Promise> {
+    Op::run_async(rename_op(&from, to), cx, done_to_js)
+}
+
+#[js_fn]
+fn rename_sync(cx: &Context, from: String, to: String) -> ion::Result<JSVal> {
+    Op::run_sync(rename_op(&from, to), cx, done_to_js)
+}
+
+#[js_fn]
+fn soft_link_fn(cx: &Context, original: String, link: String) -> Option<Promise> {
+    Op::run_async(soft_link_op(&original, link), cx, done_to_js)
+}
+
+#[js_fn]
+fn soft_link_sync(cx: &Context, original: String, link: String) -> ion::Result<JSVal> {
+    Op::run_sync(soft_link_op(&original, link), cx, done_to_js)
+}
+
+#[js_fn]
+fn hard_link(cx: &Context, original: String, link: String) -> Option<Promise> {
+    Op::run_async(hard_link_op(&original, link), cx, done_to_js)
+}
+
+#[js_fn]
+fn hard_link_sync(cx: &Context, original: String, link: String) -> ion::Result<JSVal> {
+    Op::run_sync(hard_link_op(&original, link), cx, done_to_js)
+}
+
+const METHODS: &[JSFunctionSpec] = &[
+    function_spec!(read_binary, "readBinary", 1),
+    function_spec!(read_binary_sync, "readBinarySync", 1),
+    function_spec!(read_string, "readString", 1),
+    function_spec!(read_string_sync, "readStringSync", 1),
+    function_spec!(read_dir_fn, "readDir", 1),
+    function_spec!(read_dir_sync, "readDirSync", 1),
+    function_spec!(write, "write", 2),
+    function_spec!(write_sync, "writeSync", 2),
+    function_spec!(create_dir, "createDir", 1),
+    function_spec!(create_dir_sync, "createDirSync", 1),
+    function_spec!(create_dir_recursive, "createDirRecursive", 1),
+    function_spec!(create_dir_recursive_sync, "createDirRecursiveSync", 1),
+    function_spec!(remove_file, "removeFile", 1),
+    function_spec!(remove_file_sync, "removeFileSync", 1),
+    function_spec!(remove_dir, "removeDir", 1),
+    function_spec!(remove_dir_sync, "removeDirSync", 1),
+    function_spec!(remove_dir_recursive, "removeDirRecursive", 1),
+    function_spec!(remove_dir_recursive_sync, "removeDirRecursiveSync", 1),
+    function_spec!(copy, "copy", 2),
+    function_spec!(copy_sync, "copySync", 2),
+    function_spec!(rename, "rename", 2),
+    function_spec!(rename_sync, "renameSync", 2),
+    function_spec!(soft_link_fn, "softLink", 2),
+    function_spec!(soft_link_sync, "softLinkSync", 2),
+    function_spec!(hard_link, "hardLink", 2),
+    function_spec!(hard_link_sync, "hardLinkSync", 2),
+    JSFunctionSpec::ZERO,
+];
+
+pub fn define(cx: &Context, global: &Object) -> bool {
+    let fs = Object::new(cx);
+    unsafe { fs.define_methods(cx, METHODS) }
+    &&global.set_as(cx, "fs", &Value::object(cx, &fs))
+}
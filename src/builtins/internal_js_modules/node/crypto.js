@@ -0,0 +1,865 @@
+// A subset of Node's crypto module, implemented on top of the same native
+// algorithms that back crypto.subtle.
+//
+// Signers and verifiers buffer their input and do all the work in sign() and
+// verify(). Ciphers return their output from update() as it becomes
+// available, but AES-GCM still keeps the whole message around to compute
+// or check the authentication tag in final().
+
+import * as native from "__winterjs_crypto_";
+import { Buffer } from "node:buffer";
+
+const HASHES = {
+  md5: "MD5",
+  sha1: "SHA-1",
+  sha256: "SHA-256",
+  sha384: "SHA-384",
+  sha512: "SHA-512",
+};
+
+const HASH_LENGTHS = {
+  "SHA-1": 20,
+  "SHA-256": 32,
+  "SHA-384": 48,
+  "SHA-512": 64,
+};
+
+const CIPHER_REGEX = /^aes-(128|192|256)-(cbc|ctr|gcm)$/;
+
+const RANDOM_INT_RANGE = 2 ** 48;
+
+export const constants = Object.freeze({
+  RSA_PKCS1_PADDING: 1,
+  RSA_PKCS1_PSS_PADDING: 6,
+  RSA_PSS_SALTLEN_DIGEST: -1,
+  RSA_PSS_SALTLEN_MAX_SIGN: -2,
+  RSA_PSS_SALTLEN_AUTO: -2,
+});
+
+function hashName(algorithm) {
+  if (typeof algorithm !== "string") {
+    throw new TypeError("The digest algorithm must be a string");
+  }
+  const name = algorithm
+    .toLowerCase()
+    .replace(/^rsa-/, "")
+    .replace("-", "");
+  const hash = HASHES[name];
+  if (hash === undefined) {
+    throw new Error(`Digest method not supported: ${algorithm}`);
+  }
+  return hash;
+}
+
+function toBuffer(data, encoding) {
+  if (typeof data === "string") {
+    return Buffer.from(data, encoding ?? "utf8");
+  }
+  if (data instanceof ArrayBuffer) {
+    return Buffer.from(data);
+  }
+  if (ArrayBuffer.isView(data)) {
+    return Buffer.from(data.buffer, data.byteOffset, data.byteLength);
+  }
+  throw new TypeError(
+    "The data argument must be a string, Buffer, TypedArray, DataView or ArrayBuffer"
+  );
+}
+
+function encodeOutput(buffer, encoding) {
+  return encoding === undefined || encoding === "buffer"
+    ? buffer
+    : buffer.toString(encoding);
+}
+
+function defer(callback, compute) {
+  Promise.resolve().then(() => {
+    let result;
+    try {
+      result = compute();
+    } catch (e) {
+      callback(e);
+      return;
+    }
+    callback(null, result);
+  });
+}
+
+// Hashing
+
+class Hash {
+  #context;
+
+  constructor(algorithm) {
+    this.#context = native.createHash(hashName(algorithm));
+  }
+
+  update(data, inputEncoding) {
+    this.#context.update(toBuffer(data, inputEncoding));
+    return this;
+  }
+
+  digest(encoding) {
+    return encodeOutput(Buffer.from(this.#context.digest()), encoding);
+  }
+}
+
+class Hmac {
+  #context;
+
+  constructor(algorithm, key) {
+    const hash = hashName(algorithm);
+    if (hash === "MD5") {
+      throw new Error(`Digest method not supported for HMAC: ${algorithm}`);
+    }
+    this.#context = native.createHmac(hash, toBuffer(key));
+  }
+
+  update(data, inputEncoding) {
+    this.#context.update(toBuffer(data, inputEncoding));
+    return this;
+  }
+
+  digest(encoding) {
+    return encodeOutput(Buffer.from(this.#context.digest()), encoding);
+  }
+}
+
+export function createHash(algorithm) {
+  return new Hash(algorithm);
+}
+
+export function createHmac(algorithm, key) {
+  return new Hmac(algorithm, key);
+}
+
+export function getHashes() {
+  return Object.keys(HASHES);
+}
+
+// Randomness
+
+export function getRandomValues(array) {
+  return globalThis.crypto.getRandomValues(array);
+}
+
+export function randomUUID() {
+  return globalThis.crypto.randomUUID();
+}
+
+function randomBytesSync(size) {
+  if (!Number.isSafeInteger(size) || size < 0) {
+    throw new RangeError(`The size argument must be a non-negative integer`);
+  }
+  const buffer = Buffer.alloc(size);
+  // getRandomValues fills at most 64KiB at a time
+  for (let i = 0; i < size; i += 65536) {
+    globalThis.crypto.getRandomValues(buffer.subarray(i, i + 65536));
+  }
+  return buffer;
+}
+
+export function randomBytes(size, callback) {
+  if (typeof callback === "function") {
+    defer(callback, () => randomBytesSync(size));
+    return;
+  }
+  return randomBytesSync(size);
+}
+
+export function randomInt(min, max, callback) {
+  if (typeof max === "undefined" || typeof max === "function") {
+    callback = max;
+    max = min;
+    min = 0;
+  }
+  if (!Number.isSafeInteger(min) || !Number.isSafeInteger(max)) {
+    throw new TypeError("The min and max arguments must be safe integers");
+  }
+  const range = max - min;
+  if (range <= 0 || range > RANDOM_INT_RANGE) {
+    throw new RangeError(
+      `max - min must be positive and at most ${RANDOM_INT_RANGE}`
+    );
+  }
+
+  const compute = () => {
+    // Reject values past the last multiple of range to avoid modulo bias
+    const limit = RANDOM_INT_RANGE - (RANDOM_INT_RANGE % range);
+    for (;;) {
+      const value = randomBytesSync(6).readUIntBE(0, 6);
+      if (value < limit) {
+        return min + (value % range);
+      }
+    }
+  };
+
+  if (typeof callback === "function") {
+    defer(callback, compute);
+    return;
+  }
+  return compute();
+}
+
+// Key derivation
+
+export function pbkdf2Sync(password, salt, iterations, keylen, digest) {
+  if (!Number.isSafeInteger(keylen) || keylen < 0) {
+    throw new RangeError("The keylen argument must be a non-negative integer");
+  }
+  if (keylen === 0) {
+    return Buffer.alloc(0);
+  }
+
+  const key = native.importKey(
+    "raw",
+    toBuffer(password),
+    "PBKDF2",
+    false,
+    ["deriveBits"]
+  );
+  const params = {
+    name: "PBKDF2",
+    salt: toBuffer(salt),
+    iterations,
+    hash: hashName(digest),
+  };
+  return Buffer.from(native.deriveBits(params, key, keylen * 8));
+}
+
+export function pbkdf2(password, salt, iterations, keylen, digest, callback) {
+  if (typeof callback !== "function") {
+    throw new TypeError("The callback argument must be a function");
+  }
+  defer(callback, () => pbkdf2Sync(password, salt, iterations, keylen, digest));
+}
+
+// Ciphers
+
+const BLOCK_SIZE = 16;
+
+// Adds a number of blocks to the last `bits` bits of a counter block,
+// wrapping around within them like the counter itself does
+function addToCounter(counter, blocks, bits) {
+  const result = Buffer.from(counter);
+  for (let i = result.length - 1; i >= result.length - bits / 8; i--) {
+    const sum = result[i] + (blocks % 256);
+    result[i] = sum & 0xff;
+    blocks = Math.floor(blocks / 256) + (sum >> 8);
+  }
+  return result;
+}
+
+// The number of bytes at the end of a buffer that start a UTF-8 sequence
+// without completing it
+function incompleteUtf8Length(buffer) {
+  for (let i = 1; i <= Math.min(3, buffer.length); i++) {
+    const byte = buffer[buffer.length - i];
+    if ((byte & 0xc0) === 0x80) {
+      continue;
+    }
+    const length = byte >= 0xf0 ? 4 : byte >= 0xe0 ? 3 : byte >= 0xc0 ? 2 : 1;
+    return length > i ? i : 0;
+  }
+  return 0;
+}
+
+class Cipheriv {
+  #decrypt;
+  #mode;
+  #key;
+  // AES-GCM messages are encrypted with this AES-CTR key as they come in,
+  // and #key is only used for the tag
+  #ctrKey;
+  #iv;
+  #counter;
+  #counterBits;
+  #position = 0;
+  // CBC input that doesn't fill a block yet, or the last block when
+  // decrypting since it holds the padding
+  #pending = Buffer.alloc(0);
+  #chunks = [];
+  #pendingOutput;
+  #aad;
+  #authTag;
+  #authTagLength;
+  #finalized = false;
+
+  constructor(decrypt, algorithm, key, iv, options) {
+    const match = CIPHER_REGEX.exec(String(algorithm).toLowerCase());
+    if (match === null) {
+      throw new Error(`Unknown cipher: ${algorithm}`);
+    }
+    const [, bits, mode] = match;
+
+    const keyBuffer = toBuffer(key);
+    if (keyBuffer.length * 8 !== Number(bits)) {
+      throw new RangeError("Invalid key length");
+    }
+
+    const ivBuffer = toBuffer(iv);
+    if (mode === "gcm" ? ivBuffer.length !== 12 : ivBuffer.length !== 16) {
+      throw new Error(
+        mode === "gcm"
+          ? "Only 12-byte IVs are supported for AES-GCM"
+          : "Invalid initialization vector"
+      );
+    }
+
+    this.#decrypt = decrypt;
+    this.#mode = mode;
+    this.#iv = ivBuffer;
+    this.#authTagLength = options?.authTagLength ?? 16;
+    // Both usages are needed, since decrypting CBC data in pieces
+    // encrypts a block of padding
+    this.#key = native.importKey(
+      "raw",
+      keyBuffer,
+      `AES-${mode.toUpperCase()}`,
+      false,
+      ["encrypt", "decrypt"]
+    );
+
+    if (mode === "ctr") {
+      this.#counter = ivBuffer;
+      this.#counterBits = 128;
+    } else if (mode === "gcm") {
+      // With a 96-bit IV, the message starts at counter block IV || 2, since
+      // IV || 1 is used for the tag. Only the last 32 bits are incremented.
+      this.#counter = Buffer.concat([ivBuffer, Buffer.from([0, 0, 0, 2])]);
+      this.#counterBits = 32;
+      this.#ctrKey = native.importKey("raw", keyBuffer, "AES-CTR", false, [
+        "encrypt",
+      ]);
+    }
+  }
+
+  update(data, inputEncoding, outputEncoding) {
+    if (this.#finalized) {
+      throw new Error("Cipher has already been finalized");
+    }
+    data = toBuffer(data, inputEncoding);
+
+    let result;
+    switch (this.#mode) {
+      case "cbc":
+        result = this.#decrypt ? this.#cbcDecrypt(data) : this.#cbcEncrypt(data);
+        break;
+      case "ctr":
+        result = this.#applyKeystream(data);
+        break;
+      case "gcm":
+        this.#chunks.push(data);
+        result = this.#applyKeystream(data);
+        break;
+    }
+    return this.#output(result, outputEncoding, false);
+  }
+
+  final(outputEncoding) {
+    if (this.#finalized) {
+      throw new Error("Cipher has already been finalized");
+    }
+    this.#finalized = true;
+
+    let result = Buffer.alloc(0);
+    switch (this.#mode) {
+      case "cbc":
+        result = this.#cbcFinal();
+        break;
+      case "gcm":
+        this.#gcmFinal();
+        break;
+    }
+    return this.#output(result, outputEncoding, true);
+  }
+
+  // Encrypts zeros in front of the data to get to the current position in
+  // the keystream, and drops them from the output
+  #applyKeystream(data) {
+    const offset = this.#position % BLOCK_SIZE;
+    const counter = addToCounter(
+      this.#counter,
+      Math.floor(this.#position / BLOCK_SIZE),
+      this.#counterBits
+    );
+    const input = Buffer.alloc(offset + data.length);
+    data.copy(input, offset);
+    this.#position += data.length;
+
+    const params = { name: "AES-CTR", counter, length: this.#counterBits };
+    return Buffer.from(
+      native.encrypt(params, this.#ctrKey ?? this.#key, input)
+    ).subarray(offset);
+  }
+
+  #cbcEncrypt(data) {
+    const input = Buffer.concat([this.#pending, data]);
+    const length = input.length - (input.length % BLOCK_SIZE);
+    this.#pending = input.subarray(length);
+    if (length === 0) {
+      return Buffer.alloc(0);
+    }
+
+    // The padding block is dropped, and the last block becomes the IV for
+    // the rest of the message
+    const params = { name: "AES-CBC", iv: this.#iv };
+    const result = Buffer.from(
+      native.encrypt(params, this.#key, input.subarray(0, length))
+    ).subarray(0, length);
+    this.#iv = result.subarray(length - BLOCK_SIZE);
+    return result;
+  }
+
+  #cbcDecrypt(data) {
+    const input = Buffer.concat([this.#pending, data]);
+    const blocks = Math.max(0, Math.ceil(input.length / BLOCK_SIZE) - 1);
+    const length = blocks * BLOCK_SIZE;
+    this.#pending = input.subarray(length);
+    if (length === 0) {
+      return Buffer.alloc(0);
+    }
+
+    // Decryption checks the padding, so a valid padding block is added by
+    // encrypting nothing with the last ciphertext block as the IV
+    const last = input.subarray(length - BLOCK_SIZE, length);
+    const padding = Buffer.from(
+      native.encrypt({ name: "AES-CBC", iv: last }, this.#key, Buffer.alloc(0))
+    );
+    const params = { name: "AES-CBC", iv: this.#iv };
+    const result = Buffer.from(
+      native.decrypt(
+        params,
+        this.#key,
+        Buffer.concat([input.subarray(0, length), padding])
+      )
+    );
+    this.#iv = last;
+    return result;
+  }
+
+  #cbcFinal() {
+    const params = { name: "AES-CBC", iv: this.#iv };
+    if (!this.#decrypt) {
+      return Buffer.from(native.encrypt(params, this.#key, this.#pending));
+    }
+    if (this.#pending.length !== BLOCK_SIZE) {
+      throw new Error("wrong final block length");
+    }
+    try {
+      return Buffer.from(native.decrypt(params, this.#key, this.#pending));
+    } catch (e) {
+      throw new Error("bad decrypt");
+    }
+  }
+
+  // The data has already been through the keystream, so this only computes
+  // or checks the tag
+  #gcmFinal() {
+    const params = {
+      name: "AES-GCM",
+      iv: this.#iv,
+      additionalData: this.#aad,
+      tagLength: this.#authTagLength * 8,
+    };
+    const data = Buffer.concat(this.#chunks);
+    this.#chunks = [];
+
+    if (this.#decrypt) {
+      if (this.#authTag === undefined) {
+        throw new Error("Unsupported state or unable to authenticate data");
+      }
+      try {
+        native.decrypt(params, this.#key, Buffer.concat([data, this.#authTag]));
+      } catch (e) {
+        throw new Error("Unsupported state or unable to authenticate data");
+      }
+    } else {
+      const result = Buffer.from(native.encrypt(params, this.#key, data));
+      this.#authTag = result.subarray(result.length - this.#authTagLength);
+    }
+  }
+
+  // Holds back bytes that would split a character when encoding output as
+  // a string, like Node's StringDecoder does, so the results of update()
+  // and final() can be concatenated
+  #output(buffer, encoding, flush) {
+    if (encoding === undefined || encoding === "buffer") {
+      return buffer;
+    }
+    if (this.#pendingOutput !== undefined) {
+      buffer = Buffer.concat([this.#pendingOutput, buffer]);
+      this.#pendingOutput = undefined;
+    }
+
+    let keep = 0;
+    if (!flush) {
+      const normalized = encoding.toLowerCase();
+      if (normalized === "base64" || normalized === "base64url") {
+        keep = buffer.length % 3;
+      } else if (normalized === "utf8" || normalized === "utf-8") {
+        keep = incompleteUtf8Length(buffer);
+      }
+    }
+    if (keep > 0) {
+      this.#pendingOutput = buffer.subarray(buffer.length - keep);
+      buffer = buffer.subarray(0, buffer.length - keep);
+    }
+    return buffer.toString(encoding);
+  }
+
+  setAAD(buffer) {
+    if (this.#mode !== "gcm") {
+      throw new Error("setAAD is only supported for AES-GCM");
+    }
+    this.#aad = toBuffer(buffer);
+    return this;
+  }
+
+  getAuthTag() {
+    if (this.#decrypt || this.#mode !== "gcm" || !this.#finalized) {
+      throw new Error("Invalid state for operation getAuthTag");
+    }
+    return this.#authTag;
+  }
+
+  setAuthTag(buffer) {
+    if (!this.#decrypt || this.#mode !== "gcm" || this.#finalized) {
+      throw new Error("Invalid state for operation setAuthTag");
+    }
+    this.#authTag = toBuffer(buffer);
+    this.#authTagLength = this.#authTag.length;
+    return this;
+  }
+
+  setAutoPadding(autoPadding) {
+    if (autoPadding === false) {
+      throw new Error("Disabling padding is not supported");
+    }
+    return this;
+  }
+}
+
+export function createCipheriv(algorithm, key, iv, options) {
+  return new Cipheriv(false, algorithm, key, iv, options);
+}
+
+export function createDecipheriv(algorithm, key, iv, options) {
+  return new Cipheriv(true, algorithm, key, iv, options);
+}
+
+export function getCiphers() {
+  return ["128", "192", "256"].flatMap((bits) =>
+    ["cbc", "ctr", "gcm"].map((mode) => `aes-${bits}-${mode}`)
+  );
+}
+
+// Signatures
+
+const EC_FIELD_SIZES = { "P-256": 32, "P-384": 48, "P-521": 66 };
+
+function derLength(length) {
+  if (length < 0x80) {
+    return [length];
+  }
+  const bytes = [];
+  for (; length > 0; length >>= 8) {
+    bytes.unshift(length & 0xff);
+  }
+  return [0x80 | bytes.length, ...bytes];
+}
+
+function derWrap(tag, content) {
+  return [tag, ...derLength(content.length), ...content];
+}
+
+// Turns a PEM key into DER data that importKey understands, along with the
+// algorithm from the key's AlgorithmIdentifier
+function parsePem(pem) {
+  const match =
+    /-----BEGIN ([A-Z ]+)-----([\s\S]*?)-----END \1-----/.exec(pem);
+  if (match === null) {
+    throw new TypeError("Invalid PEM key");
+  }
+  const [, label, body] = match;
+  return native.parseKey(
+    label,
+    Buffer.from(body.replace(/\s+/g, ""), "base64")
+  );
+}
+
+function importSigningKey(keyInput, hash, usage) {
+  const options =
+    typeof keyInput === "object" && !ArrayBuffer.isView(keyInput)
+      ? keyInput
+      : { key: keyInput };
+  if (options.passphrase !== undefined) {
+    throw new Error("Encrypted private keys are not supported");
+  }
+
+  const pem =
+    typeof options.key === "string"
+      ? options.key
+      : toBuffer(options.key).toString("utf8");
+  let { format, der, algorithm } = parsePem(pem);
+
+  if (algorithm.name === "RSA") {
+    if (hash === undefined) {
+      throw new TypeError("A digest algorithm is required for RSA keys");
+    }
+    algorithm =
+      options.padding === constants.RSA_PKCS1_PSS_PADDING
+        ? { name: "RSA-PSS", hash }
+        : { name: "RSASSA-PKCS1-v1_5", hash };
+  } else if (algorithm.name === "ECDSA" && hash === undefined) {
+    throw new TypeError("A digest algorithm is required for EC keys");
+  }
+
+  // Private keys can verify signatures too, using their public half
+  if (usage === "verify" && format === "pkcs8") {
+    const privateKey = native.importKey(format, der, algorithm, false, [
+      "sign",
+    ]);
+    return { key: native.publicKey(privateKey), algorithm, options };
+  }
+
+  const key = native.importKey(format, der, algorithm, false, [usage]);
+  return { key, algorithm, options };
+}
+
+// RSA_PSS_SALTLEN_MAX_SIGN and RSA_PSS_SALTLEN_AUTO share a value, which
+// means the largest possible salt when signing and any salt when verifying.
+// For the latter, the salt length is read from the signature itself.
+function signatureParams({ key, algorithm, options }, hash, signature) {
+  switch (algorithm.name) {
+    case "RSA-PSS": {
+      let saltLength = options.saltLength ?? constants.RSA_PSS_SALTLEN_AUTO;
+      if (saltLength === constants.RSA_PSS_SALTLEN_DIGEST) {
+        saltLength = HASH_LENGTHS[hash];
+      } else if (saltLength === constants.RSA_PSS_SALTLEN_AUTO) {
+        if (signature === undefined) {
+          const modulusBytes = Math.ceil((key.algorithm.modulusLength - 1) / 8);
+          saltLength = modulusBytes - HASH_LENGTHS[hash] - 2;
+        } else {
+          saltLength = native.pssSaltLength(key, signature);
+        }
+      }
+      return { name: "RSA-PSS", saltLength };
+    }
+    case "ECDSA":
+      return { name: "ECDSA", hash };
+    default:
+      return algorithm;
+  }
+}
+
+function derInteger(bytes) {
+  let start = 0;
+  while (start < bytes.length - 1 && bytes[start] === 0) {
+    start++;
+  }
+  const value = [...bytes.subarray(start)];
+  if (value[0] & 0x80) {
+    value.unshift(0);
+  }
+  return derWrap(0x02, value);
+}
+
+// WebCrypto produces r || s, while Node defaults to DER
+function ieeeToDer(signature) {
+  const half = signature.length / 2;
+  return Buffer.from(
+    derWrap(0x30, [
+      ...derInteger(signature.subarray(0, half)),
+      ...derInteger(signature.subarray(half)),
+    ])
+  );
+}
+
+function derToIeee(signature, fieldSize) {
+  const result = Buffer.alloc(fieldSize * 2);
+  let offset = 0;
+
+  const readLength = () => {
+    let length = signature[offset++];
+    if (length & 0x80) {
+      const count = length & 0x7f;
+      length = 0;
+      for (let i = 0; i < count; i++) {
+        length = (length << 8) | signature[offset++];
+      }
+    }
+    return length;
+  };
+
+  if (signature[offset++] !== 0x30) {
+    return result;
+  }
+  readLength();
+  for (let i = 0; i < 2; i++) {
+    if (signature[offset++] !== 0x02) {
+      return result;
+    }
+    const length = readLength();
+    let value = signature.subarray(offset, offset + length);
+    offset += length;
+    while (value.length > fieldSize && value[0] === 0) {
+      value = value.subarray(1);
+    }
+    result.set(value, (i + 1) * fieldSize - value.length);
+  }
+  return result;
+}
+
+function signData(hash, data, keyInput) {
+  const imported = importSigningKey(keyInput, hash, "sign");
+  const params = signatureParams(imported, hash);
+  let signature = Buffer.from(native.sign(params, imported.key, data));
+  if (
+    imported.algorithm.name === "ECDSA" &&
+    imported.options.dsaEncoding !== "ieee-p1363"
+  ) {
+    signature = ieeeToDer(signature);
+  }
+  return signature;
+}
+
+function verifyData(hash, data, keyInput, signature) {
+  const imported = importSigningKey(keyInput, hash, "verify");
+  const params = signatureParams(imported, hash, signature);
+  if (params.saltLength === null) {
+    // The signature isn't a valid RSA-PSS encoding
+    return false;
+  }
+  if (
+    imported.algorithm.name === "ECDSA" &&
+    imported.options.dsaEncoding !== "ieee-p1363"
+  ) {
+    signature = derToIeee(
+      signature,
+      EC_FIELD_SIZES[imported.algorithm.namedCurve]
+    );
+  }
+  return native.verify(params, imported.key, signature, data);
+}
+
+class Sign {
+  #hash;
+  #chunks = [];
+
+  constructor(algorithm) {
+    this.#hash = hashName(algorithm);
+  }
+
+  update(data, inputEncoding) {
+    this.#chunks.push(toBuffer(data, inputEncoding));
+    return this;
+  }
+
+  sign(privateKey, outputEncoding) {
+    const signature = signData(
+      this.#hash,
+      Buffer.concat(this.#chunks),
+      privateKey
+    );
+    return encodeOutput(signature, outputEncoding);
+  }
+}
+
+class Verify {
+  #hash;
+  #chunks = [];
+
+  constructor(algorithm) {
+    this.#hash = hashName(algorithm);
+  }
+
+  update(data, inputEncoding) {
+    this.#chunks.push(toBuffer(data, inputEncoding));
+    return this;
+  }
+
+  verify(publicKey, signature, signatureEncoding) {
+    return verifyData(
+      this.#hash,
+      Buffer.concat(this.#chunks),
+      publicKey,
+      toBuffer(signature, signatureEncoding)
+    );
+  }
+}
+
+export function createSign(algorithm) {
+  return new Sign(algorithm);
+}
+
+export function createVerify(algorithm) {
+  return new Verify(algorithm);
+}
+
+// One-shot variants; the algorithm is null for Ed25519 keys
+export function sign(algorithm, data, key, callback) {
+  const compute = () =>
+    signData(
+      algorithm == null ? undefined : hashName(algorithm),
+      toBuffer(data),
+      key
+    );
+  if (typeof callback === "function") {
+    defer(callback, compute);
+    return;
+  }
+  return compute();
+}
+
+export function verify(algorithm, data, key, signature, callback) {
+  const compute = () =>
+    verifyData(
+      algorithm == null ? undefined : hashName(algorithm),
+      toBuffer(data),
+      key,
+      toBuffer(signature)
+    );
+  if (typeof callback === "function") {
+    defer(callback, compute);
+    return;
+  }
+  return compute();
+}
+
+// Misc
+
+export function timingSafeEqual(a, b) {
+  const bufferA = toBuffer(a);
+  const bufferB = toBuffer(b);
+  if (bufferA.length !== bufferB.length) {
+    throw new RangeError("Input buffers must have the same byte length");
+  }
+  return globalThis.crypto.subtle.timingSafeEqual(bufferA, bufferB);
+}
+
+export const webcrypto = globalThis.crypto;
+export const subtle = globalThis.crypto.subtle;
+
+export default {
+  constants,
+  createHash,
+  createHmac,
+  getHashes,
+  getRandomValues,
+  randomUUID,
+  randomBytes,
+  randomInt,
+  pbkdf2,
+  pbkdf2Sync,
+  createCipheriv,
+  createDecipheriv,
+  getCiphers,
+  createSign,
+  createVerify,
+  sign,
+  verify,
+  timingSafeEqual,
+  webcrypto,
+  subtle,
+};
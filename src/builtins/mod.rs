@@ -4,11 +4,13 @@ use runtime::module::{init_global_module, init_module, StandardModules};
 pub mod cache;
 pub mod core;
 pub mod crypto;
+pub mod fs;
 pub mod internal_js_modules;
 pub mod js_globals;
 pub mod navigator;
 pub mod performance;
 pub mod process;
+pub mod structured_clone;
 
 pub struct Modules {
     pub include_internal: bool,
@@ -18,12 +20,14 @@ pub struct Modules {
 impl Modules {
     fn define_common(&self, cx: &Context, global: &ion::Object) -> bool {
         init_global_module::<modules::Assert>(cx, global)
-            && init_global_module::<modules::FileSystem>(cx, global)
+            && crate::egress::define(cx, global)
+            && fs::define(cx, global)
             && init_global_module::<modules::PathM>(cx, global)
             && init_global_module::<modules::UrlM>(cx, global)
             && performance::define(cx, global)
             && process::define(cx, global)
             && crypto::define(cx, global)
+            && structured_clone::define(cx, global)
             && cache::define(cx, global)
             && navigator::define(cx, global, self.hardware_concurrency)
     }
@@ -32,6 +36,7 @@ impl Modules {
 impl StandardModules for Modules {
     fn init(self, cx: &Context, global: &ion::Object) -> bool {
         let result = init_module::<core::CoreModule>(cx, global)
+            && init_module::<crypto::CryptoModule>(cx, global)
             && self.define_common(cx, global)
             && js_globals::define(cx);
 
@@ -2,20 +2,18 @@ use ion::{conversions::ToValue, flags::PropertyFlags, Context, Object};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Defines the environment variables allowed by the env policy on the given
+/// object. Secrets are defined as non-enumerable, so they're left out when
+/// the object is logged or serialized.
 pub fn populate_env_object(cx: &Context, env: &Object) -> bool {
-    for (name, value) in std::env::vars() {
-        // WINTERJS_* env vars are used to pass args to WinterJS itself, and are
-        // useless for JS code
-        if name.starts_with("WINTERJS_") {
-            continue;
-        }
+    for var in crate::env_vars::vars() {
+        let flags = if var.secret {
+            PropertyFlags::empty()
+        } else {
+            PropertyFlags::ENUMERATE
+        };
 
-        if !env.define(
-            cx,
-            name.as_str(),
-            &value.as_value(cx),
-            PropertyFlags::ENUMERATE,
-        ) {
+        if !env.define(cx, var.name.as_str(), &var.value.as_value(cx), flags) {
             return false;
         }
     }
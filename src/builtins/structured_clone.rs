@@ -0,0 +1,121 @@
+use std::{os::raw::c_void, ptr};
+
+use ion::{function_spec, ClassDefinition, Context, Local, Object, Result, Value};
+use mozjs::jsapi::{
+    CloneDataPolicy, Handle, JSStructuredCloneCallbacks, JSStructuredCloneReader,
+    JSStructuredCloneWriter, JS_StructuredClone, JS_WriteUint32Pair,
+};
+use mozjs_sys::jsapi::{JSContext, JSFunctionSpec, JSObject};
+
+use crate::{
+    builtins::crypto::{CryptoKey, KeyAlgorithm, KeyMaterial, KeyType, KeyUsage},
+    ion_mk_err,
+};
+
+// JS_SCTAG_USER_MIN, the first tag SpiderMonkey leaves to embedders
+const SCTAG_CRYPTO_KEY: u32 = 0xFFFF_8000;
+
+// The internal slots of a CryptoKey, copied out when the key is written
+// so the clone doesn't depend on the original staying alive
+struct ClonedKey {
+    extractable: bool,
+    algorithm: KeyAlgorithm,
+    key_type: KeyType,
+    usages: Vec<KeyUsage>,
+    key: KeyMaterial,
+}
+
+static CALLBACKS: JSStructuredCloneCallbacks = JSStructuredCloneCallbacks {
+    read: Some(read_callback),
+    write: Some(write_callback),
+    reportError: None,
+    readTransfer: None,
+    writeTransfer: None,
+    freeTransfer: None,
+    canTransfer: None,
+    sabCloned: None,
+};
+
+// Only called for objects SpiderMonkey can't clone by itself. Returning
+// false without an exception makes structuredClone throw the usual error.
+unsafe extern "C" fn write_callback(
+    cx: *mut JSContext,
+    w: *mut JSStructuredCloneWriter,
+    obj: Handle<*mut JSObject>,
+    _same_process_scope_required: *mut bool,
+    closure: *mut c_void,
+) -> bool {
+    let cx = Context::new_unchecked(cx);
+    let obj = Object::from(Local::from_marked(obj.ptr));
+    if !CryptoKey::instance_of(&cx, &obj) {
+        return false;
+    }
+
+    let key = CryptoKey::get_private(&cx, &obj).unwrap();
+    let keys = &mut *(closure as *mut Vec<ClonedKey>);
+    keys.push(ClonedKey {
+        extractable: key.extractable,
+        algorithm: key.algorithm.clone(),
+        key_type: key.key_type,
+        usages: key.usages.clone(),
+        key: key.key.clone(),
+    });
+    JS_WriteUint32Pair(w, SCTAG_CRYPTO_KEY, (keys.len() - 1) as u32)
+}
+
+unsafe extern "C" fn read_callback(
+    cx: *mut JSContext,
+    _r: *mut JSStructuredCloneReader,
+    _policy: *const CloneDataPolicy,
+    tag: u32,
+    data: u32,
+    closure: *mut c_void,
+) -> *mut JSObject {
+    let cx = Context::new_unchecked(cx);
+    let keys = &*(closure as *const Vec<ClonedKey>);
+    let Some(key) = keys.get(data as usize).filter(|_| tag == SCTAG_CRYPTO_KEY) else {
+        return ptr::null_mut();
+    };
+
+    match CryptoKey::new(
+        &cx,
+        key.extractable,
+        key.algorithm.clone(),
+        key.key_type,
+        key.usages.clone(),
+        key.key.clone(),
+    ) {
+        Ok(key) => CryptoKey::new_object(&cx, Box::new(key)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[js_fn]
+fn structured_clone<'cx>(cx: &'cx Context, value: Value<'cx>) -> Result<Value<'cx>> {
+    let mut keys = Vec::<ClonedKey>::new();
+    let mut result = Value::undefined(cx);
+    let cloned = unsafe {
+        JS_StructuredClone(
+            cx.as_ptr(),
+            value.handle().into(),
+            result.handle_mut().into(),
+            &CALLBACKS,
+            &mut keys as *mut Vec<ClonedKey> as *mut c_void,
+        )
+    };
+
+    if cloned {
+        Ok(result)
+    } else {
+        Err(ion_mk_err!("The value could not be cloned", Type))
+    }
+}
+
+const METHODS: &[JSFunctionSpec] = &[
+    function_spec!(structured_clone, "structuredClone", 1),
+    JSFunctionSpec::ZERO,
+];
+
+pub fn define(cx: &Context, global: &Object) -> bool {
+    unsafe { global.define_methods(cx, METHODS) }
+}
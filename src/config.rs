@@ -0,0 +1,241 @@
+//! The `winterjs.toml` config file for `winterjs serve`. Every setting in
+//! the file can also be given as a CLI flag or `WINTERJS_*` environment
+//! variable, which take precedence over the file:
+//!
+//! ```toml
+//! main = "src/index.js"
+//! mode = "cloudflare"
+//!
+//! [server]
+//! port = 8080
+//! max_js_threads = 8
+//!
+//! [permissions]
+//! allow_net = ["api.example.com"]
+//!
+//! [log]
+//! level = "winterjs=debug,warn"
+//! ```
+//!
+//! Relative paths are resolved against the directory the file lives in.
+
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{request_handlers::static_files::StaticConfig, HandlerName};
+
+pub const CONFIG_FILE_NAME: &str = "winterjs.toml";
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    /// The JS file or directory to serve.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub main: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<HandlerName>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serve_shims: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bindings: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wrangler_config: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wrangler_env: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workers: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apps: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apps_reload_interval: Option<u64>,
+
+    pub server: ServerSection,
+
+    #[serde(rename = "static")]
+    pub static_files: StaticConfig,
+
+    pub env: EnvSection,
+
+    pub permissions: PermissionsSection,
+
+    pub fetch: FetchSection,
+
+    pub log: LogSection,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<IpAddr>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_js_threads: Option<usize>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub single_threaded: Option<bool>,
+
+    /// In seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shutdown_timeout: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvSection {
+    pub allow: Vec<String>,
+
+    pub deny: Vec<String>,
+
+    pub files: Vec<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inherit: Option<bool>,
+}
+
+/// Like the `--allow-*` flags, an empty list grants access to everything.
+#[derive(Deserialize, Serialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PermissionsSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_read: Option<Vec<PathBuf>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_write: Option<Vec<PathBuf>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_net: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_all: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FetchSection {
+    /// In seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_timeout: Option<u64>,
+
+    /// In seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+
+    /// In bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_response_size: Option<u64>,
+
+    pub resolve: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deny_private_ips: Option<bool>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_subrequests: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mocks: Option<PathBuf>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mocks_strict: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSection {
+    /// A `RUST_LOG`-style filter, e.g. `winterjs=debug,warn`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<LogFormat>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    Full,
+    Compact,
+}
+
+/// The directory an app's config and env files live in. JS_PATH can be
+/// either the entrypoint itself, or a directory containing it.
+pub fn app_dir(js_path: &Path) -> &Path {
+    if js_path.is_dir() {
+        js_path
+    } else {
+        js_path.parent().unwrap_or(Path::new("."))
+    }
+}
+
+impl ConfigFile {
+    /// Looks for a `winterjs.toml` file next to the entrypoint, or in the
+    /// current directory if there is none.
+    pub fn discover(js_path: Option<&Path>) -> Option<PathBuf> {
+        let dir = js_path.map(app_dir).unwrap_or(Path::new("."));
+        let path = dir.join(CONFIG_FILE_NAME);
+        path.is_file().then_some(path)
+    }
+
+    pub fn try_parse(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let mut config = toml::from_str::<Self>(&file_content)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        config.resolve_paths(base_dir);
+        Ok(config)
+    }
+
+    fn resolve_paths(&mut self, base_dir: &Path) {
+        let resolve = |path: &mut PathBuf| *path = base_dir.join(&*path);
+        let resolve_all = |paths: &mut Vec<PathBuf>| paths.iter_mut().for_each(resolve);
+
+        for path in [
+            &mut self.main,
+            &mut self.bindings,
+            &mut self.wrangler_config,
+            &mut self.workers,
+            &mut self.apps,
+            &mut self.fetch.mocks,
+            &mut self.static_files.dir,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            resolve(path);
+        }
+
+        resolve_all(&mut self.env.files);
+        for paths in [
+            &mut self.permissions.allow_read,
+            &mut self.permissions.allow_write,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            resolve_all(paths);
+        }
+    }
+}
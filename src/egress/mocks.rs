@@ -0,0 +1,165 @@
+//! Canned responses for outbound requests, so apps can be tested without
+//! reaching third-party APIs. Mocks are declared in a JSON file passed in
+//! with `--fetch-mocks`, e.g.:
+//!
+//! ```json
+//! {
+//!   "mocks": [
+//!     { "method": "GET", "url": "https://api.example.com/users/*", "json": { "id": 1 } },
+//!     { "url": "https://api.example.com/**", "status": 503, "body": "Unavailable" }
+//!   ]
+//! }
+//! ```
+//!
+//! The first mock matching a request is used.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use hyper::Body;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct MockFile {
+    mocks: Vec<MockConfig>,
+}
+
+#[derive(Deserialize)]
+struct MockConfig {
+    /// Matches any method if left out.
+    method: Option<String>,
+
+    /// Glob pattern matched against the full URL. `*` doesn't match across
+    /// slashes, while `**` does.
+    url: String,
+
+    #[serde(default = "default_status")]
+    status: u16,
+
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+
+    body: Option<String>,
+
+    /// Serialized as the body, with a JSON content type.
+    json: Option<serde_json::Value>,
+
+    /// Path of a file to use as the body, relative to the mocks file.
+    body_file: Option<PathBuf>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+#[derive(Debug)]
+struct FetchMock {
+    method: Option<http::Method>,
+    url: String,
+    status: http::StatusCode,
+    headers: Vec<(String, String)>,
+    body: bytes::Bytes,
+}
+
+#[derive(Default, Debug)]
+pub struct FetchMocks {
+    mocks: Vec<FetchMock>,
+
+    /// Fail requests no mock matches, instead of sending them.
+    pub strict: bool,
+}
+
+impl FetchMocks {
+    pub fn try_parse(path: impl AsRef<Path>, strict: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fetch mocks file {}", path.display()))?;
+        let file = serde_json::from_str::<MockFile>(&file_content)
+            .with_context(|| format!("Failed to parse fetch mocks file {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+
+        let mocks = file
+            .mocks
+            .into_iter()
+            .map(|mock| {
+                FetchMock::new(mock, base_dir)
+                    .with_context(|| format!("Invalid fetch mock in {}", path.display()))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { mocks, strict })
+    }
+
+    /// Returns the mocked response for a request, or None if the request
+    /// should be sent over the network.
+    pub fn response_for(
+        &self,
+        method: &http::Method,
+        url: &str,
+    ) -> Option<Result<hyper::Response<Body>>> {
+        let mock = self.mocks.iter().find(|mock| {
+            mock.method.as_ref().map(|m| m == method).unwrap_or(true)
+                && glob_match::glob_match(&mock.url, url)
+        });
+
+        match mock {
+            Some(mock) => {
+                tracing::debug!(%method, url, "Responding with fetch mock");
+                Some(mock.response())
+            }
+            None if self.strict => {
+                Some(Err(anyhow::anyhow!("No fetch mock matches {method} {url}")))
+            }
+            None => None,
+        }
+    }
+}
+
+impl FetchMock {
+    fn new(config: MockConfig, base_dir: &Path) -> Result<Self> {
+        let mut headers = config.headers.into_iter().collect::<Vec<_>>();
+        let body = match (config.body, config.json, config.body_file) {
+            (None, None, None) => bytes::Bytes::new(),
+            (Some(body), None, None) => body.into(),
+            (None, Some(json), None) => {
+                if !headers
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                {
+                    headers.push(("content-type".to_string(), "application/json".to_string()));
+                }
+                serde_json::to_vec(&json)?.into()
+            }
+            (None, None, Some(file)) => {
+                let file = base_dir.join(file);
+                std::fs::read(&file)
+                    .with_context(|| format!("Failed to read mock body file {}", file.display()))?
+                    .into()
+            }
+            _ => bail!("Only one of `body`, `json` and `body_file` can be given"),
+        };
+
+        Ok(Self {
+            method: config
+                .method
+                .map(|m| m.to_ascii_uppercase().parse())
+                .transpose()
+                .context("Invalid method")?,
+            url: config.url,
+            status: http::StatusCode::from_u16(config.status).context("Invalid status")?,
+            headers,
+            body,
+        })
+    }
+
+    fn response(&self) -> Result<hyper::Response<Body>> {
+        let mut response = hyper::Response::builder().status(self.status);
+        for (name, value) in &self.headers {
+            response = response.header(name, value);
+        }
+        Ok(response.body(Body::from(self.body.clone()))?)
+    }
+}
@@ -0,0 +1,745 @@
+//! Outbound `fetch` requests. Requests are sent through a client owned by
+//! WinterJS rather than the runtime's global client, so outbound traffic
+//! can be controlled per deployment: connect and total timeouts, a maximum
+//! response size, `HTTP_PROXY`/`HTTPS_PROXY`/`NO_PROXY` support, DNS
+//! overrides, blocking of private address ranges to prevent SSRF, and a
+//! limit on the number of subrequests each request can make.
+//! Requests can also be answered with mocked responses for offline testing.
+
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    rc::Rc,
+    task::{Context as TaskContext, Poll},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use base64::Engine;
+use futures::future::{select, Either};
+use hyper::{body::HttpBody, service::Service, Body, Uri};
+use hyper_rustls::HttpsConnector;
+use ion::{
+    flags::PropertyFlags, function_spec, ClassDefinition, Context, Exception, Function, Object,
+    Promise, TracedHeap, Value,
+};
+use mozjs::jsval::JSVal;
+use mozjs_sys::jsapi::JSFunctionSpec;
+use once_cell::sync::OnceCell;
+use runtime::{
+    globals::fetch::{Request as FetchRequest, Response as FetchResponse},
+    promise::future_to_promise,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::Instant,
+};
+
+use crate::{ion_mk_err, sm_utils::error_report_option_to_anyhow_error};
+
+pub mod mocks;
+
+// Replaces the global `fetch` with one that sends each request through
+// `sendRequest`. Redirects are followed here rather than in native code, so
+// every hop goes through `sendRequest` again and is subject to the same
+// limits as the original request. `sendRequest` checks the net permission
+// for the URL of each request it sends. The globals used here are captured
+// up front, so JS code can't change how redirects are followed by replacing
+// them. Request bodies are buffered so they can be re-sent after a 307 or
+// 308 redirect, and credentials are dropped when a redirect leaves the
+// original origin. Each hop keeps the signal of the original request, and
+// `sendRequest` resolves without a response once that signal is aborted.
+const FETCH_SCRIPT: &str = r#"
+(function (sendRequest) {
+    const { Headers, Request, URL, TypeError } = globalThis;
+    const REDIRECT_STATUSES = [301, 302, 303, 307, 308];
+    const MAX_REDIRECTS = 20;
+    const CREDENTIAL_HEADERS = ['authorization', 'cookie', 'proxy-authorization'];
+
+    async function send(request) {
+        const response = await sendRequest(request);
+        if (response == null) {
+            throw request.signal.reason;
+        }
+        return response;
+    }
+
+    async function followRedirects(request, mode) {
+        const { signal } = request;
+        let url = request.url;
+        let method = request.method;
+        const headers = new Headers(request.headers);
+        let body = request.body === null ? null : await request.arrayBuffer();
+
+        for (let redirects = 0; ; redirects++) {
+            const response = await send(new Request(url, { method, headers, body, signal, redirect: 'manual' }));
+            if (!REDIRECT_STATUSES.includes(response.status)) {
+                return response;
+            }
+            const location = response.headers.get('location');
+            if (location === null) {
+                return response;
+            }
+            if (mode === 'error') {
+                throw new TypeError(`Request to ${url} was redirected to ${location}`);
+            }
+            if (redirects === MAX_REDIRECTS) {
+                throw new TypeError(`Too many redirects while fetching ${request.url}`);
+            }
+
+            const changesToGet = response.status === 303
+                ? method !== 'HEAD'
+                : response.status !== 307 && response.status !== 308 && method === 'POST';
+            if (changesToGet) {
+                method = 'GET';
+                body = null;
+                headers.delete('content-type');
+                headers.delete('content-length');
+            }
+
+            const next = new URL(location, url);
+            if (next.origin !== new URL(url).origin) {
+                for (const name of CREDENTIAL_HEADERS) {
+                    headers.delete(name);
+                }
+            }
+            url = next.href;
+        }
+    }
+
+    return function fetch(input, init) {
+        let request;
+        try {
+            request = new Request(input, init);
+        } catch (e) {
+            return Promise.reject(e);
+        }
+        if (request.signal.aborted) {
+            return Promise.reject(request.signal.reason);
+        }
+
+        const mode = request.redirect ?? init?.redirect ?? 'follow';
+        if (mode === 'manual') {
+            return send(request);
+        }
+        return followRedirects(request, mode);
+    };
+})
+"#;
+
+const CONNECT_RESPONSE_MAX_LENGTH: usize = 8 * 1024;
+
+static CONFIG: OnceCell<EgressConfig> = OnceCell::new();
+
+thread_local! {
+    static CLIENT: RefCell<Option<EgressClient>> = RefCell::new(None);
+
+    // Subrequest counts of the requests being handled on this thread, in
+    // the order they started. Empty while the app is initializing, when
+    // there is no limit.
+    static REQUESTS: RefCell<Vec<Rc<Cell<u32>>>> = RefCell::new(Vec::new());
+}
+
+// The runtime's AbortSignal doesn't dispatch abort events, so requests
+// check whether their signal was aborted at this interval instead
+const ABORT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type EgressClient = hyper::Client<HttpsConnector<EgressConnector>>;
+
+#[derive(Default, Debug)]
+pub struct EgressConfig {
+    pub connect_timeout: Option<Duration>,
+
+    /// Applies to the entire request, including reading the response body.
+    pub total_timeout: Option<Duration>,
+
+    pub max_response_size: Option<u64>,
+
+    pub resolve_overrides: Vec<ResolveOverride>,
+
+    /// Refuse to connect to loopback, private, link-local and other
+    /// non-public addresses. Addresses from `resolve_overrides` are exempt.
+    pub deny_private_ips: bool,
+
+    /// Maximum number of subrequests per incoming request.
+    pub max_subrequests: Option<u32>,
+
+    pub proxy: ProxyConfig,
+
+    /// Canned responses returned instead of sending requests over the
+    /// network.
+    pub mocks: Option<mocks::FetchMocks>,
+}
+
+/// A DNS override in `host:ip` or `host:port:ip` format, like curl's
+/// `--resolve`.
+#[derive(Debug)]
+pub struct ResolveOverride {
+    host: String,
+    port: Option<u16>,
+    addr: IpAddr,
+}
+
+impl ResolveOverride {
+    pub fn parse(value: &str) -> Result<Self> {
+        let invalid = || format!("Invalid DNS override '{value}', expected host[:port]:ip");
+        let (host, rest) = value.split_once(':').with_context(invalid)?;
+        let (port, addr) = match parse_ip(rest) {
+            Some(addr) => (None, addr),
+            None => {
+                let (port, addr) = rest.split_once(':').with_context(invalid)?;
+                (
+                    Some(port.parse().with_context(invalid)?),
+                    parse_ip(addr).with_context(invalid)?,
+                )
+            }
+        };
+        if host.is_empty() {
+            bail!(invalid());
+        }
+
+        Ok(Self {
+            host: host.to_ascii_lowercase(),
+            port,
+            addr,
+        })
+    }
+}
+
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .unwrap_or(value)
+        .parse()
+        .ok()
+}
+
+#[derive(Default, Debug)]
+pub struct ProxyConfig {
+    http: Option<Uri>,
+    https: Option<Uri>,
+    no_proxy: Vec<String>,
+}
+
+impl ProxyConfig {
+    /// Reads the proxy configuration from the standard `HTTP_PROXY`,
+    /// `HTTPS_PROXY` and `NO_PROXY` environment variables, accepting their
+    /// lowercase forms as well.
+    pub fn from_env() -> Result<Self> {
+        fn var(name: &str) -> Option<String> {
+            std::env::var(name)
+                .or_else(|_| std::env::var(name.to_ascii_lowercase()))
+                .ok()
+                .filter(|v| !v.is_empty())
+        }
+
+        fn proxy_uri(name: &str) -> Result<Option<Uri>> {
+            let Some(value) = var(name) else {
+                return Ok(None);
+            };
+            let uri = value
+                .parse::<Uri>()
+                .with_context(|| format!("Invalid proxy URL in {name}"))?;
+            match uri.scheme_str() {
+                Some("http") | None => Ok(Some(uri)),
+                Some(scheme) => bail!("Unsupported proxy scheme {scheme} in {name}"),
+            }
+        }
+
+        Ok(Self {
+            http: proxy_uri("HTTP_PROXY")?,
+            https: proxy_uri("HTTPS_PROXY")?,
+            no_proxy: var("NO_PROXY")
+                .map(|v| {
+                    v.split(',')
+                        .map(|entry| entry.trim().to_ascii_lowercase())
+                        .filter(|entry| !entry.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    fn proxy_for(&self, scheme: &str, host: &str) -> Option<&Uri> {
+        let proxy = match scheme {
+            "https" => self.https.as_ref(),
+            _ => self.http.as_ref(),
+        }?;
+
+        let bypass = self.no_proxy.iter().any(|entry| {
+            // Entries may come with a port, which we don't take into account
+            let entry = match entry.rsplit_once(':') {
+                Some((entry_host, port)) if port.parse::<u16>().is_ok() => entry_host,
+                _ => entry.as_str(),
+            };
+            let entry = entry.trim_start_matches("*.").trim_start_matches('.');
+            entry == "*"
+                || host == entry
+                || host
+                    .strip_suffix(entry)
+                    .map(|prefix| prefix.ends_with('.'))
+                    .unwrap_or(false)
+        });
+        (!bypass).then_some(proxy)
+    }
+}
+
+/// Sets the egress configuration. Must be called before any JS threads are
+/// started.
+pub fn init(config: EgressConfig) -> Result<()> {
+    if CONFIG.set(config).is_err() {
+        bail!("Egress configuration was already initialized");
+    }
+    Ok(())
+}
+
+fn config() -> &'static EgressConfig {
+    CONFIG.get_or_init(Default::default)
+}
+
+/// Counts the subrequests made while handling one incoming request, so
+/// each request is held to the limit on its own. Subrequests are counted
+/// against the latest request that's still being handled on the thread
+/// when `fetch` is called. The request stops counting once this is dropped.
+pub struct RequestSubrequests(Option<Rc<Cell<u32>>>);
+
+impl RequestSubrequests {
+    /// Called when the current thread starts handling a request.
+    pub fn start() -> Self {
+        if config().max_subrequests.is_none() {
+            return Self(None);
+        }
+        let count = Rc::new(Cell::new(0));
+        REQUESTS.with(|requests| requests.borrow_mut().push(count.clone()));
+        Self(Some(count))
+    }
+}
+
+impl Drop for RequestSubrequests {
+    fn drop(&mut self) {
+        if let Some(count) = self.0.take() {
+            REQUESTS.with(|requests| {
+                requests
+                    .borrow_mut()
+                    .retain(|other| !Rc::ptr_eq(other, &count))
+            });
+        }
+    }
+}
+
+fn current_request() -> Option<Rc<Cell<u32>>> {
+    REQUESTS.with(|requests| requests.borrow().last().cloned())
+}
+
+fn take_subrequest(count: Option<&Cell<u32>>) -> ion::Result<()> {
+    let (Some(count), Some(max)) = (count, config().max_subrequests) else {
+        return Ok(());
+    };
+    if count.get() >= max {
+        return Err(ion_mk_err!(
+            "Too many subrequests made while handling this request",
+            Normal
+        ));
+    }
+    count.set(count.get() + 1);
+    Ok(())
+}
+
+fn client() -> Result<EgressClient> {
+    CLIENT.with(|client| {
+        let mut client = client.borrow_mut();
+        if let Some(client) = client.as_ref() {
+            return Ok(client.clone());
+        }
+
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .context("Failed to load root certificates")?
+            .https_or_http()
+            .enable_http1()
+            .wrap_connector(EgressConnector);
+        let new_client = hyper::Client::builder().build(connector);
+        *client = Some(new_client.clone());
+        Ok(new_client)
+    })
+}
+
+/// Connects to the host of a request, either directly or through a CONNECT
+/// tunnel if a proxy is configured. TLS is handled by the wrapping
+/// `HttpsConnector`.
+#[derive(Clone)]
+struct EgressConnector;
+
+impl Service<Uri> for EgressConnector {
+    type Response = TcpStream;
+    type Error = anyhow::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<TcpStream>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        Box::pin(connect(uri))
+    }
+}
+
+async fn connect(uri: Uri) -> Result<TcpStream> {
+    let config = config();
+    let scheme = uri.scheme_str().unwrap_or("http");
+    let host = uri
+        .host()
+        .context("Request URL has no host")?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    let port = uri
+        .port_u16()
+        .unwrap_or(if scheme == "https" { 443 } else { 80 });
+
+    match config.proxy.proxy_for(scheme, &host) {
+        Some(proxy) => {
+            // The proxy resolves the host, so only literal addresses can be
+            // checked here
+            if let Ok(addr) = host.parse::<IpAddr>() {
+                check_addr(config, addr)?;
+            }
+            connect_through_proxy(config, proxy, &host, port).await
+        }
+        None => {
+            let addrs = resolve(config, &host, port).await?;
+            connect_to_any(config, &addrs).await
+        }
+    }
+}
+
+async fn resolve(config: &EgressConfig, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let overridden = config
+        .resolve_overrides
+        .iter()
+        .find(|o| o.host == host && o.port.map(|p| p == port).unwrap_or(true));
+    if let Some(o) = overridden {
+        return Ok(vec![SocketAddr::new(o.addr, port)]);
+    }
+
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("Failed to resolve {host}"))?
+        .collect::<Vec<_>>();
+    let allowed = addrs
+        .iter()
+        .copied()
+        .filter(|addr| check_addr(config, addr.ip()).is_ok())
+        .collect::<Vec<_>>();
+    if allowed.is_empty() && !addrs.is_empty() {
+        bail!("Connecting to {host} is not allowed, since it resolves to a private address");
+    }
+    Ok(allowed)
+}
+
+async fn connect_to_any(config: &EgressConfig, addrs: &[SocketAddr]) -> Result<TcpStream> {
+    let mut last_error = anyhow!("No addresses to connect to");
+    for addr in addrs {
+        match with_timeout(config.connect_timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => {
+                _ = stream.set_nodelay(true);
+                return Ok(stream);
+            }
+            Ok(Err(e)) => last_error = anyhow!(e).context(format!("Failed to connect to {addr}")),
+            Err(_) => last_error = anyhow!("Timed out connecting to {addr}"),
+        }
+    }
+    Err(last_error)
+}
+
+async fn connect_through_proxy(
+    config: &EgressConfig,
+    proxy: &Uri,
+    host: &str,
+    port: u16,
+) -> Result<TcpStream> {
+    let proxy_host = proxy.host().context("Proxy URL has no host")?;
+    let proxy_port = proxy.port_u16().unwrap_or(80);
+    let addrs = tokio::net::lookup_host((proxy_host, proxy_port))
+        .await
+        .with_context(|| format!("Failed to resolve proxy {proxy_host}"))?
+        .collect::<Vec<_>>();
+    let mut stream = connect_to_any(config, &addrs).await?;
+
+    let authority = match host.parse::<Ipv6Addr>() {
+        Ok(_) => format!("[{host}]:{port}"),
+        Err(_) => format!("{host}:{port}"),
+    };
+    let mut connect_request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if let Some((credentials, _)) = proxy.authority().and_then(|a| a.as_str().rsplit_once('@')) {
+        let credentials = base64::prelude::BASE64_STANDARD.encode(credentials);
+        connect_request.push_str(&format!("Proxy-Authorization: Basic {credentials}\r\n"));
+    }
+    connect_request.push_str("\r\n");
+
+    let handshake = async {
+        stream.write_all(connect_request.as_bytes()).await?;
+
+        // Read the response byte by byte, so nothing past the end of the
+        // headers is consumed from the tunnel
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() >= CONNECT_RESPONSE_MAX_LENGTH {
+                bail!("Proxy response is too long");
+            }
+            response.push(stream.read_u8().await?);
+        }
+        Ok::<_, anyhow::Error>(response)
+    };
+    let response = with_timeout(config.connect_timeout, handshake)
+        .await
+        .map_err(|_| anyhow!("Timed out connecting through proxy"))??;
+
+    let status_line = String::from_utf8_lossy(&response);
+    let status = status_line
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or_default();
+    if status != "200" {
+        bail!("Proxy refused to connect to {authority} with status {status}");
+    }
+    Ok(stream)
+}
+
+fn check_addr(config: &EgressConfig, addr: IpAddr) -> Result<()> {
+    if config.deny_private_ips && !is_public(addr) {
+        bail!("Connecting to private address {addr} is not allowed");
+    }
+    Ok(())
+}
+
+fn is_public(addr: IpAddr) -> bool {
+    fn is_public_v4(addr: Ipv4Addr) -> bool {
+        let [a, b, ..] = addr.octets();
+        !(addr.is_private()
+            || addr.is_loopback()
+            || addr.is_link_local()
+            || addr.is_unspecified()
+            || addr.is_broadcast()
+            || addr.is_multicast()
+            || a == 0
+            // Shared address space (RFC 6598)
+            || (a == 100 && (64..128).contains(&b)))
+    }
+
+    match addr {
+        IpAddr::V4(addr) => is_public_v4(addr),
+        IpAddr::V6(addr) => {
+            if let Some(mapped) = addr.to_ipv4_mapped() {
+                return is_public_v4(mapped);
+            }
+            let first = addr.segments()[0];
+            !(addr.is_loopback()
+                || addr.is_unspecified()
+                || addr.is_multicast()
+                // Unique local (fc00::/7) and link-local (fe80::/10)
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+async fn with_timeout<F: Future>(
+    timeout: Option<Duration>,
+    future: F,
+) -> Result<F::Output, tokio::time::error::Elapsed> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await,
+        None => Ok(future.await),
+    }
+}
+
+async fn send(request: hyper::Request<Body>) -> Result<hyper::Response<Body>> {
+    let config = config();
+
+    if let Some(mocks) = &config.mocks {
+        if let Some(response) = mocks.response_for(request.method(), &request.uri().to_string()) {
+            return response;
+        }
+    }
+    let deadline = config.total_timeout.map(|t| Instant::now() + t);
+
+    let response = client()?.request(request);
+    let response = match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, response)
+            .await
+            .map_err(|_| anyhow!("Request timed out"))??,
+        None => response.await?,
+    };
+
+    if let Some(max) = config.max_response_size {
+        let content_length = response
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok());
+        if content_length.map(|len| len > max).unwrap_or(false) {
+            bail!("Response is larger than the maximum of {max} bytes");
+        }
+    }
+
+    if deadline.is_none() && config.max_response_size.is_none() {
+        return Ok(response);
+    }
+    let (parts, body) = response.into_parts();
+    Ok(hyper::Response::from_parts(
+        parts,
+        limit_body(body, config.max_response_size, deadline),
+    ))
+}
+
+/// Wraps a response body so reading it fails once it grows past the
+/// maximum response size or the deadline of the request passes.
+fn limit_body(body: Body, max_size: Option<u64>, deadline: Option<Instant>) -> Body {
+    let stream = futures::stream::unfold(Some((body, 0u64)), move |state| async move {
+        let (mut body, read) = state?;
+        let chunk = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, body.data()).await {
+                Ok(chunk) => chunk,
+                Err(_) => return Some((Err(anyhow!("Request timed out")), None)),
+            },
+            None => body.data().await,
+        };
+
+        match chunk? {
+            Ok(chunk) => {
+                let read = read + chunk.len() as u64;
+                match max_size {
+                    Some(max) if read > max => Some((
+                        Err(anyhow!(
+                            "Response is larger than the maximum of {max} bytes"
+                        )),
+                        None,
+                    )),
+                    _ => Some((Ok(chunk), Some((body, read)))),
+                }
+            }
+            Err(e) => Some((Err(e.into()), None)),
+        }
+    });
+    Body::wrap_stream(stream)
+}
+
+#[js_fn]
+fn send_request<'cx>(cx: &'cx Context, request: Object<'cx>) -> Option<Promise> {
+    let request_heap = TracedHeap::new((*request).get());
+    let signal = request
+        .get(cx, "signal")
+        .ok()
+        .flatten()
+        .filter(|signal| signal.handle().is_object())
+        .map(|signal| TracedHeap::new(signal.get()));
+    let subrequests = current_request();
+
+    unsafe {
+        future_to_promise::<_, _, _, Exception>(cx, move |cx| async move {
+            let request = FetchRequest::get_mut_private(&cx, &request_heap.root(&cx).into())
+                .ok_or_else(|| ion_mk_err!("Expected a Request object", Type))?;
+
+            let url_string = request.get_url().to_string();
+            let url = url::Url::parse(&url_string)?;
+            crate::permissions::check_net(&url)?;
+            let mut http_req = http::Request::builder()
+                .uri(url_string.as_str())
+                .method(request.method());
+
+            for header in request.headers(&cx) {
+                http_req = http_req.header(header.0.clone(), header.1.clone())
+            }
+
+            let request_body = request.take_body()?;
+            let (mut cx, body_bytes) = cx.await_native_cx(|cx| request_body.into_bytes(cx)).await;
+            let body = match body_bytes? {
+                Some(bytes) => Body::from(bytes),
+                None => Body::empty(),
+            };
+            let http_req = http_req.body(body)?;
+
+            if let Some(signal) = &signal {
+                if is_aborted(&cx, signal)? {
+                    return Ok(None);
+                }
+            }
+            take_subrequest(subrequests.as_deref())?;
+
+            let mut response = Box::pin(send(http_req));
+            let response = match &signal {
+                None => {
+                    let (next_cx, response) = cx.await_native(response).await;
+                    cx = next_cx;
+                    response
+                }
+                Some(signal) => loop {
+                    let poll = Box::pin(tokio::time::sleep(ABORT_POLL_INTERVAL));
+                    let (next_cx, result) = cx.await_native(select(response, poll)).await;
+                    cx = next_cx;
+                    match result {
+                        Either::Left((response, _)) => break response,
+                        Either::Right((_, pending)) => {
+                            // Dropping the pending request cancels it
+                            if is_aborted(&cx, signal)? {
+                                return Ok(None);
+                            }
+                            response = pending;
+                        }
+                    }
+                },
+            };
+            let response =
+                response.map_err(|e| ion_mk_err!(format!("Failed to fetch {url}: {e:#}"), Type))?;
+            let response = FetchResponse::from_hyper_response(&cx, response, url)?;
+            Ok(Some(FetchResponse::new_object(&cx, Box::new(response))))
+        })
+    }
+}
+
+fn is_aborted(cx: &Context, signal: &TracedHeap<JSVal>) -> ion::Result<bool> {
+    let signal = Value::from(signal.root(cx)).to_object(cx);
+    Ok(signal
+        .get(cx, "aborted")?
+        .map(|aborted| aborted.handle().to_boolean())
+        .unwrap_or(false))
+}
+
+static SEND_REQUEST: JSFunctionSpec = function_spec!(send_request, "sendRequest", 1);
+
+/// Replaces the runtime's `fetch` with one that sends requests through the
+/// egress client.
+pub fn define(cx: &Context, global: &Object) -> bool {
+    let result = crate::sm_utils::evaluate_script(cx, FETCH_SCRIPT, "fetch.js").and_then(|setup| {
+        let setup = Function::from_object(cx, &setup.to_object(cx))
+            .context("Fetch script did not evaluate to a function")?;
+        let send_request = Function::from_spec(cx, &SEND_REQUEST);
+        setup
+            .call(
+                cx,
+                global,
+                &[Value::object(cx, &send_request.to_object(cx))],
+            )
+            .map_err(|e| error_report_option_to_anyhow_error(cx, e))
+    });
+
+    match result {
+        Ok(fetch) if fetch.handle().is_object() => {
+            global.define(cx, "fetch", &fetch, PropertyFlags::ENUMERATE)
+        }
+        Ok(_) => {
+            tracing::error!("Internal error: fetch script did not return a function");
+            false
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to set up fetch");
+            false
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Decides which environment variables are exposed to JS code through
+//! `process.env` and the `env` object in Cloudflare mode. By default, none
+//! of the host's environment is passed through; variables have to be
+//! allowed explicitly, or loaded from env files. Values loaded from env
+//! files are treated as secrets, and are defined as non-enumerable
+//! properties so they don't show up when the object is logged or
+//! serialized.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use once_cell::sync::OnceCell;
+
+/// Env files picked up from the app's directory if none are given
+/// explicitly, in order of preference. Like wrangler, only the first one
+/// found is loaded.
+const DEFAULT_ENV_FILES: &[&str] = &[".dev.vars", ".env"];
+
+static ENV_VARS: OnceCell<Vec<EnvVar>> = OnceCell::new();
+
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+    pub secret: bool,
+}
+
+#[derive(Default, Debug)]
+pub struct EnvPolicy {
+    /// Glob patterns of host variables to pass through.
+    pub allow: Vec<String>,
+
+    /// Glob patterns of host variables to never pass through, even if they
+    /// match an allow pattern or `inherit_all` is set.
+    pub deny: Vec<String>,
+
+    /// Pass through the entire host environment, except for `WINTERJS_*`
+    /// variables and the ones matching a deny pattern.
+    pub inherit_all: bool,
+
+    /// Env files to load. If empty, the default env files are looked up in
+    /// the app's directory instead.
+    pub env_files: Vec<PathBuf>,
+}
+
+impl EnvPolicy {
+    fn is_denied(&self, name: &str) -> bool {
+        // WINTERJS_* env vars are used to pass args to WinterJS itself, and are
+        // useless for JS code
+        name.starts_with("WINTERJS_") || self.deny.iter().any(|p| glob_match::glob_match(p, name))
+    }
+
+    fn is_allowed(&self, name: &str) -> bool {
+        self.inherit_all || self.allow.iter().any(|p| glob_match::glob_match(p, name))
+    }
+}
+
+/// Resolves the variables exposed to JS code. Must be called before any JS
+/// threads are started; until then, no variables are exposed.
+pub fn init(policy: EnvPolicy, app_dir: &Path) -> Result<()> {
+    let mut vars = std::env::vars()
+        .filter(|(name, _)| !policy.is_denied(name) && policy.is_allowed(name))
+        .map(|(name, value)| EnvVar {
+            name,
+            value,
+            secret: false,
+        })
+        .collect::<Vec<_>>();
+
+    let env_files = if policy.env_files.is_empty() {
+        DEFAULT_ENV_FILES
+            .iter()
+            .map(|name| app_dir.join(name))
+            .find(|path| path.is_file())
+            .into_iter()
+            .collect()
+    } else {
+        policy.env_files
+    };
+
+    for path in env_files {
+        tracing::info!("Loading secrets from {}", path.display());
+        for (name, value) in parse_env_file(&path)? {
+            vars.retain(|v| v.name != name);
+            vars.push(EnvVar {
+                name,
+                value,
+                secret: true,
+            });
+        }
+    }
+
+    if ENV_VARS.set(vars).is_err() {
+        bail!("Environment variables were already initialized");
+    }
+    Ok(())
+}
+
+pub fn vars() -> &'static [EnvVar] {
+    ENV_VARS.get().map(|v| v.as_slice()).unwrap_or_default()
+}
+
+/// Parses a dotenv-style file of `NAME=value` lines. Values can be wrapped
+/// in single quotes to be taken literally, or in double quotes to allow
+/// escape sequences such as `\n`.
+fn parse_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read env file {}", path.display()))?;
+
+    let mut result = vec![];
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let Some((name, value)) = line.split_once('=') else {
+            bail!(
+                "Invalid line {} in env file {}: expected NAME=value",
+                index + 1,
+                path.display()
+            );
+        };
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!(
+                "Invalid variable name on line {} in env file {}",
+                index + 1,
+                path.display()
+            );
+        }
+
+        let value = parse_value(value.trim()).with_context(|| {
+            format!(
+                "Invalid value on line {} in env file {}",
+                index + 1,
+                path.display()
+            )
+        })?;
+        result.push((name.to_string(), value));
+    }
+
+    Ok(result)
+}
+
+fn parse_value(value: &str) -> Result<String> {
+    if let Some(rest) = value.strip_prefix('\'') {
+        let Some(end) = rest.find('\'') else {
+            bail!("Unterminated single quote");
+        };
+        return Ok(rest[..end].to_string());
+    }
+
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut result = String::new();
+        let mut chars = rest.chars();
+        loop {
+            match chars.next() {
+                None => bail!("Unterminated double quote"),
+                Some('"') => return Ok(result),
+                Some('\\') => match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some(c) => result.push(c),
+                    None => bail!("Unterminated double quote"),
+                },
+                Some(c) => result.push(c),
+            }
+        }
+    }
+
+    // Unquoted values end at the start of a comment
+    let value = match value.find(" #") {
+        Some(index) => &value[..index],
+        None => value,
+    };
+    Ok(value.trim_end().to_string())
+}
@@ -4,25 +4,40 @@
 
 use std::{
     net::{IpAddr, SocketAddr},
-    path::PathBuf,
+    path::{Path, PathBuf},
     pin::Pin,
+    time::Duration,
 };
 
-#[cfg(not(target_os = "wasi"))]
-use std::time::Duration;
-
 use anyhow::Context as _;
 use clap::{Parser, ValueEnum};
 use request_handlers::{
-    cloudflare::CloudflareRequestHandler, wintercg::WinterCGRequestHandler, Either, UserCode,
+    cloudflare::{
+        bindings::BindingsConfig, workers::WorkersConfig, wrangler::WranglerConfig,
+        CloudflareRequestHandler,
+    },
+    static_files::StaticConfig,
+    wintercg::WinterCGRequestHandler,
+    Either, UserCode,
 };
 
-use server::BoxedDynRunner;
+use config::{
+    ConfigFile, EnvSection, FetchSection, LogFormat, LogSection, PermissionsSection, ServerSection,
+};
+use egress::{mocks::FetchMocks, EgressConfig, ProxyConfig, ResolveOverride};
+use permissions::{Grant, NetGrant, Permissions};
+use server::{BoxedDynRunner, Handler};
 use tokio::{join, task::LocalSet};
 
 #[macro_use]
 extern crate ion_proc;
 
+const DEFAULT_LOG_FILTER: &str = "winterjs=info,warn";
+const DEFAULT_MAX_JS_THREADS: usize = 16;
+const DEFAULT_APPS_RELOAD_INTERVAL: u64 = 5;
+#[cfg_attr(target_os = "wasi", allow(unused))]
+const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 60;
+
 #[allow(unused_macros)]
 macro_rules! fail_msg {
     ($cx:expr, $msg:expr) => {
@@ -45,7 +60,12 @@ macro_rules! js_try {
     };
 }
 
+mod apps;
 mod builtins;
+mod config;
+mod egress;
+mod env_vars;
+mod permissions;
 mod request_handlers;
 mod runners;
 mod server;
@@ -57,14 +77,23 @@ fn main() {
     }
 }
 
-fn run() -> Result<(), anyhow::Error> {
-    // Initialize logging.
-    if std::env::var("RUST_LOG").is_err() {
-        // Set default log level.
-        std::env::set_var("RUST_LOG", "winterjs=info,warn");
+fn init_logging(filter: Option<&str>, format: Option<LogFormat>) -> anyhow::Result<()> {
+    let filter = match filter {
+        Some(filter) => tracing_subscriber::EnvFilter::try_new(filter)
+            .with_context(|| format!("Invalid log level: '{filter}'"))?,
+        None => tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(DEFAULT_LOG_FILTER)),
+    };
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match format.unwrap_or(LogFormat::Full) {
+        LogFormat::Full => builder.init(),
+        LogFormat::Compact => builder.compact().init(),
     }
-    tracing_subscriber::fmt::init();
+    Ok(())
+}
 
+fn run() -> Result<(), anyhow::Error> {
     let args = match Args::try_parse() {
         Ok(a) => a,
         Err(err1) => {
@@ -83,85 +112,185 @@ fn run() -> Result<(), anyhow::Error> {
 
     match args.cmd {
         Cmd::Exec(cmd) => {
+            init_logging(None, None)?;
+
             runtime::config::CONFIG
                 .set(runtime::config::Config::default().log_level(runtime::config::LogLevel::Error))
                 .unwrap();
 
+            let app_dir = config::app_dir(&cmd.js_path);
+            env_vars::init(cmd.env.into_policy(), app_dir)?;
+            permissions::init(cmd.permissions.into_permissions(true)?)?;
+            egress::init(cmd.egress.into_config()?)?;
+
             runners::exec::exec_script(cmd.js_path, cmd.script)
         }
 
-        Cmd::Serve(cmd) => {
-            let interface = if let Some(iface) = cmd.ip {
-                iface
-            } else if let Ok(value) = std::env::var("LISTEN_IP") {
-                value
-                    .parse()
-                    .context(format!("Invalid interface in LISTEN_IP:  '{value}'"))?
-            } else {
-                std::net::Ipv4Addr::UNSPECIFIED.into()
-            };
+        Cmd::Config(CmdConfig {
+            cmd: ConfigCmd::Print(mut cmd),
+        }) => {
+            cmd.load_config_file()?;
+            let config = cmd.effective_config()?;
+            print!("{}", toml::to_string_pretty(&config)?);
+            Ok(())
+        }
 
-            let port = if let Some(port) = cmd.port {
-                port
-            } else if let Ok(value) = std::env::var("PORT") {
-                value
-                    .parse()
-                    .context(format!("Invalid port in PORT:  '{value}'"))?
-            } else {
-                8080
-            };
+        Cmd::Serve(mut cmd) => {
+            cmd.load_config_file()?;
+            init_logging(cmd.log_level.as_deref(), cmd.log_format)?;
+            request_handlers::static_files::init(cmd.static_files.clone())?;
 
-            let addr: SocketAddr = (interface, port).into();
+            let addr = cmd.listen_addr()?;
+            request_handlers::serve::init(addr, cmd.serve_shims)?;
             let config = crate::server::ServerConfig { addr };
+            let max_js_threads = cmd.max_js_threads.unwrap_or(DEFAULT_MAX_JS_THREADS);
 
             runtime::config::CONFIG
                 .set(runtime::config::Config::default().log_level(runtime::config::LogLevel::Error))
                 .unwrap();
 
-            let user_code = UserCode::from_path(&cmd.js_path, cmd.script)?;
-
             let runner: Either<
-                BoxedDynRunner,
+                Handler,
                 (
                     BoxedDynRunner,
                     Pin<Box<dyn runners::inline::InlineRunnerRequestHandlerFuture>>,
                 ),
-            > = match (cmd.mode, cmd.single_threaded) {
-                (Some(HandlerName::Cloudflare), false) => {
-                    tracing::info!("Starting in Cloudflare mode");
-                    Either::Left(Box::new(
-                        runners::single::SingleRunner::new_request_handler(
-                            CloudflareRequestHandler,
-                            cmd.max_js_threads,
-                            user_code,
-                        ),
-                    ))
+            > = if let Some(ref apps_path) = cmd.apps {
+                let app_dir = apps_path.parent().unwrap_or(Path::new("."));
+                env_vars::init(cmd.env.into_policy(), app_dir)?;
+                permissions::init(cmd.permissions.into_permissions(false)?)?;
+                egress::init(cmd.egress.into_config()?)?;
+
+                tracing::info!("Starting in multi-app mode");
+                Either::Left(Handler::Apps(apps::Apps::load(
+                    apps_path.clone(),
+                    max_js_threads,
+                )?))
+            } else if let Some(ref workers_path) = cmd.workers {
+                let app_dir = workers_path.parent().unwrap_or(Path::new("."));
+                env_vars::init(cmd.env.into_policy(), app_dir)?;
+                permissions::init(cmd.permissions.into_permissions(false)?)?;
+                egress::init(cmd.egress.into_config()?)?;
+
+                Either::Left(Handler::Runner(start_workers(
+                    workers_path,
+                    max_js_threads,
+                )?))
+            } else {
+                let wrangler_config_path = match cmd.wrangler_config {
+                    Some(ref path) => Some(path.clone()),
+                    None if cmd.js_path.is_none() => WranglerConfig::discover("."),
+                    None => None,
+                };
+                let wrangler_config = wrangler_config_path
+                    .as_ref()
+                    .map(|path| WranglerConfig::try_parse(path, cmd.wrangler_env.as_deref()))
+                    .transpose()?;
+                if cmd.wrangler_env.is_some() && wrangler_config.is_none() {
+                    anyhow::bail!("--wrangler-env can only be used with a wrangler config");
                 }
-                (Some(HandlerName::Cloudflare), true) => {
-                    tracing::info!("Starting in Cloudflare mode");
-                    let (runner, future) = runners::inline::InlineRunner::new_request_handler(
-                        CloudflareRequestHandler,
-                        user_code,
+
+                // A wrangler config implies Cloudflare mode
+                let mode = match (cmd.mode, &wrangler_config) {
+                    (Some(mode), _) => Some(mode),
+                    (None, Some(_)) => Some(HandlerName::Cloudflare),
+                    (None, None) => None,
+                };
+                let is_cloudflare_mode = matches!(mode, Some(HandlerName::Cloudflare));
+                if is_cloudflare_mode && cmd.static_files.dir.is_some() {
+                    tracing::warn!(
+                        "The static directory is only used in WinterCG mode, serve a directory \
+                        with a _worker.js file to serve static files in Cloudflare mode"
                     );
-                    Either::Right((Box::new(runner), Box::pin(future)))
                 }
-                (Some(HandlerName::WinterCG) | None, false) => {
-                    tracing::info!("Starting in WinterCG mode");
-                    Either::Left(Box::new(
-                        runners::single::SingleRunner::new_request_handler(
+                if wrangler_config.is_some() && !is_cloudflare_mode {
+                    tracing::warn!(
+                    "Only the `main` entry of the wrangler config is used outside Cloudflare mode"
+                );
+                }
+
+                let js_path = match (cmd.js_path, wrangler_config.as_ref()) {
+                    (Some(path), _) => path,
+                    (
+                        None,
+                        Some(WranglerConfig {
+                            main: Some(main), ..
+                        }),
+                    ) => main.clone(),
+                    (None, _) => anyhow::bail!(
+                        "No JS_PATH given, and no wrangler config with a `main` entry was found"
+                    ),
+                };
+                let user_code = UserCode::from_path(&js_path, cmd.script)?;
+
+                let app_dir = match wrangler_config_path.as_deref() {
+                    Some(path) => path.parent().unwrap_or(Path::new(".")),
+                    None => config::app_dir(&js_path),
+                };
+                env_vars::init(cmd.env.into_policy(), app_dir)?;
+                permissions::init(cmd.permissions.into_permissions(false)?)?;
+                egress::init(cmd.egress.into_config()?)?;
+
+                let bindings_config = match (cmd.bindings, wrangler_config) {
+                    (Some(_), Some(_)) => {
+                        anyhow::bail!("--bindings cannot be used together with a wrangler config")
+                    }
+                    (Some(ref path), None) => {
+                        if !is_cloudflare_mode {
+                            tracing::warn!(
+                                "Bindings are only supported in Cloudflare mode, ignoring"
+                            );
+                        }
+                        BindingsConfig::try_parse(path)?
+                    }
+                    (None, Some(config)) => config.bindings,
+                    (None, None) => BindingsConfig::default(),
+                };
+                if !bindings_config.services.is_empty() {
+                    tracing::warn!("Service bindings are only available with --workers, ignoring");
+                }
+                let cloudflare_handler = CloudflareRequestHandler::new(bindings_config);
+
+                match (mode, cmd.single_threaded) {
+                    (Some(HandlerName::Cloudflare), false) => {
+                        tracing::info!("Starting in Cloudflare mode");
+                        let runner = runners::single::SingleRunner::new_request_handler(
+                            cloudflare_handler.clone(),
+                            max_js_threads,
+                            user_code,
+                        );
+                        runners::durable_objects::set_dispatcher(Box::new(runner.clone()));
+                        cloudflare_handler.restore_durable_object_alarms()?;
+                        Either::Left(Handler::Runner(Box::new(runner)))
+                    }
+                    (Some(HandlerName::Cloudflare), true) => {
+                        tracing::info!("Starting in Cloudflare mode");
+                        let (runner, future) = runners::inline::InlineRunner::new_request_handler(
+                            cloudflare_handler.clone(),
+                            user_code,
+                        );
+                        runners::durable_objects::set_dispatcher(Box::new(runner.clone()));
+                        cloudflare_handler.restore_durable_object_alarms()?;
+                        Either::Right((Box::new(runner), Box::pin(future)))
+                    }
+                    (Some(HandlerName::WinterCG) | None, false) => {
+                        tracing::info!("Starting in WinterCG mode");
+                        Either::Left(Handler::Runner(Box::new(
+                            runners::single::SingleRunner::new_request_handler(
+                                WinterCGRequestHandler,
+                                max_js_threads,
+                                user_code,
+                            ),
+                        )))
+                    }
+                    (Some(HandlerName::WinterCG) | None, true) => {
+                        tracing::info!("Starting in WinterCG mode");
+                        let (runner, future) = runners::inline::InlineRunner::new_request_handler(
                             WinterCGRequestHandler,
-                            cmd.max_js_threads,
                             user_code,
-                        ),
-                    ))
-                }
-                (Some(HandlerName::WinterCG) | None, true) => {
-                    tracing::info!("Starting in WinterCG mode");
-                    let (runner, future) = runners::inline::InlineRunner::new_request_handler(
-                        WinterCGRequestHandler,
-                        user_code,
-                    );
-                    Either::Right((Box::new(runner), Box::pin(future)))
+                        );
+                        Either::Right((Box::new(runner), Box::pin(future)))
+                    }
                 }
             };
 
@@ -180,10 +309,8 @@ fn run() -> Result<(), anyhow::Error> {
             // for native builds only.
             #[cfg(not(target_os = "wasi"))]
             {
-                let timeout = cmd
-                    .shutdown_timeout
-                    .map(Duration::from_secs)
-                    .unwrap_or_else(|| Duration::from_secs(60));
+                let timeout =
+                    Duration::from_secs(cmd.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT));
                 let timeout = if timeout.is_zero() {
                     None
                 } else {
@@ -199,6 +326,7 @@ fn run() -> Result<(), anyhow::Error> {
                         Either::Left(r) => r.shutdown(timeout).await,
                         Either::Right(r) => r.shutdown(timeout).await,
                     }
+                    runners::services::shutdown(timeout).await;
                     _ = tx.send(());
                 });
                 ctrlc::set_handler(move || {
@@ -213,12 +341,22 @@ fn run() -> Result<(), anyhow::Error> {
                 .expect("Failed to set Ctrl-C handler");
             }
 
+            let apps_reload_interval = cmd
+                .apps_reload_interval
+                .unwrap_or(DEFAULT_APPS_RELOAD_INTERVAL);
             match runner {
-                Either::Left(runner) => tokio::runtime::Builder::new_multi_thread()
+                Either::Left(handler) => tokio::runtime::Builder::new_multi_thread()
                     .enable_all()
                     .build()
                     .expect("Failed building the Runtime")
-                    .block_on(crate::server::run_server(config, runner, rx)),
+                    .block_on(async move {
+                        if let Handler::Apps(ref apps) = handler {
+                            if apps_reload_interval > 0 {
+                                apps.watch(Duration::from_secs(apps_reload_interval));
+                            }
+                        }
+                        crate::server::run_server(config, handler, rx).await
+                    }),
                 Either::Right((runner, runner_future)) => {
                     tokio::runtime::Builder::new_current_thread()
                         .enable_all()
@@ -228,8 +366,11 @@ fn run() -> Result<(), anyhow::Error> {
                             let local_set = LocalSet::new();
                             local_set
                                 .run_until(async move {
-                                    let server_future =
-                                        crate::server::run_server(config, runner, rx);
+                                    let server_future = crate::server::run_server(
+                                        config,
+                                        Handler::Runner(runner),
+                                        rx,
+                                    );
                                     let (result, ()) = join!(server_future, runner_future);
                                     result
                                 })
@@ -241,6 +382,49 @@ fn run() -> Result<(), anyhow::Error> {
     }
 }
 
+/// Starts a runner for each worker in the workers config, and registers
+/// them as services. Returns the entrypoint's runner.
+fn start_workers(path: &Path, default_max_js_threads: usize) -> anyhow::Result<BoxedDynRunner> {
+    let config = WorkersConfig::try_parse(path)?;
+    tracing::info!(
+        "Starting {} workers in Cloudflare mode, with {} as the entrypoint",
+        config.workers.len(),
+        config.entrypoint
+    );
+
+    let mut durable_objects_worker = None;
+    let mut services = std::collections::HashMap::new();
+    for worker in config.workers {
+        let user_code = UserCode::from_path(&worker.main, worker.script)
+            .with_context(|| format!("Failed to load worker {}", worker.name))?;
+        let has_durable_objects = !worker.bindings.durable_objects.bindings.is_empty();
+        let cloudflare_handler = CloudflareRequestHandler::new(worker.bindings);
+        let runner = runners::single::SingleRunner::new_request_handler(
+            cloudflare_handler.clone(),
+            worker.max_js_threads.unwrap_or(default_max_js_threads),
+            user_code,
+        );
+
+        // Durable Objects are dispatched through a single global dispatcher
+        if has_durable_objects {
+            if let Some(other) = durable_objects_worker.replace(worker.name.clone()) {
+                anyhow::bail!(
+                    "Only one worker can declare Durable Objects, found {other} and {}",
+                    worker.name
+                );
+            }
+            runners::durable_objects::set_dispatcher(Box::new(runner.clone()));
+            cloudflare_handler.restore_durable_object_alarms()?;
+        }
+
+        services.insert(worker.name, Box::new(runner) as BoxedDynRunner);
+    }
+
+    let entrypoint = services[&config.entrypoint].clone();
+    runners::services::set_services(services);
+    Ok(entrypoint)
+}
+
 /// winterjs CLI
 #[derive(clap::Parser, Debug)]
 #[clap(version)]
@@ -254,6 +438,22 @@ struct Args {
 enum Cmd {
     Serve(CmdServe),
     Exec(CmdExec),
+    Config(CmdConfig),
+}
+
+/// Inspect the configuration of `winterjs serve`.
+#[derive(clap::Parser, Debug)]
+struct CmdConfig {
+    #[clap(subcommand)]
+    cmd: ConfigCmd,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum ConfigCmd {
+    /// Print the effective configuration `winterjs serve` would run with
+    /// given the same arguments, after merging the config file, environment
+    /// variables and CLI flags.
+    Print(CmdServe),
 }
 
 /// Start a WinterJS webserver serving the given JS app.
@@ -265,35 +465,115 @@ struct CmdServe {
 
     /// The interface to listen on.
     /// Defaults to 127.0.0.1
-    #[clap(long, default_value = "127.0.0.1", env = "WINTERJS_IP")]
+    #[clap(long, env = "WINTERJS_IP")]
     ip: Option<IpAddr>,
 
-    /// Maximum amount of Javascript worker threads to spawn.
-    #[clap(long, default_value = "16", env = "WINTERJS_MAX_JS_THREADS")]
-    max_js_threads: usize,
+    /// Maximum amount of Javascript worker threads to spawn. Defaults to 16.
+    #[clap(long, env = "WINTERJS_MAX_JS_THREADS")]
+    max_js_threads: Option<usize>,
+
+    /// Path to a `winterjs.toml` config file. Defaults to the one next to
+    /// JS_PATH, or in the current directory. Flags and environment variables
+    /// take precedence over the file.
+    #[clap(long = "config", value_name = "PATH", env = "WINTERJS_CONFIG")]
+    config_file: Option<PathBuf>,
 
     // /// Watch the Javascript file for changes and automatically reload.
     // #[clap(short, long, env = "WINTERJS_WATCH")]
     // watch: bool,
-    /// Path to a Javascript file to serve.
+    /// Path to a Javascript file to serve. Can be left out if there is a
+    /// wrangler config with a `main` entry in the current directory.
     #[clap(env = "WINTERJS_PATH")]
-    js_path: PathBuf,
+    js_path: Option<PathBuf>,
 
     /// Run in script mode. If this flag is not specified, the JS file will
     /// be loaded in module mode instead.
     #[clap(short, long, env = "WINTERJS_SCRIPT")]
     script: bool,
 
+    /// Define the `Deno.serve` and `Bun.serve` functions in WinterCG mode,
+    /// for apps written against those runtimes' HTTP server APIs.
+    #[clap(long, env = "WINTERJS_SERVE_SHIMS")]
+    serve_shims: bool,
+
     /// The operating mode of the server. Defaults to WinterCG mode if left
     /// out.
     #[clap(short = 'H', long, env = "WINTERJS_MODE")]
     mode: Option<HandlerName>,
 
+    /// Path to a JSON file declaring the bindings (KV namespaces, etc.)
+    /// to expose on the `env` object in Cloudflare mode.
+    #[clap(long, env = "WINTERJS_BINDINGS")]
+    bindings: Option<PathBuf>,
+
+    /// Path to a `wrangler.toml` or `wrangler.json` file to read the entry
+    /// point, vars and bindings from. Implies Cloudflare mode. If JS_PATH is
+    /// left out, the file is looked up in the current directory.
+    #[clap(short = 'c', long, env = "WINTERJS_WRANGLER_CONFIG")]
+    wrangler_config: Option<PathBuf>,
+
+    /// The environment to use from the wrangler config, like wrangler's
+    /// `--env` flag.
+    #[clap(long, env = "WINTERJS_WRANGLER_ENV")]
+    wrangler_env: Option<String>,
+
     /// If this flag is specified, WinterJS will run in single-threaded mode,
     /// using only the main thread.
     #[clap(long, env = "WINTERJS_SINGLE_THREADED")]
     single_threaded: bool,
 
+    /// Path to a `.toml` or `.json` file declaring several workers to run
+    /// in Cloudflare mode, which can call each other through service
+    /// bindings. Requests are served by the config's entrypoint worker.
+    #[clap(
+        long,
+        env = "WINTERJS_WORKERS",
+        conflicts_with_all = ["js_path", "script", "mode", "bindings", "wrangler_config", "wrangler_env", "single_threaded"]
+    )]
+    workers: Option<PathBuf>,
+
+    /// Path to a `.toml` or `.json` manifest declaring several apps to
+    /// serve, and the host names and path prefixes they're served on.
+    #[clap(
+        long,
+        env = "WINTERJS_APPS",
+        conflicts_with_all = ["js_path", "script", "mode", "bindings", "wrangler_config", "wrangler_env", "single_threaded", "workers"]
+    )]
+    apps: Option<PathBuf>,
+
+    /// How often to check the apps manifest for changes, in seconds. Pass
+    /// in zero to disable reloading. Defaults to 5 seconds.
+    #[clap(long, env = "WINTERJS_APPS_RELOAD_INTERVAL", requires = "apps")]
+    apps_reload_interval: Option<u64>,
+
+    /// Which log messages to show, as a `RUST_LOG`-style filter such as
+    /// `winterjs=debug,warn`. Takes precedence over `RUST_LOG`.
+    #[clap(long, value_name = "FILTER", env = "WINTERJS_LOG_LEVEL")]
+    log_level: Option<String>,
+
+    #[clap(long, env = "WINTERJS_LOG_FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Directory of static files to serve in WinterCG mode. Requests for
+    /// files in the directory are served from it without running JS code.
+    #[clap(long, value_name = "PATH", env = "WINTERJS_STATIC_DIR")]
+    static_dir: Option<PathBuf>,
+
+    /// Read from the `[static]` section of the config file.
+    #[clap(skip)]
+    static_files: StaticConfig,
+
+    #[clap(flatten)]
+    env: EnvArgs,
+
+    /// Permissions granted to JS code. By default, no filesystem or network
+    /// access is allowed.
+    #[clap(flatten)]
+    permissions: PermissionArgs,
+
+    #[clap(flatten)]
+    egress: EgressArgs,
+
     #[cfg(not(target_os = "wasi"))]
     /// Clean shutdown timeout, i.e. how long to wait before forcefully
     /// terminating request handler threads after Ctrl+C is pressed, in
@@ -303,6 +583,170 @@ struct CmdServe {
     shutdown_timeout: Option<u64>,
 }
 
+impl CmdServe {
+    /// Loads the config file given with --config, or the one found next to
+    /// the entrypoint, and fills in the settings which weren't given on the
+    /// command line or through environment variables.
+    fn load_config_file(&mut self) -> anyhow::Result<()> {
+        let path = match self.config_file {
+            Some(ref path) => Some(path.clone()),
+            None => ConfigFile::discover(self.js_path.as_deref()),
+        };
+        if let Some(path) = path {
+            self.apply_config_file(ConfigFile::try_parse(&path)?);
+            self.config_file = Some(path);
+        }
+        if let Some(ref dir) = self.static_dir {
+            self.static_files.dir = Some(dir.clone());
+        }
+        Ok(())
+    }
+
+    fn apply_config_file(&mut self, file: ConfigFile) {
+        let ConfigFile {
+            main,
+            mode,
+            script,
+            serve_shims,
+            bindings,
+            wrangler_config,
+            wrangler_env,
+            workers,
+            apps,
+            apps_reload_interval,
+            server,
+            static_files,
+            env,
+            permissions,
+            fetch,
+            log,
+        } = file;
+
+        self.js_path = self.js_path.take().or(main);
+        self.mode = self.mode.take().or(mode);
+        self.script |= script.unwrap_or(false);
+        self.serve_shims |= serve_shims.unwrap_or(false);
+        self.bindings = self.bindings.take().or(bindings);
+        self.wrangler_config = self.wrangler_config.take().or(wrangler_config);
+        self.wrangler_env = self.wrangler_env.take().or(wrangler_env);
+        self.workers = self.workers.take().or(workers);
+        self.apps = self.apps.take().or(apps);
+        self.apps_reload_interval = self.apps_reload_interval.or(apps_reload_interval);
+
+        self.ip = self.ip.or(server.ip);
+        self.port = self.port.or(server.port);
+        self.max_js_threads = self.max_js_threads.or(server.max_js_threads);
+        self.single_threaded |= server.single_threaded.unwrap_or(false);
+        #[cfg(not(target_os = "wasi"))]
+        {
+            self.shutdown_timeout = self.shutdown_timeout.or(server.shutdown_timeout);
+        }
+
+        self.static_files = static_files;
+        self.env.apply_config_file(env);
+        self.permissions.apply_config_file(permissions);
+        self.egress.apply_config_file(fetch);
+
+        // RUST_LOG is an environment variable too, so it takes precedence
+        // over the file
+        if self.log_level.is_none() && std::env::var_os("RUST_LOG").is_none() {
+            self.log_level = log.level;
+        }
+        self.log_format = self.log_format.or(log.format);
+    }
+
+    fn listen_addr(&self) -> anyhow::Result<SocketAddr> {
+        let interface = if let Some(iface) = self.ip {
+            iface
+        } else if let Ok(value) = std::env::var("LISTEN_IP") {
+            value
+                .parse()
+                .context(format!("Invalid interface in LISTEN_IP:  '{value}'"))?
+        } else {
+            std::net::Ipv4Addr::LOCALHOST.into()
+        };
+
+        let port = if let Some(port) = self.port {
+            port
+        } else if let Ok(value) = std::env::var("PORT") {
+            value
+                .parse()
+                .context(format!("Invalid port in PORT:  '{value}'"))?
+        } else {
+            8080
+        };
+
+        Ok((interface, port).into())
+    }
+
+    /// The settings `winterjs serve` runs with, including defaults, in the
+    /// format of the config file.
+    fn effective_config(&self) -> anyhow::Result<ConfigFile> {
+        let addr = self.listen_addr()?;
+
+        #[cfg(not(target_os = "wasi"))]
+        let shutdown_timeout = Some(self.shutdown_timeout.unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT));
+        #[cfg(target_os = "wasi")]
+        let shutdown_timeout = None;
+
+        let log_level = self
+            .log_level
+            .clone()
+            .or_else(|| std::env::var("RUST_LOG").ok())
+            .unwrap_or_else(|| DEFAULT_LOG_FILTER.to_string());
+
+        Ok(ConfigFile {
+            main: self.js_path.clone(),
+            mode: self.mode.clone(),
+            script: Some(self.script),
+            serve_shims: Some(self.serve_shims),
+            bindings: self.bindings.clone(),
+            wrangler_config: self.wrangler_config.clone(),
+            wrangler_env: self.wrangler_env.clone(),
+            workers: self.workers.clone(),
+            apps: self.apps.clone(),
+            apps_reload_interval: self.apps.as_ref().map(|_| {
+                self.apps_reload_interval
+                    .unwrap_or(DEFAULT_APPS_RELOAD_INTERVAL)
+            }),
+            server: ServerSection {
+                ip: Some(addr.ip()),
+                port: Some(addr.port()),
+                max_js_threads: Some(self.max_js_threads.unwrap_or(DEFAULT_MAX_JS_THREADS)),
+                single_threaded: Some(self.single_threaded),
+                shutdown_timeout,
+            },
+            static_files: self.static_files.clone(),
+            env: EnvSection {
+                allow: self.env.allow.clone(),
+                deny: self.env.deny.clone(),
+                files: self.env.env_files.clone(),
+                inherit: Some(self.env.inherit_env),
+            },
+            permissions: PermissionsSection {
+                allow_read: self.permissions.allow_read.clone(),
+                allow_write: self.permissions.allow_write.clone(),
+                allow_net: self.permissions.allow_net.clone(),
+                allow_all: Some(self.permissions.allow_all),
+            },
+            fetch: FetchSection {
+                connect_timeout: self.egress.fetch_connect_timeout,
+                timeout: self.egress.fetch_timeout,
+                max_response_size: self.egress.fetch_max_response_size,
+                resolve: self.egress.resolve.clone(),
+                deny_private_ips: Some(self.egress.deny_private_ips),
+                max_subrequests: self.egress.max_subrequests,
+                mocks: self.egress.fetch_mocks.clone(),
+                mocks_strict: Some(self.egress.fetch_mocks_strict),
+            },
+            log: LogSection {
+                level: Some(log_level),
+                format: Some(self.log_format.unwrap_or(LogFormat::Full)),
+            },
+        })
+    }
+}
+
 /// Execute a JS file directly and exit. This is useful for cron jobs, etc.
 #[derive(clap::Parser, Debug)]
 struct CmdExec {
@@ -314,9 +758,275 @@ struct CmdExec {
     /// be loaded in module mode instead.
     #[clap(short, long, env = "WINTERJS_SCRIPT")]
     script: bool,
+
+    #[clap(flatten)]
+    env: EnvArgs,
+
+    /// Permissions granted to JS code. Unless any of the --allow-* flags
+    /// are given, all access is allowed.
+    #[clap(flatten)]
+    permissions: PermissionArgs,
+
+    #[clap(flatten)]
+    egress: EgressArgs,
+}
+
+/// Controls which environment variables are exposed to JS code through
+/// `process.env` and the `env` object in Cloudflare mode.
+#[derive(clap::Args, Debug)]
+struct EnvArgs {
+    /// Pass the given host environment variable through to JS code. Can be
+    /// given multiple times, and supports glob patterns such as `APP_*`.
+    #[clap(
+        long = "env-allow",
+        alias = "allow-env",
+        value_name = "NAME",
+        value_delimiter = ',',
+        env = "WINTERJS_ENV_ALLOW"
+    )]
+    allow: Vec<String>,
+
+    /// Never pass the given host environment variable through, even if it
+    /// is allowed with --env-allow or --inherit-env. Supports glob patterns.
+    #[clap(
+        long = "env-deny",
+        value_name = "NAME",
+        value_delimiter = ',',
+        env = "WINTERJS_ENV_DENY"
+    )]
+    deny: Vec<String>,
+
+    /// Load secrets from the given dotenv-style file. Can be given multiple
+    /// times. Defaults to `.dev.vars` or `.env` in the app's directory.
+    /// Secrets are not enumerable, so they don't show up when logging or
+    /// serializing the env object.
+    #[clap(
+        long = "env-file",
+        value_name = "PATH",
+        value_delimiter = ',',
+        env = "WINTERJS_ENV_FILE"
+    )]
+    env_files: Vec<PathBuf>,
+
+    /// Pass the entire host environment through to JS code, except for
+    /// WINTERJS_* variables. This was the default in earlier versions, but
+    /// exposes any credentials in the environment to all JS code.
+    #[clap(long, env = "WINTERJS_INHERIT_ENV")]
+    inherit_env: bool,
+}
+
+impl EnvArgs {
+    fn apply_config_file(&mut self, env: EnvSection) {
+        if self.allow.is_empty() {
+            self.allow = env.allow;
+        }
+        if self.deny.is_empty() {
+            self.deny = env.deny;
+        }
+        if self.env_files.is_empty() {
+            self.env_files = env.files;
+        }
+        self.inherit_env |= env.inherit.unwrap_or(false);
+    }
+
+    fn into_policy(self) -> env_vars::EnvPolicy {
+        env_vars::EnvPolicy {
+            allow: self.allow,
+            deny: self.deny,
+            inherit_all: self.inherit_env,
+            env_files: self.env_files,
+        }
+    }
+}
+
+/// Controls outbound requests made with `fetch`. Proxies are configured
+/// through the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment
+/// variables.
+#[derive(clap::Args, Debug)]
+struct EgressArgs {
+    /// Timeout for connecting to the host (or proxy) of outbound requests,
+    /// in seconds.
+    #[clap(long, value_name = "SECONDS", env = "WINTERJS_FETCH_CONNECT_TIMEOUT")]
+    fetch_connect_timeout: Option<u64>,
+
+    /// Timeout for entire outbound requests, including reading the
+    /// response body, in seconds.
+    #[clap(long, value_name = "SECONDS", env = "WINTERJS_FETCH_TIMEOUT")]
+    fetch_timeout: Option<u64>,
+
+    /// Maximum size of the responses to outbound requests, in bytes.
+    #[clap(long, value_name = "BYTES", env = "WINTERJS_FETCH_MAX_RESPONSE_SIZE")]
+    fetch_max_response_size: Option<u64>,
+
+    /// Resolve a host to a fixed address in outbound requests, in
+    /// `host[:port]:ip` format. Can be given multiple times.
+    #[clap(
+        long,
+        value_name = "HOST:IP",
+        value_delimiter = ',',
+        env = "WINTERJS_RESOLVE"
+    )]
+    resolve: Vec<String>,
+
+    /// Refuse outbound requests to loopback, private, link-local and other
+    /// non-public addresses, to prevent SSRF attacks. Addresses given with
+    /// --resolve are exempt.
+    #[clap(long, env = "WINTERJS_DENY_PRIVATE_IPS")]
+    deny_private_ips: bool,
+
+    /// Maximum number of outbound requests, including redirects, made while
+    /// handling a single incoming request.
+    #[clap(long, value_name = "COUNT", env = "WINTERJS_MAX_SUBREQUESTS")]
+    max_subrequests: Option<u32>,
+
+    /// Path to a JSON file of canned responses to return for matching
+    /// outbound requests instead of sending them, for testing.
+    #[clap(long, value_name = "PATH", env = "WINTERJS_FETCH_MOCKS")]
+    fetch_mocks: Option<PathBuf>,
+
+    /// Fail outbound requests which don't match any of the fetch mocks,
+    /// instead of sending them over the network.
+    #[clap(long, env = "WINTERJS_FETCH_MOCKS_STRICT")]
+    fetch_mocks_strict: bool,
+}
+
+impl EgressArgs {
+    fn apply_config_file(&mut self, fetch: FetchSection) {
+        self.fetch_connect_timeout = self.fetch_connect_timeout.or(fetch.connect_timeout);
+        self.fetch_timeout = self.fetch_timeout.or(fetch.timeout);
+        self.fetch_max_response_size = self.fetch_max_response_size.or(fetch.max_response_size);
+        if self.resolve.is_empty() {
+            self.resolve = fetch.resolve;
+        }
+        self.deny_private_ips |= fetch.deny_private_ips.unwrap_or(false);
+        self.max_subrequests = self.max_subrequests.or(fetch.max_subrequests);
+        self.fetch_mocks = self.fetch_mocks.take().or(fetch.mocks);
+        self.fetch_mocks_strict |= fetch.mocks_strict.unwrap_or(false);
+    }
+
+    fn into_config(self) -> anyhow::Result<EgressConfig> {
+        Ok(EgressConfig {
+            connect_timeout: self.fetch_connect_timeout.map(Duration::from_secs),
+            total_timeout: self.fetch_timeout.map(Duration::from_secs),
+            max_response_size: self.fetch_max_response_size,
+            resolve_overrides: self
+                .resolve
+                .iter()
+                .map(|r| ResolveOverride::parse(r))
+                .collect::<anyhow::Result<_>>()?,
+            deny_private_ips: self.deny_private_ips,
+            max_subrequests: self.max_subrequests,
+            proxy: ProxyConfig::from_env()?,
+            mocks: match self.fetch_mocks {
+                Some(path) => Some(FetchMocks::try_parse(path, self.fetch_mocks_strict)?),
+                None if self.fetch_mocks_strict => Some(FetchMocks {
+                    strict: true,
+                    ..Default::default()
+                }),
+                None => None,
+            },
+        })
+    }
+}
+
+/// Controls which filesystem and network resources JS code can access.
+#[derive(clap::Args, Debug)]
+struct PermissionArgs {
+    /// Allow reading from the given comma-separated paths and everything
+    /// under them, or from anywhere if no paths are given.
+    #[clap(
+        long,
+        value_name = "PATHS",
+        num_args = 0..,
+        value_delimiter = ',',
+        require_equals = true,
+        env = "WINTERJS_ALLOW_READ"
+    )]
+    allow_read: Option<Vec<PathBuf>>,
+
+    /// Allow writing to the given comma-separated paths and everything
+    /// under them, or anywhere if no paths are given.
+    #[clap(
+        long,
+        value_name = "PATHS",
+        num_args = 0..,
+        value_delimiter = ',',
+        require_equals = true,
+        env = "WINTERJS_ALLOW_WRITE"
+    )]
+    allow_write: Option<Vec<PathBuf>>,
+
+    /// Allow network access to the given comma-separated hosts, with an
+    /// optional port (e.g. `example.com:443`), or to any host if none are
+    /// given. Hosts can be glob patterns such as `*.example.com`.
+    #[clap(
+        long,
+        value_name = "HOSTS",
+        num_args = 0..,
+        value_delimiter = ',',
+        require_equals = true,
+        env = "WINTERJS_ALLOW_NET"
+    )]
+    allow_net: Option<Vec<String>>,
+
+    /// Allow all filesystem and network access.
+    #[clap(short = 'A', long, env = "WINTERJS_ALLOW_ALL")]
+    allow_all: bool,
+}
+
+impl PermissionArgs {
+    fn apply_config_file(&mut self, permissions: PermissionsSection) {
+        self.allow_read = self.allow_read.take().or(permissions.allow_read);
+        self.allow_write = self.allow_write.take().or(permissions.allow_write);
+        self.allow_net = self.allow_net.take().or(permissions.allow_net);
+        self.allow_all |= permissions.allow_all.unwrap_or(false);
+    }
+
+    fn into_permissions(self, allow_all_by_default: bool) -> anyhow::Result<Permissions> {
+        let no_flags =
+            self.allow_read.is_none() && self.allow_write.is_none() && self.allow_net.is_none();
+        if self.allow_all || (allow_all_by_default && no_flags) {
+            return Ok(Permissions::all());
+        }
+
+        // Passing a flag without any values grants access to everything
+        fn grant<T>(values: Option<Vec<T>>) -> Grant<T> {
+            match values {
+                None => Grant::Only(vec![]),
+                Some(values) if values.is_empty() => Grant::All,
+                Some(values) => Grant::Only(values),
+            }
+        }
+
+        let non_empty_paths = |paths: Option<Vec<PathBuf>>| {
+            paths.map(|p| {
+                p.into_iter()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let net = self
+            .allow_net
+            .map(|hosts| {
+                hosts
+                    .iter()
+                    .filter(|h| !h.is_empty())
+                    .map(|h| NetGrant::parse(h))
+                    .collect::<anyhow::Result<Vec<_>>>()
+            })
+            .transpose()?;
+
+        Ok(Permissions {
+            read: grant(non_empty_paths(self.allow_read)),
+            write: grant(non_empty_paths(self.allow_write)),
+            net: grant(net),
+        })
+    }
 }
 
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone, PartialEq, ValueEnum, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum HandlerName {
     WinterCG,
     Cloudflare,
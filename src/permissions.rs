@@ -0,0 +1,207 @@
+//! Deno-style permissions, limiting the filesystem and network access
+//! available to JS code. Permissions are granted on the command line with
+//! `--allow-read`, `--allow-write` and `--allow-net`, and checked in native
+//! code right before any access is made: by the `fs` global, by `fetch` for
+//! each request it sends (including every redirect) and by the module loader
+//! for imports from outside the app's directory. Denied operations fail with
+//! a `PermissionDenied` error.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context as _, Result};
+use once_cell::sync::OnceCell;
+
+use crate::ion_mk_err;
+
+static PERMISSIONS: OnceCell<Permissions> = OnceCell::new();
+
+#[derive(Debug)]
+pub enum Grant<T> {
+    All,
+    Only(Vec<T>),
+}
+
+impl<T> Grant<T> {
+    fn none() -> Self {
+        Self::Only(vec![])
+    }
+}
+
+#[derive(Debug)]
+pub struct NetGrant {
+    host: String,
+    port: Option<u16>,
+}
+
+impl NetGrant {
+    /// Parses a `host[:port]` pair, where the host may be a glob pattern
+    /// such as `*.example.com` and IPv6 addresses must be in brackets if
+    /// a port is given.
+    pub fn parse(value: &str) -> Result<Self> {
+        let (host, port) = match value.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+                let port = port
+                    .parse()
+                    .with_context(|| format!("Invalid port in net permission '{value}'"))?;
+                (host, Some(port))
+            }
+            _ => (value, None),
+        };
+        if host.is_empty() {
+            bail!("Invalid net permission '{value}'");
+        }
+
+        Ok(Self {
+            host: host.to_ascii_lowercase(),
+            port,
+        })
+    }
+
+    fn matches(&self, host: &str, port: u16) -> bool {
+        glob_match::glob_match(&self.host, host) && self.port.map(|p| p == port).unwrap_or(true)
+    }
+}
+
+#[derive(Debug)]
+pub struct Permissions {
+    pub read: Grant<PathBuf>,
+    pub write: Grant<PathBuf>,
+    pub net: Grant<NetGrant>,
+}
+
+impl Permissions {
+    pub fn all() -> Self {
+        Self {
+            read: Grant::All,
+            write: Grant::All,
+            net: Grant::All,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            read: Grant::none(),
+            write: Grant::none(),
+            net: Grant::none(),
+        }
+    }
+
+    fn check_net(&self, host: &str, port: u16) -> ion::Result<()> {
+        let allowed = match &self.net {
+            Grant::All => true,
+            Grant::Only(grants) => {
+                let host = host.to_ascii_lowercase();
+                grants.iter().any(|g| g.matches(&host, port))
+            }
+        };
+        if allowed {
+            return Ok(());
+        }
+        Err(denied(format!(
+            "Requires net access to \"{host}:{port}\", run again with --allow-net"
+        )))
+    }
+}
+
+/// Sets the permissions granted to JS code. Must be called before any JS
+/// threads are started; until then, everything is denied.
+pub fn init(mut permissions: Permissions) -> Result<()> {
+    for grant in [&mut permissions.read, &mut permissions.write] {
+        if let Grant::Only(paths) = grant {
+            for path in paths.iter_mut() {
+                *path = resolve_path(path);
+            }
+        }
+    }
+
+    if PERMISSIONS.set(permissions).is_err() {
+        bail!("Permissions were already initialized");
+    }
+    Ok(())
+}
+
+fn get() -> &'static Permissions {
+    static NONE: once_cell::sync::Lazy<Permissions> = once_cell::sync::Lazy::new(Permissions::none);
+    PERMISSIONS.get().unwrap_or(&NONE)
+}
+
+/// Makes a path absolute and resolves symlinks, so it can't be used to
+/// escape a granted directory. Paths that don't exist yet (e.g. files that
+/// are about to be created) are resolved through their closest existing
+/// ancestor.
+fn resolve_path(path: &Path) -> PathBuf {
+    let path = match std::env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_path_buf(),
+    };
+
+    let mut existing = path.as_path();
+    let mut rest = vec![];
+    loop {
+        if let Ok(canonical) = existing.canonicalize() {
+            return rest
+                .into_iter()
+                .rev()
+                .fold(canonical, |acc: PathBuf, c| acc.join(c));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name.to_os_string());
+                existing = parent;
+            }
+            _ => return path,
+        }
+    }
+}
+
+/// Checks that JS code may read from the given path.
+pub fn check_read(path: &Path) -> ion::Result<()> {
+    check_path(&get().read, path, "read")
+}
+
+/// Checks that JS code may write to the given path.
+pub fn check_write(path: &Path) -> ion::Result<()> {
+    check_path(&get().write, path, "write")
+}
+
+/// Checks that JS code may import a module from the given path. The app's
+/// own directory is always readable, since the app can't run without it.
+pub fn check_import(app_dir: &Path, path: &Path) -> ion::Result<()> {
+    if resolve_path(path).starts_with(resolve_path(app_dir)) {
+        return Ok(());
+    }
+    check_read(path)
+}
+
+/// Checks that JS code may send a request to the given URL. Only the host
+/// and port are taken into account.
+pub fn check_net(url: &url::Url) -> ion::Result<()> {
+    let Some(host) = url.host_str() else {
+        return Ok(());
+    };
+    let Some(port) = url.port_or_known_default() else {
+        return Ok(());
+    };
+    get().check_net(host, port)
+}
+
+fn check_path(grant: &Grant<PathBuf>, path: &Path, kind: &str) -> ion::Result<()> {
+    let allowed = match grant {
+        Grant::All => true,
+        Grant::Only(paths) => {
+            let path = resolve_path(path);
+            paths.iter().any(|p| path.starts_with(p))
+        }
+    };
+    if allowed {
+        return Ok(());
+    }
+    Err(denied(format!(
+        "Requires {kind} access to \"{}\", run again with --allow-{kind}",
+        path.display()
+    )))
+}
+
+fn denied(message: String) -> ion::Error {
+    ion_mk_err!(format!("PermissionDenied: {message}"), Normal)
+}
@@ -0,0 +1,545 @@
+use std::{
+    marker::PhantomData,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context as _, Result};
+use ion::{
+    class::Reflector,
+    conversions::{FromValue, ToValue},
+    function::{Opt, Rest},
+    BigInt, ClassDefinition, Context, Object, Promise, Value,
+};
+use mozjs::jsval::JSVal;
+use mozjs_sys::jsapi::JSObject;
+use runtime::promise::future_to_promise;
+use rusqlite::{types::Value as SqlValue, Connection, TransactionBehavior};
+
+use crate::{ion_err, ion_mk_err};
+
+use super::run_blocking;
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_MIGRATIONS_TABLE: &str = "d1_migrations";
+const MAX_SAFE_INTEGER: i64 = (1 << 53) - 1;
+
+/// A connection to the SQLite file backing a database. Each request handler
+/// thread opens its own connection, and SQLite takes care of locking.
+#[derive(Clone)]
+pub struct D1Store {
+    conn: Arc<Mutex<Connection>>,
+}
+
+pub struct QueryMeta {
+    duration: f64,
+    changes: u64,
+    last_row_id: i64,
+    changed_db: bool,
+    rows_read: u64,
+    rows_written: u64,
+    size_after: u64,
+}
+
+pub struct QueryResult {
+    columns: Vec<String>,
+    rows: Vec<Vec<SqlValue>>,
+    meta: QueryMeta,
+}
+
+pub struct Query {
+    sql: String,
+    params: Vec<SqlValue>,
+}
+
+impl D1Store {
+    pub fn open(
+        path: impl AsRef<Path>,
+        migrations_dir: Option<&Path>,
+        migrations_table: Option<&str>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+
+        let mut conn = Connection::open(path)
+            .with_context(|| format!("Failed to open database {}", path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+
+        if let Some(migrations_dir) = migrations_dir {
+            apply_migrations(
+                &mut conn,
+                migrations_dir,
+                migrations_table.unwrap_or(DEFAULT_MIGRATIONS_TABLE),
+            )?;
+        }
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| anyhow!("Database connection was poisoned"))
+    }
+
+    pub fn query(&self, query: &Query) -> Result<QueryResult> {
+        let conn = self.lock()?;
+        run_query(&conn, query)
+    }
+
+    /// Runs all queries in a single transaction, which is rolled back if
+    /// any of them fails.
+    pub fn batch(&self, queries: &[Query]) -> Result<Vec<QueryResult>> {
+        let mut conn = self.lock()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let results = queries
+            .iter()
+            .map(|q| run_query(&tx, q))
+            .collect::<Result<Vec<_>>>()?;
+        tx.commit()?;
+        Ok(results)
+    }
+
+    /// Returns the number of statements executed.
+    pub fn exec(&self, sql: &str) -> Result<u64> {
+        let conn = self.lock()?;
+        let mut batch = rusqlite::Batch::new(&conn, sql);
+        let mut count = 0;
+        while let Some(mut stmt) = batch.next().map_err(d1_error)? {
+            let mut rows = stmt.raw_query();
+            while rows.next().map_err(d1_error)?.is_some() {}
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+fn d1_error(e: rusqlite::Error) -> anyhow::Error {
+    anyhow!("D1_ERROR: {e}")
+}
+
+fn total_changes(conn: &Connection) -> Result<u64> {
+    Ok(conn.query_row("SELECT total_changes()", [], |row| row.get::<_, i64>(0))? as u64)
+}
+
+fn run_query(conn: &Connection, query: &Query) -> Result<QueryResult> {
+    let start = Instant::now();
+    let changes_before = total_changes(conn)?;
+
+    let mut stmt = conn.prepare(&query.sql).map_err(d1_error)?;
+    let columns = stmt
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+
+    let expected_params = stmt.parameter_count();
+    if expected_params != query.params.len() {
+        bail!(
+            "D1_ERROR: Wrong number of parameter bindings for SQL query, \
+            expected {expected_params} but got {}",
+            query.params.len()
+        );
+    }
+
+    let mut rows = vec![];
+    let mut result_rows = stmt
+        .query(rusqlite::params_from_iter(query.params.iter()))
+        .map_err(d1_error)?;
+    while let Some(row) = result_rows.next().map_err(d1_error)? {
+        rows.push(
+            (0..columns.len())
+                .map(|i| row.get::<_, SqlValue>(i))
+                .collect::<rusqlite::Result<Vec<_>>>()?,
+        );
+    }
+    drop(result_rows);
+
+    // `changes()` keeps the count of the last INSERT, UPDATE or DELETE, so
+    // it would be reported again for statements that didn't change anything
+    let changes = total_changes(conn)? - changes_before;
+    let size_after = conn.query_row(
+        "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+        [],
+        |row| row.get::<_, i64>(0),
+    )? as u64;
+
+    Ok(QueryResult {
+        meta: QueryMeta {
+            duration: start.elapsed().as_secs_f64() * 1000.0,
+            changes,
+            last_row_id: conn.last_insert_rowid(),
+            changed_db: changes > 0,
+            rows_read: rows.len() as u64,
+            rows_written: changes,
+            size_after,
+        },
+        columns,
+        rows,
+    })
+}
+
+/// Applies the `.sql` files in the migrations directory in name order,
+/// recording the applied ones in the same table wrangler uses. The whole
+/// process runs in one transaction, so threads opening the database at the
+/// same time don't apply a migration twice.
+fn apply_migrations(conn: &mut Connection, dir: &Path, table: &str) -> Result<()> {
+    let mut files = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read migrations directory {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    files.retain(|f| f.extension().map(|e| e == "sql").unwrap_or(false));
+    files.sort();
+
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    tx.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS \"{table}\" (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT UNIQUE,
+            applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )"
+    ))?;
+
+    for file in files {
+        let name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let applied = tx.query_row(
+            &format!("SELECT COUNT(*) FROM \"{table}\" WHERE name = ?1"),
+            [&name],
+            |row| row.get::<_, i64>(0),
+        )? > 0;
+        if applied {
+            continue;
+        }
+
+        tracing::info!("Applying D1 migration {name}");
+        let sql = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read migration {}", file.display()))?;
+        tx.execute_batch(&sql)
+            .with_context(|| format!("Failed to apply migration {name}"))?;
+        tx.execute(
+            &format!("INSERT INTO \"{table}\" (name) VALUES (?1)"),
+            [&name],
+        )?;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+fn value_to_sql(cx: &Context, value: &Value) -> ion::Result<SqlValue> {
+    let handle = value.handle();
+    if handle.is_null() {
+        return Ok(SqlValue::Null);
+    }
+    if handle.is_boolean() {
+        return Ok(SqlValue::Integer(handle.to_boolean() as i64));
+    }
+    if handle.is_number() {
+        let number = handle.to_number();
+        return Ok(
+            if number.fract() == 0.0 && number.abs() < (1u64 << 53) as f64 {
+                SqlValue::Integer(number as i64)
+            } else {
+                SqlValue::Real(number)
+            },
+        );
+    }
+    if handle.is_bigint() {
+        let number = BigInt::from_value(cx, value, true, ())?
+            .to_i64()
+            .ok_or_else(|| ion_mk_err!("D1_TYPE_ERROR: BigInt does not fit in 64 bits", Range))?;
+        return Ok(SqlValue::Integer(number));
+    }
+    if handle.is_string() {
+        return Ok(SqlValue::Text(String::from_value(cx, value, true, ())?));
+    }
+    if handle.is_object() {
+        if let Ok(blob) = D1Blob::from_value(cx, value, true, ()) {
+            return Ok(SqlValue::Blob(blob.to_owned()));
+        }
+    }
+
+    ion_err!(
+        "D1_TYPE_ERROR: Type is not supported, values must be null, booleans, \
+        numbers, BigInts, strings or ArrayBuffers",
+        Type
+    )
+}
+
+fn sql_to_value(cx: &Context, value: SqlValue) -> JSVal {
+    match value {
+        SqlValue::Null => Value::null(cx).get(),
+        SqlValue::Integer(i) if (-MAX_SAFE_INTEGER..=MAX_SAFE_INTEGER).contains(&i) => {
+            (i as f64).as_value(cx).get()
+        }
+        // Numbers would silently round these
+        SqlValue::Integer(i) => BigInt::from_i64(cx, i).as_value(cx).get(),
+        SqlValue::Real(f) => f.as_value(cx).get(),
+        SqlValue::Text(s) => s.as_value(cx).get(),
+        // D1 returns blobs as arrays of bytes
+        SqlValue::Blob(b) => b.as_value(cx).get(),
+    }
+}
+
+#[derive(FromValue)]
+pub enum D1Blob<'cx> {
+    #[ion(inherit)]
+    ArrayBuffer(mozjs::typedarray::ArrayBuffer, PhantomData<&'cx ()>),
+    #[ion(inherit)]
+    ArrayBufferView(mozjs::typedarray::ArrayBufferView, PhantomData<&'cx ()>),
+}
+
+impl<'cx> D1Blob<'cx> {
+    fn to_owned(&self) -> Vec<u8> {
+        unsafe {
+            match self {
+                Self::ArrayBuffer(buf, _) => buf.as_slice().to_vec(),
+                Self::ArrayBufferView(buf, _) => buf.as_slice().to_vec(),
+            }
+        }
+    }
+}
+
+fn row_to_object(cx: &Context, columns: &[String], row: Vec<SqlValue>) -> JSVal {
+    let obj = Object::new(cx);
+    for (column, value) in columns.iter().zip(row) {
+        obj.set_as(cx, column.as_str(), &sql_to_value(cx, value));
+    }
+    obj.as_value(cx).get()
+}
+
+fn meta_to_js(cx: &Context, meta: &QueryMeta) -> Object {
+    let obj = Object::new(cx);
+    obj.set_as(cx, "served_by", &"winterjs");
+    obj.set_as(cx, "duration", &meta.duration);
+    obj.set_as(cx, "changes", &(meta.changes as f64));
+    obj.set_as(cx, "last_row_id", &(meta.last_row_id as f64));
+    obj.set_as(cx, "changed_db", &meta.changed_db);
+    obj.set_as(cx, "size_after", &(meta.size_after as f64));
+    obj.set_as(cx, "rows_read", &(meta.rows_read as f64));
+    obj.set_as(cx, "rows_written", &(meta.rows_written as f64));
+    obj
+}
+
+fn result_to_js(cx: &Context, result: QueryResult) -> JSVal {
+    let results = result
+        .rows
+        .into_iter()
+        .map(|row| row_to_object(cx, &result.columns, row))
+        .collect::<Vec<_>>();
+
+    let obj = Object::new(cx);
+    obj.set_as(cx, "success", &true);
+    obj.set_as(cx, "results", &results);
+    obj.set_as(cx, "meta", &meta_to_js(cx, &result.meta));
+    obj.as_value(cx).get()
+}
+
+#[js_class]
+pub struct D1Database {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    store: D1Store,
+}
+
+impl D1Database {
+    pub fn new_obj(cx: &Context, store: D1Store) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                store,
+            }),
+        )
+    }
+}
+
+#[js_class]
+impl D1Database {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<D1Database> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    pub fn prepare(&self, cx: &Context, query: String) -> *mut JSObject {
+        D1PreparedStatement::new_obj(cx, self.store.clone(), query, vec![])
+    }
+
+    pub fn batch<'cx>(
+        &self,
+        cx: &'cx Context,
+        statements: Vec<&'cx D1PreparedStatement>,
+    ) -> Option<Promise> {
+        let queries = statements
+            .iter()
+            .map(|s| Query {
+                sql: s.sql.clone(),
+                params: s.params.clone(),
+            })
+            .collect::<Vec<_>>();
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, results) = run_blocking(cx, move || store.batch(&queries)).await;
+                let results = results?
+                    .into_iter()
+                    .map(|r| result_to_js(&cx, r))
+                    .collect::<Vec<_>>();
+                Ok(results.as_value(&cx).get())
+            })
+        }
+    }
+
+    pub fn exec(&self, cx: &Context, query: String) -> Option<Promise> {
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let start = Instant::now();
+                let (cx, count) = run_blocking(cx, move || store.exec(&query)).await;
+
+                let result = Object::new(&cx);
+                result.set_as(&cx, "count", &(count? as f64));
+                result.set_as(&cx, "duration", &(start.elapsed().as_secs_f64() * 1000.0));
+                Ok(result.as_value(&cx).get())
+            })
+        }
+    }
+}
+
+#[derive(FromValue, Default)]
+pub struct D1RawOptions {
+    column_names: Option<bool>,
+}
+
+#[js_class]
+pub struct D1PreparedStatement {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    store: D1Store,
+
+    #[trace(no_trace)]
+    sql: String,
+
+    #[trace(no_trace)]
+    params: Vec<SqlValue>,
+}
+
+impl D1PreparedStatement {
+    fn new_obj(cx: &Context, store: D1Store, sql: String, params: Vec<SqlValue>) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                store,
+                sql,
+                params,
+            }),
+        )
+    }
+
+    fn run_query<F>(&self, cx: &Context, to_js: F) -> Option<Promise>
+    where
+        F: FnOnce(&Context, QueryResult) -> ion::Result<JSVal> + 'static,
+    {
+        let store = self.store.clone();
+        let query = Query {
+            sql: self.sql.clone(),
+            params: self.params.clone(),
+        };
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, result) = run_blocking(cx, move || store.query(&query)).await;
+                to_js(&cx, result?)
+            })
+        }
+    }
+}
+
+#[js_class]
+impl D1PreparedStatement {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<D1PreparedStatement> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    pub fn bind(&self, cx: &Context, Rest(values): Rest<Value>) -> ion::Result<*mut JSObject> {
+        let params = values
+            .iter()
+            .map(|v| value_to_sql(cx, v))
+            .collect::<ion::Result<Vec<_>>>()?;
+        Ok(Self::new_obj(
+            cx,
+            self.store.clone(),
+            self.sql.clone(),
+            params,
+        ))
+    }
+
+    pub fn first(&self, cx: &Context, Opt(column): Opt<String>) -> Option<Promise> {
+        self.run_query(cx, move |cx, result| {
+            let Some(row) = result.rows.into_iter().next() else {
+                return Ok(Value::null(cx).get());
+            };
+
+            match column {
+                Some(column) => {
+                    let index = result
+                        .columns
+                        .iter()
+                        .position(|c| *c == column)
+                        .ok_or_else(|| {
+                            ion_mk_err!(
+                                format!("D1_COLUMN_NOTFOUND: Column not found ({column})"),
+                                Normal
+                            )
+                        })?;
+                    let value = row.into_iter().nth(index).unwrap_or(SqlValue::Null);
+                    Ok(sql_to_value(cx, value))
+                }
+                None => Ok(row_to_object(cx, &result.columns, row)),
+            }
+        })
+    }
+
+    /// `run` and `all` return the same result object in current versions
+    /// of D1.
+    #[ion(alias = ["all"])]
+    pub fn run(&self, cx: &Context) -> Option<Promise> {
+        self.run_query(cx, |cx, result| Ok(result_to_js(cx, result)))
+    }
+
+    pub fn raw(&self, cx: &Context, Opt(options): Opt<D1RawOptions>) -> Option<Promise> {
+        let column_names = options.unwrap_or_default().column_names.unwrap_or(false);
+
+        self.run_query(cx, move |cx, result| {
+            let mut rows = vec![];
+            if column_names {
+                rows.push(result.columns.as_value(cx).get());
+            }
+            for row in result.rows {
+                let row = row
+                    .into_iter()
+                    .map(|v| sql_to_value(cx, v))
+                    .collect::<Vec<_>>();
+                rows.push(row.as_value(cx).get());
+            }
+            Ok(rows.as_value(cx).get())
+        })
+    }
+}
@@ -0,0 +1,730 @@
+//! Durable Objects are instances of classes exported from the worker's main
+//! module. Each object lives on the request handler thread its ID maps to,
+//! where it's created on first use and kept around for the lifetime of the
+//! thread. As in Workers, an object whose `blockConcurrencyWhile` callback
+//! fails is replaced with a new instance on its next event. Storage is a
+//! SQLite database per object, under
+//! `<persist_to>/durable_objects/<class name>/<id>.sqlite3`.
+
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, Result};
+use ion::{
+    class::{NativeObject, Reflector},
+    conversions::{FromValue, ToValue},
+    flags::PropertyFlags,
+    function::Opt,
+    ClassDefinition, Context, Function, Heap, Object, Promise, PromiseFuture, TracedHeap, Value,
+};
+use mozjs::jsval::{JSVal, UndefinedValue};
+use mozjs_sys::jsapi::JSObject;
+use runtime::{
+    globals::fetch::{
+        Request as FetchRequest, RequestInfo, RequestInit, Response as FetchResponse,
+    },
+    promise::future_to_promise,
+};
+use sha2::Digest;
+
+use crate::{
+    ion_err, ion_mk_err,
+    request_handlers::Request,
+    runners::durable_objects::{
+        dispatch, schedule_alarm, DurableObjectEvent, DurableObjectMessage, DurableObjectTarget,
+    },
+    sm_utils::{self, call_method, error_report_option_to_anyhow_error},
+};
+
+use self::storage::{DurableObjectStorage, ObjectStore};
+
+use super::BindingsConfig;
+
+mod storage;
+
+const HELPERS_SCRIPT: &str = "({
+    construct(cls, state, env) {
+        return new cls(state, env);
+    },
+    toMap(entries) {
+        return new Map(entries);
+    },
+})";
+
+struct DurableObjectInstance {
+    object: TracedHeap<*mut JSObject>,
+    state: TracedHeap<*mut JSObject>,
+    store: ObjectStore,
+}
+
+/// The Durable Object namespaces and the objects living on one thread.
+pub struct DurableObjects {
+    namespaces: Vec<(String, String)>,
+    storage_dir: PathBuf,
+    helpers: OnceCell<TracedHeap<*mut JSObject>>,
+    instances: RefCell<HashMap<DurableObjectTarget, DurableObjectInstance>>,
+}
+
+impl DurableObjects {
+    pub fn new(config: &BindingsConfig) -> Self {
+        let namespaces = config
+            .durable_objects
+            .bindings
+            .iter()
+            .map(|binding| {
+                if binding.script_name.is_some() {
+                    tracing::warn!(
+                        "Durable Object binding {} refers to another worker, which is not \
+                        supported; the class will be looked up in this worker instead",
+                        binding.name
+                    );
+                }
+                (binding.name.clone(), binding.class_name.clone())
+            })
+            .collect();
+
+        Self {
+            namespaces,
+            storage_dir: storage_dir(config),
+            helpers: OnceCell::new(),
+            instances: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn define_on(&self, cx: &Context, env: &Object) -> bool {
+        for (name, class_name) in &self.namespaces {
+            let namespace = DurableObjectNamespace::new_obj(cx, class_name.clone());
+            if !env.define(
+                cx,
+                name.as_str(),
+                &Value::object(cx, &cx.root(namespace).into()),
+                PropertyFlags::ENUMERATE,
+            ) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn helpers<'cx>(&self, cx: &'cx Context) -> Result<Object<'cx>> {
+        if self.helpers.get().is_none() {
+            let helpers = sm_utils::evaluate_script(cx, HELPERS_SCRIPT, "durable_objects.js")?;
+            _ = self
+                .helpers
+                .set(TracedHeap::new((*helpers.to_object(cx)).get()));
+        }
+        Ok(Object::from(self.helpers.get().unwrap().root(cx)))
+    }
+
+    fn get_or_create_instance(
+        &self,
+        cx: &Context,
+        exports: &Object,
+        new_env: impl FnOnce() -> *mut JSObject,
+        target: &DurableObjectTarget,
+    ) -> Result<(
+        TracedHeap<*mut JSObject>,
+        TracedHeap<*mut JSObject>,
+        ObjectStore,
+    )> {
+        let mut existing_store = None;
+        if let Some(instance) = self.instances.borrow().get(target) {
+            let state =
+                DurableObjectState::get_private(cx, &instance.state.root(cx).into()).unwrap();
+            if !state.failed {
+                return Ok((
+                    TracedHeap::new(instance.object.get()),
+                    TracedHeap::new(instance.state.get()),
+                    instance.store.clone(),
+                ));
+            }
+            existing_store = Some(instance.store.clone());
+        }
+
+        let class = exports
+            .get(cx, target.class_name.as_str())
+            .map_err(|e| anyhow!("Failed to read module exports: {e:?}"))?
+            .filter(|class| class.handle().is_object())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Durable Object class {} is not exported from the worker",
+                    target.class_name
+                )
+            })?;
+
+        // A replaced instance keeps using the storage of the one before it
+        let store = match existing_store {
+            Some(store) => store,
+            None => ObjectStore::open(
+                self.storage_dir
+                    .join(&target.class_name)
+                    .join(format!("{}.sqlite3", target.id)),
+                target.clone(),
+            )?,
+        };
+        let helpers = self.helpers(cx)?;
+
+        let storage = DurableObjectStorage::new_obj(cx, store.clone(), (*helpers).get(), None);
+        let id = DurableObjectId::new_obj(cx, target.id.clone(), None);
+        let state = DurableObjectState::new_obj(cx, id, storage);
+        let state_value = Value::object(cx, &cx.root(state).into());
+        let env = Value::object(cx, &cx.root(new_env()).into());
+
+        let object =
+            call_method(cx, &helpers, "construct", &[class, state_value, env]).map_err(|e| {
+                anyhow!(
+                    "Failed to construct Durable Object {}: {e:?}",
+                    target.class_name
+                )
+            })?;
+
+        let instance = DurableObjectInstance {
+            object: TracedHeap::new((*object.to_object(cx)).get()),
+            state: TracedHeap::new(state),
+            store: store.clone(),
+        };
+        let result = (
+            TracedHeap::new(instance.object.get()),
+            TracedHeap::new(instance.state.get()),
+            store,
+        );
+        self.instances.borrow_mut().insert(target.clone(), instance);
+        Ok(result)
+    }
+
+    /// Delivers an event to the object it's addressed to. The returned promise
+    /// resolves to the object's response.
+    pub fn start_event(
+        &self,
+        cx: &Context,
+        exports: &Object,
+        new_env: impl FnOnce() -> *mut JSObject,
+        message: DurableObjectMessage,
+    ) -> Result<Promise> {
+        let (object, state, store) =
+            self.get_or_create_instance(cx, exports, new_env, &message.target)?;
+
+        let (request, retry_count) = match message.event {
+            DurableObjectEvent::Fetch(request) => (
+                Some(TracedHeap::new(
+                    crate::request_handlers::build_fetch_request(cx, request)?,
+                )),
+                0,
+            ),
+            DurableObjectEvent::Alarm { retry_count } => (None, retry_count),
+        };
+        let class_name = message.target.class_name;
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                // Events wait for blockConcurrencyWhile callbacks and open
+                // transactions to finish
+                let gate = DurableObjectState::get_private(&cx, &state.root(&cx).into())
+                    .unwrap()
+                    .gate
+                    .as_ref()
+                    .map(|gate| TracedHeap::new(gate.get()));
+                if let Some(gate) = gate {
+                    let future = {
+                        let gate = Promise::from_unchecked(gate.root(&cx));
+                        PromiseFuture::new(cx.duplicate(), &gate)
+                    };
+                    future.await;
+                }
+                if DurableObjectState::get_private(&cx, &state.root(&cx).into())
+                    .unwrap()
+                    .failed
+                {
+                    ion_err!(
+                        format!("blockConcurrencyWhile callback of {class_name} failed"),
+                        Normal
+                    );
+                }
+                store.gate.clone().wait(None).await;
+
+                match request {
+                    Some(request) => {
+                        let object = Object::from(object.root(&cx));
+                        let request = Value::object(&cx, &request.root(&cx).into());
+                        let response = call_method(&cx, &object, "fetch", &[request])?;
+                        Ok::<JSVal, _>(response.get())
+                    }
+                    None => run_alarm(cx, object, store, retry_count, &class_name).await,
+                }
+            })
+        }
+        .ok_or_else(|| anyhow!("Future queue must be initialized"))
+    }
+}
+
+async fn run_alarm(
+    cx: Context,
+    object: TracedHeap<*mut JSObject>,
+    store: ObjectStore,
+    retry_count: u32,
+    class_name: &str,
+) -> ion::Result<JSVal> {
+    let to_ion_error = |e: anyhow::Error| ion_mk_err!(format!("{e:#}"), Normal);
+
+    // The alarm may have been deleted or moved after it was scheduled
+    let scheduled_time = store.alarm().map_err(to_ion_error)?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as f64)
+        .unwrap_or_default();
+    let Some(scheduled_time) = scheduled_time.filter(|time| *time <= now) else {
+        return no_content_response(&cx);
+    };
+
+    // The alarm is cleared before the handler runs, so it can set a new one
+    store.store_alarm(None).map_err(to_ion_error)?;
+
+    let object = Object::from(object.root(&cx));
+    let alarm_info = Object::new(&cx);
+    alarm_info.set_as(&cx, "retryCount", &retry_count);
+    alarm_info.set_as(&cx, "isRetry", &(retry_count > 0));
+    let result = call_method(&cx, &object, "alarm", &[alarm_info.as_value(&cx)]);
+
+    let error = match result {
+        Err(e) => Some(format!("{e:?}")),
+        Ok(result)
+            if result.handle().is_object() && Promise::is_promise(&result.to_object(&cx)) =>
+        {
+            let future = {
+                let promise =
+                    unsafe { Promise::from_unchecked(result.to_object(&cx).into_local()) };
+                PromiseFuture::new(cx.duplicate(), &promise)
+            };
+            match future.await.1 {
+                Ok(_) => None,
+                Err(error) => {
+                    let error = Value::from(error.root(&cx));
+                    Some(String::from_value(&cx, &error, false, ()).unwrap_or_default())
+                }
+            }
+        }
+        Ok(_) => None,
+    };
+
+    if let Some(error) = error {
+        // Keep the alarm so it's retried, unless the handler set a new one
+        if store.alarm().map_err(to_ion_error)?.is_none() {
+            store
+                .store_alarm(Some(scheduled_time))
+                .map_err(to_ion_error)?;
+        }
+        ion_err!(
+            format!("Alarm handler of {class_name} failed: {error}"),
+            Normal
+        );
+    }
+
+    no_content_response(&cx)
+}
+
+fn no_content_response(cx: &Context) -> ion::Result<JSVal> {
+    let response = hyper::Response::builder()
+        .status(204)
+        .body(hyper::Body::empty())
+        .map_err(|e| ion_mk_err!(format!("Failed to build response: {e}"), Normal))?;
+    let url = url::Url::parse("http://durable-object.internal/").unwrap();
+    let response = FetchResponse::from_hyper_response(cx, response, url)?;
+    Ok(FetchResponse::new_object(cx, Box::new(response))
+        .as_value(cx)
+        .get())
+}
+
+fn storage_dir(config: &BindingsConfig) -> PathBuf {
+    config.persist_dir().join("durable_objects")
+}
+
+/// Schedules the alarms persisted by a previous run of the server. Must be
+/// called after the runner is registered as the Durable Object dispatcher.
+pub fn restore_alarms(config: &BindingsConfig) -> Result<()> {
+    let class_names = config
+        .durable_objects
+        .bindings
+        .iter()
+        .map(|binding| binding.class_name.as_str())
+        .collect::<HashSet<_>>();
+
+    for class_name in class_names {
+        let dir = storage_dir(config).join(class_name);
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().map(|e| e != "sqlite3").unwrap_or(true) {
+                continue;
+            }
+            let Some(id) = path.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+                continue;
+            };
+
+            if let Some(time) = storage::read_alarm(&path)? {
+                tracing::debug!("Restoring alarm of Durable Object {class_name} {id}");
+                schedule_alarm(
+                    DurableObjectTarget {
+                        class_name: class_name.to_string(),
+                        id,
+                    },
+                    Some(time),
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn is_valid_id(id: &str) -> bool {
+    id.len() == 64
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+#[js_class]
+pub struct DurableObjectNamespace {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    class_name: String,
+}
+
+impl DurableObjectNamespace {
+    pub fn new_obj(cx: &Context, class_name: String) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                class_name,
+            }),
+        )
+    }
+}
+
+#[js_class]
+impl DurableObjectNamespace {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<DurableObjectNamespace> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    #[ion(name = "idFromName")]
+    pub fn id_from_name(&self, cx: &Context, name: String) -> *mut JSObject {
+        let hash = sha2::Sha256::digest(format!("{}:{name}", self.class_name).as_bytes());
+        DurableObjectId::new_obj(cx, hex(&hash), Some(name))
+    }
+
+    #[ion(name = "newUniqueId")]
+    pub fn new_unique_id(&self, cx: &Context) -> *mut JSObject {
+        let id = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        DurableObjectId::new_obj(cx, id, None)
+    }
+
+    #[ion(name = "idFromString")]
+    pub fn id_from_string(&self, cx: &Context, id: String) -> ion::Result<*mut JSObject> {
+        if !is_valid_id(&id) {
+            ion_err!("Invalid Durable Object ID, must be 64 hex digits", Type);
+        }
+        Ok(DurableObjectId::new_obj(cx, id, None))
+    }
+
+    pub fn get(&self, cx: &Context, id: &DurableObjectId) -> *mut JSObject {
+        DurableObjectStub::new_obj(
+            cx,
+            id.reflector().get(),
+            DurableObjectTarget {
+                class_name: self.class_name.clone(),
+                id: id.id.clone(),
+            },
+            id.name.clone(),
+        )
+    }
+}
+
+#[js_class]
+pub struct DurableObjectId {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    id: String,
+
+    #[trace(no_trace)]
+    name: Option<String>,
+}
+
+impl DurableObjectId {
+    fn new_obj(cx: &Context, id: String, name: Option<String>) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                id,
+                name,
+            }),
+        )
+    }
+}
+
+#[js_class]
+impl DurableObjectId {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<DurableObjectId> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    #[ion(name = "toString")]
+    pub fn to_string(&self) -> String {
+        self.id.clone()
+    }
+
+    pub fn equals(&self, other: &DurableObjectId) -> bool {
+        self.id == other.id
+    }
+
+    #[ion(get)]
+    pub fn get_name(&self) -> Option<String> {
+        self.name.clone()
+    }
+}
+
+#[js_class]
+pub struct DurableObjectStub {
+    reflector: Reflector,
+
+    id: Heap<*mut JSObject>,
+
+    #[trace(no_trace)]
+    target: DurableObjectTarget,
+
+    #[trace(no_trace)]
+    name: Option<String>,
+}
+
+impl DurableObjectStub {
+    fn new_obj(
+        cx: &Context,
+        id: *mut JSObject,
+        target: DurableObjectTarget,
+        name: Option<String>,
+    ) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                id: Heap::new(id),
+                target,
+                name,
+            }),
+        )
+    }
+}
+
+#[js_class]
+impl DurableObjectStub {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<DurableObjectStub> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    #[ion(get)]
+    pub fn get_id(&self) -> *mut JSObject {
+        self.id.get()
+    }
+
+    #[ion(get)]
+    pub fn get_name(&self) -> Option<String> {
+        self.name.clone()
+    }
+
+    pub fn fetch(
+        &self,
+        cx: &Context,
+        input: RequestInfo,
+        Opt(init): Opt<RequestInit>,
+    ) -> ion::Result<Option<Promise>> {
+        let request = FetchRequest::constructor(cx, input, Opt(init))?;
+        let request_heap = TracedHeap::new(FetchRequest::new_object(cx, Box::new(request)));
+        let target = self.target.clone();
+
+        Ok(unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let request =
+                    FetchRequest::get_mut_private(&cx, &request_heap.root(&cx).into()).unwrap();
+
+                let mut http_req = http::Request::builder()
+                    .uri(request.get_url())
+                    .method(request.method());
+
+                for header in request.headers(&cx) {
+                    http_req = http_req.header(header.0.clone(), header.1.clone())
+                }
+
+                let request_body = request.take_body()?;
+                let (cx, body_bytes) = cx.await_native_cx(|cx| request_body.into_bytes(cx)).await;
+                let body = match body_bytes? {
+                    Some(bytes) => hyper::Body::from(bytes),
+                    None => hyper::Body::empty(),
+                };
+                let (parts, body) = http_req
+                    .body(body)
+                    .map_err(|e| ion_mk_err!(format!("Invalid request: {e}"), Type))?
+                    .into_parts();
+
+                let url = url::Url::parse(parts.uri.to_string().as_str())?;
+                let (cx, response) = cx
+                    .await_native(dispatch(DurableObjectMessage {
+                        target,
+                        event: DurableObjectEvent::Fetch(Request { parts, body }),
+                    }))
+                    .await;
+                let response = response.map_err(|e| {
+                    ion_mk_err!(format!("Durable Object request failed: {e:#}"), Normal)
+                })?;
+                let response = FetchResponse::from_hyper_response(&cx, response, url)?;
+                Ok(FetchResponse::new_object(&cx, Box::new(response)))
+            })
+        })
+    }
+}
+
+#[js_class]
+pub struct DurableObjectState {
+    reflector: Reflector,
+
+    id: Heap<*mut JSObject>,
+
+    storage: Heap<*mut JSObject>,
+
+    // Settles once the last blockConcurrencyWhile callback is done, and is
+    // cleared at that point
+    gate: Option<Heap<*mut JSObject>>,
+
+    // Incremented by each blockConcurrencyWhile call, so a callback that
+    // finishes doesn't clear the gate of a later call
+    gate_generation: u32,
+
+    // Set when a blockConcurrencyWhile callback fails, after which the
+    // object is replaced with a new instance
+    failed: bool,
+}
+
+impl DurableObjectState {
+    fn new_obj(cx: &Context, id: *mut JSObject, storage: *mut JSObject) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                id: Heap::new(id),
+                storage: Heap::new(storage),
+                gate: None,
+                gate_generation: 0,
+                failed: false,
+            }),
+        )
+    }
+}
+
+#[js_class]
+impl DurableObjectState {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<DurableObjectState> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    #[ion(get)]
+    pub fn get_id(&self) -> *mut JSObject {
+        self.id.get()
+    }
+
+    #[ion(get)]
+    pub fn get_storage(&self) -> *mut JSObject {
+        self.storage.get()
+    }
+
+    #[ion(name = "blockConcurrencyWhile")]
+    pub fn block_concurrency_while(
+        &mut self,
+        cx: &Context,
+        callback: Function,
+    ) -> ion::Result<*mut JSObject> {
+        let result = match callback.call(cx, &Object::null(cx), &[]) {
+            Ok(result) => result,
+            Err(e) => {
+                self.failed = true;
+                ion_err!(
+                    format!(
+                        "blockConcurrencyWhile callback failed: {}",
+                        error_report_option_to_anyhow_error(cx, e)
+                    ),
+                    Normal
+                );
+            }
+        };
+
+        let promise = if result.handle().is_object() && Promise::is_promise(&result.to_object(cx)) {
+            (*result.to_object(cx)).get()
+        } else {
+            (*Promise::resolved(cx, result)).get()
+        };
+
+        self.gate_generation = self.gate_generation.wrapping_add(1);
+        let generation = self.gate_generation;
+        let state = TracedHeap::new(self.reflector().get());
+        let callback_promise = TracedHeap::new(promise);
+        let gate = unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let future = {
+                    let promise = Promise::from_unchecked(callback_promise.root(&cx));
+                    PromiseFuture::new(cx.duplicate(), &promise)
+                };
+                let result = future.await.1;
+
+                let state =
+                    DurableObjectState::get_mut_private(&cx, &state.root(&cx).into()).unwrap();
+                if state.gate_generation == generation {
+                    state.gate = None;
+                }
+                // Events check this flag once the gate settles, so the gate
+                // itself never rejects
+                state.failed |= result.is_err();
+                Ok::<JSVal, _>(UndefinedValue())
+            })
+        }
+        .ok_or_else(|| ion_mk_err!("Future queue must be initialized", Normal))?;
+        self.gate = Some(Heap::new((*gate).get()));
+
+        Ok(promise)
+    }
+
+    #[ion(name = "waitUntil")]
+    pub fn wait_until(&self, _promise: Promise) {
+        // No need to do anything, the runtime will run the promise anyway
+    }
+}
+
+pub fn define(cx: &Context, global: &Object) -> bool {
+    DurableObjectNamespace::init_class(cx, global).0
+        && DurableObjectId::init_class(cx, global).0
+        && DurableObjectStub::init_class(cx, global).0
+        && DurableObjectState::init_class(cx, global).0
+        && DurableObjectStorage::init_class(cx, global).0
+}
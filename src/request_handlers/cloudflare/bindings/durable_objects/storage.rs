@@ -0,0 +1,680 @@
+use std::{
+    cell::{Cell, RefCell},
+    path::Path,
+    rc::Rc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use futures::channel::oneshot;
+use ion::{
+    class::Reflector,
+    conversions::{FromValue, ToValue},
+    function::Opt,
+    ClassDefinition, Context, Function, Heap, Object, Promise, PromiseFuture, TracedHeap, Value,
+};
+use mozjs::jsval::JSVal;
+use mozjs_sys::jsapi::JSObject;
+use runtime::promise::future_to_promise;
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::{
+    ion_err, ion_mk_err,
+    runners::durable_objects::{schedule_alarm, DurableObjectTarget},
+    sm_utils::{call_method, error_report_option_to_anyhow_error, json_parse, json_stringify},
+};
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The SQLite database an object's storage lives in. Objects only ever run
+/// on one thread, so the connection is never shared across threads.
+#[derive(Clone)]
+pub struct ObjectStore {
+    conn: Rc<Connection>,
+    target: DurableObjectTarget,
+    pub gate: Rc<InputGate>,
+}
+
+/// Serialises access to an object's storage while a transaction is open.
+/// Transactions are savepoints on the object's one connection, so storage
+/// operations made outside the transaction, other transactions and new
+/// events wait until it's finished instead of ending up inside it.
+#[derive(Default)]
+pub struct InputGate {
+    // The transaction currently holding the gate
+    holder: Cell<Option<u64>>,
+    last_id: Cell<u64>,
+    waiters: RefCell<Vec<oneshot::Sender<()>>>,
+}
+
+/// Releases the gate when dropped.
+struct GateLock(Rc<InputGate>);
+
+impl Drop for GateLock {
+    fn drop(&mut self) {
+        self.0.holder.set(None);
+        for waiter in self.0.waiters.take() {
+            _ = waiter.send(());
+        }
+    }
+}
+
+impl InputGate {
+    /// Operations of the transaction holding the gate always go through.
+    fn is_open_to(&self, owner: Option<u64>) -> bool {
+        self.holder.get().is_none() || self.holder.get() == owner
+    }
+
+    /// Resolves once operations made by `owner` may run.
+    pub async fn wait(self: Rc<Self>, owner: Option<u64>) {
+        while !self.is_open_to(owner) {
+            let (sender, receiver) = oneshot::channel();
+            self.waiters.borrow_mut().push(sender);
+            _ = receiver.await;
+        }
+    }
+
+    /// Must only be called while the gate is open.
+    fn lock(self: &Rc<Self>) -> (u64, GateLock) {
+        let id = self.last_id.get() + 1;
+        self.last_id.set(id);
+        self.holder.set(Some(id));
+        (id, GateLock(self.clone()))
+    }
+}
+
+#[derive(FromValue, Default)]
+pub struct StorageListOptions {
+    start: Option<String>,
+    start_after: Option<String>,
+    end: Option<String>,
+    prefix: Option<String>,
+    reverse: Option<bool>,
+    limit: Option<u32>,
+}
+
+impl ObjectStore {
+    pub fn open(path: impl AsRef<Path>, target: DurableObjectTarget) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open object storage {}", path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.pragma_update_and_check(None, "journal_mode", "wal", |_| Ok(()))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS _winterjs_kv (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS _winterjs_alarm (
+                id INTEGER PRIMARY KEY CHECK (id = 0),
+                scheduled_time REAL NOT NULL
+            );",
+        )?;
+
+        Ok(Self {
+            conn: Rc::new(conn),
+            target,
+            gate: Default::default(),
+        })
+    }
+
+    /// Runs `f` inside a savepoint, so multi-key operations are atomic even
+    /// when they're not part of an explicit transaction.
+    fn atomically<T>(&self, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        self.conn.execute_batch("SAVEPOINT _winterjs_op")?;
+        match f(&self.conn) {
+            Ok(value) => {
+                self.conn.execute_batch("RELEASE _winterjs_op")?;
+                Ok(value)
+            }
+            Err(e) => {
+                self.conn
+                    .execute_batch("ROLLBACK TO _winterjs_op; RELEASE _winterjs_op")?;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn get(&self, keys: &[String]) -> Result<Vec<(String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT value FROM _winterjs_kv WHERE key = ?1")?;
+        let mut entries = vec![];
+        for key in keys {
+            if let Some(value) = stmt
+                .query_row([key], |row| row.get::<_, String>(0))
+                .optional()?
+            {
+                entries.push((key.clone(), value));
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn put(&self, entries: &[(String, String)]) -> Result<()> {
+        self.atomically(|conn| {
+            let mut stmt = conn.prepare_cached(
+                "INSERT INTO _winterjs_kv (key, value) VALUES (?1, ?2)
+                ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            )?;
+            for (key, value) in entries {
+                stmt.execute([key, value])?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the number of keys that existed.
+    pub fn delete(&self, keys: &[String]) -> Result<usize> {
+        self.atomically(|conn| {
+            let mut stmt = conn.prepare_cached("DELETE FROM _winterjs_kv WHERE key = ?1")?;
+            let mut deleted = 0;
+            for key in keys {
+                deleted += stmt.execute([key])?;
+            }
+            Ok(deleted)
+        })
+    }
+
+    pub fn delete_all(&self) -> Result<()> {
+        self.conn.execute("DELETE FROM _winterjs_kv", [])?;
+        Ok(())
+    }
+
+    pub fn list(&self, options: &StorageListOptions) -> Result<Vec<(String, String)>> {
+        let mut conditions = vec!["1 = 1"];
+        let mut params = vec![];
+        if let Some(start) = &options.start {
+            conditions.push("key >= ?");
+            params.push(start.clone());
+        }
+        if let Some(start_after) = &options.start_after {
+            conditions.push("key > ?");
+            params.push(start_after.clone());
+        }
+        if let Some(end) = &options.end {
+            conditions.push("key < ?");
+            params.push(end.clone());
+        }
+        if let Some(prefix) = &options.prefix {
+            conditions.push("substr(key, 1, length(?)) = ?");
+            params.push(prefix.clone());
+            params.push(prefix.clone());
+        }
+
+        let sql = format!(
+            "SELECT key, value FROM _winterjs_kv WHERE {} ORDER BY key {} LIMIT {}",
+            conditions.join(" AND "),
+            if options.reverse.unwrap_or(false) {
+                "DESC"
+            } else {
+                "ASC"
+            },
+            options.limit.map(i64::from).unwrap_or(-1)
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(params.iter()), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn alarm(&self) -> Result<Option<f64>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT scheduled_time FROM _winterjs_alarm WHERE id = 0",
+                [],
+                |row| row.get::<_, f64>(0),
+            )
+            .optional()?)
+    }
+
+    /// Stores the alarm without scheduling it.
+    pub fn store_alarm(&self, scheduled_time: Option<f64>) -> Result<()> {
+        match scheduled_time {
+            Some(time) => self.conn.execute(
+                "INSERT INTO _winterjs_alarm (id, scheduled_time) VALUES (0, ?1)
+                ON CONFLICT (id) DO UPDATE SET scheduled_time = excluded.scheduled_time",
+                [time],
+            )?,
+            None => self
+                .conn
+                .execute("DELETE FROM _winterjs_alarm WHERE id = 0", [])?,
+        };
+        Ok(())
+    }
+
+    pub fn set_alarm(&self, scheduled_time: Option<f64>) -> Result<()> {
+        self.store_alarm(scheduled_time)?;
+        schedule_alarm(self.target.clone(), scheduled_time);
+        Ok(())
+    }
+
+    fn savepoint(&self, name: &str) -> Result<()> {
+        self.conn.execute_batch(&format!("SAVEPOINT \"{name}\""))?;
+        Ok(())
+    }
+
+    fn release(&self, name: &str) -> Result<()> {
+        self.conn.execute_batch(&format!("RELEASE \"{name}\""))?;
+        Ok(())
+    }
+
+    fn rollback_to(&self, name: &str) -> Result<()> {
+        self.conn
+            .execute_batch(&format!("ROLLBACK TO \"{name}\""))?;
+        Ok(())
+    }
+}
+
+fn to_ion_error(e: anyhow::Error) -> ion::Error {
+    ion_mk_err!(format!("{e:#}"), Normal)
+}
+
+fn settle(cx: &Context, result: ion::Result<JSVal>) -> Promise {
+    match result {
+        Ok(value) => Promise::resolved(cx, value),
+        Err(e) => Promise::rejected(cx, e),
+    }
+}
+
+#[derive(FromValue)]
+pub enum StorageKeys {
+    #[ion(inherit)]
+    Many(Vec<String>),
+    #[ion(inherit)]
+    One(String),
+}
+
+pub struct TransactionState {
+    // The ID the transaction holds the input gate with
+    owner: u64,
+    savepoint: String,
+    rolled_back: Cell<bool>,
+}
+
+impl TransactionState {
+    fn finish(&self, store: &ObjectStore, success: bool) -> Result<()> {
+        if !success && !self.rolled_back.get() {
+            store.rollback_to(&self.savepoint)?;
+        }
+        store.release(&self.savepoint)
+    }
+}
+
+#[js_class]
+pub struct DurableObjectStorage {
+    reflector: Reflector,
+
+    // The helper object of the thread, used to create `Map`s
+    helpers: Heap<*mut JSObject>,
+
+    #[trace(no_trace)]
+    store: ObjectStore,
+
+    // Set when this object is passed to a `transaction` callback
+    #[trace(no_trace)]
+    transaction: Option<Rc<TransactionState>>,
+}
+
+impl DurableObjectStorage {
+    pub fn new_obj(
+        cx: &Context,
+        store: ObjectStore,
+        helpers: *mut JSObject,
+        transaction: Option<Rc<TransactionState>>,
+    ) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                helpers: Heap::new(helpers),
+                store,
+                transaction,
+            }),
+        )
+    }
+
+    fn owner(&self) -> Option<u64> {
+        self.transaction.as_ref().map(|t| t.owner)
+    }
+
+    /// Runs a storage operation right away if the input gate lets it
+    /// through, or once the transaction holding the gate is finished.
+    fn run<F>(&self, cx: &Context, op: F) -> Option<Promise>
+    where
+        F: FnOnce(&Context, &ObjectStore, &Object) -> ion::Result<JSVal> + 'static,
+    {
+        let owner = self.owner();
+        if self.store.gate.is_open_to(owner) {
+            let helpers = Object::from(cx.root(self.helpers.get()));
+            return Some(settle(cx, op(cx, &self.store, &helpers)));
+        }
+
+        let store = self.store.clone();
+        let helpers = TracedHeap::new(self.helpers.get());
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                store.gate.clone().wait(owner).await;
+                let helpers = Object::from(helpers.root(&cx));
+                op(&cx, &store, &helpers)
+            })
+        }
+    }
+}
+
+fn entries_to_map(
+    cx: &Context,
+    helpers: &Object,
+    entries: Vec<(String, String)>,
+) -> ion::Result<JSVal> {
+    let entries = entries
+        .into_iter()
+        .map(|(key, value)| {
+            let value = json_parse(cx, &value)?;
+            Ok(vec![key.as_value(cx).get(), value.get()].as_value(cx).get())
+        })
+        .collect::<ion::Result<Vec<JSVal>>>()?;
+    Ok(call_method(cx, helpers, "toMap", &[entries.as_value(cx)])?.get())
+}
+
+fn begin_transaction<'cx>(
+    cx: &'cx Context,
+    store: &ObjectStore,
+    helpers: *mut JSObject,
+    owner: u64,
+) -> ion::Result<(Rc<TransactionState>, Value<'cx>)> {
+    let transaction = Rc::new(TransactionState {
+        owner,
+        savepoint: format!("_winterjs_txn_{}", uuid::Uuid::new_v4().simple()),
+        rolled_back: Cell::new(false),
+    });
+    store
+        .savepoint(&transaction.savepoint)
+        .map_err(to_ion_error)?;
+    let txn = DurableObjectStorage::new_obj(cx, store.clone(), helpers, Some(transaction.clone()));
+    Ok((transaction, Value::object(cx, &cx.root(txn).into())))
+}
+
+fn alarm_time(cx: &Context, value: &Value) -> ion::Result<f64> {
+    if value.handle().is_number() {
+        return Ok(value.handle().to_number());
+    }
+    if value.handle().is_object() {
+        let time = call_method(cx, &value.to_object(cx), "getTime", &[])?;
+        if time.handle().is_number() {
+            return Ok(time.handle().to_number());
+        }
+    }
+    ion_err!("The scheduled time must be a number or a Date", Type)
+}
+
+#[js_class]
+impl DurableObjectStorage {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<DurableObjectStorage> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    pub fn get(&self, cx: &Context, keys: StorageKeys) -> Option<Promise> {
+        self.run(cx, move |cx, store, helpers| match keys {
+            StorageKeys::One(key) => {
+                match store.get(&[key]).map_err(to_ion_error)?.into_iter().next() {
+                    Some((_, value)) => Ok(json_parse(cx, &value)?.get()),
+                    None => Ok(Value::undefined(cx).get()),
+                }
+            }
+            StorageKeys::Many(keys) => {
+                let entries = store.get(&keys).map_err(to_ion_error)?;
+                entries_to_map(cx, helpers, entries)
+            }
+        })
+    }
+
+    pub fn put(
+        &self,
+        cx: &Context,
+        key_or_entries: Value,
+        Opt(value): Opt<Value>,
+    ) -> Option<Promise> {
+        // Values are serialized right away, so later changes to them don't
+        // affect what's stored
+        let entries = (|| {
+            if key_or_entries.handle().is_string() {
+                let key = String::from_value(cx, &key_or_entries, true, ())?;
+                let Some(value) = value else {
+                    ion_err!("A value is required when putting a single key", Type);
+                };
+                Ok(vec![(key, json_stringify(cx, value)?)])
+            } else if key_or_entries.handle().is_object() {
+                let entries = json_stringify(cx, key_or_entries)?;
+                let entries =
+                    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&entries)
+                        .map_err(|e| ion_mk_err!(format!("Invalid entries: {e}"), Type))?;
+                Ok(entries
+                    .into_iter()
+                    .map(|(key, value)| (key, value.to_string()))
+                    .collect())
+            } else {
+                ion_err!("Expected a key or an object of entries", Type);
+            }
+        })();
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => return Some(Promise::rejected(cx, e)),
+        };
+
+        self.run(cx, move |cx, store, _| {
+            store.put(&entries).map_err(to_ion_error)?;
+            Ok(Value::undefined(cx).get())
+        })
+    }
+
+    pub fn delete(&self, cx: &Context, keys: StorageKeys) -> Option<Promise> {
+        self.run(cx, move |cx, store, _| {
+            Ok(match keys {
+                StorageKeys::One(key) => {
+                    let deleted = store.delete(&[key]).map_err(to_ion_error)?;
+                    (deleted > 0).as_value(cx).get()
+                }
+                StorageKeys::Many(keys) => {
+                    let deleted = store.delete(&keys).map_err(to_ion_error)?;
+                    (deleted as f64).as_value(cx).get()
+                }
+            })
+        })
+    }
+
+    #[ion(name = "deleteAll")]
+    pub fn delete_all(&self, cx: &Context) -> Option<Promise> {
+        self.run(cx, |cx, store, _| {
+            store.delete_all().map_err(to_ion_error)?;
+            Ok(Value::undefined(cx).get())
+        })
+    }
+
+    pub fn list(&self, cx: &Context, Opt(options): Opt<StorageListOptions>) -> Option<Promise> {
+        let options = options.unwrap_or_default();
+        self.run(cx, move |cx, store, helpers| {
+            let entries = store.list(&options).map_err(to_ion_error)?;
+            entries_to_map(cx, helpers, entries)
+        })
+    }
+
+    #[ion(name = "getAlarm")]
+    pub fn get_alarm(&self, cx: &Context) -> Option<Promise> {
+        self.run(cx, |cx, store, _| {
+            Ok(match store.alarm().map_err(to_ion_error)? {
+                Some(time) => time.as_value(cx).get(),
+                None => Value::null(cx).get(),
+            })
+        })
+    }
+
+    #[ion(name = "setAlarm")]
+    pub fn set_alarm(&self, cx: &Context, scheduled_time: Value) -> Option<Promise> {
+        let time = match alarm_time(cx, &scheduled_time) {
+            Ok(time) => time,
+            Err(e) => return Some(Promise::rejected(cx, e)),
+        };
+        self.run(cx, move |cx, store, _| {
+            store.set_alarm(Some(time)).map_err(to_ion_error)?;
+            Ok(Value::undefined(cx).get())
+        })
+    }
+
+    #[ion(name = "deleteAlarm")]
+    pub fn delete_alarm(&self, cx: &Context) -> Option<Promise> {
+        self.run(cx, |cx, store, _| {
+            store.set_alarm(None).map_err(to_ion_error)?;
+            Ok(Value::undefined(cx).get())
+        })
+    }
+
+    pub fn sync(&self, cx: &Context) -> Option<Promise> {
+        // Writes are committed as soon as they're made, but the ones waiting
+        // for a transaction aren't made yet
+        self.run(cx, |cx, _, _| Ok(Value::undefined(cx).get()))
+    }
+
+    /// Waits for the transaction holding the input gate, if any, and holds it
+    /// until the callback's promise settles. Storage operations have to go
+    /// through the transaction object passed to the callback; using the
+    /// object's own storage from the callback would wait for the transaction
+    /// to finish.
+    pub fn transaction(&self, cx: &Context, callback: Object) -> Option<Promise> {
+        if Function::from_object(cx, &callback).is_none() {
+            return Some(Promise::rejected(
+                cx,
+                ion_mk_err!("The transaction callback must be a function", Type),
+            ));
+        }
+
+        let store = self.store.clone();
+        let owner = self.owner();
+        let helpers = TracedHeap::new(self.helpers.get());
+        let callback = TracedHeap::new((*callback).get());
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                store.gate.clone().wait(owner).await;
+                // Nested transactions already hold the gate
+                let nested = owner.filter(|owner| store.gate.holder.get() == Some(*owner));
+                let (owner, _lock) = match nested {
+                    Some(owner) => (owner, None),
+                    None => {
+                        let (owner, lock) = store.gate.lock();
+                        (owner, Some(lock))
+                    }
+                };
+
+                let (transaction, txn) = begin_transaction(&cx, &store, helpers.get(), owner)?;
+                let callback = Object::from(callback.root(&cx));
+                let callback = Function::from_object(&cx, &callback).unwrap();
+                let result = match callback.call(&cx, &Object::null(&cx), &[txn]) {
+                    Ok(result) => TracedHeap::new(result.get()),
+                    Err(e) => {
+                        let error = error_report_option_to_anyhow_error(&cx, e);
+                        let error = match transaction.finish(&store, false) {
+                            Ok(()) => error,
+                            Err(e) => e.context(error),
+                        };
+                        return Err(to_ion_error(error));
+                    }
+                };
+
+                let value = Value::from(result.root(&cx));
+                let success = if value.handle().is_object()
+                    && Promise::is_promise(&value.to_object(&cx))
+                {
+                    let future = {
+                        let promise = Promise::from_unchecked(value.to_object(&cx).into_local());
+                        PromiseFuture::new(cx.duplicate(), &promise)
+                    };
+                    future.await.1.is_ok()
+                } else {
+                    true
+                };
+
+                transaction.finish(&store, success).map_err(to_ion_error)?;
+
+                // Resolving with the callback's result also carries over its
+                // rejection, if any
+                Ok::<JSVal, _>(result.root(&cx).get())
+            })
+        }
+    }
+
+    #[ion(name = "transactionSync")]
+    pub fn transaction_sync<'cx>(
+        &self,
+        cx: &'cx Context,
+        callback: Function,
+    ) -> ion::Result<Value<'cx>> {
+        let owner = self.owner();
+        if !self.store.gate.is_open_to(owner) {
+            ion_err!(
+                "transactionSync() can't be used while another transaction is open",
+                Normal
+            );
+        }
+        let nested = owner.filter(|owner| self.store.gate.holder.get() == Some(*owner));
+        let (owner, _lock) = match nested {
+            Some(owner) => (owner, None),
+            None => {
+                let (owner, lock) = self.store.gate.lock();
+                (owner, Some(lock))
+            }
+        };
+
+        let (transaction, txn) = begin_transaction(cx, &self.store, self.helpers.get(), owner)?;
+        match callback.call(cx, &Object::null(cx), &[txn]) {
+            Ok(result) => {
+                transaction
+                    .finish(&self.store, true)
+                    .map_err(to_ion_error)?;
+                Ok(result)
+            }
+            Err(e) => {
+                let error = error_report_option_to_anyhow_error(cx, e);
+                transaction
+                    .finish(&self.store, false)
+                    .map_err(to_ion_error)?;
+                Err(to_ion_error(error))
+            }
+        }
+    }
+
+    pub fn rollback(&self) -> ion::Result<()> {
+        let Some(transaction) = &self.transaction else {
+            ion_err!("rollback() can only be called on a transaction", Normal);
+        };
+        if transaction.rolled_back.replace(true) {
+            return Ok(());
+        }
+        self.store
+            .rollback_to(&transaction.savepoint)
+            .map_err(to_ion_error)
+    }
+}
+
+/// Reads the alarm of a stored object, without opening it for writing.
+pub fn read_alarm(path: &Path) -> Result<Option<f64>> {
+    let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open object storage {}", path.display()))?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.query_row(
+        "SELECT scheduled_time FROM _winterjs_alarm WHERE id = 0",
+        [],
+        |row| row.get::<_, f64>(0),
+    )
+    .optional()
+    .map_err(|e| anyhow!("Failed to read alarm from {}: {e}", path.display()))
+}
@@ -0,0 +1,529 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use base64::Engine;
+use ion::{
+    class::Reflector, conversions::ToValue, function::Opt, typedarray::ArrayBuffer,
+    ClassDefinition, Context, Object, Promise, Value,
+};
+use mozjs_sys::jsapi::JSObject;
+use runtime::{globals::fetch::FetchBody, promise::future_to_promise};
+use serde::{Deserialize, Serialize};
+use strum::EnumString;
+
+use crate::{
+    ion_err, ion_mk_err,
+    sm_utils::{json_parse, json_stringify},
+};
+
+use super::{
+    bytes_to_stream, entry_file_name, read_entry_header, run_blocking, unix_time_now,
+    write_entry_file,
+};
+
+const MAX_KEY_LENGTH: usize = 512;
+const MAX_METADATA_LENGTH: usize = 1024;
+const MAX_LIST_LIMIT: u32 = 1000;
+const MIN_EXPIRATION_TTL: u64 = 60;
+
+/// Stores each entry of a namespace in its own file, named after the SHA-256
+/// hash of the key. The [`EntryHeader`] is stored in front of the value.
+#[derive(Clone)]
+pub struct KvStore {
+    root: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntryHeader {
+    key: String,
+    expiration: Option<u64>,
+    // Stored as a JSON string, since it's opaque to us
+    metadata: Option<String>,
+}
+
+impl EntryHeader {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expiration.map(|e| e <= now).unwrap_or(false)
+    }
+}
+
+pub struct KvEntry {
+    pub value: Vec<u8>,
+    pub metadata: Option<String>,
+}
+
+pub struct KvKey {
+    pub name: String,
+    pub expiration: Option<u64>,
+    pub metadata: Option<String>,
+}
+
+pub struct KvListing {
+    pub keys: Vec<KvKey>,
+    pub cursor: Option<String>,
+}
+
+impl KvStore {
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root)
+            .with_context(|| format!("Failed to create directory {}", root.display()))?;
+        Ok(Self { root })
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.root.join(entry_file_name(key))
+    }
+
+    fn open_entry(&self, path: &Path) -> Result<Option<(File, EntryHeader)>> {
+        let mut file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to open KV entry"),
+        };
+        let header: EntryHeader = read_entry_header(&mut file)?;
+
+        if header.is_expired(unix_time_now()) {
+            drop(file);
+            // Another thread may have deleted or replaced the entry already,
+            // which is fine
+            _ = std::fs::remove_file(path);
+            return Ok(None);
+        }
+
+        Ok(Some((file, header)))
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<KvEntry>> {
+        let Some((mut file, header)) = self.open_entry(&self.entry_path(key))? else {
+            return Ok(None);
+        };
+
+        let mut value = vec![];
+        file.read_to_end(&mut value)
+            .context("Failed to read KV entry")?;
+        Ok(Some(KvEntry {
+            value,
+            metadata: header.metadata,
+        }))
+    }
+
+    pub fn put(
+        &self,
+        key: String,
+        value: &[u8],
+        expiration: Option<u64>,
+        metadata: Option<String>,
+    ) -> Result<()> {
+        let path = self.entry_path(&key);
+        let header = EntryHeader {
+            key,
+            expiration,
+            metadata,
+        };
+
+        write_entry_file(&path, &header, |file| Ok(file.write_all(value)?))
+            .context("Failed to write KV entry")
+    }
+
+    pub fn delete(&self, key: &str) -> Result<()> {
+        match std::fs::remove_file(self.entry_path(key)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to delete KV entry"),
+        }
+    }
+
+    pub fn list(
+        &self,
+        prefix: Option<&str>,
+        limit: u32,
+        cursor: Option<&str>,
+    ) -> Result<KvListing> {
+        let after = cursor
+            .map(|c| {
+                base64::prelude::BASE64_URL_SAFE_NO_PAD
+                    .decode(c)
+                    .ok()
+                    .and_then(|k| String::from_utf8(k).ok())
+                    .context("Invalid cursor")
+            })
+            .transpose()?;
+
+        let mut keys = vec![];
+        for entry in std::fs::read_dir(&self.root).context("Failed to read KV directory")? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let Some((_, header)) = self.open_entry(&entry.path())? else {
+                continue;
+            };
+
+            if prefix.map(|p| !header.key.starts_with(p)).unwrap_or(false) {
+                continue;
+            }
+
+            if after.as_ref().map(|a| header.key <= *a).unwrap_or(false) {
+                continue;
+            }
+
+            keys.push(KvKey {
+                name: header.key,
+                expiration: header.expiration,
+                metadata: header.metadata,
+            });
+        }
+
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let cursor = if keys.len() > limit as usize {
+            keys.truncate(limit as usize);
+            keys.last()
+                .map(|k| base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(&k.name))
+        } else {
+            None
+        };
+
+        Ok(KvListing { keys, cursor })
+    }
+}
+
+#[derive(EnumString, Clone, Copy)]
+#[strum(serialize_all = "camelCase")]
+enum KvValueType {
+    Text,
+    Json,
+    ArrayBuffer,
+    Stream,
+}
+
+#[derive(FromValue)]
+pub struct KvGetOptionsObject {
+    #[ion(name = "type")]
+    value_type: Option<String>,
+}
+
+#[derive(FromValue)]
+pub enum KvGetOptions {
+    #[ion(inherit)]
+    Object(KvGetOptionsObject),
+    #[ion(inherit)]
+    Type(String),
+}
+
+impl KvGetOptions {
+    fn value_type(options: Option<Self>) -> ion::Result<KvValueType> {
+        let value_type = match options {
+            None | Some(Self::Object(KvGetOptionsObject { value_type: None })) => {
+                return Ok(KvValueType::Text)
+            }
+            Some(Self::Object(KvGetOptionsObject {
+                value_type: Some(t),
+            }))
+            | Some(Self::Type(t)) => t,
+        };
+
+        value_type.parse().map_err(|_| {
+            ion_mk_err!(
+                format!(
+                    "Unknown response type '{value_type}', must be one of \
+                    'text', 'json', 'arrayBuffer' or 'stream'"
+                ),
+                Type
+            )
+        })
+    }
+}
+
+#[derive(FromValue)]
+pub struct KvPutOptions<'cx> {
+    expiration: Option<f64>,
+    expiration_ttl: Option<f64>,
+    metadata: Option<Value<'cx>>,
+}
+
+#[derive(FromValue, Default)]
+pub struct KvListOptions {
+    prefix: Option<String>,
+    limit: Option<u32>,
+    cursor: Option<String>,
+}
+
+#[js_class]
+pub struct KvNamespace {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    store: KvStore,
+}
+
+impl KvNamespace {
+    pub fn new_obj(cx: &Context, store: KvStore) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                store,
+            }),
+        )
+    }
+
+    fn get_impl(
+        &self,
+        cx: &Context,
+        key: String,
+        options: Option<KvGetOptions>,
+        with_metadata: bool,
+    ) -> Option<Promise> {
+        let value_type = match validate_key(&key).and_then(|()| KvGetOptions::value_type(options)) {
+            Ok(t) => t,
+            Err(e) => return Some(Promise::rejected(cx, e)),
+        };
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, entry) = run_blocking(cx, move || store.get(&key)).await;
+
+                let (value, metadata) = match entry? {
+                    Some(entry) => (
+                        value_to_js(&cx, entry.value, value_type)?,
+                        metadata_to_js(&cx, entry.metadata.as_deref())?,
+                    ),
+                    None => (Value::null(&cx), Value::null(&cx)),
+                };
+
+                if with_metadata {
+                    let result = Object::new(&cx);
+                    if !result.set(&cx, "value", &value)
+                        || !result.set(&cx, "metadata", &metadata)
+                        || !result.set(&cx, "cacheStatus", &Value::null(&cx))
+                    {
+                        ion_err!("Failed to create result object", Normal);
+                    }
+                    Ok(result.as_value(&cx).get())
+                } else {
+                    Ok(value.get())
+                }
+            })
+        }
+    }
+}
+
+#[js_class]
+impl KvNamespace {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<KvNamespace> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    pub fn get(
+        &self,
+        cx: &Context,
+        key: String,
+        Opt(options): Opt<KvGetOptions>,
+    ) -> Option<Promise> {
+        self.get_impl(cx, key, options, false)
+    }
+
+    #[ion(name = "getWithMetadata")]
+    pub fn get_with_metadata(
+        &self,
+        cx: &Context,
+        key: String,
+        Opt(options): Opt<KvGetOptions>,
+    ) -> Option<Promise> {
+        self.get_impl(cx, key, options, true)
+    }
+
+    pub fn put(
+        &self,
+        cx: &Context,
+        key: String,
+        value: FetchBody,
+        Opt(options): Opt<KvPutOptions>,
+    ) -> Option<Promise> {
+        let (expiration, metadata) = match validate_key(&key)
+            .and_then(|()| options.map(|o| put_options(cx, o)).transpose())
+        {
+            Ok(o) => o.unwrap_or_default(),
+            Err(e) => return Some(Promise::rejected(cx, e)),
+        };
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, bytes) = cx.await_native_cx(|cx| value.into_bytes(cx)).await;
+                let bytes = bytes?.unwrap_or_default();
+
+                let (_, result) = run_blocking(cx, move || {
+                    store.put(key, bytes.as_ref(), expiration, metadata)
+                })
+                .await;
+                result
+            })
+        }
+    }
+
+    pub fn delete(&self, cx: &Context, key: String) -> Option<Promise> {
+        if let Err(e) = validate_key(&key) {
+            return Some(Promise::rejected(cx, e));
+        }
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (_, result) = run_blocking(cx, move || store.delete(&key)).await;
+                result
+            })
+        }
+    }
+
+    pub fn list(&self, cx: &Context, Opt(options): Opt<KvListOptions>) -> Option<Promise> {
+        let options = options.unwrap_or_default();
+        let limit = options.limit.unwrap_or(MAX_LIST_LIMIT);
+        if limit == 0 || limit > MAX_LIST_LIMIT {
+            return Some(Promise::rejected(
+                cx,
+                ion_mk_err!(
+                    format!("Invalid list limit, must be between 1 and {MAX_LIST_LIMIT}"),
+                    Range
+                ),
+            ));
+        }
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, listing) = run_blocking(cx, move || {
+                    store.list(options.prefix.as_deref(), limit, options.cursor.as_deref())
+                })
+                .await;
+                let listing = listing?;
+
+                let mut keys = vec![];
+                for key in listing.keys {
+                    let key_obj = Object::new(&cx);
+                    key_obj.set_as(&cx, "name", &key.name);
+                    if let Some(expiration) = key.expiration {
+                        key_obj.set_as(&cx, "expiration", &(expiration as f64));
+                    }
+                    if key.metadata.is_some() {
+                        key_obj.set(
+                            &cx,
+                            "metadata",
+                            &metadata_to_js(&cx, key.metadata.as_deref())?,
+                        );
+                    }
+                    keys.push((*key_obj).get());
+                }
+
+                let result = Object::new(&cx);
+                result.set_as(&cx, "keys", &keys);
+                result.set_as(&cx, "list_complete", &listing.cursor.is_none());
+                if let Some(cursor) = listing.cursor {
+                    result.set_as(&cx, "cursor", &cursor);
+                }
+                result.set(&cx, "cacheStatus", &Value::null(&cx));
+
+                Ok((*result).get())
+            })
+        }
+    }
+}
+
+fn validate_key(key: &str) -> ion::Result<()> {
+    if key.is_empty() {
+        ion_err!("Key names must not be empty", Type);
+    }
+    if key == "." || key == ".." {
+        ion_err!("Illegal key name, '.' and '..' are not allowed", Type);
+    }
+    if key.len() > MAX_KEY_LENGTH {
+        ion_err!(
+            format!("Key names must be at most {MAX_KEY_LENGTH} bytes long"),
+            Range
+        );
+    }
+    Ok(())
+}
+
+fn put_options(cx: &Context, options: KvPutOptions) -> ion::Result<(Option<u64>, Option<String>)> {
+    let now = unix_time_now();
+
+    let expiration = match (options.expiration, options.expiration_ttl) {
+        (Some(_), Some(_)) => {
+            ion_err!(
+                "Only one of expiration and expirationTtl may be specified",
+                Type
+            )
+        }
+        (Some(expiration), None) => {
+            let expiration = expiration as u64;
+            if expiration < now + MIN_EXPIRATION_TTL {
+                ion_err!(
+                    format!(
+                        "Invalid expiration of {expiration}, expiration times must be \
+                        at least {MIN_EXPIRATION_TTL} seconds in the future"
+                    ),
+                    Range
+                );
+            }
+            Some(expiration)
+        }
+        (None, Some(ttl)) => {
+            let ttl = ttl as u64;
+            if ttl < MIN_EXPIRATION_TTL {
+                ion_err!(
+                    format!(
+                        "Invalid expirationTtl of {ttl}, expiration TTL must be \
+                        at least {MIN_EXPIRATION_TTL}"
+                    ),
+                    Range
+                );
+            }
+            Some(now + ttl)
+        }
+        (None, None) => None,
+    };
+
+    let metadata = match options.metadata {
+        Some(metadata) if !metadata.handle().is_undefined() && !metadata.handle().is_null() => {
+            let metadata = json_stringify(cx, metadata)?;
+            if metadata.len() > MAX_METADATA_LENGTH {
+                ion_err!(
+                    format!("Metadata must be at most {MAX_METADATA_LENGTH} bytes once serialized"),
+                    Range
+                );
+            }
+            Some(metadata)
+        }
+        _ => None,
+    };
+
+    Ok((expiration, metadata))
+}
+
+fn value_to_js(cx: &Context, value: Vec<u8>, value_type: KvValueType) -> ion::Result<Value> {
+    match value_type {
+        KvValueType::Text => Ok(String::from_utf8_lossy(&value).as_value(cx)),
+        KvValueType::Json => json_parse(cx, &String::from_utf8_lossy(&value)),
+        KvValueType::ArrayBuffer => Ok(ArrayBuffer::copy_from_bytes(cx, &value)
+            .ok_or_else(|| ion_mk_err!("Failed to allocate array", Normal))?
+            .as_value(cx)),
+        KvValueType::Stream => bytes_to_stream(cx, value),
+    }
+}
+
+fn metadata_to_js<'cx>(cx: &'cx Context, metadata: Option<&str>) -> ion::Result<Value<'cx>> {
+    match metadata {
+        Some(metadata) => json_parse(cx, metadata),
+        None => Ok(Value::null(cx)),
+    }
+}
@@ -0,0 +1,370 @@
+//! Local implementations of the bindings Cloudflare exposes on the `env`
+//! object. Bindings are declared in a JSON file passed in with `--bindings`,
+//! which uses the same field names as `wrangler.toml`. All state is kept
+//! on local disk under the `persist_to` directory.
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use ion::{conversions::ToValue, flags::PropertyFlags, ClassDefinition, Context, Object, Value};
+use runtime::globals::fetch::hyper_body_to_stream;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Digest;
+
+use crate::ion_mk_err;
+
+pub mod d1;
+pub mod durable_objects;
+pub mod kv;
+pub mod r2;
+pub mod services;
+
+const DEFAULT_PERSIST_DIR: &str = ".winterjs/state";
+
+#[derive(Deserialize, Default, Debug)]
+pub struct BindingsConfig {
+    /// Root directory for the state of all bindings. Relative paths are
+    /// resolved against the directory the bindings file lives in.
+    #[serde(default)]
+    pub persist_to: Option<PathBuf>,
+
+    /// Plain values exposed on `env`. Strings are exposed as-is, anything
+    /// else as the equivalent JS value.
+    #[serde(default)]
+    pub vars: serde_json::Map<String, serde_json::Value>,
+
+    #[serde(default)]
+    pub kv_namespaces: Vec<KvNamespaceConfig>,
+
+    #[serde(default)]
+    pub r2_buckets: Vec<R2BucketConfig>,
+
+    #[serde(default)]
+    pub d1_databases: Vec<D1DatabaseConfig>,
+
+    #[serde(default)]
+    pub durable_objects: DurableObjectsConfig,
+
+    #[serde(default)]
+    pub services: Vec<ServiceBindingConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct KvNamespaceConfig {
+    pub binding: String,
+    /// Used as the name of the namespace's directory, so namespaces with
+    /// the same ID share their data. Defaults to the binding name.
+    pub id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct R2BucketConfig {
+    pub binding: String,
+    /// Used as the name of the bucket's directory. Defaults to the binding
+    /// name.
+    pub bucket_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct D1DatabaseConfig {
+    pub binding: String,
+    pub database_name: Option<String>,
+    /// Used as the name of the database file, falling back to the database
+    /// name and then the binding name.
+    pub database_id: Option<String>,
+    /// Directory of `.sql` files applied to the database at startup.
+    /// Relative paths are resolved like `persist_to`.
+    pub migrations_dir: Option<PathBuf>,
+    pub migrations_table: Option<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct DurableObjectsConfig {
+    #[serde(default)]
+    pub bindings: Vec<DurableObjectBindingConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DurableObjectBindingConfig {
+    pub name: String,
+    /// Name of the class exported from the worker's main module.
+    pub class_name: String,
+    /// Classes exported from other workers are not supported.
+    pub script_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ServiceBindingConfig {
+    pub binding: String,
+    /// Name of the worker requests are sent to.
+    pub service: String,
+    /// Named entrypoints are not supported.
+    pub entrypoint: Option<String>,
+}
+
+impl BindingsConfig {
+    pub fn try_parse(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read bindings file {}", path.display()))?;
+        let mut config = serde_json::from_str::<Self>(&file_content)
+            .with_context(|| format!("Failed to parse bindings file {}", path.display()))?;
+
+        config.resolve_paths(path.parent().unwrap_or(Path::new(".")));
+        Ok(config)
+    }
+
+    /// Resolves relative paths against the directory of the file the config
+    /// was read from.
+    pub fn resolve_paths(&mut self, base_dir: &Path) {
+        self.persist_to = Some(
+            base_dir.join(
+                self.persist_to
+                    .take()
+                    .unwrap_or_else(|| PathBuf::from(DEFAULT_PERSIST_DIR)),
+            ),
+        );
+        for db in &mut self.d1_databases {
+            db.migrations_dir = db.migrations_dir.take().map(|d| base_dir.join(d));
+        }
+    }
+
+    pub fn persist_dir(&self) -> PathBuf {
+        self.persist_to
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_PERSIST_DIR))
+    }
+}
+
+/// The bindings as instantiated on a single request handler thread.
+pub struct Bindings {
+    vars: serde_json::Map<String, serde_json::Value>,
+    kv_namespaces: Vec<(String, kv::KvStore)>,
+    r2_buckets: Vec<(String, r2::R2Store)>,
+    d1_databases: Vec<(String, d1::D1Store)>,
+    pub(super) durable_objects: durable_objects::DurableObjects,
+    services: Vec<(String, String)>,
+}
+
+impl Bindings {
+    pub fn new(config: &BindingsConfig) -> Result<Self> {
+        let persist_dir = config.persist_dir();
+
+        let kv_namespaces = config
+            .kv_namespaces
+            .iter()
+            .map(|ns| {
+                let dir_name = ns.id.as_deref().unwrap_or(ns.binding.as_str());
+                let store = kv::KvStore::open(persist_dir.join("kv").join(dir_name))
+                    .with_context(|| format!("Failed to open KV namespace {}", ns.binding))?;
+                Ok((ns.binding.clone(), store))
+            })
+            .collect::<Result<_>>()?;
+
+        let r2_buckets = config
+            .r2_buckets
+            .iter()
+            .map(|bucket| {
+                let dir_name = bucket
+                    .bucket_name
+                    .as_deref()
+                    .unwrap_or(bucket.binding.as_str());
+                let store = r2::R2Store::open(persist_dir.join("r2").join(dir_name))
+                    .with_context(|| format!("Failed to open R2 bucket {}", bucket.binding))?;
+                Ok((bucket.binding.clone(), store))
+            })
+            .collect::<Result<_>>()?;
+
+        let d1_databases = config
+            .d1_databases
+            .iter()
+            .map(|db| {
+                let file_name = db
+                    .database_id
+                    .as_deref()
+                    .or(db.database_name.as_deref())
+                    .unwrap_or(db.binding.as_str());
+                let store = d1::D1Store::open(
+                    persist_dir.join("d1").join(format!("{file_name}.sqlite3")),
+                    db.migrations_dir.as_deref(),
+                    db.migrations_table.as_deref(),
+                )
+                .with_context(|| format!("Failed to open D1 database {}", db.binding))?;
+                Ok((db.binding.clone(), store))
+            })
+            .collect::<Result<_>>()?;
+
+        let services = config
+            .services
+            .iter()
+            .map(|service| (service.binding.clone(), service.service.clone()))
+            .collect();
+
+        Ok(Self {
+            vars: config.vars.clone(),
+            kv_namespaces,
+            r2_buckets,
+            d1_databases,
+            durable_objects: durable_objects::DurableObjects::new(config),
+            services,
+        })
+    }
+
+    pub fn define_on(&self, cx: &Context, env: &Object) -> bool {
+        for (name, value) in &self.vars {
+            let value = match value {
+                serde_json::Value::String(s) => s.as_value(cx),
+                value => match crate::sm_utils::json_parse(cx, &value.to_string()) {
+                    Ok(value) => value,
+                    Err(_) => return false,
+                },
+            };
+            if !env.define(cx, name.as_str(), &value, PropertyFlags::ENUMERATE) {
+                return false;
+            }
+        }
+
+        for (name, store) in &self.kv_namespaces {
+            let namespace = kv::KvNamespace::new_obj(cx, store.clone());
+            if !env.define(
+                cx,
+                name.as_str(),
+                &Value::object(cx, &cx.root(namespace).into()),
+                PropertyFlags::ENUMERATE,
+            ) {
+                return false;
+            }
+        }
+
+        for (name, store) in &self.r2_buckets {
+            let bucket = r2::R2Bucket::new_obj(cx, store.clone());
+            if !env.define(
+                cx,
+                name.as_str(),
+                &Value::object(cx, &cx.root(bucket).into()),
+                PropertyFlags::ENUMERATE,
+            ) {
+                return false;
+            }
+        }
+
+        for (name, store) in &self.d1_databases {
+            let database = d1::D1Database::new_obj(cx, store.clone());
+            if !env.define(
+                cx,
+                name.as_str(),
+                &Value::object(cx, &cx.root(database).into()),
+                PropertyFlags::ENUMERATE,
+            ) {
+                return false;
+            }
+        }
+
+        for (name, service) in &self.services {
+            let fetcher = services::Fetcher::new_obj(cx, service.clone());
+            if !env.define(
+                cx,
+                name.as_str(),
+                &Value::object(cx, &cx.root(fetcher).into()),
+                PropertyFlags::ENUMERATE,
+            ) {
+                return false;
+            }
+        }
+
+        self.durable_objects.define_on(cx, env)
+    }
+}
+
+/// Runs blocking IO on tokio's blocking thread pool, so the event loop of
+/// the request handler thread is free to make progress in the meantime.
+pub(super) async fn run_blocking<T, F>(cx: Context, f: F) -> (Context, ion::Result<T>)
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    let (cx, result) = cx.await_native(tokio::task::spawn_blocking(f)).await;
+    let result = match result {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(ion_mk_err!(format!("{e:#}"), Normal)),
+        Err(e) => Err(ion_mk_err!(format!("Background task failed: {e}"), Normal)),
+    };
+    (cx, result)
+}
+
+pub(super) fn bytes_to_stream<'cx>(cx: &'cx Context, bytes: Vec<u8>) -> ion::Result<Value<'cx>> {
+    let stream = hyper_body_to_stream(cx, hyper::Body::from(bytes))
+        .ok_or_else(|| ion_mk_err!("Failed to create ReadableStream", Normal))?;
+    Ok(stream.as_value(cx))
+}
+
+/// Name of the file an entry is stored in, derived from its key so keys
+/// can contain arbitrary characters.
+pub(super) fn entry_file_name(key: &str) -> String {
+    let hash = sha2::Sha256::digest(key.as_bytes());
+    hash.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Reads the header of a file written by [`write_entry_file`], leaving the
+/// file positioned at the start of the contents.
+pub(super) fn read_entry_header<H: DeserializeOwned>(file: &mut File) -> Result<H> {
+    let mut len = [0u8; 4];
+    file.read_exact(&mut len)?;
+    let mut header = vec![0u8; u32::from_le_bytes(len) as usize];
+    file.read_exact(&mut header)?;
+    serde_json::from_slice(&header).context("Corrupt entry header")
+}
+
+/// Writes an entry file, made up of the length of the JSON-encoded header as
+/// a little-endian u32, followed by the header itself and the contents. The
+/// file is written under a temporary name and then renamed over `path`, so
+/// readers on other threads never observe a partial write.
+pub(super) fn write_entry_file<H: Serialize>(
+    path: &Path,
+    header: &H,
+    write_contents: impl FnOnce(&mut File) -> Result<()>,
+) -> Result<()> {
+    let dir = path
+        .parent()
+        .context("Entry path has no parent directory")?;
+    let header = serde_json::to_vec(header)?;
+
+    let temp_path = dir.join(format!(".tmp-{}", uuid::Uuid::new_v4()));
+    let mut file = File::create(&temp_path).context("Failed to create entry file")?;
+    let result = file
+        .write_all(&(header.len() as u32).to_le_bytes())
+        .and_then(|()| file.write_all(&header))
+        .map_err(anyhow::Error::from)
+        .and_then(|()| write_contents(&mut file));
+    drop(file);
+
+    if let Err(e) = result {
+        _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    std::fs::rename(&temp_path, path).context("Failed to write entry file")
+}
+
+pub(super) fn unix_time_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn define(cx: &Context, global: &Object) -> bool {
+    kv::KvNamespace::init_class(cx, global).0
+        && r2::R2Bucket::init_class(cx, global).0
+        && r2::R2MultipartUpload::init_class(cx, global).0
+        && r2::R2Object::init_class(cx, global).0
+        && r2::R2ObjectBody::init_class(cx, global).0
+        && d1::D1Database::init_class(cx, global).0
+        && d1::D1PreparedStatement::init_class(cx, global).0
+        && durable_objects::define(cx, global)
+        && services::Fetcher::init_class(cx, global).0
+}
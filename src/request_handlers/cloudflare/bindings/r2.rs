@@ -0,0 +1,1744 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _, Result};
+use base64::Engine;
+use ion::{
+    class::Reflector,
+    conversions::{FromValue, ToValue},
+    function::Opt,
+    typedarray::ArrayBuffer,
+    ClassDefinition, Context, Date, Heap, Object, Promise, PromiseFuture, TracedHeap, Value,
+};
+use mozjs::jsval::JSVal;
+use mozjs_sys::jsapi::JSObject;
+use runtime::{
+    globals::fetch::{hyper_body_to_stream, FetchBody, FetchBodyInner},
+    promise::future_to_promise,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ion_err, ion_mk_err,
+    sm_utils::{call_method, json_parse, json_stringify},
+};
+
+use super::{entry_file_name, read_entry_header, run_blocking, write_entry_file};
+
+const MAX_KEY_LENGTH: usize = 1024;
+const MAX_LIST_LIMIT: u32 = 1000;
+const READ_CHUNK_SIZE: u64 = 64 * 1024;
+const MAX_PART_NUMBER: u32 = 10000;
+const MAX_CUSTOM_METADATA_LENGTH: usize = 2048;
+
+/// Stores the objects of a bucket under `objects`, one file per object named
+/// after the SHA-256 hash of the key, with the [`ObjectMeta`] stored in front
+/// of the contents. In-progress multipart uploads live under
+/// `multipart/<upload ID>`, with one file per uploaded part.
+#[derive(Clone)]
+pub struct R2Store {
+    root: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpMetadata {
+    content_type: Option<String>,
+    content_language: Option<String>,
+    content_disposition: Option<String>,
+    content_encoding: Option<String>,
+    cache_control: Option<String>,
+    /// Milliseconds since the Unix epoch
+    cache_expiry: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectMeta {
+    key: String,
+    version: String,
+    size: u64,
+    etag: String,
+    /// Milliseconds since the Unix epoch
+    uploaded: i64,
+    http_metadata: HttpMetadata,
+    custom_metadata: BTreeMap<String, String>,
+    /// Hex-encoded MD5 of the contents, not available for objects created
+    /// by multipart uploads
+    md5: Option<String>,
+}
+
+impl ObjectMeta {
+    fn new(
+        key: String,
+        size: u64,
+        etag: String,
+        md5: Option<String>,
+        http_metadata: HttpMetadata,
+        custom_metadata: BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            key,
+            version: uuid::Uuid::new_v4().simple().to_string(),
+            size,
+            etag,
+            uploaded: unix_time_now_millis(),
+            http_metadata,
+            custom_metadata,
+            md5,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MultipartUploadMeta {
+    key: String,
+    http_metadata: HttpMetadata,
+    custom_metadata: BTreeMap<String, String>,
+}
+
+/// The equivalent of the `If-*` HTTP request headers. As with HTTP, the
+/// upload time checks are ignored when the matching ETag check is present.
+#[derive(Default)]
+pub struct Conditional {
+    etag_matches: Option<Vec<String>>,
+    etag_does_not_match: Option<Vec<String>>,
+    uploaded_before: Option<i64>,
+    uploaded_after: Option<i64>,
+    // HTTP dates only have second precision
+    compare_seconds: bool,
+}
+
+impl Conditional {
+    fn is_met(&self, meta: Option<&ObjectMeta>) -> bool {
+        let Some(meta) = meta else {
+            return self.etag_matches.is_none();
+        };
+
+        let uploaded = if self.compare_seconds {
+            meta.uploaded / 1000 * 1000
+        } else {
+            meta.uploaded
+        };
+
+        if let Some(etags) = &self.etag_matches {
+            if !etag_in_list(etags, &meta.etag) {
+                return false;
+            }
+        } else if let Some(before) = self.uploaded_before {
+            if uploaded > before {
+                return false;
+            }
+        }
+
+        if let Some(etags) = &self.etag_does_not_match {
+            if etag_in_list(etags, &meta.etag) {
+                return false;
+            }
+        } else if let Some(after) = self.uploaded_after {
+            if uploaded <= after {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+fn parse_etag_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|e| {
+            e.trim()
+                .trim_start_matches("W/")
+                .trim_matches('"')
+                .to_string()
+        })
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+fn etag_in_list(list: &[String], etag: &str) -> bool {
+    list.iter().any(|e| e == "*" || e == etag)
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct ByteRange {
+    offset: Option<u64>,
+    length: Option<u64>,
+    suffix: Option<u64>,
+}
+
+impl ByteRange {
+    /// Returns the offset and length of the range within an object of the
+    /// given size.
+    fn resolve(&self, size: u64) -> Result<(u64, u64)> {
+        if let Some(suffix) = self.suffix {
+            if self.offset.is_some() || self.length.is_some() {
+                bail!("A range suffix can't be combined with an offset or length");
+            }
+            let length = suffix.min(size);
+            return Ok((size - length, length));
+        }
+
+        let offset = self.offset.unwrap_or(0);
+        if offset > size {
+            bail!("Range offset {offset} is past the end of the object ({size} bytes)");
+        }
+        let length = self
+            .length
+            .map(|l| l.min(size - offset))
+            .unwrap_or(size - offset);
+        Ok((offset, length))
+    }
+}
+
+pub enum GetResult {
+    NotFound,
+    PreconditionFailed(ObjectMeta),
+    /// `contents` is positioned at the start of the requested range.
+    Found {
+        meta: ObjectMeta,
+        contents: File,
+        range: Option<(u64, u64)>,
+    },
+}
+
+/// An object body copied into a temporary file before it's stored, so its
+/// size and MD5 are known by the time the entry header is written.
+pub struct StagedBody {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    md5: md5::Context,
+}
+
+impl StagedBody {
+    fn write(&mut self, chunk: &[u8]) -> Result<()> {
+        self.file
+            .write_all(chunk)
+            .context("Failed to write object body")?;
+        self.md5.consume(chunk);
+        self.size += chunk.len() as u64;
+        Ok(())
+    }
+
+    fn md5(&self) -> String {
+        hex(&self.md5.clone().compute().0)
+    }
+
+    fn copy_to(&mut self, file: &mut File) -> Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        std::io::copy(&mut self.file, file)?;
+        Ok(())
+    }
+}
+
+impl Drop for StagedBody {
+    fn drop(&mut self) {
+        _ = std::fs::remove_file(&self.path);
+    }
+}
+
+pub struct ListOptions {
+    prefix: Option<String>,
+    delimiter: Option<String>,
+    start_after: Option<String>,
+    cursor: Option<String>,
+    limit: u32,
+}
+
+pub struct Listing {
+    pub objects: Vec<ObjectMeta>,
+    pub delimited_prefixes: Vec<String>,
+    pub cursor: Option<String>,
+}
+
+impl R2Store {
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        for dir in ["objects", "multipart", "staging"] {
+            let dir = root.join(dir);
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+        }
+        Ok(Self { root })
+    }
+
+    fn object_path(&self, key: &str) -> PathBuf {
+        self.root.join("objects").join(entry_file_name(key))
+    }
+
+    fn upload_dir(&self, upload_id: &str) -> Result<PathBuf> {
+        // Upload IDs are generated by us, but resumeMultipartUpload lets
+        // users pass in arbitrary strings
+        if upload_id.is_empty() || !upload_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            bail!("Invalid upload ID '{upload_id}'");
+        }
+        Ok(self.root.join("multipart").join(upload_id))
+    }
+
+    pub fn stage_body(&self) -> Result<StagedBody> {
+        let path = self
+            .root
+            .join("staging")
+            .join(uuid::Uuid::new_v4().simple().to_string());
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .context("Failed to create staging file")?;
+        Ok(StagedBody {
+            path,
+            file,
+            size: 0,
+            md5: md5::Context::new(),
+        })
+    }
+
+    fn open_object(path: &Path) -> Result<Option<(File, ObjectMeta)>> {
+        let mut file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to open R2 object"),
+        };
+        let meta = read_entry_header(&mut file)?;
+        Ok(Some((file, meta)))
+    }
+
+    pub fn head(&self, key: &str) -> Result<Option<ObjectMeta>> {
+        Ok(Self::open_object(&self.object_path(key))?.map(|(_, meta)| meta))
+    }
+
+    pub fn get(
+        &self,
+        key: &str,
+        only_if: Option<&Conditional>,
+        range: Option<ByteRange>,
+    ) -> Result<GetResult> {
+        let Some((mut file, meta)) = Self::open_object(&self.object_path(key))? else {
+            return Ok(GetResult::NotFound);
+        };
+
+        if !only_if.map(|c| c.is_met(Some(&meta))).unwrap_or(true) {
+            return Ok(GetResult::PreconditionFailed(meta));
+        }
+
+        let (offset, length) = match range {
+            Some(range) => range.resolve(meta.size)?,
+            None => (0, meta.size),
+        };
+
+        file.seek(SeekFrom::Current(offset as i64))
+            .context("Failed to read R2 object")?;
+
+        Ok(GetResult::Found {
+            meta,
+            contents: file,
+            range: range.map(|_| (offset, length)),
+        })
+    }
+
+    /// Returns `None` if the conditions in `only_if` were not met.
+    pub fn put(
+        &self,
+        key: String,
+        mut body: StagedBody,
+        http_metadata: HttpMetadata,
+        custom_metadata: BTreeMap<String, String>,
+        expected_md5: Option<String>,
+        only_if: Option<&Conditional>,
+    ) -> Result<Option<ObjectMeta>> {
+        let path = self.object_path(&key);
+
+        if let Some(only_if) = only_if {
+            let existing = Self::open_object(&path)?.map(|(_, meta)| meta);
+            if !only_if.is_met(existing.as_ref()) {
+                return Ok(None);
+            }
+        }
+
+        let md5 = body.md5();
+        if let Some(expected) = expected_md5 {
+            if expected != md5 {
+                bail!("The MD5 checksum you specified did not match what we received");
+            }
+        }
+
+        let meta = ObjectMeta::new(
+            key,
+            body.size,
+            md5.clone(),
+            Some(md5),
+            http_metadata,
+            custom_metadata,
+        );
+        write_entry_file(&path, &meta, |file| body.copy_to(file))
+            .context("Failed to write R2 object")?;
+        Ok(Some(meta))
+    }
+
+    pub fn delete(&self, keys: &[String]) -> Result<()> {
+        for key in keys {
+            match std::fs::remove_file(self.object_path(key)) {
+                Ok(()) => (),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e).context("Failed to delete R2 object"),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn list(&self, options: &ListOptions) -> Result<Listing> {
+        let cursor = options
+            .cursor
+            .as_deref()
+            .map(|c| {
+                base64::prelude::BASE64_URL_SAFE_NO_PAD
+                    .decode(c)
+                    .ok()
+                    .and_then(|k| String::from_utf8(k).ok())
+                    .context("Invalid cursor")
+            })
+            .transpose()?;
+        let after = match (cursor, options.start_after.clone()) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+
+        let mut all = vec![];
+        let objects_dir = self.root.join("objects");
+        for entry in std::fs::read_dir(objects_dir).context("Failed to read R2 directory")? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let Some((_, meta)) = Self::open_object(&entry.path())? else {
+                continue;
+            };
+
+            if let Some(prefix) = &options.prefix {
+                if !meta.key.starts_with(prefix.as_str()) {
+                    continue;
+                }
+            }
+
+            all.push(meta);
+        }
+        all.sort_by(|a, b| a.key.cmp(&b.key));
+
+        let prefix_len = options.prefix.as_ref().map(|p| p.len()).unwrap_or(0);
+        let mut objects = vec![];
+        let mut delimited_prefixes: Vec<String> = vec![];
+        let mut last_item = None;
+        let mut truncated = false;
+
+        for meta in all {
+            // Delimited prefixes end up in the cursor too, in which case all
+            // the keys they contain were already returned
+            if let Some(after) = &after {
+                let in_returned_prefix = options
+                    .delimiter
+                    .as_ref()
+                    .map(|d| after.ends_with(d.as_str()) && meta.key.starts_with(after.as_str()))
+                    .unwrap_or(false);
+                if meta.key <= *after || in_returned_prefix {
+                    continue;
+                }
+            }
+
+            let delimited_prefix = options.delimiter.as_ref().and_then(|d| {
+                meta.key[prefix_len..]
+                    .find(d.as_str())
+                    .map(|i| meta.key[..prefix_len + i + d.len()].to_string())
+            });
+
+            if let Some(p) = &delimited_prefix {
+                if delimited_prefixes.last() == Some(p) {
+                    continue;
+                }
+            }
+
+            if objects.len() + delimited_prefixes.len() == options.limit as usize {
+                truncated = true;
+                break;
+            }
+
+            match delimited_prefix {
+                Some(p) => {
+                    last_item = Some(p.clone());
+                    delimited_prefixes.push(p);
+                }
+                None => {
+                    last_item = Some(meta.key.clone());
+                    objects.push(meta);
+                }
+            }
+        }
+
+        let cursor = if truncated {
+            last_item.map(|k| base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(k))
+        } else {
+            None
+        };
+
+        Ok(Listing {
+            objects,
+            delimited_prefixes,
+            cursor,
+        })
+    }
+
+    pub fn create_multipart_upload(
+        &self,
+        key: String,
+        http_metadata: HttpMetadata,
+        custom_metadata: BTreeMap<String, String>,
+    ) -> Result<String> {
+        let upload_id = uuid::Uuid::new_v4().simple().to_string();
+        let dir = self.upload_dir(&upload_id)?;
+        std::fs::create_dir_all(&dir).context("Failed to create multipart upload")?;
+
+        let meta = MultipartUploadMeta {
+            key,
+            http_metadata,
+            custom_metadata,
+        };
+        std::fs::write(dir.join("upload.json"), serde_json::to_vec(&meta)?)
+            .context("Failed to create multipart upload")?;
+
+        Ok(upload_id)
+    }
+
+    fn read_upload_meta(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<(PathBuf, MultipartUploadMeta)> {
+        let dir = self.upload_dir(upload_id)?;
+        let meta = match std::fs::read(dir.join("upload.json")) {
+            Ok(m) => serde_json::from_slice::<MultipartUploadMeta>(&m)
+                .context("Corrupt multipart upload")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                bail!("Multipart upload {upload_id} does not exist")
+            }
+            Err(e) => return Err(e).context("Failed to read multipart upload"),
+        };
+
+        if meta.key != key {
+            bail!("Multipart upload {upload_id} does not belong to key '{key}'");
+        }
+
+        Ok((dir, meta))
+    }
+
+    /// Returns the ETag of the uploaded part.
+    pub fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: u32,
+        mut body: StagedBody,
+    ) -> Result<String> {
+        let (dir, _) = self.read_upload_meta(key, upload_id)?;
+
+        let etag = body.md5();
+        let header = UploadedPart {
+            part_number,
+            etag: etag.clone(),
+        };
+        write_entry_file(&dir.join(format!("part-{part_number}")), &header, |file| {
+            body.copy_to(file)
+        })
+        .context("Failed to write part")?;
+
+        Ok(etag)
+    }
+
+    pub fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<()> {
+        let (dir, _) = self.read_upload_meta(key, upload_id)?;
+        std::fs::remove_dir_all(dir).context("Failed to abort multipart upload")
+    }
+
+    pub fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        mut parts: Vec<UploadedPart>,
+    ) -> Result<ObjectMeta> {
+        let (dir, upload_meta) = self.read_upload_meta(key, upload_id)?;
+
+        if parts.is_empty() {
+            bail!("At least one part must be provided to complete a multipart upload");
+        }
+        parts.sort_by_key(|p| p.part_number);
+
+        let mut part_files = vec![];
+        let mut size = 0;
+        let mut etag_digests = vec![];
+        for part in &parts {
+            let path = dir.join(format!("part-{}", part.part_number));
+            let mut file = File::open(&path)
+                .with_context(|| format!("Part {} was not uploaded", part.part_number))?;
+            let stored: UploadedPart = read_entry_header(&mut file)?;
+            if stored.etag != part.etag.trim_matches('"') {
+                bail!("The ETag of part {} does not match", part.part_number);
+            }
+
+            size += file.metadata()?.len() - file.stream_position()?;
+            etag_digests.extend_from_slice(&unhex(&stored.etag)?);
+            part_files.push(file);
+        }
+
+        // Same as the ETags S3 generates for multipart uploads
+        let etag = format!(
+            "{}-{}",
+            hex(&md5::compute(&etag_digests).0),
+            part_files.len()
+        );
+        let meta = ObjectMeta::new(
+            upload_meta.key,
+            size,
+            etag,
+            None,
+            upload_meta.http_metadata,
+            upload_meta.custom_metadata,
+        );
+
+        write_entry_file(&self.object_path(key), &meta, |file| {
+            for mut part in part_files {
+                std::io::copy(&mut part, file)?;
+            }
+            Ok(())
+        })
+        .context("Failed to write R2 object")?;
+
+        _ = std::fs::remove_dir_all(dir);
+
+        Ok(meta)
+    }
+}
+
+#[derive(FromValue, Serialize, Deserialize)]
+pub struct UploadedPart {
+    part_number: u32,
+    etag: String,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &str) -> Result<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        bail!("Invalid hex string");
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+fn unix_time_now_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+#[derive(FromValue)]
+pub enum R2Keys {
+    #[ion(inherit)]
+    Many(Vec<String>),
+    #[ion(inherit)]
+    One(String),
+}
+
+#[derive(FromValue)]
+pub enum R2Checksum<'cx> {
+    #[ion(inherit)]
+    ArrayBuffer(mozjs::typedarray::ArrayBuffer, PhantomData<&'cx ()>),
+    #[ion(inherit)]
+    ArrayBufferView(mozjs::typedarray::ArrayBufferView, PhantomData<&'cx ()>),
+    #[ion(inherit)]
+    Hex(String),
+}
+
+impl<'cx> R2Checksum<'cx> {
+    fn to_hex(&self) -> ion::Result<String> {
+        let hex = unsafe {
+            match self {
+                Self::ArrayBuffer(buf, _) => hex(buf.as_slice()),
+                Self::ArrayBufferView(buf, _) => hex(buf.as_slice()),
+                Self::Hex(s) => s.to_ascii_lowercase(),
+            }
+        };
+        if hex.len() != 32 || unhex(&hex).is_err() {
+            ion_err!("The MD5 checksum must be 16 bytes long", Type);
+        }
+        Ok(hex)
+    }
+}
+
+#[derive(FromValue, Default)]
+pub struct R2GetOptions<'cx> {
+    only_if: Option<Value<'cx>>,
+    range: Option<Value<'cx>>,
+}
+
+#[derive(FromValue, Default)]
+pub struct R2PutOptions<'cx> {
+    only_if: Option<Value<'cx>>,
+    http_metadata: Option<Value<'cx>>,
+    custom_metadata: Option<Value<'cx>>,
+    md5: Option<R2Checksum<'cx>>,
+}
+
+#[derive(FromValue, Default)]
+pub struct R2MultipartOptions<'cx> {
+    http_metadata: Option<Value<'cx>>,
+    custom_metadata: Option<Value<'cx>>,
+}
+
+#[derive(FromValue, Default)]
+pub struct R2ListOptions {
+    limit: Option<u32>,
+    prefix: Option<String>,
+    cursor: Option<String>,
+    delimiter: Option<String>,
+    start_after: Option<String>,
+    include: Option<Vec<String>>,
+}
+
+#[js_class]
+pub struct R2Bucket {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    store: R2Store,
+}
+
+impl R2Bucket {
+    pub fn new_obj(cx: &Context, store: R2Store) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                store,
+            }),
+        )
+    }
+}
+
+#[js_class]
+impl R2Bucket {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<R2Bucket> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    pub fn head(&self, cx: &Context, key: String) -> Option<Promise> {
+        if let Err(e) = validate_key(&key) {
+            return Some(Promise::rejected(cx, e));
+        }
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, meta) = run_blocking(cx, move || store.head(&key)).await;
+                match meta? {
+                    Some(meta) => Ok(R2Object::new_obj(&cx, meta)?.as_value(&cx).get()),
+                    None => Ok(Value::null(&cx).get()),
+                }
+            })
+        }
+    }
+
+    pub fn get(
+        &self,
+        cx: &Context,
+        key: String,
+        Opt(options): Opt<R2GetOptions>,
+    ) -> Option<Promise> {
+        let options = options.unwrap_or_default();
+        let parsed = validate_key(&key).and_then(|()| {
+            let only_if = options
+                .only_if
+                .map(|v| conditional_from_js(cx, v))
+                .transpose()?
+                .flatten();
+            let range = options
+                .range
+                .map(|v| range_from_js(cx, v))
+                .transpose()?
+                .flatten();
+            Ok((only_if, range))
+        });
+        let (only_if, range) = match parsed {
+            Ok(p) => p,
+            Err(e) => return Some(Promise::rejected(cx, e)),
+        };
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, result) =
+                    run_blocking(cx, move || store.get(&key, only_if.as_ref(), range)).await;
+                match result? {
+                    GetResult::NotFound => Ok(Value::null(&cx).get()),
+                    GetResult::PreconditionFailed(meta) => {
+                        Ok(R2Object::new_obj(&cx, meta)?.as_value(&cx).get())
+                    }
+                    GetResult::Found {
+                        meta,
+                        contents,
+                        range,
+                    } => {
+                        let length = range.map(|(_, length)| length).unwrap_or(meta.size);
+                        let body = file_to_body(contents, length);
+                        Ok(R2ObjectBody::new_obj(&cx, meta, body, range)?
+                            .as_value(&cx)
+                            .get())
+                    }
+                }
+            })
+        }
+    }
+
+    pub fn put(
+        &self,
+        cx: &Context,
+        key: String,
+        value: Option<FetchBody>,
+        Opt(options): Opt<R2PutOptions>,
+    ) -> Option<Promise> {
+        let options = options.unwrap_or_default();
+        let parsed = validate_key(&key).and_then(|()| {
+            let only_if = options
+                .only_if
+                .map(|v| conditional_from_js(cx, v))
+                .transpose()?
+                .flatten();
+            let http_metadata = options
+                .http_metadata
+                .map(|v| http_metadata_from_js(cx, v))
+                .transpose()?
+                .unwrap_or_default();
+            let custom_metadata = options
+                .custom_metadata
+                .map(|v| custom_metadata_from_js(cx, v))
+                .transpose()?
+                .unwrap_or_default();
+            let md5 = options.md5.map(|m| m.to_hex()).transpose()?;
+            Ok((only_if, http_metadata, custom_metadata, md5))
+        });
+        let (only_if, http_metadata, custom_metadata, md5) = match parsed {
+            Ok(p) => p,
+            Err(e) => return Some(Promise::rejected(cx, e)),
+        };
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, body) = stage_body(cx, store.clone(), value).await;
+                let body = body?;
+
+                let (cx, meta) = run_blocking(cx, move || {
+                    store.put(
+                        key,
+                        body,
+                        http_metadata,
+                        custom_metadata,
+                        md5,
+                        only_if.as_ref(),
+                    )
+                })
+                .await;
+
+                match meta? {
+                    Some(meta) => Ok(R2Object::new_obj(&cx, meta)?.as_value(&cx).get()),
+                    None => Ok(Value::null(&cx).get()),
+                }
+            })
+        }
+    }
+
+    pub fn delete(&self, cx: &Context, keys: R2Keys) -> Option<Promise> {
+        let keys = match keys {
+            R2Keys::Many(keys) => keys,
+            R2Keys::One(key) => vec![key],
+        };
+        if let Err(e) = keys.iter().try_for_each(|k| validate_key(k)) {
+            return Some(Promise::rejected(cx, e));
+        }
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (_, result) = run_blocking(cx, move || store.delete(&keys)).await;
+                result
+            })
+        }
+    }
+
+    pub fn list(&self, cx: &Context, Opt(options): Opt<R2ListOptions>) -> Option<Promise> {
+        let options = options.unwrap_or_default();
+        let limit = options.limit.unwrap_or(MAX_LIST_LIMIT);
+        if limit == 0 || limit > MAX_LIST_LIMIT {
+            return Some(Promise::rejected(
+                cx,
+                ion_mk_err!(
+                    format!("Invalid list limit, must be between 1 and {MAX_LIST_LIMIT}"),
+                    Range
+                ),
+            ));
+        }
+
+        let include = options.include.unwrap_or_default();
+        let include_http_metadata = include.iter().any(|i| i == "httpMetadata");
+        let include_custom_metadata = include.iter().any(|i| i == "customMetadata");
+        let list_options = ListOptions {
+            prefix: options.prefix,
+            delimiter: options.delimiter.filter(|d| !d.is_empty()),
+            start_after: options.start_after,
+            cursor: options.cursor,
+            limit,
+        };
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, listing) = run_blocking(cx, move || store.list(&list_options)).await;
+                let listing = listing?;
+
+                let mut objects = vec![];
+                for mut meta in listing.objects {
+                    if !include_http_metadata {
+                        meta.http_metadata = Default::default();
+                    }
+                    if !include_custom_metadata {
+                        meta.custom_metadata = Default::default();
+                    }
+                    objects.push(R2Object::new_obj(&cx, meta)?.as_value(&cx).get());
+                }
+
+                let result = Object::new(&cx);
+                result.set_as(&cx, "objects", &objects);
+                result.set_as(&cx, "truncated", &listing.cursor.is_some());
+                if let Some(cursor) = listing.cursor {
+                    result.set_as(&cx, "cursor", &cursor);
+                }
+                result.set_as(&cx, "delimitedPrefixes", &listing.delimited_prefixes);
+
+                Ok((*result).get())
+            })
+        }
+    }
+
+    #[ion(name = "createMultipartUpload")]
+    pub fn create_multipart_upload(
+        &self,
+        cx: &Context,
+        key: String,
+        Opt(options): Opt<R2MultipartOptions>,
+    ) -> Option<Promise> {
+        let options = options.unwrap_or_default();
+        let parsed = validate_key(&key).and_then(|()| {
+            let http_metadata = options
+                .http_metadata
+                .map(|v| http_metadata_from_js(cx, v))
+                .transpose()?
+                .unwrap_or_default();
+            let custom_metadata = options
+                .custom_metadata
+                .map(|v| custom_metadata_from_js(cx, v))
+                .transpose()?
+                .unwrap_or_default();
+            Ok((http_metadata, custom_metadata))
+        });
+        let (http_metadata, custom_metadata) = match parsed {
+            Ok(p) => p,
+            Err(e) => return Some(Promise::rejected(cx, e)),
+        };
+        let store = self.store.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let upload_key = key.clone();
+                let upload_store = store.clone();
+                let (cx, upload_id) = run_blocking(cx, move || {
+                    upload_store.create_multipart_upload(upload_key, http_metadata, custom_metadata)
+                })
+                .await;
+                Ok(R2MultipartUpload::new_obj(&cx, store, key, upload_id?))
+            })
+        }
+    }
+
+    #[ion(name = "resumeMultipartUpload")]
+    pub fn resume_multipart_upload(
+        &self,
+        cx: &Context,
+        key: String,
+        upload_id: String,
+    ) -> ion::Result<*mut JSObject> {
+        validate_key(&key)?;
+        Ok(R2MultipartUpload::new_obj(
+            cx,
+            self.store.clone(),
+            key,
+            upload_id,
+        ))
+    }
+}
+
+#[js_class]
+pub struct R2MultipartUpload {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    store: R2Store,
+
+    #[trace(no_trace)]
+    key: String,
+
+    #[trace(no_trace)]
+    upload_id: String,
+}
+
+impl R2MultipartUpload {
+    fn new_obj(cx: &Context, store: R2Store, key: String, upload_id: String) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                store,
+                key,
+                upload_id,
+            }),
+        )
+    }
+}
+
+#[js_class]
+impl R2MultipartUpload {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<R2MultipartUpload> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    #[ion(get)]
+    pub fn get_key(&self) -> String {
+        self.key.clone()
+    }
+
+    #[ion(name = "uploadId", get)]
+    pub fn get_upload_id(&self) -> String {
+        self.upload_id.clone()
+    }
+
+    #[ion(name = "uploadPart")]
+    pub fn upload_part(&self, cx: &Context, part_number: u32, value: FetchBody) -> Option<Promise> {
+        if part_number == 0 || part_number > MAX_PART_NUMBER {
+            return Some(Promise::rejected(
+                cx,
+                ion_mk_err!(
+                    format!("Part numbers must be between 1 and {MAX_PART_NUMBER}"),
+                    Range
+                ),
+            ));
+        }
+        let store = self.store.clone();
+        let key = self.key.clone();
+        let upload_id = self.upload_id.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, body) = stage_body(cx, store.clone(), Some(value)).await;
+                let body = body?;
+
+                let (cx, etag) = run_blocking(cx, move || {
+                    store.upload_part(&key, &upload_id, part_number, body)
+                })
+                .await;
+
+                let result = Object::new(&cx);
+                result.set_as(&cx, "partNumber", &part_number);
+                result.set_as(&cx, "etag", &etag?);
+                Ok((*result).get())
+            })
+        }
+    }
+
+    pub fn abort(&self, cx: &Context) -> Option<Promise> {
+        let store = self.store.clone();
+        let key = self.key.clone();
+        let upload_id = self.upload_id.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (_, result) =
+                    run_blocking(cx, move || store.abort_multipart_upload(&key, &upload_id)).await;
+                result
+            })
+        }
+    }
+
+    pub fn complete(&self, cx: &Context, uploaded_parts: Vec<UploadedPart>) -> Option<Promise> {
+        let store = self.store.clone();
+        let key = self.key.clone();
+        let upload_id = self.upload_id.clone();
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, meta) = run_blocking(cx, move || {
+                    store.complete_multipart_upload(&key, &upload_id, uploaded_parts)
+                })
+                .await;
+                Ok(R2Object::new_obj(&cx, meta?)?)
+            })
+        }
+    }
+}
+
+#[js_class]
+pub struct R2Object {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    http_metadata: HttpMetadata,
+}
+
+impl R2Object {
+    fn new_obj(cx: &Context, meta: ObjectMeta) -> ion::Result<*mut JSObject> {
+        let obj = Object::from(cx.root(Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                http_metadata: meta.http_metadata.clone(),
+            }),
+        )));
+        define_object_properties(cx, &obj, &meta, None)?;
+        Ok((*obj).get())
+    }
+}
+
+#[js_class]
+impl R2Object {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<R2Object> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    #[ion(name = "writeHttpMetadata")]
+    pub fn write_http_metadata(&self, cx: &Context, headers: Object) -> ion::Result<()> {
+        write_http_metadata(cx, &self.http_metadata, &headers)
+    }
+}
+
+#[js_class]
+pub struct R2ObjectBody {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    http_metadata: HttpMetadata,
+
+    #[trace(no_trace)]
+    body: Option<hyper::Body>,
+
+    stream: Option<Heap<*mut JSObject>>,
+}
+
+impl R2ObjectBody {
+    fn new_obj(
+        cx: &Context,
+        meta: ObjectMeta,
+        body: hyper::Body,
+        range: Option<(u64, u64)>,
+    ) -> ion::Result<*mut JSObject> {
+        let obj = Object::from(cx.root(Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                http_metadata: meta.http_metadata.clone(),
+                body: Some(body),
+                stream: None,
+            }),
+        )));
+        define_object_properties(cx, &obj, &meta, range)?;
+        Ok((*obj).get())
+    }
+
+    fn take_body(&mut self) -> ion::Result<hyper::Body> {
+        match self.body.take() {
+            Some(body) => Ok(body),
+            None => ion_err!("Body has already been used", Type),
+        }
+    }
+
+    /// Reads the rest of the body and hands it to `f` once it's all there.
+    fn read_body<F>(&mut self, cx: &Context, f: F) -> Option<Promise>
+    where
+        F: FnOnce(&Context, bytes::Bytes) -> ion::Result<JSVal> + 'static,
+    {
+        let body = match self.take_body() {
+            Ok(body) => body,
+            Err(e) => return Some(Promise::rejected(cx, e)),
+        };
+
+        unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let (cx, bytes) = cx.await_native(hyper::body::to_bytes(body)).await;
+                let bytes = bytes
+                    .map_err(|e| ion_mk_err!(format!("Failed to read R2 object: {e}"), Normal))?;
+                f(&cx, bytes)
+            })
+        }
+    }
+}
+
+#[js_class]
+impl R2ObjectBody {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<R2ObjectBody> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    #[ion(get)]
+    pub fn get_body(&mut self, cx: &Context) -> ion::Result<*mut JSObject> {
+        if let Some(stream) = &self.stream {
+            return Ok(stream.get());
+        }
+
+        let stream = hyper_body_to_stream(cx, self.take_body()?)
+            .ok_or_else(|| ion_mk_err!("Failed to create ReadableStream", Normal))?
+            .as_value(cx)
+            .handle()
+            .to_object();
+        self.stream = Some(Heap::new(stream));
+        Ok(stream)
+    }
+
+    #[ion(name = "bodyUsed", get)]
+    pub fn get_body_used(&self) -> bool {
+        self.body.is_none()
+    }
+
+    pub fn text(&mut self, cx: &Context) -> Option<Promise> {
+        self.read_body(cx, |cx, body| {
+            Ok(String::from_utf8_lossy(&body).as_value(cx).get())
+        })
+    }
+
+    pub fn json(&mut self, cx: &Context) -> Option<Promise> {
+        self.read_body(cx, |cx, body| {
+            Ok(json_parse(cx, &String::from_utf8_lossy(&body))?.get())
+        })
+    }
+
+    #[ion(name = "arrayBuffer")]
+    pub fn array_buffer(&mut self, cx: &Context) -> Option<Promise> {
+        self.read_body(cx, |cx, body| {
+            let buffer = ArrayBuffer::copy_from_bytes(cx, &body)
+                .ok_or_else(|| ion_mk_err!("Failed to allocate array", Normal))?;
+            Ok(buffer.as_value(cx).get())
+        })
+    }
+
+    #[ion(name = "writeHttpMetadata")]
+    pub fn write_http_metadata(&self, cx: &Context, headers: Object) -> ion::Result<()> {
+        write_http_metadata(cx, &self.http_metadata, &headers)
+    }
+}
+
+#[derive(FromValue)]
+enum StreamChunk<'cx> {
+    #[ion(inherit)]
+    ArrayBuffer(mozjs::typedarray::ArrayBuffer, PhantomData<&'cx ()>),
+    #[ion(inherit)]
+    ArrayBufferView(mozjs::typedarray::ArrayBufferView, PhantomData<&'cx ()>),
+}
+
+/// Copies an object body into a staging file. Streams are read and written
+/// one chunk at a time, so bodies never have to fit in memory.
+async fn stage_body(
+    cx: Context,
+    store: R2Store,
+    value: Option<FetchBody>,
+) -> (Context, ion::Result<StagedBody>) {
+    let (mut cx, staged) = run_blocking(cx, move || store.stage_body()).await;
+    let mut staged = match staged {
+        Ok(staged) => staged,
+        Err(e) => return (cx, Err(e)),
+    };
+
+    let stream = match value {
+        None => return (cx, Ok(staged)),
+        Some(value) => match &value.body {
+            FetchBodyInner::Stream(stream) => TracedHeap::new(stream.as_value(&cx).get()),
+            _ => {
+                let (cx, bytes) = cx.await_native_cx(|cx| value.into_bytes(cx)).await;
+                let bytes = match bytes {
+                    Ok(bytes) => bytes.unwrap_or_default(),
+                    Err(e) => return (cx, Err(e)),
+                };
+                return run_blocking(cx, move || {
+                    staged.write(&bytes)?;
+                    Ok(staged)
+                })
+                .await;
+            }
+        },
+    };
+
+    let stream = Value::from(stream.root(&cx)).to_object(&cx);
+    let reader = match call_method(&cx, &stream, "getReader", &[]) {
+        Ok(reader) => TracedHeap::new(reader.get()),
+        Err(e) => return (cx, Err(e)),
+    };
+
+    loop {
+        let chunk = match read_chunk(&cx, &reader).await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => return (cx, Ok(staged)),
+            Err(e) => return (cx, Err(e)),
+        };
+
+        let result;
+        (cx, result) = run_blocking(cx, move || {
+            staged.write(&chunk)?;
+            Ok(staged)
+        })
+        .await;
+        staged = match result {
+            Ok(staged) => staged,
+            Err(e) => {
+                // Let the source know nobody is going to read the rest
+                let reader = Value::from(reader.root(&cx)).to_object(&cx);
+                _ = call_method(&cx, &reader, "cancel", &[]);
+                return (cx, Err(e));
+            }
+        };
+    }
+}
+
+/// Reads the next chunk from a stream reader, or `None` once the stream
+/// is done.
+async fn read_chunk(cx: &Context, reader: &TracedHeap<JSVal>) -> ion::Result<Option<Vec<u8>>> {
+    let reader = Value::from(reader.root(cx)).to_object(cx);
+    let result = call_method(cx, &reader, "read", &[])?;
+    let future = {
+        let promise = unsafe { Promise::from_unchecked(result.to_object(cx).into_local()) };
+        PromiseFuture::new(cx.duplicate(), &promise)
+    };
+
+    let result = match future.await.1 {
+        Ok(result) => Value::from(result.root(cx)),
+        Err(error) => {
+            let error = Value::from(error.root(cx));
+            let error = String::from_value(cx, &error, false, ()).unwrap_or_default();
+            ion_err!(format!("Failed to read the body: {error}"), Normal);
+        }
+    };
+    let result = result.to_object(cx);
+
+    let done = get_property(cx, &result, "done")?
+        .map(|done| done.handle().to_boolean())
+        .unwrap_or(false);
+    if done {
+        return Ok(None);
+    }
+
+    let Some(value) = get_property(cx, &result, "value")? else {
+        return Ok(Some(vec![]));
+    };
+    let chunk = StreamChunk::from_value(cx, &value, false, ()).map_err(|_| {
+        ion_mk_err!(
+            "Stream chunks must be ArrayBuffers or ArrayBufferViews",
+            Type
+        )
+    })?;
+    Ok(Some(unsafe {
+        match chunk {
+            StreamChunk::ArrayBuffer(buf, _) => buf.as_slice().to_vec(),
+            StreamChunk::ArrayBufferView(buf, _) => buf.as_slice().to_vec(),
+        }
+    }))
+}
+
+/// Streams the next `length` bytes of `file`, reading them on the blocking
+/// thread pool as the body is polled.
+fn file_to_body(file: File, length: u64) -> hyper::Body {
+    let chunks = futures::stream::unfold(Some((file, length)), |state| async move {
+        let (mut file, remaining) = state?;
+        if remaining == 0 {
+            return None;
+        }
+
+        let read = tokio::task::spawn_blocking(move || {
+            let mut chunk = vec![0; remaining.min(READ_CHUNK_SIZE) as usize];
+            let read = file.read(&mut chunk)?;
+            chunk.truncate(read);
+            Ok::<_, std::io::Error>((file, chunk))
+        })
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+        .and_then(|read| read);
+
+        match read {
+            Ok((_, chunk)) if chunk.is_empty() => Some((
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "R2 object is shorter than its metadata says",
+                )),
+                None,
+            )),
+            Ok((file, chunk)) => {
+                let remaining = remaining - chunk.len() as u64;
+                Some((Ok(bytes::Bytes::from(chunk)), Some((file, remaining))))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    hyper::Body::wrap_stream(chunks)
+}
+
+fn validate_key(key: &str) -> ion::Result<()> {
+    if key.is_empty() {
+        ion_err!("Key names must not be empty", Type);
+    }
+    if key.len() > MAX_KEY_LENGTH {
+        ion_err!(
+            format!("Key names must be at most {MAX_KEY_LENGTH} bytes long"),
+            Range
+        );
+    }
+    Ok(())
+}
+
+/// R2Object and R2ObjectBody share all their metadata, so we keep it as
+/// plain properties on the object rather than duplicating the getters.
+fn define_object_properties(
+    cx: &Context,
+    obj: &Object,
+    meta: &ObjectMeta,
+    range: Option<(u64, u64)>,
+) -> ion::Result<()> {
+    let checksums = Object::new(cx);
+    if let Some(md5) = &meta.md5 {
+        let md5 = unhex(md5).map_err(|e| ion_mk_err!(e.to_string(), Normal))?;
+        let buffer = ArrayBuffer::copy_from_bytes(cx, &md5)
+            .ok_or_else(|| ion_mk_err!("Failed to allocate array", Normal))?;
+        checksums.set_as(cx, "md5", &buffer);
+    }
+
+    let success = obj.set_as(cx, "key", &meta.key)
+        && obj.set_as(cx, "version", &meta.version)
+        && obj.set_as(cx, "size", &(meta.size as f64))
+        && obj.set_as(cx, "etag", &meta.etag)
+        && obj.set_as(cx, "httpEtag", &format!("\"{}\"", meta.etag))
+        && obj.set(cx, "uploaded", &date_to_js(cx, meta.uploaded)?)
+        && obj.set(
+            cx,
+            "httpMetadata",
+            &http_metadata_to_js(cx, &meta.http_metadata)?,
+        )
+        && obj.set(
+            cx,
+            "customMetadata",
+            &json_parse(
+                cx,
+                &serde_json::to_string(&meta.custom_metadata)
+                    .map_err(|e| ion_mk_err!(e.to_string(), Normal))?,
+            )?,
+        )
+        && obj.set_as(cx, "checksums", &checksums)
+        && obj.set_as(cx, "storageClass", &"Standard");
+    if !success {
+        ion_err!("Failed to create R2 object", Normal);
+    }
+
+    if let Some((offset, length)) = range {
+        let range_obj = Object::new(cx);
+        range_obj.set_as(cx, "offset", &(offset as f64));
+        range_obj.set_as(cx, "length", &(length as f64));
+        obj.set_as(cx, "range", &range_obj);
+    }
+
+    Ok(())
+}
+
+const HTTP_METADATA_HEADERS: [&str; 6] = [
+    "Content-Type",
+    "Content-Language",
+    "Content-Disposition",
+    "Content-Encoding",
+    "Cache-Control",
+    "Expires",
+];
+
+fn write_http_metadata(cx: &Context, metadata: &HttpMetadata, headers: &Object) -> ion::Result<()> {
+    let expires = metadata.cache_expiry.and_then(|e| {
+        chrono::DateTime::from_timestamp_millis(e)
+            .map(|d| d.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+    });
+    let values = [
+        &metadata.content_type,
+        &metadata.content_language,
+        &metadata.content_disposition,
+        &metadata.content_encoding,
+        &metadata.cache_control,
+        &expires,
+    ];
+
+    for (name, value) in HTTP_METADATA_HEADERS.iter().zip(values) {
+        if let Some(value) = value {
+            call_method(
+                cx,
+                headers,
+                "set",
+                &[(*name).as_value(cx), value.as_value(cx)],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn http_metadata_to_js<'cx>(cx: &'cx Context, metadata: &HttpMetadata) -> ion::Result<Value<'cx>> {
+    let obj = Object::new(cx);
+    let fields = [
+        ("contentType", &metadata.content_type),
+        ("contentLanguage", &metadata.content_language),
+        ("contentDisposition", &metadata.content_disposition),
+        ("contentEncoding", &metadata.content_encoding),
+        ("cacheControl", &metadata.cache_control),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            obj.set_as(cx, name, value);
+        }
+    }
+    if let Some(expiry) = metadata.cache_expiry {
+        obj.set(cx, "cacheExpiry", &date_to_js(cx, expiry)?);
+    }
+    Ok(obj.as_value(cx))
+}
+
+fn date_to_js(cx: &Context, millis: i64) -> ion::Result<Value> {
+    let date = chrono::DateTime::from_timestamp_millis(millis)
+        .ok_or_else(|| ion_mk_err!("Invalid date", Range))?;
+    Ok(Date::from_date(cx, date).as_value(cx))
+}
+
+/// Accepts a `Date`, a number of milliseconds since the Unix epoch or an
+/// HTTP date string.
+fn date_from_js(cx: &Context, value: &Value) -> ion::Result<i64> {
+    let handle = value.handle();
+    if handle.is_number() {
+        return Ok(handle.to_number() as i64);
+    }
+    if handle.is_string() {
+        let s = String::from_value(cx, value, true, ())?;
+        return chrono::DateTime::parse_from_rfc2822(&s)
+            .or_else(|_| chrono::DateTime::parse_from_rfc3339(&s))
+            .map(|d| d.timestamp_millis())
+            .map_err(|_| ion_mk_err!(format!("Invalid date '{s}'"), Type));
+    }
+    if handle.is_object() {
+        let time = call_method(cx, &value.to_object(cx), "getTime", &[])?;
+        if time.handle().is_number() {
+            return Ok(time.handle().to_number() as i64);
+        }
+    }
+    ion_err!("Expected a Date", Type)
+}
+
+fn get_property<'cx>(
+    cx: &'cx Context,
+    obj: &Object,
+    name: &str,
+) -> ion::Result<Option<Value<'cx>>> {
+    Ok(obj
+        .get(cx, name)?
+        .filter(|v| !v.handle().is_undefined() && !v.handle().is_null()))
+}
+
+fn get_string_property(cx: &Context, obj: &Object, name: &str) -> ion::Result<Option<String>> {
+    get_property(cx, obj, name)?
+        .map(|v| String::from_value(cx, &v, false, ()))
+        .transpose()
+}
+
+/// Options that accept either a plain object or a `Headers` instance tell
+/// the two apart by the presence of a `get` method.
+fn as_headers(cx: &Context, obj: &Object) -> ion::Result<bool> {
+    Ok(obj
+        .get(cx, "get")?
+        .map(|v| v.handle().is_object())
+        .unwrap_or(false))
+}
+
+fn get_header(cx: &Context, headers: &Object, name: &str) -> ion::Result<Option<String>> {
+    let value = call_method(cx, headers, "get", &[name.as_value(cx)])?;
+    if value.handle().is_null() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_value(cx, &value, true, ())?))
+}
+
+fn expect_object(cx: &Context, value: Value, name: &str) -> ion::Result<Option<Object>> {
+    if value.handle().is_undefined() || value.handle().is_null() {
+        return Ok(None);
+    }
+    if !value.handle().is_object() {
+        ion_err!(format!("{name} must be an object"), Type);
+    }
+    Ok(Some(value.to_object(cx)))
+}
+
+fn conditional_from_js(cx: &Context, value: Value) -> ion::Result<Option<Conditional>> {
+    let Some(obj) = expect_object(cx, value, "onlyIf")? else {
+        return Ok(None);
+    };
+
+    if as_headers(cx, &obj)? {
+        let date = |name: &str| -> ion::Result<Option<i64>> {
+            get_header(cx, &obj, name)?
+                .map(|d| date_from_js(cx, &d.as_value(cx)))
+                .transpose()
+        };
+        return Ok(Some(Conditional {
+            etag_matches: get_header(cx, &obj, "If-Match")?.map(|e| parse_etag_list(&e)),
+            etag_does_not_match: get_header(cx, &obj, "If-None-Match")?
+                .map(|e| parse_etag_list(&e)),
+            uploaded_before: date("If-Unmodified-Since")?,
+            uploaded_after: date("If-Modified-Since")?,
+            compare_seconds: true,
+        }));
+    }
+
+    let date = |name: &str| -> ion::Result<Option<i64>> {
+        get_property(cx, &obj, name)?
+            .map(|d| date_from_js(cx, &d))
+            .transpose()
+    };
+    Ok(Some(Conditional {
+        etag_matches: get_string_property(cx, &obj, "etagMatches")?.map(|e| parse_etag_list(&e)),
+        etag_does_not_match: get_string_property(cx, &obj, "etagDoesNotMatch")?
+            .map(|e| parse_etag_list(&e)),
+        uploaded_before: date("uploadedBefore")?,
+        uploaded_after: date("uploadedAfter")?,
+        compare_seconds: false,
+    }))
+}
+
+fn range_from_js(cx: &Context, value: Value) -> ion::Result<Option<ByteRange>> {
+    let Some(obj) = expect_object(cx, value, "range")? else {
+        return Ok(None);
+    };
+
+    if as_headers(cx, &obj)? {
+        return get_header(cx, &obj, "Range")?
+            .map(|r| parse_range_header(&r))
+            .transpose();
+    }
+
+    let number = |name: &str| -> ion::Result<Option<u64>> {
+        match get_property(cx, &obj, name)? {
+            Some(v) if v.handle().is_number() && v.handle().to_number() >= 0.0 => {
+                Ok(Some(v.handle().to_number() as u64))
+            }
+            Some(_) => ion_err!(format!("range.{name} must be a non-negative number"), Type),
+            None => Ok(None),
+        }
+    };
+    Ok(Some(ByteRange {
+        offset: number("offset")?,
+        length: number("length")?,
+        suffix: number("suffix")?,
+    }))
+}
+
+fn parse_range_header(header: &str) -> ion::Result<ByteRange> {
+    let invalid = || ion_mk_err!(format!("Invalid range header '{header}'"), Range);
+    let spec = header.trim().strip_prefix("bytes=").ok_or_else(invalid)?;
+    let (start, end) = spec.split_once('-').ok_or_else(invalid)?;
+    let parse = |s: &str| s.trim().parse::<u64>().map_err(|_| invalid());
+
+    match (start.trim().is_empty(), end.trim().is_empty()) {
+        (true, false) => Ok(ByteRange {
+            suffix: Some(parse(end)?),
+            ..Default::default()
+        }),
+        (false, true) => Ok(ByteRange {
+            offset: Some(parse(start)?),
+            ..Default::default()
+        }),
+        (false, false) => {
+            let (start, end) = (parse(start)?, parse(end)?);
+            if end < start {
+                return Err(invalid());
+            }
+            Ok(ByteRange {
+                offset: Some(start),
+                length: Some(end - start + 1),
+                suffix: None,
+            })
+        }
+        (true, true) => Err(invalid()),
+    }
+}
+
+fn http_metadata_from_js(cx: &Context, value: Value) -> ion::Result<HttpMetadata> {
+    let Some(obj) = expect_object(cx, value, "httpMetadata")? else {
+        return Ok(Default::default());
+    };
+
+    if as_headers(cx, &obj)? {
+        return Ok(HttpMetadata {
+            content_type: get_header(cx, &obj, "Content-Type")?,
+            content_language: get_header(cx, &obj, "Content-Language")?,
+            content_disposition: get_header(cx, &obj, "Content-Disposition")?,
+            content_encoding: get_header(cx, &obj, "Content-Encoding")?,
+            cache_control: get_header(cx, &obj, "Cache-Control")?,
+            cache_expiry: get_header(cx, &obj, "Expires")?
+                .map(|e| date_from_js(cx, &e.as_value(cx)))
+                .transpose()?,
+        });
+    }
+
+    Ok(HttpMetadata {
+        content_type: get_string_property(cx, &obj, "contentType")?,
+        content_language: get_string_property(cx, &obj, "contentLanguage")?,
+        content_disposition: get_string_property(cx, &obj, "contentDisposition")?,
+        content_encoding: get_string_property(cx, &obj, "contentEncoding")?,
+        cache_control: get_string_property(cx, &obj, "cacheControl")?,
+        cache_expiry: get_property(cx, &obj, "cacheExpiry")?
+            .map(|e| date_from_js(cx, &e))
+            .transpose()?,
+    })
+}
+
+fn custom_metadata_from_js(cx: &Context, value: Value) -> ion::Result<BTreeMap<String, String>> {
+    if value.handle().is_undefined() || value.handle().is_null() {
+        return Ok(Default::default());
+    }
+
+    let json = json_stringify(cx, value)?;
+    if json.len() > MAX_CUSTOM_METADATA_LENGTH {
+        ion_err!(
+            format!("Custom metadata must be at most {MAX_CUSTOM_METADATA_LENGTH} bytes once serialized"),
+            Range
+        );
+    }
+    serde_json::from_str(&json)
+        .map_err(|_| ion_mk_err!("customMetadata must be a record of strings", Type))
+}
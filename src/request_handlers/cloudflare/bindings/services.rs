@@ -0,0 +1,94 @@
+//! Service bindings, which send requests to other workers running in the
+//! same process. Requests are handed to the target worker's runner directly
+//! instead of going over TCP, and bodies are streamed in both directions.
+
+use ion::{class::Reflector, function::Opt, ClassDefinition, Context, Promise, TracedHeap};
+use mozjs_sys::jsapi::JSObject;
+use runtime::{
+    globals::fetch::{
+        Request as FetchRequest, RequestInfo, RequestInit, Response as FetchResponse,
+    },
+    promise::future_to_promise,
+};
+
+use crate::{ion_err, ion_mk_err, request_handlers::Request, runners::services::dispatch};
+
+#[js_class]
+pub struct Fetcher {
+    reflector: Reflector,
+
+    #[trace(no_trace)]
+    service: String,
+}
+
+impl Fetcher {
+    pub fn new_obj(cx: &Context, service: String) -> *mut JSObject {
+        Self::new_object(
+            cx,
+            Box::new(Self {
+                reflector: Default::default(),
+                service,
+            }),
+        )
+    }
+}
+
+#[js_class]
+impl Fetcher {
+    #[ion(constructor)]
+    pub fn constructor() -> ion::Result<Fetcher> {
+        ion_err!("Cannot construct this type", Type)
+    }
+
+    pub fn fetch(
+        &self,
+        cx: &Context,
+        input: RequestInfo,
+        Opt(init): Opt<RequestInit>,
+    ) -> ion::Result<Option<Promise>> {
+        let request = FetchRequest::constructor(cx, input, Opt(init))?;
+        let request_heap = TracedHeap::new(FetchRequest::new_object(cx, Box::new(request)));
+        let service = self.service.clone();
+
+        Ok(unsafe {
+            future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+                let request =
+                    FetchRequest::get_mut_private(&cx, &request_heap.root(&cx).into()).unwrap();
+
+                let mut http_req = http::Request::builder()
+                    .uri(request.get_url())
+                    .method(request.method());
+
+                for header in request.headers(&cx) {
+                    http_req = http_req.header(header.0.clone(), header.1.clone())
+                }
+
+                // The body is pumped into the HTTP body by a separate task
+                // on this thread, while the other worker consumes it
+                let (body, body_future) = request
+                    .take_body()?
+                    .into_http_body(cx.duplicate())
+                    .map_err(|e| {
+                        ion_mk_err!(format!("Failed to read request body: {e:?}"), Normal)
+                    })?;
+                if let Some(body_future) = body_future {
+                    tokio::task::spawn_local(body_future);
+                }
+
+                let (parts, body) = http_req
+                    .body(body)
+                    .map_err(|e| ion_mk_err!(format!("Invalid request: {e}"), Type))?
+                    .into_parts();
+
+                let url = url::Url::parse(parts.uri.to_string().as_str())?;
+                let (cx, response) = cx
+                    .await_native(dispatch(service, Request { parts, body }))
+                    .await;
+                let response = response
+                    .map_err(|e| ion_mk_err!(format!("Service request failed: {e:#}"), Normal))?;
+                let response = FetchResponse::from_hyper_response(&cx, response, url)?;
+                Ok(FetchResponse::new_object(&cx, Box::new(response)))
+            })
+        })
+    }
+}
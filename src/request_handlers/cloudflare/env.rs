@@ -10,6 +10,8 @@ use runtime::{
 
 use crate::{ion_err, ion_mk_err};
 
+use super::bindings::Bindings;
+
 #[js_class]
 pub struct Env {
     reflector: Reflector,
@@ -17,7 +19,7 @@ pub struct Env {
 }
 
 impl Env {
-    pub fn new_obj(cx: &Context) -> *mut JSObject {
+    pub fn new_obj(cx: &Context, bindings: &Bindings) -> *mut JSObject {
         let assets = EnvAssets::new_object(
             cx,
             Box::new(EnvAssets {
@@ -36,6 +38,10 @@ impl Env {
             panic!("Failed to populate env object");
         }
 
+        if !bindings.define_on(cx, &env) {
+            panic!("Failed to define bindings on env object");
+        }
+
         (*env).get()
     }
 }
@@ -1,5 +1,4 @@
 use std::{
-    cell::OnceCell,
     collections::HashMap,
     path::{Path, PathBuf},
     sync::Arc,
@@ -7,36 +6,38 @@ use std::{
 
 use crate::{
     ion_mk_err,
+    runners::durable_objects::DurableObjectMessage,
     sm_utils::{self, error_report_option_to_anyhow_error},
 };
 
-use self::routes::Routes;
+use self::{
+    bindings::{Bindings, BindingsConfig},
+    routes::Routes,
+};
 
 use super::{
-    ByRefStandardModules, Either, PendingResponse, ReadyResponse, Request, RequestHandler, UserCode,
+    static_files, ByRefStandardModules, Either, PendingResponse, ReadyResponse, Request,
+    RequestHandler, UserCode,
 };
 use anyhow::{bail, Context as _, Result};
 use ion::{ClassDefinition, Context, Function, Object, Promise, TracedHeap, Value};
-use mozjs_sys::jsapi::JSFunction;
-use runtime::{globals::fetch::Response as FetchResponse, promise::future_to_promise, ContextExt};
-use static_web_server::{
-    exts::path::PathExt,
-    handler::{RequestHandler as SwsRequestHandler, RequestHandlerOpts as SwsRequestHandlerOpts},
-};
+use mozjs_sys::jsapi::{JSFunction, JSObject};
+use runtime::{globals::fetch::Response as FetchResponse, ContextExt};
 
+pub mod bindings;
 mod context;
 mod env;
 mod routes;
-
-// Still operating under the one-handler-per-thread model. The correct way
-// would to attach this to the context in some way.
-thread_local! {
-    static SWS_OPTS: OnceCell<Arc<SwsRequestHandlerOpts>> = OnceCell::new();
+pub mod workers;
+pub mod wrangler;
+
+#[derive(Clone)]
+pub struct CloudflareRequestHandler {
+    // Shared with each worker thread, and dropped along with the runner
+    // when an app is stopped or restarted
+    bindings_config: Arc<BindingsConfig>,
 }
 
-#[derive(Clone, Copy)]
-pub struct CloudflareRequestHandler;
-
 enum CloudflareRequestHandlerMode {
     // This mode gets picked if we get a file or a directory with a _worker.js
     // in it.
@@ -57,13 +58,28 @@ struct CloudflareRequestHandlerPrivate {
     modules: HashMap<PathBuf, CloudflareCodeModule>,
 
     routes: Option<Routes>,
+
+    bindings: Bindings,
 }
 
 struct CloudflareCodeModule {
     fetch_function: Option<TracedHeap<*mut JSFunction>>,
+    // The module namespace, where Durable Object classes are exported from
+    exports: TracedHeap<*mut JSObject>,
 }
 
 impl CloudflareRequestHandler {
+    pub fn new(bindings_config: BindingsConfig) -> Self {
+        Self {
+            bindings_config: Arc::new(bindings_config),
+        }
+    }
+
+    /// Schedules the Durable Object alarms left over from previous runs.
+    pub fn restore_durable_object_alarms(&self) -> Result<()> {
+        bindings::durable_objects::restore_alarms(&self.bindings_config)
+    }
+
     fn get_private(cx: &Context) -> anyhow::Result<&CloudflareRequestHandlerPrivate> {
         if unsafe { cx.get_private() }.app_data.is_none() {
             bail!("Internal error: evaluate_scripts should be called before using CloudflareRequestHandler");
@@ -72,60 +88,16 @@ impl CloudflareRequestHandler {
         Ok(unsafe { cx.get_app_data::<CloudflareRequestHandlerPrivate>() })
     }
 
-    fn build_sws_request_handler(path: impl AsRef<Path>) {
-        SWS_OPTS.with(move |s| {
-            s.get_or_init(|| {
-                let path = path.as_ref().to_path_buf();
-
-                Arc::new(static_web_server::handler::RequestHandlerOpts {
-                    advanced_opts: None,
-                    basic_auth: String::new(),
-                    // TODO: have WinterJS-themed defaults
-                    page404: std::fs::read(path.join("404.html")).unwrap_or_default(),
-                    page50x: std::fs::read(path.join("500.html")).unwrap_or_default(),
-                    page_fallback: std::fs::read(path.join("_fallback.html")).unwrap_or_default(),
-                    // We need to allow hidden paths if the root path itself is
-                    // hidden, otherwise every response is a 404
-                    ignore_hidden_files: !path.is_hidden(),
-                    root_dir: path,
-                    compression: true,
-                    compression_static: false,
-                    dir_listing: false,
-                    dir_listing_format: static_web_server::directory_listing::DirListFmt::Html,
-                    dir_listing_order: 0,
-                    cache_control_headers: true,
-                    cors: None,
-                    log_remote_address: false,
-                    redirect_trailing_slash: false,
-                    security_headers: true,
-                })
-            });
-        })
-    }
-
-    fn get_sws_request_handler() -> ion::Result<SwsRequestHandler> {
-        SWS_OPTS.with(|h| {
-            Ok(SwsRequestHandler {
-                opts: h
-                    .get()
-                    .ok_or_else(|| {
-                        ion_mk_err!(
-                            "To allow static files to be served, WinterJS must be run \
-                            with the JS_PATH argument pointing to a directory",
-                            Normal
-                        )
-                    })?
-                    .clone(),
-            })
-        })
-    }
-
     async fn serve_static_file(req: Request) -> ion::Result<hyper::Response<hyper::Body>> {
-        let mut hyper_req = hyper::Request::from_parts(req.parts, req.body);
-        let response = Self::get_sws_request_handler()?
-            .handle(&mut hyper_req, None)
-            .await;
-        response
+        if !static_files::is_enabled() {
+            return Err(ion_mk_err!(
+                "To allow static files to be served, WinterJS must be run \
+                with the JS_PATH argument pointing to a directory",
+                Normal
+            ));
+        }
+        static_files::serve(req)
+            .await
             .map_err(|e| ion_mk_err!(format!("Failed to fetch static asset due to: {e}"), Normal))
     }
 }
@@ -141,6 +113,7 @@ impl RequestHandler for CloudflareRequestHandler {
         }
 
         let private: CloudflareRequestHandlerPrivate;
+        let bindings = Bindings::new(&self.bindings_config)?;
 
         match code {
             UserCode::Script { code, file_name } => {
@@ -151,6 +124,7 @@ impl RequestHandler for CloudflareRequestHandler {
                     mode: SingleSourceFile,
                     modules: Default::default(),
                     routes: None,
+                    bindings,
                 };
             }
 
@@ -161,6 +135,7 @@ impl RequestHandler for CloudflareRequestHandler {
                         .into_iter()
                         .collect(),
                     routes: None,
+                    bindings,
                 };
             }
 
@@ -172,13 +147,14 @@ impl RequestHandler for CloudflareRequestHandler {
                             "_routes.json file not found, all requests will be routed to _worker.js"
                         );
                     }
-                    Self::build_sws_request_handler(path);
+                    static_files::build_handler(path, true);
                     private = CloudflareRequestHandlerPrivate {
                         mode: SingleSourceFile,
                         modules: [(PathBuf::new(), eval_module(cx, worker_js_path)?)]
                             .into_iter()
                             .collect(),
                         routes,
+                        bindings,
                     };
                 }
                 None => {
@@ -202,35 +178,40 @@ impl RequestHandler for CloudflareRequestHandler {
 
         if let Some(ref routes) = private.routes {
             if !routes.should_route_to_function(request.parts.uri.path()) {
-                return Ok(Either::Left(PendingResponse {
-                    promise: unsafe {
-                        future_to_promise::<_, _, _, ion::Error>(&cx, move |cx| async move {
-                            let uri = super::build_request_uri(&request).map_err(|e| {
-                                ion_mk_err!(format!("Failed to parse request URI: {e}"), Normal)
-                            })?;
-                            let url = url::Url::parse(uri.to_string().as_str())?;
-                            let (cx, response) =
-                                cx.await_native(Self::serve_static_file(request)).await;
-                            let response = response.map_err(|e| {
-                                ion_mk_err!(
-                                    format!("Failed to fetch static asset due to {e}"),
-                                    Normal
-                                )
-                            })?;
-                            let response = FetchResponse::from_hyper_response(&cx, response, url)?;
-                            Ok(FetchResponse::new_object(&cx, Box::new(response)))
-                        })
-                        .expect("Future queue must be initialized")
-                    },
-                }));
+                return static_files::start_serving(&cx, request).map(Either::Left);
             }
         }
 
         match private.mode {
-            SingleSourceFile => start_request(&cx, request, private.modules.get(&PathBuf::new())),
+            SingleSourceFile => start_request(
+                &cx,
+                request,
+                private.modules.get(&PathBuf::new()),
+                &private.bindings,
+            ),
         }
     }
 
+    fn start_handling_durable_object_message(
+        &mut self,
+        cx: Context,
+        message: DurableObjectMessage,
+    ) -> Result<Either<PendingResponse, ReadyResponse>> {
+        let private = Self::get_private(&cx)?;
+        let Some(module) = private.modules.get(&PathBuf::new()) else {
+            bail!("Durable Objects can only be exported from modules, not scripts");
+        };
+
+        let exports = Object::from(module.exports.root(&cx));
+        let promise = private.bindings.durable_objects.start_event(
+            &cx,
+            &exports,
+            || env::Env::new_obj(&cx, &private.bindings),
+            message,
+        )?;
+        Ok(Either::Left(PendingResponse { promise }))
+    }
+
     fn finish_fulfilled_request(
         &mut self,
         cx: Context,
@@ -254,6 +235,7 @@ impl ByRefStandardModules for CloudflareStandardModules {
         global.set_as(cx, "self", &(**global).get())
             && super::service_workers::define(cx, global)
             && self::env::define(cx, global)
+            && self::bindings::define(cx, global)
             && self::context::define(cx, global)
     }
 }
@@ -309,6 +291,7 @@ fn eval_module(cx: &Context, path: impl AsRef<Path>) -> anyhow::Result<Cloudflar
         });
     Ok(CloudflareCodeModule {
         fetch_function: fetch_func.map(|f| TracedHeap::from_local(&f)),
+        exports: TracedHeap::new((*ns).get()),
     })
 }
 
@@ -316,6 +299,7 @@ fn start_request(
     cx: &Context,
     request: Request,
     module: Option<&CloudflareCodeModule>,
+    bindings: &Bindings,
 ) -> Result<Either<PendingResponse, ReadyResponse>> {
     match module.and_then(|m| m.fetch_function.as_ref()) {
         Some(func) => {
@@ -323,7 +307,7 @@ fn start_request(
                 cx,
                 &cx.root(super::build_fetch_request(cx, request)?).into(),
             );
-            let env = Value::object(cx, &cx.root(env::Env::new_obj(cx)).into());
+            let env = Value::object(cx, &cx.root(env::Env::new_obj(cx, bindings)).into());
             let ctx = Value::object(cx, &cx.root(context::Context::new_obj(cx)).into());
             let result = Function::from(func.root(cx))
                 .call(cx, &Object::null(cx), &[request, env, ctx])
@@ -0,0 +1,173 @@
+//! Reads the config for running several workers in one process, passed in
+//! with `--workers`. Workers can call each other through service bindings,
+//! and one of them is the entrypoint which receives incoming requests:
+//!
+//! ```toml
+//! entrypoint = "gateway"
+//!
+//! [[workers]]
+//! name = "gateway"
+//! main = "gateway/index.js"
+//! bindings = { services = [{ binding = "AUTH", service = "auth" }] }
+//!
+//! [[workers]]
+//! name = "auth"
+//! wrangler_config = "auth/wrangler.toml"
+//! ```
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use super::{bindings::BindingsConfig, wrangler::WranglerConfig};
+
+#[derive(Deserialize)]
+struct WorkersFile {
+    /// Defaults to the first worker.
+    entrypoint: Option<String>,
+
+    workers: Vec<WorkerEntry>,
+}
+
+#[derive(Deserialize)]
+struct WorkerEntry {
+    name: String,
+
+    /// Can be left out if the wrangler config has a `main` entry.
+    main: Option<PathBuf>,
+
+    /// Bindings are read from the wrangler config if one is given, and
+    /// from `bindings` otherwise.
+    wrangler_config: Option<PathBuf>,
+
+    wrangler_env: Option<String>,
+
+    bindings: Option<BindingsConfig>,
+
+    #[serde(default)]
+    script: bool,
+
+    /// Defaults to the value of `--max-js-threads`.
+    max_js_threads: Option<usize>,
+}
+
+pub struct WorkerConfig {
+    pub name: String,
+    pub main: PathBuf,
+    pub script: bool,
+    pub max_js_threads: Option<usize>,
+    pub bindings: BindingsConfig,
+}
+
+pub struct WorkersConfig {
+    pub entrypoint: String,
+    pub workers: Vec<WorkerConfig>,
+}
+
+impl WorkersConfig {
+    pub fn try_parse(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read workers config {}", path.display()))?;
+        let file = if path.extension().map(|e| e == "json").unwrap_or(false) {
+            serde_json::from_str::<WorkersFile>(&file_content).map_err(anyhow::Error::from)
+        } else {
+            toml::from_str::<WorkersFile>(&file_content).map_err(anyhow::Error::from)
+        }
+        .with_context(|| format!("Failed to parse workers config {}", path.display()))?;
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        let workers = file
+            .workers
+            .into_iter()
+            .map(|entry| {
+                let name = entry.name.clone();
+                WorkerConfig::new(entry, base_dir)
+                    .with_context(|| format!("Invalid config for worker {name}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut names = HashSet::new();
+        for worker in &workers {
+            if !names.insert(worker.name.as_str()) {
+                bail!("Worker {} is declared more than once", worker.name);
+            }
+        }
+
+        for worker in &workers {
+            for service in &worker.bindings.services {
+                if !names.contains(service.service.as_str()) {
+                    bail!(
+                        "Service binding {} of worker {} refers to unknown worker {}",
+                        service.binding,
+                        worker.name,
+                        service.service
+                    );
+                }
+                if service.entrypoint.is_some() {
+                    tracing::warn!(
+                        "Named entrypoints are not supported, service binding {} of worker {} \
+                        will use the default export of {}",
+                        service.binding,
+                        worker.name,
+                        service.service
+                    );
+                }
+            }
+        }
+
+        let entrypoint = match file.entrypoint {
+            Some(entrypoint) if names.contains(entrypoint.as_str()) => entrypoint,
+            Some(entrypoint) => bail!("Entrypoint {entrypoint} is not a declared worker"),
+            None => workers
+                .first()
+                .map(|w| w.name.clone())
+                .context("No workers declared")?,
+        };
+
+        Ok(Self {
+            entrypoint,
+            workers,
+        })
+    }
+}
+
+impl WorkerConfig {
+    fn new(entry: WorkerEntry, base_dir: &Path) -> Result<Self> {
+        let (main, bindings) = match entry.wrangler_config {
+            Some(wrangler_config) => {
+                if entry.bindings.is_some() {
+                    bail!("`bindings` cannot be used together with `wrangler_config`");
+                }
+                let wrangler = WranglerConfig::try_parse(
+                    base_dir.join(wrangler_config),
+                    entry.wrangler_env.as_deref(),
+                )?;
+                (
+                    entry.main.map(|main| base_dir.join(main)).or(wrangler.main),
+                    wrangler.bindings,
+                )
+            }
+            None => {
+                if entry.wrangler_env.is_some() {
+                    bail!("`wrangler_env` can only be used together with `wrangler_config`");
+                }
+                let mut bindings = entry.bindings.unwrap_or_default();
+                bindings.resolve_paths(base_dir);
+                (entry.main.map(|main| base_dir.join(main)), bindings)
+            }
+        };
+
+        Ok(Self {
+            name: entry.name,
+            main: main.context("No `main` entry given")?,
+            script: entry.script,
+            max_js_threads: entry.max_js_threads,
+            bindings,
+        })
+    }
+}
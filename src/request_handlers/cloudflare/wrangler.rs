@@ -0,0 +1,166 @@
+//! Reads the configuration of a Cloudflare project from its `wrangler.toml`
+//! or `wrangler.json` file, so projects can be served without having to
+//! repeat their bindings in a separate file.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use super::bindings::BindingsConfig;
+
+const CONFIG_FILE_NAMES: &[&str] = &["wrangler.toml", "wrangler.json"];
+
+const DEFAULT_MIGRATIONS_DIR: &str = "migrations";
+
+// Keys which are not inherited from the top level by environments, and must
+// be redefined in each environment instead.
+const NON_INHERITABLE_KEYS: &[&str] = &[
+    "vars",
+    "kv_namespaces",
+    "r2_buckets",
+    "d1_databases",
+    "durable_objects",
+    "services",
+    "queues",
+    "analytics_engine_datasets",
+    "ai",
+    "vectorize",
+    "hyperdrive",
+    "browser",
+];
+
+// Bindings and features we know about but don't support yet.
+const UNSUPPORTED_KEYS: &[&str] = &[
+    "queues",
+    "analytics_engine_datasets",
+    "ai",
+    "vectorize",
+    "hyperdrive",
+    "browser",
+    "mtls_certificates",
+    "dispatch_namespaces",
+    "send_email",
+    "tail_consumers",
+    "version_metadata",
+    "wasm_modules",
+    "text_blobs",
+    "data_blobs",
+    "unsafe",
+    "site",
+    "assets",
+];
+
+#[derive(Deserialize)]
+struct WranglerFile {
+    main: Option<PathBuf>,
+
+    compatibility_date: Option<String>,
+
+    #[serde(default)]
+    compatibility_flags: Vec<String>,
+
+    #[serde(default)]
+    triggers: Triggers,
+
+    #[serde(flatten)]
+    bindings: BindingsConfig,
+}
+
+#[derive(Deserialize, Default)]
+struct Triggers {
+    #[serde(default)]
+    crons: Vec<String>,
+}
+
+pub struct WranglerConfig {
+    /// The entrypoint of the worker, resolved against the directory the
+    /// config file lives in.
+    pub main: Option<PathBuf>,
+
+    pub bindings: BindingsConfig,
+}
+
+impl WranglerConfig {
+    /// Looks for a config file in the given directory.
+    pub fn discover(dir: impl AsRef<Path>) -> Option<PathBuf> {
+        CONFIG_FILE_NAMES
+            .iter()
+            .map(|name| dir.as_ref().join(name))
+            .find(|path| path.is_file())
+    }
+
+    /// Parses the config file at the given path. If an environment is given,
+    /// its section of the config is applied on top of the top-level keys.
+    pub fn try_parse(path: impl AsRef<Path>, environment: Option<&str>) -> Result<Self> {
+        let path = path.as_ref();
+        let file_content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read wrangler config {}", path.display()))?;
+        let value = if path.extension().map(|e| e == "json").unwrap_or(false) {
+            serde_json::from_str::<serde_json::Value>(&file_content).map_err(anyhow::Error::from)
+        } else {
+            toml::from_str::<serde_json::Value>(&file_content).map_err(anyhow::Error::from)
+        }
+        .with_context(|| format!("Failed to parse wrangler config {}", path.display()))?;
+
+        let serde_json::Value::Object(mut config) = value else {
+            bail!(
+                "Expected wrangler config {} to be an object",
+                path.display()
+            );
+        };
+
+        let environments = config.remove("env");
+        if let Some(environment) = environment {
+            let Some(serde_json::Value::Object(overrides)) = environments
+                .as_ref()
+                .and_then(|envs| envs.get(environment))
+                .cloned()
+            else {
+                bail!("Environment {environment} not found in wrangler config");
+            };
+
+            for key in NON_INHERITABLE_KEYS {
+                config.remove(*key);
+            }
+            config.extend(overrides);
+        }
+
+        for key in UNSUPPORTED_KEYS {
+            if config.contains_key(*key) {
+                tracing::warn!("`{key}` in wrangler config is not supported, ignoring");
+            }
+        }
+
+        let mut file = serde_json::from_value::<WranglerFile>(serde_json::Value::Object(config))
+            .with_context(|| format!("Invalid wrangler config {}", path.display()))?;
+
+        if !file.triggers.crons.is_empty() {
+            tracing::warn!("Cron triggers are not supported, ignoring");
+        }
+        if !file.compatibility_flags.is_empty() {
+            tracing::warn!(
+                "Compatibility flags are not supported, ignoring: {}",
+                file.compatibility_flags.join(", ")
+            );
+        }
+        if let Some(date) = &file.compatibility_date {
+            tracing::debug!("Ignoring compatibility date {date}");
+        }
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+
+        // Like wrangler, look for D1 migrations in `migrations` by default
+        for db in &mut file.bindings.d1_databases {
+            if db.migrations_dir.is_none() && base_dir.join(DEFAULT_MIGRATIONS_DIR).is_dir() {
+                db.migrations_dir = Some(PathBuf::from(DEFAULT_MIGRATIONS_DIR));
+            }
+        }
+        file.bindings.resolve_paths(base_dir);
+
+        Ok(Self {
+            main: file.main.map(|main| base_dir.join(main)),
+            bindings: file.bindings,
+        })
+    }
+}
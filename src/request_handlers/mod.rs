@@ -16,8 +16,12 @@ use runtime::{
     module::StandardModules,
 };
 
+use crate::runners::durable_objects::DurableObjectMessage;
+
 pub mod cloudflare;
+pub mod serve;
 pub mod service_workers;
+pub mod static_files;
 pub mod wintercg;
 
 #[derive(Clone, Debug)]
@@ -55,6 +59,11 @@ impl UserCode {
     }
 }
 
+/// The address of the client a request came from, stored in the request's
+/// extensions by the server.
+#[derive(Clone, Copy, Debug)]
+pub struct RemoteAddr(pub std::net::SocketAddr);
+
 pub struct Request {
     pub parts: http::request::Parts,
     pub body: hyper::Body,
@@ -89,6 +98,19 @@ pub trait RequestHandler: Clone + Send + Sync + 'static {
         request: Request,
     ) -> Result<Either<PendingResponse, ReadyResponse>>;
 
+    /// Start handling a message addressed to a Durable Object. Only
+    /// handlers that support Durable Objects need to implement this.
+    fn start_handling_durable_object_message(
+        &mut self,
+        _cx: Context,
+        message: DurableObjectMessage,
+    ) -> Result<Either<PendingResponse, ReadyResponse>> {
+        bail!(
+            "Durable Object '{}' cannot be handled: this handler does not support Durable Objects",
+            message.target.class_name
+        )
+    }
+
     /// Finish handling the request. The associated promise must be
     /// either fulfilled or rejected before calling this method.
     fn finish_request(
@@ -0,0 +1,446 @@
+//! `Deno.serve` and `Bun.serve`, for apps written against those runtimes'
+//! HTTP server APIs. WinterJS owns the listening socket, so calling either
+//! function only registers the request handler; the `port` and `hostname`
+//! options are checked against the address WinterJS listens on, and are
+//! otherwise ignored.
+//!
+//! The `Deno` and `Bun` globals are only defined when the shims are enabled
+//! with `--serve-shims`, so apps don't mistake WinterJS for either runtime.
+
+use std::{
+    cell::RefCell,
+    net::{IpAddr, SocketAddr},
+    sync::Once,
+};
+
+use anyhow::{anyhow, bail, Result};
+use ion::{
+    conversions::{ConversionBehavior, FromValue, ToValue},
+    function::Opt,
+    function_spec, Context, Function, Object, PermanentHeap, Promise, PromiseFuture, TracedHeap,
+    Value,
+};
+use mozjs::jsval::JSVal;
+use mozjs_sys::jsapi::{JSFunction, JSFunctionSpec, JSObject};
+use once_cell::sync::OnceCell;
+use runtime::promise::future_to_promise;
+
+use crate::{
+    ion_err, ion_mk_err,
+    sm_utils::{self, error_report_option_to_anyhow_error},
+};
+
+use super::{Either, PendingResponse, ReadyResponse, RemoteAddr, Request};
+
+static LISTEN_ADDR: OnceCell<SocketAddr> = OnceCell::new();
+static SHIMS_ENABLED: OnceCell<bool> = OnceCell::new();
+
+// Calls the handler and turns exceptions into rejections, so the error
+// handler gets the thrown value itself rather than a copy of it
+const CALL_SCRIPT: &str = "(function (handler, thisArg, request, info) {
+    try {
+        return handler.call(thisArg, request, info);
+    } catch (e) {
+        return Promise.reject(e);
+    }
+})";
+
+// Every JS thread evaluates the same code, so there's no need to warn
+// about the options more than once
+static OPTIONS_WARNING: Once = Once::new();
+
+thread_local! {
+    static SERVE_HANDLER: RefCell<Option<ServeHandler>> = RefCell::new(None);
+}
+
+#[derive(Clone, Copy)]
+enum Flavor {
+    Deno,
+    Bun,
+}
+
+impl Flavor {
+    fn function_name(self) -> &'static str {
+        match self {
+            Flavor::Deno => "Deno.serve",
+            Flavor::Bun => "Bun.serve",
+        }
+    }
+}
+
+struct ServeHandler {
+    flavor: Flavor,
+    handler: PermanentHeap<*mut JSFunction>,
+    on_error: Option<ErrorHandler>,
+
+    // The object returned from the serve call. Bun passes it to the handler.
+    server: PermanentHeap<*mut JSObject>,
+}
+
+struct ErrorHandler {
+    handler: PermanentHeap<*mut JSFunction>,
+    call: PermanentHeap<*mut JSFunction>,
+}
+
+/// Sets the address the server listens on, which the `port` and `hostname`
+/// options are checked against, and whether the `Deno` and `Bun` globals
+/// are defined.
+pub fn init(addr: SocketAddr, shims_enabled: bool) -> Result<()> {
+    if LISTEN_ADDR.set(addr).is_err() || SHIMS_ENABLED.set(shims_enabled).is_err() {
+        bail!("Serve options were already initialized");
+    }
+    Ok(())
+}
+
+pub fn define(cx: &Context, global: &Object) -> bool {
+    if !SHIMS_ENABLED.get().copied().unwrap_or(false) {
+        return true;
+    }
+
+    let deno = Object::new(cx);
+    let bun = Object::new(cx);
+    unsafe {
+        deno.define_methods(cx, DENO_METHODS)
+            && bun.define_methods(cx, BUN_METHODS)
+            && global.set_as(cx, "Deno", &deno)
+            && global.set_as(cx, "Bun", &bun)
+    }
+}
+
+/// Whether the code running on the current thread called one of the serve
+/// functions.
+pub fn is_registered() -> bool {
+    SERVE_HANDLER.with(|h| h.borrow().is_some())
+}
+
+#[js_fn]
+fn deno_serve<'cx>(
+    cx: &'cx Context,
+    options_or_handler: Value<'cx>,
+    handler: Opt<Function<'cx>>,
+) -> ion::Result<*mut JSObject> {
+    let (options, handler) = match as_function(cx, &options_or_handler) {
+        Some(handler) => (None, Some(handler)),
+        None if options_or_handler.handle().is_object() => {
+            let options = options_or_handler.to_object(cx);
+            let handler = match handler.0 {
+                Some(handler) => Some(handler),
+                None => get_function(cx, &options, "handler")?,
+            };
+            (Some(options), handler)
+        }
+        None => ion_err!(
+            "Deno.serve expects a handler function or an options object",
+            Type
+        ),
+    };
+    let Some(handler) = handler else {
+        ion_err!("Deno.serve requires a handler function", Type);
+    };
+
+    let on_error = match options {
+        Some(ref options) => {
+            check_listen_options(cx, Flavor::Deno, options)?;
+            get_function(cx, options, "onError")?
+        }
+        None => None,
+    };
+
+    let server = Object::new(cx);
+    server.set_as(cx, "finished", &Promise::new(cx));
+    if let Some(addr) = LISTEN_ADDR.get() {
+        server.set_as(cx, "addr", &net_addr(cx, *addr));
+    }
+    unsafe {
+        server.define_methods(cx, DENO_SERVER_METHODS);
+    }
+
+    register(cx, Flavor::Deno, &handler, on_error.as_ref(), &server)?;
+    Ok((*server).get())
+}
+
+#[js_fn]
+fn bun_serve<'cx>(cx: &'cx Context, options: Object<'cx>) -> ion::Result<*mut JSObject> {
+    let Some(handler) = get_function(cx, &options, "fetch")? else {
+        ion_err!("Bun.serve requires a fetch function", Type);
+    };
+    check_listen_options(cx, Flavor::Bun, &options)?;
+    let on_error = get_function(cx, &options, "error")?;
+
+    let server = Object::new(cx);
+    if let Some(addr) = LISTEN_ADDR.get() {
+        server.set_as(cx, "port", &u32::from(addr.port()));
+        server.set_as(cx, "hostname", &addr.ip().to_string());
+    }
+    server.set_as(cx, "development", &false);
+    unsafe {
+        server.define_methods(cx, BUN_SERVER_METHODS);
+    }
+
+    register(cx, Flavor::Bun, &handler, on_error.as_ref(), &server)?;
+    Ok((*server).get())
+}
+
+// The server keeps running for as long as WinterJS does, so there's nothing
+// to do when the app asks to stop it
+#[js_fn]
+fn shutdown(cx: &Context) -> Promise {
+    Promise::resolved(cx, Value::undefined(cx))
+}
+
+#[js_fn]
+fn noop() {}
+
+static DENO_METHODS: &[JSFunctionSpec] =
+    &[function_spec!(deno_serve, "serve", 2), JSFunctionSpec::ZERO];
+
+static BUN_METHODS: &[JSFunctionSpec] =
+    &[function_spec!(bun_serve, "serve", 1), JSFunctionSpec::ZERO];
+
+static DENO_SERVER_METHODS: &[JSFunctionSpec] = &[
+    function_spec!(shutdown, "shutdown", 0),
+    function_spec!(noop, "ref", 0),
+    function_spec!(noop, "unref", 0),
+    JSFunctionSpec::ZERO,
+];
+
+static BUN_SERVER_METHODS: &[JSFunctionSpec] = &[
+    function_spec!(shutdown, "stop", 0),
+    function_spec!(noop, "ref", 0),
+    function_spec!(noop, "unref", 0),
+    JSFunctionSpec::ZERO,
+];
+
+fn register(
+    cx: &Context,
+    flavor: Flavor,
+    handler: &Function,
+    on_error: Option<&Function>,
+    server: &Object,
+) -> ion::Result<()> {
+    SERVE_HANDLER.with(|h| {
+        let mut h = h.borrow_mut();
+        if h.is_some() {
+            ion_err!(
+                format!("{} can only be called once", flavor.function_name()),
+                Normal
+            );
+        }
+        let on_error = match on_error {
+            Some(on_error) => {
+                let call = sm_utils::evaluate_script(cx, CALL_SCRIPT, "serve.js")
+                    .map_err(|e| ion_mk_err!(e.to_string(), Normal))?;
+                let call = as_function(cx, &call).ok_or_else(|| {
+                    ion_mk_err!(
+                        "Internal error: serve.js should evaluate to a function",
+                        Normal
+                    )
+                })?;
+                Some(ErrorHandler {
+                    handler: PermanentHeap::from_local(on_error),
+                    call: PermanentHeap::from_local(&call),
+                })
+            }
+            None => None,
+        };
+        *h = Some(ServeHandler {
+            flavor,
+            handler: PermanentHeap::from_local(handler),
+            on_error,
+            server: PermanentHeap::new((**server).get()),
+        });
+        Ok(())
+    })
+}
+
+fn as_function<'cx>(cx: &'cx Context, value: &Value) -> Option<Function<'cx>> {
+    value
+        .handle()
+        .is_object()
+        .then(|| Function::from_object(cx, &value.to_object(cx)))
+        .flatten()
+}
+
+fn get_function<'cx>(
+    cx: &'cx Context,
+    options: &Object,
+    key: &str,
+) -> ion::Result<Option<Function<'cx>>> {
+    match options.get(cx, key)? {
+        Some(value) if !value.handle().is_undefined() => match as_function(cx, &value) {
+            Some(func) => Ok(Some(func)),
+            None => ion_err!(format!("{key} must be a function"), Type),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Warns if the app asks to listen on an address other than the one
+/// WinterJS listens on.
+fn check_listen_options(cx: &Context, flavor: Flavor, options: &Object) -> ion::Result<()> {
+    let Some(addr) = LISTEN_ADDR.get() else {
+        return Ok(());
+    };
+
+    let port = match options.get(cx, "port")? {
+        Some(port) if !port.handle().is_undefined() => Some(u16::from_value(
+            cx,
+            &port,
+            false,
+            ConversionBehavior::Default,
+        )?),
+        _ => None,
+    };
+    let hostname = match options.get(cx, "hostname")? {
+        Some(hostname) if !hostname.handle().is_undefined() => {
+            Some(String::from_value(cx, &hostname, false, ())?)
+        }
+        _ => None,
+    };
+
+    let port_matches = port.map_or(true, |port| port == 0 || port == addr.port());
+    let hostname_matches =
+        hostname
+            .as_deref()
+            .map_or(true, |hostname| match hostname.parse::<IpAddr>() {
+                Ok(ip) => ip == addr.ip(),
+                Err(_) => hostname == "localhost" && addr.ip().is_loopback(),
+            });
+
+    if !port_matches || !hostname_matches {
+        OPTIONS_WARNING.call_once(|| {
+            tracing::warn!(
+                requested_port = ?port,
+                requested_hostname = ?hostname,
+                "{} was asked to listen on a different address, but requests are served \
+                on {addr}; use --ip and --port to change it",
+                flavor.function_name()
+            )
+        });
+    }
+    Ok(())
+}
+
+fn net_addr<'cx>(cx: &'cx Context, addr: SocketAddr) -> Object<'cx> {
+    let obj = Object::new(cx);
+    obj.set_as(cx, "transport", &String::from("tcp"));
+    obj.set_as(cx, "hostname", &addr.ip().to_string());
+    obj.set_as(cx, "port", &u32::from(addr.port()));
+    obj
+}
+
+/// Calls the registered handler with the request. If the app provided an
+/// error handler, exceptions and rejections are passed to it, and its
+/// return value is used as the response instead.
+pub fn start_request(
+    cx: &Context,
+    request: Request,
+) -> Result<Either<PendingResponse, ReadyResponse>> {
+    let remote_addr = request.parts.extensions.get::<RemoteAddr>().copied();
+    let request = Value::object(
+        cx,
+        &cx.root(super::build_fetch_request(cx, request)?).into(),
+    );
+
+    let (flavor, handler, on_error, server) = SERVE_HANDLER.with(|h| {
+        let h = h.borrow();
+        let h = h
+            .as_ref()
+            .ok_or_else(|| anyhow!("Internal error: no serve handler was registered"))?;
+        anyhow::Ok((
+            h.flavor,
+            Function::from(h.handler.root(cx)),
+            h.on_error.as_ref().map(|e| {
+                (
+                    Function::from(e.handler.root(cx)),
+                    Function::from(e.call.root(cx)),
+                )
+            }),
+            Object::from(h.server.root(cx)),
+        ))
+    })?;
+
+    let (this, args) = match flavor {
+        Flavor::Deno => {
+            let info = Object::new(cx);
+            if let Some(RemoteAddr(addr)) = remote_addr {
+                info.set_as(cx, "remoteAddr", &net_addr(cx, addr));
+            }
+            (Object::global(cx), [request, info.as_value(cx)])
+        }
+        Flavor::Bun => {
+            let server_value = server.as_value(cx);
+            (server, [request, server_value])
+        }
+    };
+
+    let Some((on_error, call)) = on_error else {
+        let result = handler
+            .call(cx, &this, &args)
+            .map_err(|e| error_report_option_to_anyhow_error(cx, e))?;
+        return to_response(cx, result);
+    };
+
+    let [request, info] = args;
+    let result = call
+        .call(
+            cx,
+            &Object::global(cx),
+            &[handler.as_value(cx), this.as_value(cx), request, info],
+        )
+        .map_err(|e| error_report_option_to_anyhow_error(cx, e))?;
+    let result = TracedHeap::new(result.get());
+    let on_error = TracedHeap::from_local(&on_error);
+
+    let promise = unsafe {
+        future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+            let value = Value::from(result.root(&cx));
+            if !(value.handle().is_object() && Promise::is_promise(&value.to_object(&cx))) {
+                return Ok::<JSVal, _>(value.get());
+            }
+
+            let future = {
+                let promise = Promise::from_unchecked(value.to_object(&cx).into_local());
+                PromiseFuture::new(cx.duplicate(), &promise)
+            };
+            let (cx, error) = match future.await {
+                (_, Ok(value)) => return Ok(value.get()),
+                (cx, Err(error)) => (cx, error),
+            };
+
+            // Resolving with the error handler's result adopts its state if
+            // it's a promise
+            let error = Value::from(error.root(&cx));
+            let response = Function::from(on_error.root(&cx))
+                .call(&cx, &Object::global(&cx), &[error])
+                .map_err(|e| {
+                    ion_mk_err!(
+                        format!(
+                            "Error handler failed: {}",
+                            error_report_option_to_anyhow_error(&cx, e)
+                        ),
+                        Normal
+                    )
+                })?;
+            Ok(response.get())
+        })
+    }
+    .ok_or_else(|| anyhow!("Future queue must be initialized"))?;
+
+    Ok(Either::Left(PendingResponse { promise }))
+}
+
+fn to_response(cx: &Context, result: Value) -> Result<Either<PendingResponse, ReadyResponse>> {
+    if !result.handle().is_object() {
+        bail!("Script error: value returned from the request handler should be an object");
+    }
+
+    let result = result.to_object(cx);
+    if Promise::is_promise(&result) {
+        Ok(Either::Left(PendingResponse {
+            promise: unsafe { Promise::from_unchecked(result.into_local()) },
+        }))
+    } else {
+        super::service_workers::build_response(cx, result).map(Either::Right)
+    }
+}
@@ -0,0 +1,376 @@
+//! Serving static files, for Cloudflare Pages-style directories and for
+//! the static directory in WinterCG mode. Options are set through the
+//! `[static]` section of `winterjs.toml`.
+
+use std::{
+    cell::OnceCell,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context as _, Result};
+use once_cell::sync::OnceCell as SyncOnceCell;
+use serde::{Deserialize, Serialize};
+use static_web_server::{
+    directory_listing::DirListFmt,
+    exts::path::PathExt,
+    handler::{RequestHandler as SwsRequestHandler, RequestHandlerOpts as SwsRequestHandlerOpts},
+};
+
+use ion::{ClassDefinition, Context};
+use runtime::{globals::fetch::Response as FetchResponse, promise::future_to_promise, ContextExt};
+
+use crate::ion_mk_err;
+
+use super::{PendingResponse, Request};
+
+/// Served for requests which don't match a file in Cloudflare directories,
+/// unless another file is configured.
+const DEFAULT_FALLBACK_PAGE: &str = "_fallback.html";
+
+static STATIC_CONFIG: SyncOnceCell<StaticConfig> = SyncOnceCell::new();
+
+static CACHE_CONTROL_RULES: SyncOnceCell<Vec<ParsedCacheControlRule>> = SyncOnceCell::new();
+
+// Still operating under the one-handler-per-thread model. The correct way
+// would to attach this to the context in some way.
+thread_local! {
+    static SWS_OPTS: OnceCell<Arc<SwsRequestHandlerOpts>> = OnceCell::new();
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct StaticConfig {
+    /// Directory of static files to serve in WinterCG mode. Requests for
+    /// files which exist in the directory are served from it, and all other
+    /// requests are handled by JS code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+
+    /// Compress responses on the fly, based on the `Accept-Encoding` header.
+    pub compression: bool,
+
+    /// Serve precompressed `.br` and `.gz` files next to the requested file
+    /// instead, if the client accepts them.
+    pub precompressed: bool,
+
+    /// Add `Cache-Control` headers based on file extensions.
+    pub cache_control_headers: bool,
+
+    /// `Cache-Control` values for request paths matching a glob pattern,
+    /// overriding the ones based on file extensions. The first matching
+    /// rule is used.
+    pub cache_control: Vec<CacheControlRule>,
+
+    /// Add security headers such as `Strict-Transport-Security` and
+    /// `X-Frame-Options`.
+    pub security_headers: bool,
+
+    pub dir_listing: bool,
+
+    pub dir_listing_format: DirListingFormat,
+
+    pub dir_listing_order: DirListingOrder,
+
+    /// Redirect requests for directories to the same path with a trailing
+    /// slash.
+    pub redirect_trailing_slash: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cors: Option<CorsConfig>,
+
+    /// Credentials required to access static files, in `user:hash` format
+    /// where the password is hashed with bcrypt, e.g. with
+    /// `htpasswd -nbBC 10 user password`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basic_auth: Option<String>,
+
+    /// Page served with a 200 status for requests which don't match any
+    /// file, for single-page apps. Relative to the static directory, and
+    /// defaults to `_fallback.html` in Cloudflare directories.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spa_fallback: Option<PathBuf>,
+}
+
+impl Default for StaticConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            compression: true,
+            precompressed: false,
+            cache_control_headers: true,
+            cache_control: vec![],
+            security_headers: true,
+            dir_listing: false,
+            dir_listing_format: DirListingFormat::Html,
+            dir_listing_order: DirListingOrder::NameAsc,
+            redirect_trailing_slash: false,
+            cors: None,
+            basic_auth: None,
+            spa_fallback: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CacheControlRule {
+    /// Glob pattern matched against the request path, e.g. `/assets/**`.
+    pub path: String,
+    pub value: String,
+}
+
+/// A `cache_control` rule with its value validated and parsed up front.
+struct ParsedCacheControlRule {
+    path: String,
+    value: http::HeaderValue,
+}
+
+impl ParsedCacheControlRule {
+    fn new(rule: &CacheControlRule) -> Result<Self> {
+        if rule.path.is_empty() {
+            bail!("Cache-Control rules need a path pattern");
+        }
+
+        // Each directive is a token, optionally followed by `=` and a value
+        let is_valid_directive = |directive: &str| {
+            let name = directive
+                .split_once('=')
+                .map_or(directive, |(name, _)| name);
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        };
+        if let Some(directive) = rule
+            .value
+            .split(',')
+            .map(str::trim)
+            .find(|directive| !is_valid_directive(directive))
+        {
+            bail!(
+                "Invalid directive '{directive}' in Cache-Control value for {}",
+                rule.path
+            );
+        }
+
+        let value = rule
+            .value
+            .parse()
+            .with_context(|| format!("Invalid Cache-Control value for {}", rule.path))?;
+        Ok(Self {
+            path: rule.path.clone(),
+            value,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Origins allowed to make cross-origin requests, or `*` for all.
+    pub allow_origins: Vec<String>,
+    pub allow_headers: Vec<String>,
+    pub expose_headers: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirListingFormat {
+    Html,
+    Json,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum DirListingOrder {
+    NameAsc,
+    NameDesc,
+    ModifiedAsc,
+    ModifiedDesc,
+    SizeAsc,
+    SizeDesc,
+    Unordered,
+}
+
+/// Must be called before any JS threads are started; until then, the
+/// default options are used. Fails if any of the options are invalid.
+pub fn init(config: StaticConfig) -> Result<()> {
+    let rules = config
+        .cache_control
+        .iter()
+        .map(ParsedCacheControlRule::new)
+        .collect::<Result<Vec<_>>>()
+        .context("Invalid static file options")?;
+
+    if STATIC_CONFIG.set(config).is_err() || CACHE_CONTROL_RULES.set(rules).is_err() {
+        bail!("Static file options were already initialized");
+    }
+    Ok(())
+}
+
+pub fn config() -> &'static StaticConfig {
+    STATIC_CONFIG.get_or_init(Default::default)
+}
+
+/// Sets up serving static files from the given directory on the current
+/// thread.
+pub fn build_handler(root: impl AsRef<Path>, default_fallback: bool) {
+    SWS_OPTS.with(move |s| {
+        s.get_or_init(|| {
+            let root = root.as_ref().to_path_buf();
+            let config = config();
+
+            let fallback_page = match config.spa_fallback {
+                Some(ref page) => Some(root.join(page)),
+                None if default_fallback => Some(root.join(DEFAULT_FALLBACK_PAGE)),
+                None => None,
+            };
+
+            let cors = config.cors.as_ref().and_then(|cors| {
+                static_web_server::cors::new(
+                    &cors.allow_origins.join(","),
+                    &cors.allow_headers.join(","),
+                    &cors.expose_headers.join(","),
+                )
+            });
+
+            Arc::new(SwsRequestHandlerOpts {
+                advanced_opts: None,
+                basic_auth: config.basic_auth.clone().unwrap_or_default(),
+                // TODO: have WinterJS-themed defaults
+                page404: std::fs::read(root.join("404.html")).unwrap_or_default(),
+                page50x: std::fs::read(root.join("500.html")).unwrap_or_default(),
+                page_fallback: fallback_page
+                    .and_then(|page| std::fs::read(page).ok())
+                    .unwrap_or_default(),
+                // We need to allow hidden paths if the root path itself is
+                // hidden, otherwise every response is a 404
+                ignore_hidden_files: !root.is_hidden(),
+                root_dir: root,
+                compression: config.compression,
+                compression_static: config.precompressed,
+                dir_listing: config.dir_listing,
+                dir_listing_format: match config.dir_listing_format {
+                    DirListingFormat::Html => DirListFmt::Html,
+                    DirListingFormat::Json => DirListFmt::Json,
+                },
+                dir_listing_order: config.dir_listing_order as u8,
+                cache_control_headers: config.cache_control_headers,
+                cors,
+                log_remote_address: false,
+                redirect_trailing_slash: config.redirect_trailing_slash,
+                security_headers: config.security_headers,
+            })
+        });
+    })
+}
+
+pub fn is_enabled() -> bool {
+    SWS_OPTS.with(|s| s.get().is_some())
+}
+
+/// Finds the file (or directory, if directory listing is enabled) in the
+/// static directory a request path refers to, without touching anything
+/// outside of it. Returns the path relative to the static directory.
+pub fn find_file(path: &str) -> Option<PathBuf> {
+    let root = SWS_OPTS.with(|s| s.get().map(|opts| opts.root_dir.clone()))?;
+    let relative_path = decode_path(path)?;
+
+    // Hidden files are not served, so requests for them go to JS code
+    let is_hidden = relative_path
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+    if is_hidden && !root.is_hidden() {
+        return None;
+    }
+
+    let path = root.join(&relative_path);
+    let exists = if path.is_file() {
+        true
+    } else if path.is_dir() {
+        config().dir_listing || path.join("index.html").is_file()
+    } else {
+        false
+    };
+    exists.then_some(relative_path)
+}
+
+pub async fn serve(req: Request) -> Result<hyper::Response<hyper::Body>> {
+    let opts = SWS_OPTS
+        .with(|s| s.get().cloned())
+        .context("Static files are not enabled")?;
+
+    let path = req.parts.uri.path().to_string();
+    let mut hyper_req = hyper::Request::from_parts(req.parts, req.body);
+    let mut response = SwsRequestHandler { opts }
+        .handle(&mut hyper_req, None)
+        .await
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    let is_success = response.status().is_success() || response.status().as_u16() == 304;
+    if is_success {
+        let rule = CACHE_CONTROL_RULES
+            .get()
+            .into_iter()
+            .flatten()
+            .find(|rule| glob_match::glob_match(&rule.path, &path));
+        if let Some(rule) = rule {
+            response
+                .headers_mut()
+                .insert(http::header::CACHE_CONTROL, rule.value.clone());
+        }
+    }
+
+    Ok(response)
+}
+
+/// Serves a request from the static directory, as a promise resolving to a
+/// `Response` object.
+pub fn start_serving(cx: &Context, request: Request) -> Result<PendingResponse> {
+    let promise = unsafe {
+        future_to_promise::<_, _, _, ion::Error>(cx, move |cx| async move {
+            let uri = super::build_request_uri(&request)
+                .map_err(|e| ion_mk_err!(format!("Failed to parse request URI: {e}"), Normal))?;
+            let url = url::Url::parse(uri.to_string().as_str())?;
+            let (cx, response) = cx.await_native(serve(request)).await;
+            let response = response.map_err(|e| {
+                ion_mk_err!(format!("Failed to fetch static asset due to {e}"), Normal)
+            })?;
+            let response = FetchResponse::from_hyper_response(&cx, response, url)?;
+            Ok(FetchResponse::new_object(&cx, Box::new(response)))
+        })
+    }
+    .context("Future queue must be initialized")?;
+
+    Ok(PendingResponse { promise })
+}
+
+/// Turns a percent-encoded URL path into a relative file path, or returns
+/// None if it tries to escape the root directory.
+fn decode_path(path: &str) -> Option<PathBuf> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    let path = PathBuf::from(String::from_utf8(decoded).ok()?);
+    let mut relative_path = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative_path.push(part),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(relative_path)
+}
@@ -1,31 +1,80 @@
-use anyhow::{bail, Result};
-use ion::{Context, Object, Value};
+use std::path::{Path, PathBuf};
 
-use crate::sm_utils;
+use anyhow::{bail, Context as _, Result};
+use ion::{Context, Function, Object, Promise, TracedHeap, Value};
+use mozjs_sys::jsapi::{JSFunction, JSObject};
+use runtime::ContextExt;
+
+use crate::sm_utils::{self, error_report_option_to_anyhow_error};
 
 use super::{
-    ByRefStandardModules, Either, PendingResponse, ReadyResponse, Request, RequestHandler, UserCode,
+    static_files, ByRefStandardModules, Either, PendingResponse, ReadyResponse, Request,
+    RequestHandler, UserCode,
 };
 
+/// Entrypoints looked up in directories without a `package.json` file, or
+/// whose `package.json` doesn't declare one.
+const DEFAULT_ENTRYPOINTS: &[&str] = &["index.js", "index.mjs"];
+
+/// Conditions matched against `package.json` exports, in order of
+/// preference.
+const EXPORT_CONDITIONS: &[&str] = &["winterjs", "worker", "import", "default"];
+
+/// Subdirectory of a served directory whose files are served statically,
+/// unless another static directory is configured.
+const PUBLIC_DIR: &str = "public";
+
 #[derive(Clone, Copy)]
 pub struct WinterCGRequestHandler;
 
+struct WinterCGRequestHandlerPrivate {
+    // The default export and its fetch method, if the entrypoint is a module
+    // with `export default { fetch }`
+    default_export: Option<TracedHeap<*mut JSObject>>,
+    fetch_function: Option<TracedHeap<*mut JSFunction>>,
+}
+
 impl RequestHandler for WinterCGRequestHandler {
     fn get_standard_modules(&self) -> Box<dyn ByRefStandardModules> {
         Box::new(WinterCGStandardModules)
     }
 
     fn evaluate_scripts(&mut self, cx: &Context, code: &UserCode) -> Result<()> {
+        if unsafe { cx.get_private() }.app_data.is_some() {
+            bail!("Internal error: evaluate_scripts should only be called once");
+        }
+
+        let mut private = WinterCGRequestHandlerPrivate {
+            default_export: None,
+            fetch_function: None,
+        };
+
         match code {
             UserCode::Script { code, file_name } => {
                 sm_utils::evaluate_script(cx, code, file_name)?;
             }
             UserCode::Module(path) => {
-                sm_utils::evaluate_module(cx, path)?;
+                eval_module(cx, path, &mut private)?;
+            }
+            UserCode::Directory(dir) => {
+                let entrypoint = discover_entrypoint(dir)?;
+                eval_module(cx, dir.join(entrypoint), &mut private)?;
             }
-            UserCode::Directory(_) => bail!("WinterCG mode does not support directories"),
         };
 
+        // Only an explicitly public directory is served statically, so the
+        // app's own code and config are never exposed
+        match (&static_files::config().dir, code) {
+            (Some(dir), _) => static_files::build_handler(dir, false),
+            (None, UserCode::Directory(dir)) if dir.join(PUBLIC_DIR).is_dir() => {
+                static_files::build_handler(dir.join(PUBLIC_DIR), false)
+            }
+            _ => {}
+        }
+
+        let private: Box<dyn std::any::Any> = Box::new(private);
+        cx.set_app_data(private);
+
         Ok(())
     }
 
@@ -34,7 +83,44 @@ impl RequestHandler for WinterCGRequestHandler {
         cx: Context,
         request: Request,
     ) -> Result<Either<PendingResponse, ReadyResponse>> {
-        super::service_workers::start_request(&cx, request)
+        let private = get_private(&cx)?;
+
+        let is_read = matches!(request.parts.method, http::Method::GET | http::Method::HEAD);
+        if is_read && static_files::find_file(request.parts.uri.path()).is_some() {
+            return static_files::start_serving(&cx, request).map(Either::Left);
+        }
+
+        match private.fetch_function {
+            Some(ref func) => {
+                let this = match private.default_export {
+                    Some(ref obj) => Object::from(obj.root(&cx)),
+                    None => Object::null(&cx),
+                };
+                let request = Value::object(
+                    &cx,
+                    &cx.root(super::build_fetch_request(&cx, request)?).into(),
+                );
+                let result = Function::from(func.root(&cx))
+                    .call(&cx, &this, &[request])
+                    .map_err(|e| error_report_option_to_anyhow_error(&cx, e))?;
+                if !result.handle().is_object() {
+                    bail!(
+                        "Script error: value returned from the fetch function should be an object"
+                    );
+                }
+
+                let result = result.to_object(&cx);
+                if Promise::is_promise(&result) {
+                    Ok(Either::Left(PendingResponse {
+                        promise: unsafe { Promise::from_unchecked(result.into_local()) },
+                    }))
+                } else {
+                    super::service_workers::build_response(&cx, result).map(Either::Right)
+                }
+            }
+            None if super::serve::is_registered() => super::serve::start_request(&cx, request),
+            None => super::service_workers::start_request(&cx, request),
+        }
     }
 
     fn finish_fulfilled_request(
@@ -49,6 +135,112 @@ impl RequestHandler for WinterCGRequestHandler {
     }
 }
 
+fn get_private(cx: &Context) -> Result<&WinterCGRequestHandlerPrivate> {
+    if unsafe { cx.get_private() }.app_data.is_none() {
+        bail!(
+            "Internal error: evaluate_scripts should be called before using WinterCGRequestHandler"
+        );
+    }
+
+    Ok(unsafe { cx.get_app_data::<WinterCGRequestHandlerPrivate>() })
+}
+
+/// Evaluates a module, and picks up its default export's `fetch` function
+/// if there is one. Modules without one are expected to register a fetch
+/// event listener instead.
+fn eval_module(
+    cx: &Context,
+    path: impl AsRef<Path>,
+    private: &mut WinterCGRequestHandlerPrivate,
+) -> Result<()> {
+    let module = sm_utils::evaluate_module(cx, path)?;
+    let ns = module.module_namespace(cx);
+
+    let Some(default_export) = ns.get(cx, "default").ok().flatten() else {
+        return Ok(());
+    };
+    if !default_export.handle().is_object() {
+        return Ok(());
+    }
+
+    let default_export = default_export.to_object(cx);
+    let fetch = default_export.get(cx, "fetch").ok().flatten();
+    let fetch_func = fetch.and_then(|fetch| {
+        fetch
+            .handle()
+            .is_object()
+            .then(|| Function::from_object(cx, &fetch.to_object(cx)))
+            .flatten()
+    });
+
+    match fetch_func {
+        Some(func) => {
+            private.fetch_function = Some(TracedHeap::from_local(&func));
+            private.default_export = Some(TracedHeap::new((*default_export).get()));
+        }
+        None => tracing::warn!("Expected exported default.fetch to be a function"),
+    }
+    Ok(())
+}
+
+/// Finds the entrypoint of a directory, from the `exports` or `main` field
+/// of its `package.json` file, or one of the default entrypoints. Returns
+/// the path relative to the directory.
+fn discover_entrypoint(dir: &Path) -> Result<PathBuf> {
+    let package_json_path = dir.join("package.json");
+    if package_json_path.is_file() {
+        let package_json =
+            std::fs::read_to_string(&package_json_path).context("Failed to read package.json")?;
+        let package_json = serde_json::from_str::<serde_json::Value>(&package_json)
+            .context("Failed to parse package.json")?;
+
+        let exports = package_json.get("exports").map(|exports| match exports {
+            // Exports can either be a map of subpaths, or the conditions for
+            // the main entrypoint directly
+            serde_json::Value::Object(map) if map.keys().any(|k| k.starts_with('.')) => {
+                map.get(".").and_then(resolve_export_target)
+            }
+            _ => resolve_export_target(exports),
+        });
+        let main = package_json.get("main").and_then(|main| main.as_str());
+
+        if let Some(entrypoint) = exports.flatten().or(main) {
+            let path = PathBuf::from(entrypoint);
+            if !dir.join(&path).is_file() {
+                bail!(
+                    "The entrypoint {entrypoint} declared in {} does not exist",
+                    package_json_path.display()
+                );
+            }
+            return Ok(path);
+        }
+    }
+
+    DEFAULT_ENTRYPOINTS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| dir.join(path).is_file())
+        .with_context(|| {
+            format!(
+                "No entrypoint found in {}, expected a package.json file with a \
+                `main` or `exports` field, or one of: {}",
+                dir.display(),
+                DEFAULT_ENTRYPOINTS.join(", ")
+            )
+        })
+}
+
+fn resolve_export_target(target: &serde_json::Value) -> Option<&str> {
+    match target {
+        serde_json::Value::String(path) => Some(path.as_str()),
+        serde_json::Value::Object(conditions) => EXPORT_CONDITIONS
+            .iter()
+            .find_map(|c| conditions.get(*c).and_then(resolve_export_target)),
+        serde_json::Value::Array(targets) => targets.iter().find_map(resolve_export_target),
+        _ => None,
+    }
+}
+
 struct WinterCGStandardModules;
 
 impl ByRefStandardModules for WinterCGStandardModules {
@@ -57,6 +249,6 @@ impl ByRefStandardModules for WinterCGStandardModules {
     }
 
     fn init_globals(&self, cx: &Context, global: &Object) -> bool {
-        super::service_workers::define(cx, global)
+        super::service_workers::define(cx, global) && super::serve::define(cx, global)
     }
 }
@@ -0,0 +1,159 @@
+//! Durable Objects are pinned to a single request handler thread, chosen
+//! from the object's ID. Requests for an object are routed to its thread by
+//! the runner, which registers itself as the global dispatcher at startup.
+//! Alarms are scheduled on a background thread and delivered the same way.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use once_cell::sync::{Lazy, OnceCell};
+
+use crate::request_handlers::Request;
+
+const MAX_ALARM_RETRIES: u32 = 6;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DurableObjectTarget {
+    pub class_name: String,
+    /// Hex-encoded, 32 bytes long
+    pub id: String,
+}
+
+impl DurableObjectTarget {
+    pub fn thread_index(&self, thread_count: usize) -> usize {
+        let hash = u64::from_str_radix(&self.id[..16.min(self.id.len())], 16).unwrap_or(0);
+        (hash % thread_count as u64) as usize
+    }
+}
+
+pub enum DurableObjectEvent {
+    Fetch(Request),
+    Alarm { retry_count: u32 },
+}
+
+pub struct DurableObjectMessage {
+    pub target: DurableObjectTarget,
+    pub event: DurableObjectEvent,
+}
+
+#[async_trait]
+pub trait DurableObjectDispatcher: Send + Sync {
+    async fn dispatch(&self, message: DurableObjectMessage)
+        -> Result<hyper::Response<hyper::Body>>;
+}
+
+static DISPATCHER: OnceCell<Box<dyn DurableObjectDispatcher>> = OnceCell::new();
+
+pub fn set_dispatcher(dispatcher: Box<dyn DurableObjectDispatcher>) {
+    if DISPATCHER.set(dispatcher).is_err() {
+        panic!("Durable Object dispatcher should only be set once");
+    }
+}
+
+pub async fn dispatch(message: DurableObjectMessage) -> Result<hyper::Response<hyper::Body>> {
+    DISPATCHER
+        .get()
+        .context("Durable Objects are not available in this mode")?
+        .dispatch(message)
+        .await
+}
+
+// Alarms outlive the requests that set them, so they get a thread of their own.
+static ALARM_RUNTIME: Lazy<tokio::runtime::Handle> = Lazy::new(|| {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed building the alarm runtime");
+        tx.send(rt.handle().clone())
+            .expect("Failed to send alarm runtime handle");
+        rt.block_on(std::future::pending::<()>());
+    });
+    rx.recv().expect("Failed to start alarm runtime")
+});
+
+// The scheduled time of each object's alarm, in milliseconds since the Unix
+// epoch. A scheduled alarm only fires if it's still the current one.
+static ALARMS: Lazy<Mutex<HashMap<DurableObjectTarget, f64>>> = Lazy::new(Default::default);
+
+/// Schedules the alarm of an object, replacing the previous one if any.
+/// Passing `None` cancels the alarm.
+pub fn schedule_alarm(target: DurableObjectTarget, scheduled_time: Option<f64>) {
+    let mut alarms = ALARMS.lock().unwrap();
+    match scheduled_time {
+        None => {
+            alarms.remove(&target);
+        }
+        Some(time) => {
+            alarms.insert(target.clone(), time);
+            spawn_alarm_task(target, time, 0, Duration::ZERO);
+        }
+    }
+}
+
+fn is_current_alarm(target: &DurableObjectTarget, time: f64) -> bool {
+    ALARMS.lock().unwrap().get(target) == Some(&time)
+}
+
+fn spawn_alarm_task(target: DurableObjectTarget, time: f64, retry_count: u32, backoff: Duration) {
+    ALARM_RUNTIME.spawn(async move {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as f64)
+            .unwrap_or_default();
+        let delay = Duration::from_millis((time - now).max(0.0) as u64);
+        tokio::time::sleep(delay.max(backoff)).await;
+
+        if !is_current_alarm(&target, time) {
+            return;
+        }
+
+        let result = dispatch(DurableObjectMessage {
+            target: target.clone(),
+            event: DurableObjectEvent::Alarm { retry_count },
+        })
+        .await;
+
+        let error = match result {
+            Ok(response) if response.status().is_success() => None,
+            Ok(response) => Some(format!("alarm handler returned {}", response.status())),
+            Err(e) => Some(format!("{e:?}")),
+        };
+
+        match error {
+            None => {
+                let mut alarms = ALARMS.lock().unwrap();
+                if alarms.get(&target) == Some(&time) {
+                    alarms.remove(&target);
+                }
+            }
+            Some(error) if retry_count < MAX_ALARM_RETRIES => {
+                let backoff = Duration::from_secs(2u64.pow(retry_count + 1));
+                tracing::warn!(
+                    class = %target.class_name,
+                    id = %target.id,
+                    "Alarm failed, retrying in {} seconds: {error}",
+                    backoff.as_secs()
+                );
+                spawn_alarm_task(target, time, retry_count + 1, backoff);
+            }
+            Some(error) => {
+                tracing::error!(
+                    class = %target.class_name,
+                    id = %target.id,
+                    "Alarm failed too many times, giving up: {error}"
+                );
+                let mut alarms = ALARMS.lock().unwrap();
+                if alarms.get(&target) == Some(&time) {
+                    alarms.remove(&target);
+                }
+            }
+        }
+    });
+}
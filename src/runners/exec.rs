@@ -5,11 +5,15 @@ use tokio::task::LocalSet;
 
 use crate::{
     builtins,
-    sm_utils::{error_report_option_to_anyhow_error, evaluate_module, evaluate_script, JsApp},
+    sm_utils::{
+        error_report_option_to_anyhow_error, evaluate_module, evaluate_script, JsApp,
+        PermissionedLoader,
+    },
 };
 
 async fn exec_script_inner(path: impl AsRef<Path>, script_mode: bool) -> Result<()> {
-    let module_loader = (!script_mode).then(runtime::module::Loader::default);
+    let app_dir = path.as_ref().parent().unwrap_or(Path::new("."));
+    let module_loader = (!script_mode).then(|| PermissionedLoader::new(app_dir));
     let standard_modules = builtins::Modules {
         include_internal: !script_mode,
         hardware_concurrency: 1,
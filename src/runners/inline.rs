@@ -10,7 +10,6 @@ use std::{
     time::{Duration, Instant},
 };
 
-use anyhow::anyhow;
 use async_trait::async_trait;
 use futures::Future;
 use tokio::sync::mpsc;
@@ -18,8 +17,8 @@ use tokio::sync::mpsc;
 use crate::request_handlers::{RequestHandler, UserCode};
 
 use super::{
+    durable_objects::{DurableObjectDispatcher, DurableObjectMessage},
     request_loop::{handle_requests, ControlMessage, RequestData},
-    ResponseData,
 };
 
 #[derive(Clone)]
@@ -34,7 +33,7 @@ impl<T: Future<Output = ()>> InlineRunnerRequestHandlerFuture for T {}
 
 impl InlineRunner {
     pub fn new_request_handler(
-        handler: impl RequestHandler + Copy + Unpin,
+        handler: impl RequestHandler + Unpin,
         user_code: UserCode,
     ) -> (Self, impl InlineRunnerRequestHandlerFuture) {
         let (tx, rx) = mpsc::unbounded_channel();
@@ -70,17 +69,7 @@ impl crate::server::Runner for InlineRunner {
 
         let response = rx.await?;
 
-        // TODO: handle script errors
-        match response {
-            ResponseData::Done(resp) => Ok(resp),
-            ResponseData::RequestError(err) => Err(err),
-            ResponseData::ScriptError(err) => {
-                if let Some(err) = err {
-                    println!("{err:?}");
-                }
-                Err(anyhow!("Error encountered while evaluating user script"))
-            }
-        }
+        response.into_response()
     }
 
     async fn shutdown(&self, timeout: Option<Duration>) {
@@ -119,3 +108,18 @@ impl crate::server::Runner for InlineRunner {
         );
     }
 }
+
+#[async_trait]
+impl DurableObjectDispatcher for InlineRunner {
+    async fn dispatch(
+        &self,
+        message: DurableObjectMessage,
+    ) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        self.channel
+            .send(ControlMessage::HandleDurableObjectMessage(message, tx))?;
+
+        rx.await?.into_response()
+    }
+}
@@ -1,8 +1,10 @@
+pub mod durable_objects;
 mod event_loop_stream;
 pub mod exec;
 pub mod inline;
 mod request_loop;
 mod request_queue;
+pub mod services;
 pub mod single;
 pub mod watch;
 
@@ -15,3 +17,21 @@ pub enum ResponseData {
     // thread will return None instead
     ScriptError(Option<anyhow::Error>),
 }
+
+impl ResponseData {
+    pub fn into_response(self) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+        // TODO: handle script errors
+        match self {
+            ResponseData::Done(resp) => Ok(resp),
+            ResponseData::RequestError(err) => Err(err),
+            ResponseData::ScriptError(err) => {
+                if let Some(err) = err {
+                    tracing::error!(error = format!("{err:#}"), "user script failed");
+                }
+                Err(anyhow::anyhow!(
+                    "Error encountered while evaluating user script"
+                ))
+            }
+        }
+    }
+}
@@ -6,9 +6,12 @@ use tokio::{select, sync::oneshot};
 
 use crate::{
     builtins,
-    request_handlers::{Either, Request, RequestHandler, UserCode},
-    runners::ResponseData,
-    sm_utils::{error_report_option_to_anyhow_error, JsApp, TwoStandardModules},
+    egress::RequestSubrequests,
+    request_handlers::{Either, PendingResponse, ReadyResponse, Request, RequestHandler, UserCode},
+    runners::{durable_objects::DurableObjectMessage, ResponseData},
+    sm_utils::{
+        error_report_option_to_anyhow_error, JsApp, PermissionedLoader, TwoStandardModules,
+    },
 };
 
 use super::{
@@ -24,6 +27,10 @@ pub struct RequestData {
 
 pub enum ControlMessage {
     HandleRequest(RequestData, tokio::sync::oneshot::Sender<ResponseData>),
+    HandleDurableObjectMessage(
+        DurableObjectMessage,
+        tokio::sync::oneshot::Sender<ResponseData>,
+    ),
     Shutdown,
     Terminate,
 }
@@ -32,6 +39,7 @@ impl std::fmt::Debug for ControlMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::HandleRequest(_, _) => write!(f, "HandleRequest"),
+            Self::HandleDurableObjectMessage(_, _) => write!(f, "HandleDurableObjectMessage"),
             Self::Shutdown => write!(f, "Shutdown"),
             Self::Terminate => write!(f, "Terminate"),
         }
@@ -43,7 +51,7 @@ impl std::fmt::Debug for ControlMessage {
 // there really isn't anything we can do
 fn ignore_error<E>(_r: std::result::Result<(), E>) {}
 
-pub(super) async fn handle_requests<H: RequestHandler + Copy + Unpin>(
+pub(super) async fn handle_requests<H: RequestHandler + Unpin>(
     handler: H,
     user_code: UserCode,
     mut recv: tokio::sync::mpsc::UnboundedReceiver<ControlMessage>,
@@ -61,7 +69,8 @@ pub(super) async fn handle_requests<H: RequestHandler + Copy + Unpin>(
         loop {
             match recv.recv().await {
                 None | Some(ControlMessage::Shutdown) | Some(ControlMessage::Terminate) => break,
-                Some(ControlMessage::HandleRequest(_, resp_tx)) => {
+                Some(ControlMessage::HandleRequest(_, resp_tx))
+                | Some(ControlMessage::HandleDurableObjectMessage(_, resp_tx)) => {
                     ignore_error(resp_tx.send(ResponseData::ScriptError(error.take())))
                 }
             }
@@ -69,18 +78,20 @@ pub(super) async fn handle_requests<H: RequestHandler + Copy + Unpin>(
     }
 }
 
-async fn handle_requests_inner<H: RequestHandler + Copy + Unpin>(
+async fn handle_requests_inner<H: RequestHandler + Unpin>(
     mut handler: H,
     user_code: UserCode,
     recv: &mut tokio::sync::mpsc::UnboundedReceiver<ControlMessage>,
     max_request_threads: u32,
 ) -> Result<(), anyhow::Error> {
-    let is_module_mode = match user_code {
-        UserCode::Script { .. } => false,
-        UserCode::Directory(_) | UserCode::Module(_) => true,
+    let module_loader = match &user_code {
+        UserCode::Script { .. } => None,
+        UserCode::Directory(dir) => Some(PermissionedLoader::new(dir)),
+        UserCode::Module(path) => Some(PermissionedLoader::new(
+            path.parent().unwrap_or(std::path::Path::new(".")),
+        )),
     };
-
-    let module_loader = is_module_mode.then(runtime::module::Loader::default);
+    let is_module_mode = module_loader.is_some();
     let standard_modules = TwoStandardModules(
         builtins::Modules {
             include_internal: is_module_mode,
@@ -131,13 +142,28 @@ async fn handle_requests_inner<H: RequestHandler + Copy + Unpin>(
                         } else {
                             handle_new_request(
                                 cx,
-                                handler,
+                                handler.clone(),
                                 &mut request_queue,
                                 req,
                                 resp_tx
                             );
                         }
                     }
+                    Some(ControlMessage::HandleDurableObjectMessage(msg, resp_tx)) => {
+                        if shutdown_requested {
+                            ignore_error(resp_tx.send(ResponseData::ScriptError(Some(
+                                anyhow!("New request received after shutdown requested")
+                            ))));
+                        } else {
+                            handle_durable_object_message(
+                                cx,
+                                handler.clone(),
+                                &mut request_queue,
+                                msg,
+                                resp_tx
+                            );
+                        }
+                    }
                 }
             }
 
@@ -168,7 +194,7 @@ async fn handle_requests_inner<H: RequestHandler + Copy + Unpin>(
     Ok(())
 }
 
-fn handle_new_request<H: RequestHandler + Copy + Unpin>(
+fn handle_new_request<H: RequestHandler + Unpin>(
     cx: &Context,
     mut handler: H,
     request_queue: &mut RequestQueue<RequestFinishedCallback<H>>,
@@ -176,13 +202,39 @@ fn handle_new_request<H: RequestHandler + Copy + Unpin>(
     resp_tx: oneshot::Sender<ResponseData>,
 ) {
     tracing::trace!(%req.req.method, %req.req.uri, ?req.req.headers, "Incoming request");
-    match handler.start_handling_request(
+    let subrequests = RequestSubrequests::start();
+    let result = handler.start_handling_request(
         cx.duplicate(),
         Request {
             parts: req.req,
             body: req.body,
         },
-    ) {
+    );
+    queue_request(cx, handler, request_queue, result, resp_tx, subrequests);
+}
+
+fn handle_durable_object_message<H: RequestHandler + Unpin>(
+    cx: &Context,
+    mut handler: H,
+    request_queue: &mut RequestQueue<RequestFinishedCallback<H>>,
+    msg: DurableObjectMessage,
+    resp_tx: oneshot::Sender<ResponseData>,
+) {
+    tracing::trace!(class = %msg.target.class_name, id = %msg.target.id, "Incoming Durable Object message");
+    let subrequests = RequestSubrequests::start();
+    let result = handler.start_handling_durable_object_message(cx.duplicate(), msg);
+    queue_request(cx, handler, request_queue, result, resp_tx, subrequests);
+}
+
+fn queue_request<H: RequestHandler + Unpin>(
+    cx: &Context,
+    handler: H,
+    request_queue: &mut RequestQueue<RequestFinishedCallback<H>>,
+    result: anyhow::Result<Either<PendingResponse, ReadyResponse>>,
+    resp_tx: oneshot::Sender<ResponseData>,
+    subrequests: RequestSubrequests,
+) {
+    match result {
         Err(f) => ignore_error(resp_tx.send(ResponseData::RequestError(f))),
         Ok(Either::Left(pending)) => request_queue.push(
             pending,
@@ -190,6 +242,7 @@ fn handle_new_request<H: RequestHandler + Copy + Unpin>(
                 cx: cx.as_ptr(),
                 handler,
                 resp_tx: Some(resp_tx),
+                _subrequests: subrequests,
             },
         ),
         Ok(Either::Right(resp)) => {
@@ -207,13 +260,16 @@ enum RequestCancelledReason {
     ServerShuttingDown,
 }
 
-struct RequestFinishedCallback<H: RequestHandler + Copy + Unpin> {
+struct RequestFinishedCallback<H: RequestHandler + Unpin> {
     cx: *mut JSContext,
     handler: H,
     resp_tx: Option<oneshot::Sender<ResponseData>>,
+
+    // Keeps counting the request's subrequests until it's finished
+    _subrequests: RequestSubrequests,
 }
 
-impl<H: RequestHandler + Copy + Unpin> RequestFinishedCallback<H> {
+impl<H: RequestHandler + Unpin> RequestFinishedCallback<H> {
     fn get_resp_tx(&mut self) -> oneshot::Sender<ResponseData> {
         self.resp_tx
             .take()
@@ -221,7 +277,7 @@ impl<H: RequestHandler + Copy + Unpin> RequestFinishedCallback<H> {
     }
 }
 
-impl<H: RequestHandler + Copy + Unpin> RequestFinishedHandler for RequestFinishedCallback<H> {
+impl<H: RequestHandler + Unpin> RequestFinishedHandler for RequestFinishedCallback<H> {
     type CancelReason = RequestCancelledReason;
 
     fn request_finished(
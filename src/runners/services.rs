@@ -0,0 +1,38 @@
+//! When running several workers in one process, each worker's runner is
+//! registered here under the worker's name, so service bindings can hand
+//! requests to it directly.
+
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+use anyhow::{Context as _, Result};
+use once_cell::sync::OnceCell;
+
+use crate::{request_handlers::Request, server::BoxedDynRunner};
+
+static SERVICES: OnceCell<HashMap<String, BoxedDynRunner>> = OnceCell::new();
+
+pub fn set_services(services: HashMap<String, BoxedDynRunner>) {
+    if SERVICES.set(services).is_err() {
+        panic!("Services should only be set once");
+    }
+}
+
+pub async fn dispatch(service: String, request: Request) -> Result<hyper::Response<hyper::Body>> {
+    let runner = SERVICES
+        .get()
+        .and_then(|services| services.get(&service))
+        .with_context(|| format!("Service {service} is not available"))?;
+
+    // There is no remote address for in-process requests
+    let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+    runner.handle(addr, request.parts, request.body).await
+}
+
+/// Shuts down all registered services. Should be called after the
+/// entrypoint has shut down, so in-flight requests can still reach the
+/// services they depend on.
+pub async fn shutdown(timeout: Option<Duration>) {
+    if let Some(services) = SERVICES.get() {
+        futures::future::join_all(services.values().map(|runner| runner.shutdown(timeout))).await;
+    }
+}
@@ -8,16 +8,18 @@ use std::{
     time::{Duration, Instant},
 };
 
-use anyhow::anyhow;
 use async_trait::async_trait;
 use tokio::{sync::Mutex, task::LocalSet};
 
 use crate::{
     request_handlers::{RequestHandler, UserCode},
-    runners::{request_loop::handle_requests, ResponseData},
+    runners::request_loop::handle_requests,
 };
 
-use super::request_loop::{ControlMessage, RequestData};
+use super::{
+    durable_objects::{DurableObjectDispatcher, DurableObjectMessage},
+    request_loop::{ControlMessage, RequestData},
+};
 
 pub struct WorkerThreadInfo {
     thread: std::thread::JoinHandle<()>,
@@ -32,7 +34,7 @@ impl WorkerThreadInfo {
 }
 
 // TODO: replace failing threads
-pub struct SingleRunner<H: RequestHandler + Copy + Unpin> {
+pub struct SingleRunner<H: RequestHandler + Unpin> {
     threads: Vec<WorkerThreadInfo>,
     max_threads: usize,
     handler: H,
@@ -42,7 +44,7 @@ pub struct SingleRunner<H: RequestHandler + Copy + Unpin> {
 
 pub type SharedSingleRunner<H> = Arc<Mutex<SingleRunner<H>>>;
 
-impl<H: RequestHandler + Copy + Unpin> SingleRunner<H> {
+impl<H: RequestHandler + Unpin> SingleRunner<H> {
     pub fn new(max_threads: usize, handler: H, user_code: UserCode) -> Self {
         if max_threads == 0 {
             panic!("max_threads must be at least 1");
@@ -67,7 +69,7 @@ impl<H: RequestHandler + Copy + Unpin> SingleRunner<H> {
 
     fn spawn_thread(&mut self) -> &WorkerThreadInfo {
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-        let handler = self.handler;
+        let handler = self.handler.clone();
         let user_code = self.user_code.clone();
         let max_threads = self.max_threads;
         let join_handle = std::thread::spawn(move || {
@@ -137,10 +139,31 @@ impl<H: RequestHandler + Copy + Unpin> SingleRunner<H> {
         );
         Some(&self.threads[min.0])
     }
+
+    // Each Durable Object lives on a fixed thread, so all of its events
+    // are delivered to the same instance.
+    fn find_or_spawn_durable_object_thread(
+        &mut self,
+        message: &DurableObjectMessage,
+    ) -> Option<&WorkerThreadInfo> {
+        if self.shut_down {
+            return None;
+        }
+
+        let index = message.target.thread_index(self.max_threads);
+        while self.threads.len() <= index {
+            self.spawn_thread();
+        }
+        tracing::debug!(
+            "Using handler thread #{index} for Durable Object {}",
+            message.target.id
+        );
+        Some(&self.threads[index])
+    }
 }
 
 #[async_trait]
-impl<H: RequestHandler + Copy + Unpin> crate::server::Runner for SharedSingleRunner<H> {
+impl<H: RequestHandler + Unpin> crate::server::Runner for SharedSingleRunner<H> {
     async fn handle(
         &self,
         _addr: std::net::SocketAddr,
@@ -173,17 +196,7 @@ impl<H: RequestHandler + Copy + Unpin> crate::server::Runner for SharedSingleRun
 
         drop(increment_guard);
 
-        // TODO: handle script errors
-        match response {
-            ResponseData::Done(resp) => Ok(resp),
-            ResponseData::RequestError(err) => Err(err),
-            ResponseData::ScriptError(err) => {
-                if let Some(err) = err {
-                    println!("{err:?}");
-                }
-                Err(anyhow!("Error encountered while evaluating user script"))
-            }
-        }
+        response.into_response()
     }
 
     async fn shutdown(&self, timeout: Option<Duration>) {
@@ -234,6 +247,41 @@ impl<H: RequestHandler + Copy + Unpin> crate::server::Runner for SharedSingleRun
     }
 }
 
+#[async_trait]
+impl<H: RequestHandler + Unpin> DurableObjectDispatcher for SharedSingleRunner<H> {
+    async fn dispatch(
+        &self,
+        message: DurableObjectMessage,
+    ) -> Result<hyper::Response<hyper::Body>, anyhow::Error> {
+        let mut this = self.lock().await;
+        let Some(thread) = this.find_or_spawn_durable_object_thread(&message) else {
+            let response = hyper::Response::builder()
+                .status(503)
+                .body(hyper::Body::from("Server is shutting down"))
+                .expect("Failed to construct 503 response");
+            return Ok(response);
+        };
+
+        let request_count = thread.in_flight_requests.clone();
+        let increment_guard = IncrementGuard::new(request_count);
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        thread
+            .channel
+            .send(ControlMessage::HandleDurableObjectMessage(message, tx))?;
+
+        // explicitly drop mutex guard to unlock mutex
+        drop(this);
+
+        let response = rx.await?;
+
+        drop(increment_guard);
+
+        response.into_response()
+    }
+}
+
 struct IncrementGuard {
     value: Arc<AtomicI32>,
 }
@@ -8,6 +8,11 @@ use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{Body, Request, Response, Server};
 
+use crate::{
+    apps::Apps,
+    request_handlers::{get_host, RemoteAddr},
+};
+
 #[derive(Clone, Debug)]
 pub struct ServerConfig {
     pub addr: SocketAddr,
@@ -15,10 +20,10 @@ pub struct ServerConfig {
 
 pub async fn run_server(
     config: ServerConfig,
-    handler: BoxedDynRunner,
+    handler: Handler,
     shutdown_signal: tokio::sync::oneshot::Receiver<()>,
 ) -> Result<(), anyhow::Error> {
-    let context = AppContext { runner: handler };
+    let context = AppContext { handler };
 
     let make_service = make_service_fn(move |conn: &AddrStream| {
         let context = context.clone();
@@ -57,9 +62,26 @@ pub trait Runner: Send + Sync + Clone + 'static {
 
 pub type BoxedDynRunner = Box<dyn Runner>;
 
+/// What incoming requests are sent to: either a single app, or one of
+/// several apps picked by host name and path.
+#[derive(Clone)]
+pub enum Handler {
+    Runner(BoxedDynRunner),
+    Apps(Apps),
+}
+
+impl Handler {
+    pub async fn shutdown(&self, timeout: Option<Duration>) {
+        match self {
+            Handler::Runner(runner) => runner.shutdown(timeout).await,
+            Handler::Apps(apps) => apps.shutdown(timeout).await,
+        }
+    }
+}
+
 #[derive(Clone)]
 struct AppContext {
-    runner: BoxedDynRunner,
+    handler: Handler,
 }
 
 async fn handle(
@@ -87,9 +109,25 @@ async fn handle_inner(
     addr: SocketAddr,
     req: Request<Body>,
 ) -> Result<Response<Body>, anyhow::Error> {
-    let (parts, body) = req.into_parts();
-    context
-        .runner
+    let (mut parts, body) = req.into_parts();
+    parts.extensions.insert(RemoteAddr(addr));
+    let runner = match context.handler {
+        Handler::Runner(runner) => runner,
+        Handler::Apps(apps) => {
+            let host = get_host(&parts.uri, &parts.headers)?;
+            match apps.route(host, parts.uri.path()) {
+                Some(runner) => runner,
+                None => {
+                    // The host comes from the request, so it's not echoed back
+                    return Ok(Response::builder()
+                        .status(hyper::StatusCode::NOT_FOUND)
+                        .header(hyper::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                        .body(Body::from("No app is configured for this host"))?);
+                }
+            }
+        }
+    };
+    runner
         .handle(addr, parts, body)
         .await
         .context("JavaScript failed")
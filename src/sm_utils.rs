@@ -1,7 +1,13 @@
-use std::{ffi::OsStr, path::Path};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{anyhow, Context as _};
-use ion::{module::ModuleLoader, Context, ErrorReport};
+use ion::{
+    module::{Module, ModuleData, ModuleLoader, ModuleRequest},
+    Context, ErrorReport, Function, Object, ResultExc, Value,
+};
 use mozjs::{
     jsapi::WeakRefSpecifier,
     rust::{JSEngine, JSEngineHandle, RealmOptions},
@@ -92,6 +98,67 @@ impl JsApp {
     }
 }
 
+/// Wraps the runtime's module loader to check the read permission for
+/// modules imported from outside the app's directory.
+pub struct PermissionedLoader {
+    inner: runtime::module::Loader,
+    app_dir: PathBuf,
+}
+
+impl PermissionedLoader {
+    pub fn new(app_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner: Default::default(),
+            app_dir: app_dir.into(),
+        }
+    }
+
+    /// Finds the file a module specifier refers to, the same way the
+    /// runtime's loader does. Returns None for built-in modules.
+    fn module_path(&self, cx: &Context, private: &Value, specifier: &str) -> Option<PathBuf> {
+        let specifier = specifier.strip_prefix("file://").unwrap_or(specifier);
+        if specifier.starts_with("./") || specifier.starts_with("../") {
+            let referrer = ModuleData::from_private(cx, private)
+                .and_then(|data| data.path)
+                .map(PathBuf::from)
+                .unwrap_or_else(|| self.app_dir.join("_"));
+            let base = referrer.parent().unwrap_or(&self.app_dir);
+            return Some(base.join(specifier));
+        }
+
+        let path = Path::new(specifier);
+        (path.is_absolute() || path.exists()).then(|| path.to_path_buf())
+    }
+}
+
+impl ModuleLoader for PermissionedLoader {
+    fn resolve<'cx>(
+        &mut self,
+        cx: &'cx Context,
+        private: &Value,
+        request: &ModuleRequest,
+    ) -> ResultExc<Module<'cx>> {
+        let specifier = request.specifier(cx).to_owned(cx)?;
+        if let Some(path) = self.module_path(cx, private, &specifier) {
+            crate::permissions::check_import(&self.app_dir, &path)?;
+        }
+        self.inner.resolve(cx, private, request)
+    }
+
+    fn register(
+        &mut self,
+        cx: &Context,
+        module: &Object,
+        request: &ModuleRequest,
+    ) -> ResultExc<*mut mozjs::jsapi::JSObject> {
+        self.inner.register(cx, module, request)
+    }
+
+    fn metadata(&self, cx: &Context, private: &Value, meta: &Object) -> ResultExc<()> {
+        self.inner.metadata(cx, private, meta)
+    }
+}
+
 pub fn evaluate_script(
     cx: &Context,
     code: impl AsRef<str>,
@@ -147,6 +214,57 @@ pub fn error_report_option_to_anyhow_error(
     }
 }
 
+/// Parses the given text using the global `JSON.parse` function.
+pub fn json_parse<'cx>(cx: &'cx Context, text: &str) -> ion::Result<Value<'cx>> {
+    use ion::conversions::ToValue;
+    call_json_method(cx, "parse", text.as_value(cx))
+}
+
+/// Serializes the given value using the global `JSON.stringify` function.
+pub fn json_stringify(cx: &Context, value: Value) -> ion::Result<String> {
+    let result = call_json_method(cx, "stringify", value)?;
+    if !result.handle().is_string() {
+        return Err(crate::ion_mk_err!(
+            "Value cannot be serialized to JSON",
+            Type
+        ));
+    }
+    ion::String::from(cx.root(result.handle().to_string())).to_owned(cx)
+}
+
+fn call_json_method<'cx>(cx: &'cx Context, method: &str, arg: Value) -> ion::Result<Value<'cx>> {
+    let json = Object::global(cx)
+        .get(cx, "JSON")?
+        .filter(|v| v.handle().is_object())
+        .map(|v| v.to_object(cx))
+        .ok_or_else(|| crate::ion_mk_err!("The JSON global object is missing", Normal))?;
+    call_method(cx, &json, method, &[arg])
+}
+
+/// Calls the method with the given name on a JS object, turning exceptions
+/// thrown by the method into regular errors.
+pub fn call_method<'cx>(
+    cx: &'cx Context,
+    this: &Object,
+    method: &str,
+    args: &[Value],
+) -> ion::Result<Value<'cx>> {
+    let func = this
+        .get(cx, method)?
+        .filter(|v| v.handle().is_object())
+        .and_then(|v| Function::from_object(cx, &v.to_object(cx)))
+        .ok_or_else(|| crate::ion_mk_err!(format!("{method} is not a function"), Type))?;
+    func.call(cx, this, args).map_err(|e| {
+        crate::ion_mk_err!(
+            format!(
+                "{method} failed: {}",
+                error_report_option_to_anyhow_error(cx, e)
+            ),
+            Normal
+        )
+    })
+}
+
 // We can't take a list of modules because StandardModules::init takes self by value, which
 // means that Vec<dyn StandardModule> is out of the question.
 pub struct TwoStandardModules<M1: StandardModules, M2: StandardModules>(pub M1, pub M2);
@@ -0,0 +1,3 @@
+addEventListener("fetch", (event) => {
+  event.respondWith(new Response(`two ${new URL(event.request.url).hostname}`));
+});
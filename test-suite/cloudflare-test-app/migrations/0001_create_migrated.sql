@@ -0,0 +1,4 @@
+-- Applied at startup, since wrangler looks for migrations in `migrations`
+-- by default
+CREATE TABLE migrated (id INTEGER PRIMARY KEY, name TEXT);
+INSERT INTO migrated (name) VALUES ('first');
@@ -0,0 +1,62 @@
+import { handleRequest as handleKv } from "./test-files/1-kv.js";
+import { handleRequest as handleR2 } from "./test-files/2-r2.js";
+import { handleRequest as handleD1 } from "./test-files/3-d1.js";
+import { handleRequest as handleDurableObjects } from "./test-files/4-durable-objects.js";
+import { handleRequest as handleWranglerConfig } from "./test-files/5-wrangler-config.js";
+import { handleRequest as handleEnv } from "./test-files/6-env.js";
+import { handleRequest as handlePermissions } from "./test-files/7-permissions.js";
+import {
+  handleRequest as handleFetchRedirects,
+  echoHeaders,
+  redirect,
+} from "./test-files/8-fetch-redirects.js";
+import { handleRequest as handleFetchMocks } from "./test-files/9-fetch-mocks.js";
+import { handleRequest as handleNodeCrypto } from "./test-files/10-node-crypto.js";
+import { handleRequest as handleFetchAbort, slow } from "./test-files/11-fetch-abort.js";
+import { handleRequest as handleSubrequests, fetchOnce } from "./test-files/12-subrequests.js";
+
+export { TestObject } from "./test-files/4-durable-objects.js";
+
+const routes = {
+  "1-kv": handleKv,
+  "2-r2": handleR2,
+  "3-d1": handleD1,
+  "4-durable-objects": handleDurableObjects,
+  "5-wrangler-config": handleWranglerConfig,
+  "6-env": handleEnv,
+  "7-permissions": handlePermissions,
+  "8-fetch-redirects": handleFetchRedirects,
+  "9-fetch-mocks": handleFetchMocks,
+  "10-node-crypto": handleNodeCrypto,
+  "11-fetch-abort": handleFetchAbort,
+  "12-subrequests": handleSubrequests,
+};
+
+// Endpoints the tests send requests to, which return their own responses
+const fixtures = {
+  "fixtures/redirect": redirect,
+  "fixtures/echo-headers": echoHeaders,
+  "fixtures/slow": slow,
+  "fixtures/fetch-once": fetchOnce,
+};
+
+export default {
+  async fetch(request, env, ctx) {
+    const path = new URL(request.url).pathname.slice(1);
+    if (fixtures[path]) {
+      return fixtures[path](request);
+    }
+
+    const route = Object.keys(routes).find((r) => path.startsWith(r));
+    if (!route) {
+      return new Response(`Route Not Found - ${path}`, { status: 404 });
+    }
+
+    try {
+      await routes[route](request, env, ctx);
+      return new Response("All tests passed!");
+    } catch (e) {
+      return new Response(e.toString(), { status: 500 });
+    }
+  },
+};
@@ -0,0 +1,70 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  promise_rejects_js,
+  promise_test,
+  readStream,
+} from "../../../js-test-app/src/test-utils.js";
+
+export async function handleRequest(request, env) {
+  const prefix = `${crypto.randomUUID()}/`;
+
+  await promise_test(async () => {
+    await env.KV.put(`${prefix}text`, "hello");
+    assert_equals(await env.KV.get(`${prefix}text`), "hello", "text");
+    assert_equals(
+      await env.KV.get(`${prefix}text`, { type: "text" }),
+      "hello",
+      "text with options"
+    );
+    assert_equals(await env.KV.get(`${prefix}missing`), null, "missing key");
+  }, "KV text round-trip");
+
+  await promise_test(async () => {
+    await env.KV.put(`${prefix}json`, JSON.stringify({ a: [1, 2] }));
+    const value = await env.KV.get(`${prefix}json`, "json");
+    assert_array_equals(value.a, [1, 2], "json");
+  }, "KV JSON round-trip");
+
+  await promise_test(async () => {
+    await env.KV.put(`${prefix}bytes`, new Uint8Array([0, 1, 255]));
+    const value = await env.KV.get(`${prefix}bytes`, "arrayBuffer");
+    assert_array_equals(new Uint8Array(value), [0, 1, 255], "arrayBuffer");
+
+    const stream = await env.KV.get(`${prefix}text`, "stream");
+    assert_equals(await readStream(stream), "hello", "stream");
+  }, "KV binary round-trip");
+
+  await promise_test(async () => {
+    await env.KV.put(`${prefix}meta`, "value", { metadata: { n: 1 } });
+    const { value, metadata } = await env.KV.getWithMetadata(`${prefix}meta`);
+    assert_equals(value, "value", "value");
+    assert_equals(metadata.n, 1, "metadata");
+  }, "KV metadata round-trip");
+
+  await promise_test(async () => {
+    const listing = await env.KV.list({ prefix });
+    assert_array_equals(
+      listing.keys.map((k) => k.name),
+      ["bytes", "json", "meta", "text"].map((k) => prefix + k),
+      "listed keys"
+    );
+    assert_equals(listing.list_complete, true, "list_complete");
+
+    const page = await env.KV.list({ prefix, limit: 2 });
+    assert_equals(page.keys.length, 2, "page length");
+    assert_equals(page.list_complete, false, "page list_complete");
+    const rest = await env.KV.list({ prefix, cursor: page.cursor });
+    assert_equals(rest.keys[0].name, `${prefix}meta`, "cursor");
+  }, "KV list");
+
+  await promise_test(async () => {
+    await env.KV.delete(`${prefix}text`);
+    assert_equals(await env.KV.get(`${prefix}text`), null, "deleted");
+    await promise_rejects_js(env.KV.get(""), "empty key");
+    await promise_rejects_js(
+      env.KV.get(`${prefix}text`, "unknown"),
+      "unknown type"
+    );
+  }, "KV delete and validation");
+}
@@ -0,0 +1,65 @@
+import {
+  assert_equals,
+  assert_true,
+  assert_unreached,
+  promise_test,
+} from "../../../js-test-app/src/test-utils.js";
+
+const ORIGIN = "http://127.0.0.1:8081";
+
+// Fixture route, served without the test wrapper. Responds after the
+// number of milliseconds in the `delay` parameter.
+export async function slow(request) {
+  const delay = Number(new URL(request.url).searchParams.get("delay"));
+  await new Promise((resolve) => setTimeout(resolve, delay));
+  return new Response("slow");
+}
+
+const assert_aborts = async (promise, message) => {
+  try {
+    await promise;
+  } catch (e) {
+    assert_equals(e.message, "AbortError", message);
+    return;
+  }
+  assert_unreached(`Should have been aborted: ${message}`);
+};
+
+export async function handleRequest(request) {
+  await promise_test(async () => {
+    const controller = new AbortController();
+    controller.abort();
+    await assert_aborts(
+      fetch(`${ORIGIN}/fixtures/slow?delay=0`, { signal: controller.signal }),
+      "already aborted"
+    );
+  }, "fetch rejects with an already aborted signal");
+
+  await promise_test(async () => {
+    const controller = new AbortController();
+    const started = Date.now();
+    const promise = fetch(`${ORIGIN}/fixtures/slow?delay=5000`, { signal: controller.signal });
+    setTimeout(() => controller.abort(), 50);
+    await assert_aborts(promise, "aborted while waiting for the response");
+    assert_true(Date.now() - started < 4000, "fetch doesn't wait for the response");
+  }, "fetch rejects when the signal is aborted");
+
+  await promise_test(async () => {
+    const controller = new AbortController();
+    const to = encodeURIComponent(`${ORIGIN}/fixtures/slow?delay=5000`);
+    const promise = fetch(`${ORIGIN}/fixtures/redirect?to=${to}`, {
+      signal: controller.signal,
+    });
+    setTimeout(() => controller.abort(), 50);
+    await assert_aborts(promise, "aborted after a redirect");
+  }, "redirects keep the signal of the original request");
+
+  await promise_test(async () => {
+    const controller = new AbortController();
+    const response = await fetch(`${ORIGIN}/fixtures/slow?delay=0`, {
+      signal: controller.signal,
+    });
+    controller.abort();
+    assert_equals(await response.text(), "slow", "body");
+  }, "aborting after the response arrived doesn't affect it");
+}
@@ -0,0 +1,37 @@
+import {
+  assert_equals,
+  promise_rejects_js,
+  promise_test,
+} from "../../../js-test-app/src/test-utils.js";
+
+const ORIGIN = "http://127.0.0.1:8081";
+
+// Matches --max-subrequests in the CI workflow
+const MAX_SUBREQUESTS = 50;
+
+// Fixture route, served without the test wrapper. Makes one subrequest of
+// its own, which counts against this request rather than its caller.
+export async function fetchOnce(request) {
+  const response = await fetch(`${ORIGIN}/fixtures/echo-headers`);
+  return new Response(null, { status: response.status });
+}
+
+export async function handleRequest(request) {
+  await promise_test(async () => {
+    for (let i = 0; i < MAX_SUBREQUESTS - 1; i++) {
+      const response = await fetch(`${ORIGIN}/fixtures/echo-headers`);
+      assert_equals(response.status, 200, `subrequest ${i + 1}`);
+      await response.arrayBuffer();
+    }
+
+    // The last allowed subrequest is handled by a request that makes a
+    // subrequest of its own, which isn't taken from this request's limit
+    const response = await fetch(`${ORIGIN}/fixtures/fetch-once`);
+    assert_equals(response.status, 200, "subrequest of another request");
+
+    await promise_rejects_js(
+      fetch(`${ORIGIN}/fixtures/echo-headers`),
+      "subrequest over the limit"
+    );
+  }, "each request has its own subrequest limit");
+}
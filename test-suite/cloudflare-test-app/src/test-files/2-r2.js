@@ -0,0 +1,159 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  assert_true,
+  promise_rejects_js,
+  promise_test,
+  readStream,
+  readableStreamFromArray,
+} from "../../../js-test-app/src/test-utils.js";
+
+const CHUNK_SIZE = 16 * 1024;
+const CHUNK_COUNT = 64;
+
+function chunk(index) {
+  return new Uint8Array(CHUNK_SIZE).fill(index % 256);
+}
+
+function assert_chunks(bytes, first, count, message) {
+  assert_equals(bytes.length, count * CHUNK_SIZE, `${message}: length`);
+  for (let i = 0; i < count; i++) {
+    assert_equals(bytes[i * CHUNK_SIZE], (first + i) % 256, `${message}: chunk ${i}`);
+    assert_equals(
+      bytes[(i + 1) * CHUNK_SIZE - 1],
+      (first + i) % 256,
+      `${message}: end of chunk ${i}`
+    );
+  }
+}
+
+export async function handleRequest(request, env) {
+  const prefix = `${crypto.randomUUID()}/`;
+
+  await promise_test(async () => {
+    const object = await env.BUCKET.put(`${prefix}text`, "hello world", {
+      httpMetadata: { contentType: "text/plain" },
+      customMetadata: { owner: "tests" },
+    });
+    assert_equals(object.size, 11, "size");
+    assert_equals(object.etag, "5eb63bbbe01eeed093cb22bb8f5acdc3", "etag");
+
+    const body = await env.BUCKET.get(`${prefix}text`);
+    assert_equals(body.httpMetadata.contentType, "text/plain", "content type");
+    assert_equals(body.customMetadata.owner, "tests", "custom metadata");
+    assert_equals(body.bodyUsed, false, "bodyUsed before reading");
+    assert_equals(await body.text(), "hello world", "text");
+    assert_equals(body.bodyUsed, true, "bodyUsed after reading");
+    await promise_rejects_js(body.text(), "reading the body twice");
+
+    await env.BUCKET.put(`${prefix}json`, '{"a":1}');
+    assert_equals((await (await env.BUCKET.get(`${prefix}json`)).json()).a, 1, "json");
+
+    assert_equals(await env.BUCKET.get(`${prefix}missing`), null, "missing key");
+    assert_equals((await env.BUCKET.head(`${prefix}text`)).size, 11, "head");
+  }, "R2 round-trip");
+
+  await promise_test(async () => {
+    const chunks = [];
+    for (let i = 0; i < CHUNK_COUNT; i++) {
+      chunks.push(chunk(i));
+    }
+    const object = await env.BUCKET.put(
+      `${prefix}stream`,
+      readableStreamFromArray(chunks)
+    );
+    assert_equals(object.size, CHUNK_SIZE * CHUNK_COUNT, "streamed size");
+
+    const body = await env.BUCKET.get(`${prefix}stream`);
+    const reader = body.body.getReader();
+    const received = new Uint8Array(CHUNK_SIZE * CHUNK_COUNT);
+    let offset = 0;
+    let reads = 0;
+    while (true) {
+      const { done, value } = await reader.read();
+      if (done) {
+        break;
+      }
+      received.set(value, offset);
+      offset += value.length;
+      reads++;
+    }
+    assert_true(reads > 1, "body is read in several chunks");
+    assert_chunks(received.subarray(0, offset), 0, CHUNK_COUNT, "streamed body");
+
+    const copy = await env.BUCKET.get(`${prefix}stream`);
+    const bytes = new Uint8Array(await copy.arrayBuffer());
+    assert_chunks(bytes, 0, CHUNK_COUNT, "arrayBuffer");
+
+    await promise_rejects_js(
+      env.BUCKET.put(`${prefix}bad-stream`, readableStreamFromArray(["text"])),
+      "non-binary chunks"
+    );
+  }, "R2 streamed bodies");
+
+  await promise_test(async () => {
+    const ranged = await env.BUCKET.get(`${prefix}stream`, {
+      range: { offset: CHUNK_SIZE * 3, length: CHUNK_SIZE * 2 },
+    });
+    assert_equals(ranged.range.offset, CHUNK_SIZE * 3, "range offset");
+    assert_chunks(new Uint8Array(await ranged.arrayBuffer()), 3, 2, "range");
+
+    const suffix = await env.BUCKET.get(`${prefix}text`, { range: { suffix: 5 } });
+    assert_equals(await suffix.text(), "world", "suffix");
+
+    const headers = new Headers({ Range: "bytes=0-4" });
+    const header = await env.BUCKET.get(`${prefix}text`, { range: headers });
+    assert_equals(await readStream(header.body), "hello", "range header");
+  }, "R2 ranged reads");
+
+  await promise_test(async () => {
+    const { etag } = await env.BUCKET.head(`${prefix}text`);
+    const unmet = await env.BUCKET.get(`${prefix}text`, {
+      onlyIf: { etagDoesNotMatch: etag },
+    });
+    assert_equals(unmet.body, undefined, "unmet condition returns no body");
+
+    const skipped = await env.BUCKET.put(`${prefix}text`, "changed", {
+      onlyIf: { etagMatches: "0".repeat(32) },
+    });
+    assert_equals(skipped, null, "unmet put condition");
+
+    await promise_rejects_js(
+      env.BUCKET.put(`${prefix}md5`, "hello world", { md5: "0".repeat(32) }),
+      "mismatched md5"
+    );
+    const checked = await env.BUCKET.put(`${prefix}md5`, "hello world", {
+      md5: "5eb63bbbe01eeed093cb22bb8f5acdc3",
+    });
+    assert_array_equals(
+      new Uint8Array(checked.checksums.md5),
+      [94, 182, 59, 187, 224, 30, 238, 208, 147, 203, 34, 187, 143, 90, 205, 195],
+      "md5 checksum"
+    );
+  }, "R2 conditional writes and checksums");
+
+  await promise_test(async () => {
+    const upload = await env.BUCKET.createMultipartUpload(`${prefix}multipart`);
+    const part2 = await upload.uploadPart(2, readableStreamFromArray([chunk(2), chunk(3)]));
+    const part1 = await upload.uploadPart(1, chunk(1));
+    const object = await upload.complete([part2, part1]);
+    assert_equals(object.size, CHUNK_SIZE * 3, "multipart size");
+    assert_true(object.etag.endsWith("-2"), "multipart etag");
+
+    const body = await env.BUCKET.get(`${prefix}multipart`);
+    assert_chunks(new Uint8Array(await body.arrayBuffer()), 1, 3, "multipart body");
+  }, "R2 multipart uploads");
+
+  await promise_test(async () => {
+    const listing = await env.BUCKET.list({ prefix });
+    assert_array_equals(
+      listing.objects.map((o) => o.key),
+      ["json", "md5", "multipart", "stream", "text"].map((k) => prefix + k),
+      "listed keys"
+    );
+
+    await env.BUCKET.delete([`${prefix}json`, `${prefix}text`]);
+    assert_equals(await env.BUCKET.head(`${prefix}text`), null, "deleted");
+    assert_equals((await env.BUCKET.list({ prefix })).objects.length, 3, "remaining");
+  }, "R2 list and delete");
+}
@@ -0,0 +1,105 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  promise_rejects_js,
+  promise_test,
+} from "../../../js-test-app/src/test-utils.js";
+
+export async function handleRequest(request, env) {
+  const table = `t_${crypto.randomUUID().replaceAll("-", "")}`;
+
+  await promise_test(async () => {
+    await env.DB.exec(
+      `CREATE TABLE ${table} (id INTEGER PRIMARY KEY, name TEXT, n INTEGER, data BLOB)`
+    );
+
+    const insert = env.DB.prepare(`INSERT INTO ${table} (name, n) VALUES (?1, ?2)`);
+    const inserted = await insert.bind("a", 1).run();
+    assert_equals(inserted.success, true, "success");
+    assert_equals(inserted.meta.changes, 1, "insert changes");
+    assert_equals(inserted.meta.last_row_id, 1, "last_row_id");
+    await insert.bind("b", 2).run();
+    await insert.bind("c", 3).run();
+
+    const selected = await env.DB.prepare(`SELECT * FROM ${table}`).run();
+    assert_equals(selected.meta.changes, 0, "select doesn't repeat the last insert's changes");
+    assert_equals(selected.results.length, 3, "select results");
+
+    const updated = await env.DB.prepare(`UPDATE ${table} SET n = n + 1 WHERE n > ?1`)
+      .bind(1)
+      .run();
+    assert_equals(updated.meta.changes, 2, "update changes");
+
+    const unchanged = await env.DB.prepare(`UPDATE ${table} SET n = 0 WHERE n > 100`).run();
+    assert_equals(unchanged.meta.changes, 0, "update without matches");
+  }, "D1 changes");
+
+  await promise_test(async () => {
+    const statement = env.DB.prepare(`SELECT name, n FROM ${table} ORDER BY id`);
+    const all = await statement.all();
+    const run = await statement.run();
+    assert_array_equals(
+      all.results.map((r) => r.name),
+      ["a", "b", "c"],
+      "all results"
+    );
+    assert_array_equals(
+      run.results.map((r) => r.n),
+      all.results.map((r) => r.n),
+      "run and all return the same results"
+    );
+
+    assert_equals(await statement.first("name"), "a", "first column");
+    assert_equals((await statement.first()).n, 1, "first row");
+    const raw = await statement.raw({ columnNames: true });
+    assert_array_equals(raw[0], ["name", "n"], "raw column names");
+    assert_array_equals(raw[1], ["a", 1], "raw row");
+    await promise_rejects_js(statement.first("missing"), "unknown column");
+  }, "D1 result shapes");
+
+  await promise_test(async () => {
+    const insert = env.DB.prepare(`INSERT INTO ${table} (name, n, data) VALUES (?1, ?2, ?3)`);
+    await insert.bind("big", 9007199254740993n, new Uint8Array([1, 2])).run();
+    await insert.bind("safe", Number.MAX_SAFE_INTEGER, null).run();
+
+    const big = await env.DB.prepare(`SELECT n, data FROM ${table} WHERE name = ?1`)
+      .bind("big")
+      .first();
+    assert_equals(big.n, 9007199254740993n, "unsafe integers are returned as BigInts");
+    assert_array_equals(big.data, [1, 2], "blob");
+
+    const safe = await env.DB.prepare(`SELECT n FROM ${table} WHERE name = ?1`)
+      .bind("safe")
+      .first("n");
+    assert_equals(safe, Number.MAX_SAFE_INTEGER, "safe integers are returned as numbers");
+
+    const negative = await env.DB.prepare("SELECT -9007199254740993 AS n").first("n");
+    assert_equals(negative, -9007199254740993n, "negative unsafe integers");
+
+    await promise_rejects_js(
+      env.DB.prepare("SELECT ?1").bind(2n ** 64n).run(),
+      "BigInts larger than 64 bits"
+    );
+  }, "D1 integer precision");
+
+  await promise_test(async () => {
+    await promise_rejects_js(
+      env.DB.batch([
+        env.DB.prepare(`INSERT INTO ${table} (name) VALUES ('batched')`),
+        env.DB.prepare(`INSERT INTO missing_table VALUES (1)`),
+      ]),
+      "failing batch"
+    );
+    const count = await env.DB.prepare(`SELECT COUNT(*) AS c FROM ${table} WHERE name = 'batched'`)
+      .first("c");
+    assert_equals(count, 0, "failed batches are rolled back");
+
+    const results = await env.DB.batch([
+      env.DB.prepare(`DELETE FROM ${table} WHERE name = 'a'`),
+      env.DB.prepare(`SELECT COUNT(*) AS c FROM ${table}`),
+    ]);
+    assert_equals(results[0].meta.changes, 1, "batched delete changes");
+    assert_equals(results[1].results[0].c, 4, "batched select");
+    await env.DB.exec(`DROP TABLE ${table}`);
+  }, "D1 batches");
+}
@@ -0,0 +1,183 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  assert_not_equals,
+  delay,
+  promise_rejects_js,
+  promise_test,
+} from "../../../js-test-app/src/test-utils.js";
+
+export class TestObject {
+  constructor(state, env) {
+    this.state = state;
+    this.storage = state.storage;
+    this.instance = crypto.randomUUID();
+    state.blockConcurrencyWhile(async () => {
+      await delay(20);
+      this.ready = true;
+    });
+  }
+
+  async fetch(request) {
+    const test = new URL(request.url).pathname.slice(1);
+    if (events[test]) {
+      return new Response(await events[test](this));
+    }
+    try {
+      await tests[test](this);
+      return new Response("ok");
+    } catch (e) {
+      return new Response(e.toString(), { status: 500 });
+    }
+  }
+}
+
+// Events that return a value instead of running a test
+const events = {
+  async instance(object) {
+    return object.instance;
+  },
+
+  async fail({ state }) {
+    state
+      .blockConcurrencyWhile(async () => {
+        await delay(20);
+        throw new Error("failing callback");
+      })
+      .catch(() => {});
+    return "ok";
+  },
+};
+
+const tests = {
+  async ready(object) {
+    assert_equals(object.ready, true, "events wait for blockConcurrencyWhile");
+  },
+
+  async storage({ storage }) {
+    await storage.put("a", { n: 1 });
+    await storage.put({ b: [1, 2], c: "three" });
+    assert_equals((await storage.get("a")).n, 1, "single get");
+    assert_equals(await storage.get("missing"), undefined, "missing key");
+
+    const many = await storage.get(["a", "c", "missing"]);
+    assert_array_equals([...many.keys()], ["a", "c"], "multi get");
+
+    const listed = await storage.list({ prefix: "b" });
+    assert_array_equals(listed.get("b"), [1, 2], "list");
+    const reversed = await storage.list({ reverse: true, limit: 2 });
+    assert_array_equals([...reversed.keys()], ["c", "b"], "reverse list");
+
+    assert_equals(await storage.delete("a"), true, "delete existing key");
+    assert_equals(await storage.delete("a"), false, "delete missing key");
+    assert_equals(await storage.delete(["b", "c", "d"]), 2, "delete many");
+  },
+
+  async transactions({ storage }) {
+    await storage.deleteAll();
+
+    await storage.transaction(async (txn) => {
+      await txn.put("committed", 1);
+    });
+    assert_equals(await storage.get("committed"), 1, "committed transaction");
+
+    await storage.transaction(async (txn) => {
+      await txn.put("rolled-back", 1);
+      txn.rollback();
+    });
+    assert_equals(await storage.get("rolled-back"), undefined, "rollback()");
+
+    await promise_rejects_js(
+      storage.transaction(async (txn) => {
+        await txn.put("failed", 1);
+        throw new Error("failing transaction");
+      }),
+      "failing transaction"
+    );
+    assert_equals(await storage.get("failed"), undefined, "failed transaction");
+
+    await storage.transaction(async (txn) => {
+      await txn.put("outer", 1);
+      await txn.transaction(async (inner) => {
+        await inner.put("inner", 1);
+        inner.rollback();
+      });
+    });
+    assert_equals(await storage.get("outer"), 1, "outer transaction");
+    assert_equals(await storage.get("inner"), undefined, "nested rollback");
+
+    storage.transactionSync((txn) => {
+      txn.put("sync", 1);
+    });
+    assert_equals(await storage.get("sync"), 1, "transactionSync");
+  },
+
+  async gate({ storage }) {
+    await storage.deleteAll();
+
+    let started;
+    const transactionStarted = new Promise((resolve) => (started = resolve));
+    let transactionDone = false;
+    const transaction = storage.transaction(async (txn) => {
+      started();
+      await txn.put("inside", 1);
+      await delay(50);
+      transactionDone = true;
+      txn.rollback();
+    });
+    await transactionStarted;
+
+    // Made while the transaction is open, so they must wait for it instead
+    // of ending up inside its savepoint
+    const outside = storage.put("outside", 1);
+    const read = storage.get("inside").then((value) => {
+      assert_equals(transactionDone, true, "reads wait for the transaction");
+      return value;
+    });
+    let secondStartedEarly = false;
+    const second = storage.transaction(async (txn) => {
+      secondStartedEarly = !transactionDone;
+      await txn.put("second", 1);
+    });
+    let syncRan = false;
+    try {
+      storage.transactionSync(() => (syncRan = true));
+    } catch (e) {}
+    assert_equals(syncRan, false, "transactionSync can't run while a transaction is open");
+
+    await Promise.all([transaction, outside, second]);
+    assert_equals(await read, undefined, "reads see the rolled back value");
+    assert_equals(secondStartedEarly, false, "transactions are serialised");
+    assert_equals(await storage.get("outside"), 1, "writes outside the transaction survive its rollback");
+    assert_equals(await storage.get("second"), 1, "second transaction");
+    assert_equals(await storage.get("inside"), undefined, "rolled back write");
+  },
+};
+
+export async function handleRequest(request, env) {
+  const stub = env.TEST_OBJECT.get(env.TEST_OBJECT.idFromName(crypto.randomUUID()));
+  for (const test of Object.keys(tests)) {
+    await promise_test(async () => {
+      const response = await stub.fetch(`http://object/${test}`);
+      assert_equals(await response.text(), "ok", test);
+    }, `Durable Object ${test}`);
+  }
+
+  await promise_test(async () => {
+    const stub = env.TEST_OBJECT.get(env.TEST_OBJECT.idFromName(crypto.randomUUID()));
+    const instance = async () => (await stub.fetch("http://object/instance")).text();
+    const first = await instance();
+    assert_equals(await instance(), first, "the object is kept between events");
+
+    await stub.fetch("http://object/fail");
+    const waiting = await stub.fetch("http://object/instance").then(
+      (response) => response.status,
+      () => "rejected"
+    );
+    assert_not_equals(waiting, 200, "events waiting for the failed callback fail");
+
+    const second = await instance();
+    assert_not_equals(second, first, "the object is replaced");
+    assert_equals(await instance(), second, "the new object is kept");
+  }, "Durable Object is reset when blockConcurrencyWhile fails");
+}
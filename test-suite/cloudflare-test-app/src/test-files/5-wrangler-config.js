@@ -0,0 +1,28 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  promise_test,
+} from "../../../js-test-app/src/test-utils.js";
+
+export async function handleRequest(request, env) {
+  await promise_test(async () => {
+    assert_equals(env.GREETING, "hello", "string var");
+    assert_equals(env.NUMBER, 42, "number var");
+    assert_array_equals(env.NESTED.list, [1, 2], "object var");
+  }, "wrangler.toml vars");
+
+  await promise_test(async () => {
+    const { results } = await env.DB.prepare("SELECT name FROM migrated").all();
+    assert_array_equals(
+      results.map((r) => r.name),
+      ["first"],
+      "migration applied once"
+    );
+    const applied = await env.DB.prepare("SELECT name FROM d1_migrations").all();
+    assert_array_equals(
+      applied.results.map((r) => r.name),
+      ["0001_create_migrated.sql"],
+      "applied migrations"
+    );
+  }, "wrangler.toml default migrations directory");
+}
@@ -0,0 +1,17 @@
+import { assert_equals, assert_false, promise_test } from "../../../js-test-app/src/test-utils.js";
+
+export async function handleRequest(request, env) {
+  await promise_test(async () => {
+    assert_equals(env.SECRET_TOKEN, "s3cret", "secret on env");
+    assert_equals(process.env.SECRET_TOKEN, "s3cret", "secret on process.env");
+    assert_false(Object.keys(env).includes("SECRET_TOKEN"), "secrets aren't enumerable");
+    assert_false(JSON.stringify(process.env).includes("s3cret"), "secrets aren't serialized");
+  }, ".dev.vars secrets");
+
+  await promise_test(async () => {
+    for (const name of ["PATH", "HOME", "WINTERJS_PORT"]) {
+      assert_equals(env[name], undefined, `${name} on env`);
+      assert_equals(process.env[name], undefined, `${name} on process.env`);
+    }
+  }, "host environment isn't exposed");
+}
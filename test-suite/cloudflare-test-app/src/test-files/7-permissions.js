@@ -0,0 +1,86 @@
+import {
+  assert_equals,
+  assert_true,
+  assert_unreached,
+  promise_test,
+} from "../../../js-test-app/src/test-utils.js";
+
+// The app is served with read access to the shared test utilities, and net
+// access to itself and to allowed.test, which is answered by fetch mocks.
+
+const assert_denied = (e, message) =>
+  assert_true(
+    String(e.message).startsWith("PermissionDenied"),
+    `${message}: expected a permission error, got ${e}`
+  );
+
+const expect_denied = async (promise, message) => {
+  try {
+    await promise;
+  } catch (e) {
+    assert_denied(e, message);
+    return;
+  }
+  assert_unreached(`${message}: should have been denied`);
+};
+
+const expect_denied_sync = (f, message) => {
+  try {
+    f();
+  } catch (e) {
+    assert_denied(e, message);
+    return;
+  }
+  assert_unreached(`${message}: should have been denied`);
+};
+
+export async function handleRequest(request) {
+  await promise_test(async () => {
+    expect_denied_sync(() => fs.readStringSync("/etc/hosts"), "readStringSync");
+    expect_denied_sync(() => fs.writeSync("/tmp/winterjs-denied", "x"), "writeSync");
+    expect_denied_sync(
+      () => fs.copySync("./test-suite/js-test-app/src/test-utils.js", "/tmp/winterjs-denied"),
+      "copySync to a denied path"
+    );
+    await expect_denied(fs.readString("/etc/hosts"), "readString");
+    await expect_denied(fs.readDir("/"), "readDir");
+    await expect_denied(fs.removeFile("/tmp/winterjs-denied"), "removeFile");
+  }, "fs access outside granted paths is denied");
+
+  await promise_test(async () => {
+    // A path that changes between being checked and being used
+    let calls = 0;
+    const path = {
+      toString() {
+        calls++;
+        return calls === 1 ? "/tmp" : "/etc/hosts";
+      },
+    };
+    expect_denied_sync(() => fs.readStringSync(path), "path object");
+    assert_equals(calls, 1, "path is converted once");
+  }, "fs paths are converted before being checked");
+
+  await promise_test(async () => {
+    const readString = fs.readStringSync;
+    expect_denied_sync(() => readString.call({}, "/etc/hosts"), "detached function");
+  }, "fs functions check permissions however they're called");
+
+  await promise_test(async () => {
+    const response = await fetch("http://allowed.test/ok");
+    assert_equals(await response.text(), "ok");
+
+    await expect_denied(fetch("http://denied.test/target"), "fetch");
+    await expect_denied(fetch(new Request("http://denied.test/target")), "fetch with a Request");
+    await expect_denied(fetch("http://allowed.test:8080/ok"), "fetch on another port");
+    await expect_denied(fetch("http://allowed.test/redirect"), "redirect to a denied host");
+
+    const manual = await fetch("http://allowed.test/redirect", { redirect: "manual" });
+    assert_equals(manual.status, 302, "manual redirects aren't followed");
+  }, "fetch checks the net permission for every hop");
+
+  await promise_test(async () => {
+    await expect_denied(import("../../../src/main.rs"), "import outside the app");
+    const utils = await import("../../../js-test-app/src/test-utils.js");
+    assert_equals(typeof utils.assert_equals, "function", "granted import");
+  }, "imports from outside the app need read access");
+}
@@ -0,0 +1,75 @@
+import { assert_equals, promise_test } from "../../../js-test-app/src/test-utils.js";
+
+// The app sends requests to itself through both 127.0.0.1 and localhost,
+// which are different origins served by the same server.
+const SAME_ORIGIN = "http://127.0.0.1:8081";
+const OTHER_ORIGIN = "http://localhost:8081";
+
+// Fixture routes, served without the test wrapper.
+export function redirect(request) {
+  const to = new URL(request.url).searchParams.get("to");
+  return new Response(null, { status: 302, headers: { location: to } });
+}
+
+export function echoHeaders(request) {
+  return new Response(JSON.stringify(Object.fromEntries(request.headers)), {
+    headers: { "content-type": "application/json" },
+  });
+}
+
+const credentials = {
+  authorization: "Bearer secret",
+  cookie: "session=secret",
+  "proxy-authorization": "Basic secret",
+  "x-custom": "kept",
+};
+
+const fetchThroughRedirect = async (target) => {
+  const to = encodeURIComponent(`${target}/fixtures/echo-headers`);
+  const response = await fetch(`${SAME_ORIGIN}/fixtures/redirect?to=${to}`, {
+    headers: credentials,
+  });
+  assert_equals(response.status, 200, "redirect is followed");
+  return response.json();
+};
+
+export async function handleRequest(request) {
+  await promise_test(async () => {
+    const headers = await fetchThroughRedirect(SAME_ORIGIN);
+    for (const [name, value] of Object.entries(credentials)) {
+      assert_equals(headers[name], value, name);
+    }
+  }, "credentials are kept on same-origin redirects");
+
+  await promise_test(async () => {
+    const headers = await fetchThroughRedirect(OTHER_ORIGIN);
+    assert_equals(headers.authorization, undefined, "authorization");
+    assert_equals(headers.cookie, undefined, "cookie");
+    assert_equals(headers["proxy-authorization"], undefined, "proxy-authorization");
+    assert_equals(headers["x-custom"], "kept", "other headers");
+  }, "credentials are dropped on cross-origin redirects");
+
+  await promise_test(async () => {
+    const originalFetch = globalThis.fetch;
+    const originalRequest = globalThis.Request;
+    const seen = [];
+    globalThis.fetch = (...args) => {
+      seen.push(args[0]);
+      return originalFetch(...args);
+    };
+    globalThis.Request = class extends originalRequest {
+      constructor(input, init) {
+        seen.push(input);
+        super(input, init);
+      }
+    };
+    try {
+      const headers = await fetchThroughRedirect(OTHER_ORIGIN);
+      assert_equals(headers.authorization, undefined, "authorization");
+      assert_equals(seen.length, 1, "only the initial call goes through the globals");
+    } finally {
+      globalThis.fetch = originalFetch;
+      globalThis.Request = originalRequest;
+    }
+  }, "redirects don't go through replaced globals");
+}
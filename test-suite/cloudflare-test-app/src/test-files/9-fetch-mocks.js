@@ -0,0 +1,46 @@
+import { assert_equals, promise_rejects_js, promise_test } from "../../../js-test-app/src/test-utils.js";
+
+// Responses come from fetch-mocks.json, which the app is served with.
+
+export async function handleRequest(request) {
+  await promise_test(async () => {
+    const response = await fetch("http://mocks.test/users/1");
+    assert_equals(response.status, 200, "status");
+    assert_equals(response.headers.get("content-type"), "application/json", "content type");
+    const user = await response.json();
+    assert_equals(user.id, 1, "id");
+    assert_equals(user.name, "Alice", "name");
+  }, "JSON mocks");
+
+  await promise_test(async () => {
+    const response = await fetch("http://mocks.test/users/1", { method: "POST", body: "{}" });
+    assert_equals(response.status, 201, "status");
+    assert_equals(await response.text(), "created", "body");
+  }, "mocks are matched by method");
+
+  await promise_test(async () => {
+    const response = await fetch("http://mocks.test/unavailable");
+    assert_equals(response.status, 503, "status");
+    assert_equals(response.headers.get("retry-after"), "120", "headers");
+    assert_equals(await response.text(), "Unavailable", "body");
+  }, "mocks with a status and headers");
+
+  await promise_test(async () => {
+    const response = await fetch("http://mocks.test/file");
+    assert_equals(await response.text(), "Body from a file\n");
+  }, "mocks with a body file");
+
+  await promise_test(async () => {
+    const response = await fetch("http://mocks.test/deep/first");
+    assert_equals(await response.text(), "deep", "first matching mock wins");
+    const nested = await fetch("http://mocks.test/deep/a/b/c");
+    assert_equals(await nested.text(), "deep", "** matches across slashes");
+  }, "mock URL patterns");
+
+  await promise_test(async () => {
+    // `*` doesn't match across slashes, so this goes out to the network,
+    // where .test domains never resolve
+    await promise_rejects_js(fetch("http://mocks.test/users/1/posts"), "unmatched request");
+    await promise_rejects_js(fetch("http://mocks.test/users/1", { method: "DELETE" }), "unmatched method");
+  }, "unmatched requests aren't mocked");
+}
@@ -0,0 +1,85 @@
+#!/usr/bin/env bash
+# Checks how `winterjs config print` merges the config file, environment
+# variables and CLI flags. Usage: run.sh <path to winterjs binary>
+
+set -uo pipefail
+
+WINTERJS="$(realpath "$1")"
+DIR="$(cd "$(dirname "$0")" && pwd)"
+FAILED=0
+
+# Runs `winterjs config print` with the given arguments and checks that the
+# output contains each expected line
+expect() {
+  local name="$1"
+  shift
+  local expected=()
+  while [ "$1" != "--" ]; do
+    expected+=("$1")
+    shift
+  done
+  shift
+
+  local output
+  if ! output="$(cd "$DIR" && env -u RUST_LOG "$WINTERJS" config print "$@" 2>&1)"; then
+    echo "FAIL $name: config print failed: $output"
+    FAILED=1
+    return
+  fi
+  for line in "${expected[@]}"; do
+    if ! grep -qF -- "$line" <<<"$output"; then
+      echo "FAIL $name: expected '$line' in:"
+      echo "$output"
+      FAILED=1
+      return
+    fi
+  done
+  echo "ok   $name"
+}
+
+expect "values come from the config file" \
+  "port = 9001" "max_js_threads = 3" 'mode = "cloudflare"' 'level = "winterjs=debug"' '"api.example.com"' \
+  -- --config winterjs.toml
+
+expect "relative paths are resolved against the file" \
+  "$DIR/app/index.js" "$DIR/data" \
+  -- --config "$DIR/winterjs.toml"
+
+expect "CLI flags override the file" \
+  "port = 9002" "max_js_threads = 3" '"other.example.com"' \
+  -- --config winterjs.toml --port 9002 --allow-net=other.example.com
+
+WINTERJS_PORT=9003 WINTERJS_MAX_JS_THREADS=5 expect "environment variables override the file" \
+  "port = 9003" "max_js_threads = 5" \
+  -- --config winterjs.toml
+
+expect "the file is discovered next to the entrypoint" \
+  "port = 9100" \
+  -- app/index.js
+
+expect "the file is discovered in a JS_PATH directory" \
+  "port = 9100" \
+  -- app
+
+if output="$(cd "$DIR" && "$WINTERJS" config print --config invalid.toml 2>&1)"; then
+  echo "FAIL unknown keys are rejected: config print succeeded with:"
+  echo "$output"
+  FAILED=1
+else
+  echo "ok   unknown keys are rejected"
+fi
+
+# Static file options are validated when the server starts, rather than
+# when the first request comes in
+if output="$(cd "$DIR" && timeout 10 "$WINTERJS" serve --config invalid-cache-control.toml 2>&1)"; then
+  echo "FAIL invalid Cache-Control rules fail at startup: serve exited successfully"
+  FAILED=1
+elif ! grep -qF "Invalid directive 'max age=60'" <<<"$output"; then
+  echo "FAIL invalid Cache-Control rules fail at startup:"
+  echo "$output"
+  FAILED=1
+else
+  echo "ok   invalid Cache-Control rules fail at startup"
+fi
+
+exit $FAILED
@@ -21,6 +21,15 @@ import { handleRequest as handleTimers } from "./test-files/15-timers.js";
 import { handleRequest as handleCrypto } from "./test-files/16-crypto.js";
 import { handleRequest as handleCryptoHmac } from "./test-files/16.1-crypto-hmac.js";
 import { handleRequest as handleCryptoSha } from "./test-files/16.2-crypto-sha.js";
+import { handleRequest as handleCryptoAes } from "./test-files/16.3-crypto-aes.js";
+import { handleRequest as handleCryptoRsa } from "./test-files/16.4-crypto-rsa.js";
+import { handleRequest as handleCryptoEc } from "./test-files/16.5-crypto-ec.js";
+import { handleRequest as handleCryptoCurve25519 } from "./test-files/16.6-crypto-curve25519.js";
+import { handleRequest as handleCryptoKdf } from "./test-files/16.7-crypto-kdf.js";
+import { handleRequest as handleCryptoWrap } from "./test-files/16.8-crypto-wrap.js";
+import { handleRequest as handleCryptoDigestStream } from "./test-files/16.9-crypto-digest-stream.js";
+import { handleRequest as handleCryptoTimingSafeEqual } from "./test-files/16.10-crypto-timing-safe-equal.js";
+import { handleRequest as handleCryptoKeyClone } from "./test-files/16.11-crypto-key-clone.js";
 import { handleRequest as handleCache } from "./test-files/17-cache.js";
 import { handleRequest as handleEvent } from "./test-files/18-event.js";
 import { handleRequest as handleAbort } from "./test-files/19-abort.js";
@@ -98,6 +107,33 @@ function router(req) {
   if (path.startsWith("/16.2-crypto-sha")) {
     return handleCryptoSha(req);
   }
+  if (path.startsWith("/16.3-crypto-aes")) {
+    return handleCryptoAes(req);
+  }
+  if (path.startsWith("/16.4-crypto-rsa")) {
+    return handleCryptoRsa(req);
+  }
+  if (path.startsWith("/16.5-crypto-ec")) {
+    return handleCryptoEc(req);
+  }
+  if (path.startsWith("/16.6-crypto-curve25519")) {
+    return handleCryptoCurve25519(req);
+  }
+  if (path.startsWith("/16.7-crypto-kdf")) {
+    return handleCryptoKdf(req);
+  }
+  if (path.startsWith("/16.8-crypto-wrap")) {
+    return handleCryptoWrap(req);
+  }
+  if (path.startsWith("/16.9-crypto-digest-stream")) {
+    return handleCryptoDigestStream(req);
+  }
+  if (path.startsWith("/16.10-crypto-timing-safe-equal")) {
+    return handleCryptoTimingSafeEqual(req);
+  }
+  if (path.startsWith("/16.11-crypto-key-clone")) {
+    return handleCryptoKeyClone(req);
+  }
   if (path.startsWith("/17-cache")) {
     return handleCache(req);
   }
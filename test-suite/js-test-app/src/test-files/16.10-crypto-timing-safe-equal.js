@@ -0,0 +1,93 @@
+import {
+  assert_equals,
+  assert_false,
+  assert_true,
+  assert_unreached,
+  promise_test,
+} from "../test-utils";
+
+const encoder = new TextEncoder();
+
+function assert_throws_type_error(f, message) {
+  try {
+    f();
+  } catch (e) {
+    assert_true(e instanceof TypeError, `${message}: expected a TypeError but got ${e}`);
+    return;
+  }
+  assert_unreached(`Should have thrown error: ${message}`);
+}
+
+async function handleRequest(request) {
+  try {
+    const subtle = crypto.subtle;
+
+    await promise_test(async () => {
+      assert_equals(typeof subtle.timingSafeEqual, "function", "timingSafeEqual is a function");
+
+      const a = encoder.encode("WinterJS");
+      assert_true(subtle.timingSafeEqual(a, encoder.encode("WinterJS")), "equal");
+      assert_false(subtle.timingSafeEqual(a, encoder.encode("WinterJs")), "last byte differs");
+      assert_false(subtle.timingSafeEqual(a, encoder.encode("winterJS")), "first byte differs");
+      assert_true(subtle.timingSafeEqual(new Uint8Array(), new Uint8Array()), "empty buffers");
+    }, "timingSafeEqual compares bytes");
+
+    await promise_test(async () => {
+      const bytes = encoder.encode("__WinterJS__");
+      const view = new Uint8Array(bytes.buffer, 2, 8);
+      const expected = encoder.encode("WinterJS");
+
+      assert_true(subtle.timingSafeEqual(view, expected), "view with an offset");
+      assert_true(subtle.timingSafeEqual(expected.buffer, view), "ArrayBuffer and view");
+      assert_true(
+        subtle.timingSafeEqual(new DataView(bytes.buffer, 2, 8), expected),
+        "DataView with an offset"
+      );
+      const words = new Uint32Array([0x01020304]);
+      assert_true(
+        subtle.timingSafeEqual(words, new Uint8Array(words.buffer.slice(0))),
+        "views of different element types"
+      );
+    }, "timingSafeEqual with buffer sources");
+
+    await promise_test(async () => {
+      assert_throws_type_error(
+        () => subtle.timingSafeEqual(encoder.encode("WinterJS"), encoder.encode("Winter")),
+        "different lengths"
+      );
+      assert_throws_type_error(
+        () => subtle.timingSafeEqual(new Uint8Array(1), new Uint8Array()),
+        "one empty buffer"
+      );
+      assert_throws_type_error(() => subtle.timingSafeEqual("WinterJS", "WinterJS"), "strings");
+    }, "timingSafeEqual rejects invalid inputs");
+
+    await promise_test(async () => {
+      const key = await subtle.importKey(
+        "raw",
+        encoder.encode("secret"),
+        { name: "HMAC", hash: "SHA-256" },
+        false,
+        ["sign", "verify"]
+      );
+      const message = encoder.encode("WinterJS");
+      const signature = new Uint8Array(await subtle.sign("HMAC", key, message));
+
+      assert_true(await subtle.verify("HMAC", key, signature, message), "valid signature");
+      assert_false(
+        await subtle.verify("HMAC", key, signature.slice(0, 31), message),
+        "truncated signature"
+      );
+      const extended = new Uint8Array(33);
+      extended.set(signature);
+      assert_false(await subtle.verify("HMAC", key, extended, message), "extended signature");
+      assert_false(await subtle.verify("HMAC", key, new Uint8Array(), message), "empty signature");
+    }, "HMAC verify with signatures of the wrong length");
+
+    return new Response("All tests passed!");
+  } catch (e) {
+    return new Response(e.toString(), { status: 500 });
+  }
+}
+
+export { handleRequest };
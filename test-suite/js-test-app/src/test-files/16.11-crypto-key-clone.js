@@ -0,0 +1,115 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  assert_not_equals,
+  assert_throws_js,
+  assert_true,
+  promise_test,
+} from "../test-utils";
+
+const encoder = new TextEncoder();
+
+function assert_same_key(clone, key, message) {
+  assert_true(clone instanceof CryptoKey, `${message}: clone is a CryptoKey`);
+  assert_not_equals(clone, key, `${message}: clone is a new object`);
+  assert_equals(clone.type, key.type, `${message}: type`);
+  assert_equals(clone.extractable, key.extractable, `${message}: extractable`);
+  assert_array_equals(clone.usages, key.usages, `${message}: usages`);
+  assert_equals(
+    JSON.stringify(clone.algorithm),
+    JSON.stringify(key.algorithm),
+    `${message}: algorithm`
+  );
+}
+
+async function handleRequest(request) {
+  try {
+    const subtle = crypto.subtle;
+    const data = encoder.encode("WinterJS");
+
+    await promise_test(async () => {
+      assert_equals(typeof structuredClone, "function", "structuredClone is a function");
+
+      const aes = await subtle.generateKey({ name: "AES-GCM", length: 256 }, true, [
+        "encrypt",
+        "decrypt",
+      ]);
+      const aesClone = structuredClone(aes);
+      assert_same_key(aesClone, aes, "AES-GCM");
+      assert_equals(aesClone.algorithm.length, 256, "AES-GCM length");
+
+      const iv = new Uint8Array(12);
+      const ciphertext = await subtle.encrypt({ name: "AES-GCM", iv }, aes, data);
+      const plaintext = await subtle.decrypt({ name: "AES-GCM", iv }, aesClone, ciphertext);
+      assert_array_equals(new Uint8Array(plaintext), data, "clone decrypts");
+
+      const hmac = await subtle.importKey(
+        "raw",
+        encoder.encode("secret"),
+        { name: "HMAC", hash: "SHA-256" },
+        false,
+        ["sign", "verify"]
+      );
+      const hmacClone = structuredClone(hmac);
+      assert_same_key(hmacClone, hmac, "HMAC");
+      assert_equals(hmacClone.algorithm.hash.name, "SHA-256", "HMAC hash");
+      const signature = await subtle.sign("HMAC", hmac, data);
+      assert_true(await subtle.verify("HMAC", hmacClone, signature, data), "clone verifies");
+    }, "structuredClone copies secret keys");
+
+    await promise_test(async () => {
+      const rsa = await subtle.generateKey(
+        {
+          name: "RSASSA-PKCS1-v1_5",
+          modulusLength: 1024,
+          publicExponent: new Uint8Array([1, 0, 1]),
+          hash: "SHA-256",
+        },
+        false,
+        ["sign", "verify"]
+      );
+      const rsaClone = structuredClone(rsa);
+      assert_same_key(rsaClone.privateKey, rsa.privateKey, "RSA private key");
+      assert_same_key(rsaClone.publicKey, rsa.publicKey, "RSA public key");
+      assert_equals(rsaClone.publicKey.algorithm.modulusLength, 1024, "RSA modulus length");
+      assert_array_equals(
+        rsaClone.publicKey.algorithm.publicExponent,
+        new Uint8Array([1, 0, 1]),
+        "RSA public exponent"
+      );
+      const rsaSignature = await subtle.sign("RSASSA-PKCS1-v1_5", rsaClone.privateKey, data);
+      assert_true(
+        await subtle.verify("RSASSA-PKCS1-v1_5", rsa.publicKey, rsaSignature, data),
+        "RSA clone signs"
+      );
+
+      const ec = await subtle.generateKey({ name: "ECDSA", namedCurve: "P-256" }, true, [
+        "sign",
+        "verify",
+      ]);
+      const [privateKey, publicKey] = structuredClone([ec.privateKey, ec.publicKey]);
+      assert_same_key(privateKey, ec.privateKey, "EC private key");
+      assert_same_key(publicKey, ec.publicKey, "EC public key");
+      assert_equals(publicKey.algorithm.namedCurve, "P-256", "EC named curve");
+      const params = { name: "ECDSA", hash: "SHA-256" };
+      const ecSignature = await subtle.sign(params, ec.privateKey, data);
+      assert_true(await subtle.verify(params, publicKey, ecSignature, data), "EC clone verifies");
+    }, "structuredClone copies key pairs");
+
+    await promise_test(async () => {
+      const key = await subtle.generateKey({ name: "AES-CBC", length: 128 }, true, ["encrypt"]);
+      const clone = structuredClone({ keys: [key, key], name: "key" });
+      assert_equals(clone.name, "key", "other properties");
+      assert_same_key(clone.keys[0], key, "nested key");
+      assert_same_key(clone.keys[1], key, "second nested key");
+
+      assert_throws_js(() => structuredClone({ key, f: () => {} }), "functions can't be cloned");
+    }, "structuredClone copies keys nested in other values");
+
+    return new Response("All tests passed!");
+  } catch (e) {
+    return new Response(e.toString(), { status: 500 });
+  }
+}
+
+export { handleRequest };
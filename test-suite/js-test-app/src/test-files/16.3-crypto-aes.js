@@ -0,0 +1,266 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  assert_true,
+  concat,
+  hex,
+  promise_rejects_js,
+  promise_test,
+} from "../test-utils";
+
+// Test case 4 from the GCM specification
+const gcm = {
+  key: hex("feffe9928665731c6d6a8f9467308308"),
+  iv: hex("cafebabefacedbaddecaf888"),
+  additionalData: hex("feedfacedeadbeeffeedfacedeadbeefabaddad2"),
+  plaintext: hex(
+    "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72" +
+      "1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39"
+  ),
+  ciphertext: hex(
+    "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e" +
+      "21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091"
+  ),
+  tag: hex("5bc94fbc3221a5db94fae95ae7121a47"),
+};
+
+// Test cases 5 and 6 from the GCM specification, which encrypt the same
+// message with a 64-bit and a 480-bit IV
+const gcmIvLengths = [
+  {
+    iv: hex("cafebabefacedbad"),
+    ciphertext: hex(
+      "61353b4c2806934a777ff51fa22a4755699b2a714fcdc6f83766e5f97b6c7423" +
+        "73806900e49f24b22b097544d4896b424989b5e1ebac0f07c23f4598"
+    ),
+    tag: hex("3612d2e79e3b0785561be14aaca2fccb"),
+  },
+  {
+    iv: hex(
+      "9313225df88406e555909c5aff5269aa6a7a9538534f7da1e4c303d2a318a728" +
+        "c3c0c95156809539fcf0e2429a6b525416aedbf5a0de6a57a637b39b"
+    ),
+    ciphertext: hex(
+      "8ce24998625615b603a033aca13fb894be9112a5c3a211a8ba262a3cca7e2ca7" +
+        "01e4a9a4fba43c90ccdcb281d48c7c6fd62875d2aca417034c34aee5"
+    ),
+    tag: hex("619cc5aefffe0bfa462af43c1699d050"),
+  },
+];
+
+// F.2.1 and F.5.1 from NIST SP 800-38A
+const sp800_38a = {
+  key: hex("2b7e151628aed2a6abf7158809cf4f3c"),
+  plaintext: hex(
+    "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51" +
+      "30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710"
+  ),
+  cbcIv: hex("000102030405060708090a0b0c0d0e0f"),
+  // The last block is the PKCS#7 padding, which the Web Crypto API adds
+  cbcCiphertext: hex(
+    "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b2" +
+      "73bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7" +
+      "8cb82807230e1321d3fae00d18cc2012"
+  ),
+  ctrCounter: hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff"),
+  ctrCiphertext: hex(
+    "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff" +
+      "5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee"
+  ),
+};
+
+async function handleRequest(request) {
+  try {
+    const subtle = crypto.subtle;
+
+    await promise_test(async () => {
+      const key = await subtle.importKey("raw", gcm.key, "AES-GCM", false, ["encrypt", "decrypt"]);
+      const params = { name: "AES-GCM", iv: gcm.iv, additionalData: gcm.additionalData };
+
+      const encrypted = await subtle.encrypt(params, key, gcm.plaintext);
+      assert_array_equals(new Uint8Array(encrypted), concat(gcm.ciphertext, gcm.tag), "ciphertext");
+
+      const decrypted = await subtle.decrypt(params, key, encrypted);
+      assert_array_equals(new Uint8Array(decrypted), gcm.plaintext, "plaintext");
+    }, "AES-GCM known answer");
+
+    await promise_test(async () => {
+      const key = await subtle.importKey("raw", gcm.key, "AES-GCM", false, ["encrypt"]);
+      const params = {
+        name: "AES-GCM",
+        iv: gcm.iv,
+        additionalData: gcm.additionalData,
+        tagLength: 96,
+      };
+
+      const encrypted = await subtle.encrypt(params, key, gcm.plaintext);
+      assert_array_equals(
+        new Uint8Array(encrypted),
+        concat(gcm.ciphertext, gcm.tag.slice(0, 12)),
+        "truncated tag"
+      );
+    }, "AES-GCM with a 96-bit tag");
+
+    await promise_test(async () => {
+      const key = await subtle.importKey("raw", gcm.key, "AES-GCM", false, ["encrypt", "decrypt"]);
+      for (const { iv, ciphertext, tag } of gcmIvLengths) {
+        const params = { name: "AES-GCM", iv, additionalData: gcm.additionalData };
+        const encrypted = await subtle.encrypt(params, key, gcm.plaintext);
+        assert_array_equals(
+          new Uint8Array(encrypted),
+          concat(ciphertext, tag),
+          `ciphertext with a ${iv.length * 8}-bit IV`
+        );
+
+        const decrypted = await subtle.decrypt(params, key, encrypted);
+        assert_array_equals(
+          new Uint8Array(decrypted),
+          gcm.plaintext,
+          `plaintext with a ${iv.length * 8}-bit IV`
+        );
+      }
+    }, "AES-GCM with IVs other than 96 bits");
+
+    await promise_test(async () => {
+      const key = await subtle.importKey("raw", gcm.key, "AES-GCM", false, ["encrypt", "decrypt"]);
+      for (const tagLength of [32, 64]) {
+        const params = {
+          name: "AES-GCM",
+          iv: gcm.iv,
+          additionalData: gcm.additionalData,
+          tagLength,
+        };
+        const encrypted = await subtle.encrypt(params, key, gcm.plaintext);
+        assert_array_equals(
+          new Uint8Array(encrypted),
+          concat(gcm.ciphertext, gcm.tag.slice(0, tagLength / 8)),
+          `${tagLength}-bit tag`
+        );
+
+        const decrypted = await subtle.decrypt(params, key, encrypted);
+        assert_array_equals(new Uint8Array(decrypted), gcm.plaintext, `plaintext, ${tagLength}-bit tag`);
+
+        const tampered = new Uint8Array(encrypted);
+        tampered[tampered.length - 1] ^= 1;
+        await promise_rejects_js(subtle.decrypt(params, key, tampered), `tampered ${tagLength}-bit tag`);
+      }
+
+      for (const tagLength of [0, 40, 136]) {
+        await promise_rejects_js(
+          subtle.encrypt({ name: "AES-GCM", iv: gcm.iv, tagLength }, key, gcm.plaintext),
+          `tagLength ${tagLength}`
+        );
+      }
+    }, "AES-GCM with 32 and 64-bit tags");
+
+    await promise_test(async () => {
+      const key = await subtle.importKey("raw", gcm.key, "AES-GCM", false, ["decrypt"]);
+      const tampered = concat(gcm.ciphertext, gcm.tag);
+      tampered[0] ^= 1;
+
+      await promise_rejects_js(
+        subtle.decrypt({ name: "AES-GCM", iv: gcm.iv, additionalData: gcm.additionalData }, key, tampered),
+        "tampered ciphertext"
+      );
+      await promise_rejects_js(
+        subtle.decrypt({ name: "AES-GCM", iv: gcm.iv }, key, concat(gcm.ciphertext, gcm.tag)),
+        "missing additional data"
+      );
+    }, "AES-GCM rejects modified messages");
+
+    await promise_test(async () => {
+      const key = await subtle.importKey("raw", sp800_38a.key, "AES-CBC", false, ["encrypt", "decrypt"]);
+      const params = { name: "AES-CBC", iv: sp800_38a.cbcIv };
+
+      const encrypted = await subtle.encrypt(params, key, sp800_38a.plaintext);
+      assert_array_equals(new Uint8Array(encrypted), sp800_38a.cbcCiphertext, "ciphertext");
+
+      const decrypted = await subtle.decrypt(params, key, encrypted);
+      assert_array_equals(new Uint8Array(decrypted), sp800_38a.plaintext, "plaintext");
+    }, "AES-CBC known answer");
+
+    await promise_test(async () => {
+      const key = await subtle.importKey("raw", sp800_38a.key, "AES-CTR", false, ["encrypt", "decrypt"]);
+      const params = { name: "AES-CTR", counter: sp800_38a.ctrCounter, length: 64 };
+
+      const encrypted = await subtle.encrypt(params, key, sp800_38a.plaintext);
+      assert_array_equals(new Uint8Array(encrypted), sp800_38a.ctrCiphertext, "ciphertext");
+
+      const decrypted = await subtle.decrypt(params, key, encrypted);
+      assert_array_equals(new Uint8Array(decrypted), sp800_38a.plaintext, "plaintext");
+    }, "AES-CTR known answer");
+
+    await promise_test(async () => {
+      const key = await subtle.importKey("raw", sp800_38a.key, "AES-CTR", false, ["encrypt"]);
+      const data = new Uint8Array(32);
+
+      // With an 8-bit counter, the block after ...ff uses ...00 and leaves
+      // the rest of the counter block alone
+      const wrapped = await subtle.encrypt(
+        { name: "AES-CTR", counter: sp800_38a.ctrCounter, length: 8 },
+        key,
+        data
+      );
+      const restarted = sp800_38a.ctrCounter.slice();
+      restarted[15] = 0;
+      const expected = await subtle.encrypt(
+        { name: "AES-CTR", counter: restarted, length: 8 },
+        key,
+        data.slice(16)
+      );
+      assert_array_equals(new Uint8Array(wrapped).slice(16), new Uint8Array(expected), "second block");
+
+      await promise_rejects_js(
+        subtle.encrypt(
+          { name: "AES-CTR", counter: sp800_38a.ctrCounter, length: 1 },
+          key,
+          new Uint8Array(48)
+        ),
+        "counter reuse"
+      );
+    }, "AES-CTR counter length");
+
+    for (const name of ["AES-GCM", "AES-CBC", "AES-CTR"]) {
+      await promise_test(async () => {
+        const key = await subtle.importKey("raw", gcm.key, name, true, ["encrypt", "decrypt"]);
+        const jwk = await subtle.exportKey("jwk", key);
+        assert_equals(jwk.kty, "oct", "kty");
+        assert_equals(jwk.k, "_v_pkoZlcxxtao-UZzCDCA", "k is base64url without padding");
+        assert_equals(jwk.alg, "A128" + name.slice(4), "alg");
+        assert_true(jwk.ext, "ext");
+        assert_array_equals(jwk.key_ops, ["encrypt", "decrypt"], "key_ops");
+
+        const imported = await subtle.importKey("jwk", jwk, name, true, ["encrypt", "decrypt"]);
+        assert_array_equals(
+          new Uint8Array(await subtle.exportKey("raw", imported)),
+          gcm.key,
+          "raw key"
+        );
+
+        await promise_rejects_js(
+          subtle.importKey("jwk", { ...jwk, alg: "A256" + name.slice(4) }, name, true, ["encrypt"]),
+          "mismatched alg"
+        );
+      }, name + " JWK round trip");
+    }
+
+    for (const length of [128, 192, 256]) {
+      await promise_test(async () => {
+        const key = await subtle.generateKey({ name: "AES-GCM", length }, true, ["encrypt"]);
+        assert_equals(key.algorithm.length, length, "algorithm.length");
+        assert_equals((await subtle.exportKey("raw", key)).byteLength, length / 8, "raw key length");
+      }, "AES-GCM generateKey with length " + length);
+    }
+
+    await promise_rejects_js(
+      subtle.importKey("raw", new Uint8Array(20), "AES-GCM", false, ["encrypt"]),
+      "invalid key length"
+    );
+
+    return new Response("All tests passed!");
+  } catch (e) {
+    return new Response(e.toString(), { status: 500 });
+  }
+}
+
+export { handleRequest };
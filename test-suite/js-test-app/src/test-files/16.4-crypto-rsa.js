@@ -0,0 +1,243 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  assert_false,
+  assert_true,
+  promise_rejects_js,
+  promise_test,
+} from "../test-utils";
+
+function base64(str) {
+  return Uint8Array.from(atob(str.replace(/\s/g, "")), (c) => c.charCodeAt(0));
+}
+
+// A 2048-bit key, with signatures and ciphertexts of `message` made by
+// another implementation. PSS signatures use a 32-byte salt and OAEP uses
+// SHA-256, with and without the label "label".
+const message = new TextEncoder().encode("WinterJS RSA test message");
+
+const pkcs8 = base64(`
+  MIIEvAIBADANBgkqhkiG9w0BAQEFAASCBKYwggSiAgEAAoIBAQCx5UJR4jznXN3X
+  skgOcwyjzaQqH09XjxUUc01YlkTTWK+FMDheIo9wdvgljMC1/YBBpCHN97tHKm2V
+  76QuyVavOYwmtHlmE2rtqQHaRmgPRuy0u/H1SjVk9R7lZR076u+EUQ4nrG1TrOoV
+  lx4nkJGB/M5WYGoOHvdFIaNS2+XUoH+74iNYSwobamBzvx4jqO/yv/JjrOX+IYAp
+  ON7VkDUauDFKLPksl76/JOTu6RZClsg0ow1yXSXO1d9uxCiyb8n9KXdvCjvDvk7C
+  UmwckZeLIzXEsFKut2Q5d1WVLxZpxsgQOfh1zInAu0x0wcDk6C1Fo0/EWzfrrEeT
+  vtH9KbfXAgMBAAECggEAAMARk++hh7neinOLPKeQXOIX8fPvDVSvJ0aG0JU7Qq26
+  Ri3te7taYeqk7uBosUIzPkbUIng/AMFvDa87DaKpWO49mkdi+hYIep24QkfPViRb
+  omkaHImGYoY3ur2LoZ5C1r4FknYQGy0JhIAxdZB1Uwtr6CiNifQbet312fppCu9S
+  Jy6SQDZWc0x0WqWM0E8gQNhv4pY7sqyltSbr0J9DJdmuhDRnYHr9UdgrZhs1Sz8J
+  M4sRbB5I7LSlRcFFKStW/RhXnNcdy3uYQN/EzOyFJgkN6PCq19r8txX8F9pNCF0H
+  oZcah4YoVp0/s7OFGdA5dNfjMDfzn1BIRhTG6qMcIQKBgQDbobwmnSbTP3bxLrVJ
+  mZh7Wft94CQphB1+Nu3hNcj+gK8K2Efh8WEwsH8wI8RoPpmIrziPUdXw1M5UhyjM
+  LGCew9VJRoh/9NH2/EkaYqS6+U/0Y2rU/F5cqSxOLjpWYLKu9spoPyiEqpyi2pcD
+  F2hlDyXfpQT+B51/MjwKfcrsSQKBgQDPWk+iuF+eMFKZOaRy3RtrXHsqNf6noVZF
+  MFI+4XdnNxdCVf9pZjg40OFQI37kDg6d07UtAU8KMf/IZ0oengwPCQum8zlFz9vq
+  az+12sk6huS38ByRlrgVkvqnmVizMcXuqT3O+9bnGpPPn1VYKx0rn5KGn5PfbxM9
+  fFLN98BDHwKBgDdwoQziOG20pHKHBs34Tow8j4YbsSUlq0gz12I08HKFyymipKp9
+  ArqGouRk8uwZeSoOiOlsj+OF5DtkDsFTJmSNLGadvlEGXIXK8y0M32YvDqay/cGb
+  nronHf7yHlm1eQU/WKJt5u1uH7HgGaR3ISv5YecNQbBZ/cCgzpUbYcC5AoGAGBfX
+  TwH7YkcdId63w/z7FaN+UC5L87BkdM+jjtjl5eFZ24RglB+jgr6Kn6QwN1GG00Hp
+  OFyBkZ6zqNaPnzUgE5MqfnCehAUd+YQSuA+++AT6iIDm4OfRZfhZFaZUzyWGaS/T
+  FbzfgKTu7d4GraL81bfJ78iVBw5B/w/2EyapeWMCgYBQzp54JFr8DdLokRkxwLYw
+  6QUR/JEJbCVmEXcn4TrMhKvHBXGrsr0QwdLE9aQhvXAt2aNRdzsuvW9rKQgFL51M
+  LtA7NghnDK6FnTzP/eiwacxfnnRPY2swQntrVSZkunNW7zw2NK9HnBwT8m82KbGB
+  GmjgIRcR189PK1VELSzmNw==
+`);
+
+const spki = base64(`
+  MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAseVCUeI851zd17JIDnMM
+  o82kKh9PV48VFHNNWJZE01ivhTA4XiKPcHb4JYzAtf2AQaQhzfe7Ryptle+kLslW
+  rzmMJrR5ZhNq7akB2kZoD0bstLvx9Uo1ZPUe5WUdO+rvhFEOJ6xtU6zqFZceJ5CR
+  gfzOVmBqDh73RSGjUtvl1KB/u+IjWEsKG2pgc78eI6jv8r/yY6zl/iGAKTje1ZA1
+  GrgxSiz5LJe+vyTk7ukWQpbINKMNcl0lztXfbsQosm/J/Sl3bwo7w75OwlJsHJGX
+  iyM1xLBSrrdkOXdVlS8WacbIEDn4dcyJwLtMdMHA5OgtRaNPxFs366xHk77R/Sm3
+  1wIDAQAB
+`);
+
+const pkcs1Signature = base64(`
+  dSTzuQ+VqED0Yk09/GtbXt/NZB0Tf+6PjS2GBzU7rpYhsi6YEzkZVfnZk8+OSwmm
+  qRIhTtS9n6UxkD2OorUlGudo3P0Cy7ZNI1Cg/2l/q2Mt2iwuuPSWoWdXKbvY/733
+  Ib2i/Nit/1dAm8/OA4wcz3F1mkd31wRElH5QpzMHdCFOifjLWkVELJGRO3nd6L0M
+  qTJhhqPrqZqeBOEbsVEwsyVCM2ojNWh7mgmG73QIgqwTCIwgNYyjwWk/FzGbN7Rb
+  DXgS3A2QwGv3iSPw9xxsZR2YRw1lsGSYbpBq5BRJ2ta4tLAZxhp4WFTDsBj5H3BM
+  XHqCDCx2ds9+7ouDDUBJVA==
+`);
+
+const pssSignature = base64(`
+  i/UX9bzGeNQqBEqfSYyhsal1N56RuFFh8uaXlMd1nn4hWIwR3dDxWuorsBDQZZgU
+  OqD2Na0EI/7uXmA/mG2/wvp0VG39ujggvv7Mn0eBjRii+cV2ow1B2U1CXBvfEOPg
+  2ObcELpBWobKErFGzrnleK45nV4U7hFM8fCk2lGdJ3UsfGyTxGuUHZeHJrBu/NHl
+  d02hsCN/+TkpCeHTyRlRFWp1Nm1L/DGrSFI8s8aAnG3XLARRWQRvycnLoywGDFRP
+  VHDN+9eik4dTGc55fzJBWnvtOnRyAbUt/8lUmdkmy9NhmExW0bvEtS7eA61NAOpW
+  JdIT+3ciL5Ty2xLSenHxmQ==
+`);
+
+const oaepCiphertext = base64(`
+  DximdHm1t9M+JoNpoBUdwARDhfT+j2cob+FH3413N7FcP4lWmJuwB6Hgf2txL7OY
+  aMel5eOb9HugBkNcPZZFZcbeFqBcYTg7YVwq4cgFc6Y6ipyA03+HxfuEuHuJ77wm
+  2zsaAbg+8SE2gCv/WluDMy4GHI/QHN09AdTAhchZGzWXF5vC+h8OkBJQGHM0eYz5
+  HjW8MTP9yJ4/5S5e90bTJUjEDz4n3bngleSotQyy5WCVkn1Op4hNhnnxnTK0I4Zh
+  Ao2CBL2tkOS8CiEo2MhzlRtEJCwG1ieeYrtF3y0HIhoVTSxcQaIkCD9nNr0Ouxwu
+  iwXye37pmWQDxsOK7dYIbw==
+`);
+
+const oaepLabelCiphertext = base64(`
+  nz63xH4yrufZ0tPbcPKCl4nctg3YXZXC7ZbwRQfsLJbJy7UhWEUrAimq3foJYofu
+  1ttSe/VR9f7vXF5g7sooDSsKx+7QvWzq84LnfTdyZlV3DXmXgXzRBeBaskpb38qw
+  fxLQV2qcTuBSTUG7sIHRgqluBbNN+o2yaxMgO+mHOeU3BpFMLKBXgt8NFNRrKb2h
+  GoKRNeQ6/kYF5ly+5zJfTh5e8He0PBLoDFS812TTYReJtweJOK+U6qhZr7w6HS/q
+  nALMdhJnwfra6HDyTrfKM2qaDKqSVLqZ00Mkwl5l+41U9RcUjtwUcguYOpi9E1Gj
+  7TH4XA0r08hl5THKKBquCQ==
+`);
+
+const sha256 = { name: "SHA-256" };
+
+async function handleRequest(request) {
+  try {
+    const subtle = crypto.subtle;
+
+    await promise_test(async () => {
+      const params = { name: "RSASSA-PKCS1-v1_5", hash: "SHA-256" };
+      const privateKey = await subtle.importKey("pkcs8", pkcs8, params, true, ["sign"]);
+      const publicKey = await subtle.importKey("spki", spki, params, true, ["verify"]);
+
+      // PKCS#1 v1.5 signatures are deterministic
+      const signature = await subtle.sign(params, privateKey, message);
+      assert_array_equals(new Uint8Array(signature), pkcs1Signature, "signature");
+
+      assert_true(await subtle.verify(params, publicKey, pkcs1Signature, message), "valid signature");
+      assert_false(
+        await subtle.verify(params, publicKey, pkcs1Signature, new Uint8Array(message.length)),
+        "other message"
+      );
+    }, "RSASSA-PKCS1-v1_5 known answer");
+
+    await promise_test(async () => {
+      const importParams = { name: "RSA-PSS", hash: sha256 };
+      const privateKey = await subtle.importKey("pkcs8", pkcs8, importParams, false, ["sign"]);
+      const publicKey = await subtle.importKey("spki", spki, importParams, false, ["verify"]);
+      const params = { name: "RSA-PSS", saltLength: 32 };
+
+      assert_true(await subtle.verify(params, publicKey, pssSignature, message), "known signature");
+      assert_false(
+        await subtle.verify({ name: "RSA-PSS", saltLength: 20 }, publicKey, pssSignature, message),
+        "wrong salt length"
+      );
+
+      const signature = await subtle.sign(params, privateKey, message);
+      assert_true(await subtle.verify(params, publicKey, signature, message), "new signature");
+    }, "RSA-PSS known answer");
+
+    await promise_test(async () => {
+      const importParams = { name: "RSA-OAEP", hash: "SHA-256" };
+      const privateKey = await subtle.importKey("pkcs8", pkcs8, importParams, false, ["decrypt"]);
+      const publicKey = await subtle.importKey("spki", spki, importParams, false, ["encrypt"]);
+
+      const decrypted = await subtle.decrypt({ name: "RSA-OAEP" }, privateKey, oaepCiphertext);
+      assert_array_equals(new Uint8Array(decrypted), message, "without a label");
+
+      const label = new TextEncoder().encode("label");
+      const decryptedWithLabel = await subtle.decrypt(
+        { name: "RSA-OAEP", label },
+        privateKey,
+        oaepLabelCiphertext
+      );
+      assert_array_equals(new Uint8Array(decryptedWithLabel), message, "with a label");
+      await promise_rejects_js(
+        subtle.decrypt({ name: "RSA-OAEP" }, privateKey, oaepLabelCiphertext),
+        "missing label"
+      );
+
+      const encrypted = await subtle.encrypt({ name: "RSA-OAEP" }, publicKey, message);
+      assert_array_equals(
+        new Uint8Array(await subtle.decrypt({ name: "RSA-OAEP" }, privateKey, encrypted)),
+        message,
+        "round trip"
+      );
+    }, "RSA-OAEP known answer");
+
+    await promise_test(async () => {
+      const params = { name: "RSASSA-PKCS1-v1_5", hash: "SHA-256" };
+      const privateKey = await subtle.importKey("pkcs8", pkcs8, params, true, ["sign"]);
+      const publicKey = await subtle.importKey("spki", spki, params, true, ["verify"]);
+
+      assert_equals(privateKey.algorithm.name, "RSASSA-PKCS1-v1_5", "algorithm.name");
+      assert_equals(privateKey.algorithm.modulusLength, 2048, "algorithm.modulusLength");
+      assert_array_equals(privateKey.algorithm.publicExponent, [1, 0, 1], "algorithm.publicExponent");
+      assert_equals(privateKey.algorithm.hash.name, "SHA-256", "algorithm.hash");
+
+      assert_array_equals(new Uint8Array(await subtle.exportKey("spki", publicKey)), spki, "SPKI");
+      assert_array_equals(new Uint8Array(await subtle.exportKey("pkcs8", privateKey)), pkcs8, "PKCS#8");
+
+      const privateJwk = await subtle.exportKey("jwk", privateKey);
+      const publicJwk = await subtle.exportKey("jwk", publicKey);
+      assert_equals(privateJwk.kty, "RSA", "kty");
+      assert_equals(privateJwk.alg, "RS256", "alg");
+      assert_equals(publicJwk.n, privateJwk.n, "n");
+      assert_equals(publicJwk.e, "AQAB", "e");
+      assert_equals(publicJwk.d, undefined, "public keys have no d");
+      for (const member of ["n", "e", "d", "p", "q", "dp", "dq", "qi"]) {
+        assert_true(/^[A-Za-z0-9_-]+$/.test(privateJwk[member]), member + " is base64url");
+      }
+
+      const reimported = await subtle.importKey("jwk", privateJwk, params, true, ["sign"]);
+      assert_array_equals(
+        new Uint8Array(await subtle.exportKey("pkcs8", reimported)),
+        pkcs8,
+        "PKCS#8 after a JWK round trip"
+      );
+
+      const publicFromJwk = await subtle.importKey(
+        "jwk",
+        { kty: "RSA", n: publicJwk.n, e: publicJwk.e },
+        params,
+        true,
+        ["verify"]
+      );
+      assert_true(await subtle.verify(params, publicFromJwk, pkcs1Signature, message), "verify");
+
+      await promise_rejects_js(
+        subtle.importKey("jwk", { ...privateJwk, alg: "PS256" }, params, true, ["sign"]),
+        "mismatched alg"
+      );
+      await promise_rejects_js(
+        subtle.importKey("jwk", { ...publicJwk, use: "enc" }, params, true, ["verify"]),
+        "mismatched use"
+      );
+      await promise_rejects_js(
+        subtle.importKey("spki", spki, params, true, ["sign"]),
+        "public keys can't sign"
+      );
+    }, "RSA key import and export");
+
+    await promise_test(async () => {
+      const params = {
+        name: "RSA-PSS",
+        modulusLength: 1024,
+        publicExponent: new Uint8Array([1, 0, 1]),
+        hash: "SHA-384",
+      };
+      const { publicKey, privateKey } = await subtle.generateKey(params, false, ["sign", "verify"]);
+      assert_equals(publicKey.type, "public", "public key type");
+      assert_true(publicKey.extractable, "public keys are extractable");
+      assert_array_equals(publicKey.usages, ["verify"], "public key usages");
+      assert_equals(privateKey.type, "private", "private key type");
+      assert_false(privateKey.extractable, "private key extractable");
+      assert_array_equals(privateKey.usages, ["sign"], "private key usages");
+      assert_equals(privateKey.algorithm.hash.name, "SHA-384", "algorithm.hash");
+
+      const signature = await subtle.sign({ name: "RSA-PSS", saltLength: 48 }, privateKey, message);
+      assert_true(
+        await subtle.verify({ name: "RSA-PSS", saltLength: 48 }, publicKey, signature, message),
+        "verify"
+      );
+    }, "RSA-PSS generateKey");
+
+    return new Response("All tests passed!");
+  } catch (e) {
+    return new Response(e.toString(), { status: 500 });
+  }
+}
+
+export { handleRequest };
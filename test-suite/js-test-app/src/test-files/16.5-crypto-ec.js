@@ -0,0 +1,210 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  assert_false,
+  assert_true,
+  promise_rejects_js,
+  promise_test,
+} from "../test-utils";
+
+function base64(str) {
+  return Uint8Array.from(atob(str), (c) => c.charCodeAt(0));
+}
+
+const message = new TextEncoder().encode("WinterJS ECDSA test message");
+
+// For each curve, a key pair with an ECDSA signature of `message` in IEEE
+// P1363 format, and the ECDH secret it shares with a second key pair, all
+// made by another implementation
+const vectors = {
+  "P-256": {
+    hash: "SHA-256",
+    pkcs8: base64(
+      "MIGHAgEAMBMGByqGSM49AgEGCCqGSM49AwEHBG0wawIBAQQgN9eqaM+RwKLCy3nT" +
+      "SNzlm1OG5LmF4RF/Strg8Ere+TChRANCAAROPBRk3dZ3jqC3WaD59S6y2sxZdh5o" +
+      "6s9TKJwWh6slrncjcBQeK0ckU5sNStI5Z+z0dsASz3udwRZHio6cR5T3"
+    ),
+    spki: base64(
+      "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAETjwUZN3Wd46gt1mg+fUustrMWXYe" +
+      "aOrPUyicFoerJa53I3AUHitHJFObDUrSOWfs9HbAEs97ncEWR4qOnEeU9w=="
+    ),
+    signature: base64(
+      "2m4sgI0PW2lr4fES+AvxYB9nAWj+uVdSYqqUl2OxsIiKzU7hKVxBQ2eV9+FNaW23" +
+      "6Ag3nvwi4ub4nZy5fhSC7A=="
+    ),
+    peerSpki: base64(
+      "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAER73oyFiBi26ikRGvldpujyjxCW4X" +
+      "wbTWpZi21KMPpnOvDgLf9pdEwabvsoA4ZjkjifJrwFQpyCpVgwsXr5egTA=="
+    ),
+    shared: base64(
+      "+293MpNgrsyUdbEbpLvSQe7cL8XVfd+GOpvw3pgRZ08="
+    ),
+  },
+  "P-384": {
+    hash: "SHA-384",
+    pkcs8: base64(
+      "MIG2AgEAMBAGByqGSM49AgEGBSuBBAAiBIGeMIGbAgEBBDD+OJNdi+FytMVb/AXj" +
+      "9ivPfTMEc1VOsIanXkcU+wRXCjgvf3f0Tbg1AD7pmQ8pESOhZANiAATea6LrqqaB" +
+      "kWVEf65YRlEVhtXq+Pc9k5301WEkfMVgpsVRj2tWv9so/KWDADPYDmztlvfyIAwf" +
+      "49K1hrQy8wBwsAvuQsLRGfs1n5k//ZPOl+hDXUqFoaDIP5douSp3tBQ="
+    ),
+    spki: base64(
+      "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAE3mui66qmgZFlRH+uWEZRFYbV6vj3PZOd" +
+      "9NVhJHzFYKbFUY9rVr/bKPylgwAz2A5s7Zb38iAMH+PStYa0MvMAcLAL7kLC0Rn7" +
+      "NZ+ZP/2TzpfoQ11KhaGgyD+XaLkqd7QU"
+    ),
+    signature: base64(
+      "zP3k2PbuoRAOdd9Dh+Uw8/4wz62fxVAun0qYUzCUyLSeIorUBbx3OjxW743lIdw+" +
+      "ozovrzBsae5KUqSRiHtY3pZjzZP6wyeNWKpw4YXkDawRG2fZEvOMxQmdo0jXnlzF"
+    ),
+    peerSpki: base64(
+      "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAEWmMCXF0axlt6I3wHqeKesQkpcvsrCyTV" +
+      "Z3KRPWnXgOmgko7KT1j4a2ajpOVxBdpseg2lwZIAidfTCaC1KxqpKTTYxg2FY/JD" +
+      "G8kOGg9rxSeAMpxPNkIYxj8ZWOZGygUx"
+    ),
+    shared: base64(
+      "bBtc5Ka6BiTrg4cGnZjqAhoUrmwaKwmNPuCAmHTl6Ra2dmcVA2XglvrjbCVbgXiU"
+    ),
+  },
+  "P-521": {
+    hash: "SHA-512",
+    pkcs8: base64(
+      "MIHuAgEAMBAGByqGSM49AgEGBSuBBAAjBIHWMIHTAgEBBEIBtbIKCEK2RXum+kZc" +
+      "WK1zDPecR+BrO6jkRKP5AzZ1ekb3TEtcKd7MBjYcS5MW+NizBKOGfDzmll3QxLQv" +
+      "jzbne3GhgYkDgYYABAAPpnWLIkdu6nA8bmggL/t7VfaPcQYU7WS/7ZhfE4T4qfxZ" +
+      "aVKbgvViVbRW4iWzHE3OEbmz22W+GQaFBV/mnX/+nQBckDSVHU/iReRMKR9s+y2d" +
+      "ta67WOLjRafRh75TOHbc34gtgI23nlLZgBmdyq2B1MHOndwAljpfwpSNrx8yTQz+" +
+      "pA=="
+    ),
+    spki: base64(
+      "MIGbMBAGByqGSM49AgEGBSuBBAAjA4GGAAQAD6Z1iyJHbupwPG5oIC/7e1X2j3EG" +
+      "FO1kv+2YXxOE+Kn8WWlSm4L1YlW0VuIlsxxNzhG5s9tlvhkGhQVf5p1//p0AXJA0" +
+      "lR1P4kXkTCkfbPstnbWuu1ji40Wn0Ye+Uzh23N+ILYCNt55S2YAZncqtgdTBzp3c" +
+      "AJY6X8KUja8fMk0M/qQ="
+    ),
+    signature: base64(
+      "Ad78Jn1iye02wveJzE3womEe75wKggur8RmLKm8Xkev/QQHSOvvyJnF+jQrbuKgR" +
+      "YAOOD7AQ4utSvyFdWWBSHn9iAesUZy/i5ZObtxDwkqZt+C8YzPg8KurKpCuAyyMw" +
+      "pZi+fqUTHdzyPr/CfCLkHKEIODEt3Bx/F97/Zsmh/IiXkR5y"
+    ),
+    peerSpki: base64(
+      "MIGbMBAGByqGSM49AgEGBSuBBAAjA4GGAAQA0m/dAxBhzL1tbCUEqDOmJWWwjVx8" +
+      "/70wywS8ASvF0XyOft5VyoT8qMbysrEs/OH4ulKUA+h8KttuAeu7mYKb++QAQI4f" +
+      "qe68q4jx6rTFwaPpGxDIV28R2FvsuwgXsGbi82l2Dce22+AEzAs8PVCkpd9j149X" +
+      "6M2hbK3xGpUEHrN4H+c="
+    ),
+    shared: base64(
+      "AL7SfDLSYCMi8sKlq+ULBIcOWhfSRkYSziQW9DfwPfGRAOMimYysvVnW+FozyedT" +
+      "R9xTZJZFy90BqfcPsP+C85kJ"
+    ),
+  },
+};
+
+async function handleRequest(request) {
+  try {
+    const subtle = crypto.subtle;
+
+    for (const [namedCurve, vector] of Object.entries(vectors)) {
+      const fieldSize = vector.shared.length;
+
+      await promise_test(async () => {
+        const algorithm = { name: "ECDSA", namedCurve };
+        const params = { name: "ECDSA", hash: vector.hash };
+        const privateKey = await subtle.importKey("pkcs8", vector.pkcs8, algorithm, true, ["sign"]);
+        const publicKey = await subtle.importKey("spki", vector.spki, algorithm, true, ["verify"]);
+        assert_equals(publicKey.algorithm.namedCurve, namedCurve, "algorithm.namedCurve");
+
+        assert_true(await subtle.verify(params, publicKey, vector.signature, message), "known signature");
+        const tampered = vector.signature.slice();
+        tampered[0] ^= 1;
+        assert_false(await subtle.verify(params, publicKey, tampered, message), "tampered signature");
+
+        const signature = await subtle.sign(params, privateKey, message);
+        assert_equals(signature.byteLength, fieldSize * 2, "signature length");
+        assert_true(await subtle.verify(params, publicKey, signature, message), "new signature");
+      }, "ECDSA on " + namedCurve);
+
+      await promise_test(async () => {
+        const algorithm = { name: "ECDH", namedCurve };
+        const privateKey = await subtle.importKey("pkcs8", vector.pkcs8, algorithm, false, ["deriveBits", "deriveKey"]);
+        const peerKey = await subtle.importKey("spki", vector.peerSpki, algorithm, false, []);
+        const params = { name: "ECDH", public: peerKey };
+
+        const bits = await subtle.deriveBits(params, privateKey, fieldSize * 8);
+        assert_array_equals(new Uint8Array(bits), vector.shared, "shared secret");
+
+        const truncated = await subtle.deriveBits(params, privateKey, 128);
+        assert_array_equals(new Uint8Array(truncated), vector.shared.slice(0, 16), "truncated secret");
+
+        const aesKey = await subtle.deriveKey(params, privateKey, { name: "AES-GCM", length: 128 }, true, ["encrypt"]);
+        assert_array_equals(
+          new Uint8Array(await subtle.exportKey("raw", aesKey)),
+          vector.shared.slice(0, 16),
+          "derived AES key"
+        );
+      }, "ECDH on " + namedCurve);
+
+      await promise_test(async () => {
+        const algorithm = { name: "ECDSA", namedCurve };
+        const privateKey = await subtle.importKey("pkcs8", vector.pkcs8, algorithm, true, ["sign"]);
+        const publicKey = await subtle.importKey("spki", vector.spki, algorithm, true, ["verify"]);
+
+        assert_array_equals(new Uint8Array(await subtle.exportKey("spki", publicKey)), vector.spki, "SPKI");
+
+        // Uncompressed points are at the end of the SPKI structure
+        const raw = new Uint8Array(await subtle.exportKey("raw", publicKey));
+        assert_array_equals(raw, vector.spki.slice(vector.spki.length - (fieldSize * 2 + 1)), "raw");
+
+        const jwk = await subtle.exportKey("jwk", privateKey);
+        assert_equals(jwk.kty, "EC", "kty");
+        assert_equals(jwk.crv, namedCurve, "crv");
+        for (const member of ["x", "y", "d"]) {
+          assert_true(/^[A-Za-z0-9_-]+$/.test(jwk[member]), member + " is base64url");
+        }
+
+        const fromJwk = await subtle.importKey("jwk", jwk, algorithm, true, ["sign"]);
+        const signature = await subtle.sign({ name: "ECDSA", hash: vector.hash }, fromJwk, message);
+        assert_true(
+          await subtle.verify({ name: "ECDSA", hash: vector.hash }, publicKey, signature, message),
+          "signature with a key imported from a JWK"
+        );
+
+        const { d, ...publicJwk } = jwk;
+        publicJwk.key_ops = ["verify"];
+        const publicFromJwk = await subtle.importKey("jwk", publicJwk, algorithm, true, ["verify"]);
+        assert_array_equals(new Uint8Array(await subtle.exportKey("raw", publicFromJwk)), raw, "public JWK");
+
+        const otherCurve = namedCurve === "P-256" ? "P-384" : "P-256";
+        await promise_rejects_js(
+          subtle.importKey("jwk", jwk, { name: "ECDSA", namedCurve: otherCurve }, true, ["sign"]),
+          "mismatched crv"
+        );
+        await promise_rejects_js(
+          subtle.importKey("jwk", { ...jwk, x: publicJwk.y }, algorithm, true, ["sign"]),
+          "mismatched public key"
+        );
+      }, "EC key import and export on " + namedCurve);
+    }
+
+    await promise_test(async () => {
+      const { publicKey, privateKey } = await subtle.generateKey(
+        { name: "ECDH", namedCurve: "P-256" },
+        true,
+        ["deriveBits"]
+      );
+      assert_array_equals(publicKey.usages, [], "public key usages");
+      assert_array_equals(privateKey.usages, ["deriveBits"], "private key usages");
+
+      const other = await subtle.generateKey({ name: "ECDH", namedCurve: "P-256" }, true, ["deriveBits"]);
+      const a = await subtle.deriveBits({ name: "ECDH", public: other.publicKey }, privateKey, 256);
+      const b = await subtle.deriveBits({ name: "ECDH", public: publicKey }, other.privateKey, 256);
+      assert_array_equals(new Uint8Array(a), new Uint8Array(b), "both sides agree");
+    }, "ECDH generateKey");
+
+    return new Response("All tests passed!");
+  } catch (e) {
+    return new Response(e.toString(), { status: 500 });
+  }
+}
+
+export { handleRequest };
@@ -0,0 +1,222 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  assert_false,
+  assert_true,
+  hex,
+  promise_rejects_js,
+  promise_test,
+} from "../test-utils";
+
+// DER encodings from RFC 8410. PKCS#8 version 2 structures can also hold
+// the public key.
+const spki = (oid, publicKey) => hex("302a300506032b65" + oid + "032100" + publicKey);
+const pkcs8 = (oid, privateKey) => hex("302e020100300506032b65" + oid + "04220420" + privateKey);
+const pkcs8v2 = (oid, privateKey, publicKey) =>
+  hex("3051020101300506032b65" + oid + "04220420" + privateKey + "81210" + "0" + publicKey);
+const ED25519 = "70";
+const X25519 = "6e";
+
+// Tests 1 and 2 from RFC 8032
+const ed25519 = [
+  {
+    privateKey: "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+    publicKey: "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+    message: new Uint8Array(),
+    signature: hex(
+      "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155" +
+        "5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+    ),
+  },
+  {
+    privateKey: "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+    publicKey: "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+    message: hex("72"),
+    signature: hex(
+      "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da" +
+        "085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00"
+    ),
+  },
+];
+
+// Section 6.1 of RFC 7748
+const x25519 = {
+  alicePrivateKey: "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a",
+  alicePublicKey: "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a",
+  bobPrivateKey: "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb",
+  bobPublicKey: "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f",
+  shared: hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742"),
+};
+
+async function handleRequest(request) {
+  try {
+    const subtle = crypto.subtle;
+
+    for (const [i, vector] of ed25519.entries()) {
+      await promise_test(async () => {
+        const privateKey = await subtle.importKey(
+          "pkcs8",
+          pkcs8(ED25519, vector.privateKey),
+          "Ed25519",
+          true,
+          ["sign"]
+        );
+        const publicKey = await subtle.importKey("raw", hex(vector.publicKey), "Ed25519", true, ["verify"]);
+
+        // Ed25519 signatures are deterministic
+        const signature = await subtle.sign("Ed25519", privateKey, vector.message);
+        assert_array_equals(new Uint8Array(signature), vector.signature, "signature");
+        assert_true(await subtle.verify("Ed25519", publicKey, vector.signature, vector.message), "verify");
+        assert_false(
+          await subtle.verify("Ed25519", publicKey, vector.signature, hex("00")),
+          "other message"
+        );
+      }, "Ed25519 known answer " + (i + 1));
+    }
+
+    await promise_test(async () => {
+      const vector = ed25519[0];
+      const privateKey = await subtle.importKey(
+        "pkcs8",
+        pkcs8v2(ED25519, vector.privateKey, vector.publicKey),
+        { name: "Ed25519" },
+        true,
+        ["sign"]
+      );
+      const signature = await subtle.sign("Ed25519", privateKey, vector.message);
+      assert_array_equals(new Uint8Array(signature), vector.signature, "signature");
+
+      await promise_rejects_js(
+        subtle.importKey(
+          "pkcs8",
+          pkcs8v2(ED25519, vector.privateKey, ed25519[1].publicKey),
+          "Ed25519",
+          true,
+          ["sign"]
+        ),
+        "mismatched public key"
+      );
+    }, "Ed25519 PKCS#8 version 2");
+
+    await promise_test(async () => {
+      const vector = ed25519[0];
+      const privateKey = await subtle.importKey(
+        "pkcs8",
+        pkcs8(ED25519, vector.privateKey),
+        "Ed25519",
+        true,
+        ["sign"]
+      );
+      const publicKey = await subtle.importKey(
+        "spki",
+        spki(ED25519, vector.publicKey),
+        "Ed25519",
+        true,
+        ["verify"]
+      );
+      assert_equals(publicKey.algorithm.name, "Ed25519", "algorithm.name");
+
+      assert_array_equals(
+        new Uint8Array(await subtle.exportKey("pkcs8", privateKey)),
+        pkcs8(ED25519, vector.privateKey),
+        "PKCS#8"
+      );
+      assert_array_equals(
+        new Uint8Array(await subtle.exportKey("spki", publicKey)),
+        spki(ED25519, vector.publicKey),
+        "SPKI"
+      );
+      assert_array_equals(
+        new Uint8Array(await subtle.exportKey("raw", publicKey)),
+        hex(vector.publicKey),
+        "raw"
+      );
+
+      const jwk = await subtle.exportKey("jwk", privateKey);
+      assert_equals(jwk.kty, "OKP", "kty");
+      assert_equals(jwk.crv, "Ed25519", "crv");
+      assert_equals(jwk.x, "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo", "x");
+      assert_equals(jwk.d, "nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A", "d");
+
+      const fromJwk = await subtle.importKey("jwk", jwk, "Ed25519", true, ["sign"]);
+      assert_array_equals(
+        new Uint8Array(await subtle.sign("Ed25519", fromJwk, vector.message)),
+        vector.signature,
+        "signature with a key imported from a JWK"
+      );
+
+      await promise_rejects_js(
+        subtle.importKey("jwk", { ...jwk, x: "PUAXw-hDiVqStwqnTRt-vJyYLM8uxJaMwM1V8Sr0Zgw" }, "Ed25519", true, ["sign"]),
+        "mismatched public key"
+      );
+      await promise_rejects_js(
+        subtle.importKey("pkcs8", pkcs8(X25519, vector.privateKey), "Ed25519", true, ["sign"]),
+        "X25519 key"
+      );
+      await promise_rejects_js(
+        subtle.importKey("spki", spki(ED25519, vector.publicKey).slice(0, 40), "Ed25519", true, ["verify"]),
+        "truncated SPKI"
+      );
+    }, "Ed25519 key import and export");
+
+    await promise_test(async () => {
+      const alice = await subtle.importKey(
+        "pkcs8",
+        pkcs8(X25519, x25519.alicePrivateKey),
+        "X25519",
+        true,
+        ["deriveBits"]
+      );
+      const bob = await subtle.importKey(
+        "pkcs8",
+        pkcs8v2(X25519, x25519.bobPrivateKey, x25519.bobPublicKey),
+        "X25519",
+        true,
+        ["deriveBits"]
+      );
+      const alicePublic = await subtle.importKey("raw", hex(x25519.alicePublicKey), "X25519", true, []);
+      const bobPublic = await subtle.importKey(
+        "spki",
+        spki(X25519, x25519.bobPublicKey),
+        "X25519",
+        true,
+        []
+      );
+
+      const aliceShared = await subtle.deriveBits({ name: "X25519", public: bobPublic }, alice, 256);
+      assert_array_equals(new Uint8Array(aliceShared), x25519.shared, "Alice's shared secret");
+      const bobShared = await subtle.deriveBits({ name: "X25519", public: alicePublic }, bob, 256);
+      assert_array_equals(new Uint8Array(bobShared), x25519.shared, "Bob's shared secret");
+
+      assert_array_equals(
+        new Uint8Array(await subtle.exportKey("pkcs8", bob)),
+        pkcs8(X25519, x25519.bobPrivateKey),
+        "PKCS#8"
+      );
+      const jwk = await subtle.exportKey("jwk", alice);
+      assert_equals(jwk.crv, "X25519", "crv");
+      const fromJwk = await subtle.importKey("jwk", jwk, "X25519", false, ["deriveBits"]);
+      assert_array_equals(
+        new Uint8Array(await subtle.deriveBits({ name: "X25519", public: bobPublic }, fromJwk, 128)),
+        x25519.shared.slice(0, 16),
+        "truncated shared secret with a key imported from a JWK"
+      );
+    }, "X25519 known answer");
+
+    await promise_test(async () => {
+      const { publicKey, privateKey } = await subtle.generateKey("Ed25519", false, ["sign", "verify"]);
+      assert_true(publicKey.extractable, "public keys are extractable");
+      assert_false(privateKey.extractable, "private key extractable");
+
+      const message = new TextEncoder().encode("WinterJS");
+      const signature = await subtle.sign("Ed25519", privateKey, message);
+      assert_true(await subtle.verify("Ed25519", publicKey, signature, message), "verify");
+    }, "Ed25519 generateKey");
+
+    return new Response("All tests passed!");
+  } catch (e) {
+    return new Response(e.toString(), { status: 500 });
+  }
+}
+
+export { handleRequest };
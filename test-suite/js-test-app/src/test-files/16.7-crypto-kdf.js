@@ -0,0 +1,146 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  hex,
+  promise_rejects_js,
+  promise_test,
+} from "../test-utils";
+
+const encoder = new TextEncoder();
+
+// From RFC 6070, and the SHA-256 equivalents of its first test
+const pbkdf2 = [
+  { hash: "SHA-1", iterations: 1, derived: hex("0c60c80f961f0e71f3a9b524af6012062fe037a6") },
+  { hash: "SHA-1", iterations: 2, derived: hex("ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957") },
+  { hash: "SHA-1", iterations: 4096, derived: hex("4b007901b765489abead49d926f721d065a429c1") },
+  {
+    hash: "SHA-256",
+    iterations: 1,
+    derived: hex("120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"),
+  },
+];
+
+// Tests 1 and 3 from RFC 5869
+const hkdf = [
+  {
+    key: hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b"),
+    salt: hex("000102030405060708090a0b0c"),
+    info: hex("f0f1f2f3f4f5f6f7f8f9"),
+    derived: hex(
+      "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+    ),
+  },
+  {
+    key: hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b"),
+    salt: new Uint8Array(),
+    info: new Uint8Array(),
+    derived: hex(
+      "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8"
+    ),
+  },
+];
+
+async function handleRequest(request) {
+  try {
+    const subtle = crypto.subtle;
+
+    const password = await subtle.importKey("raw", encoder.encode("password"), "PBKDF2", false, [
+      "deriveBits",
+      "deriveKey",
+    ]);
+    assert_equals(password.algorithm.name, "PBKDF2", "algorithm.name");
+    assert_equals(password.type, "secret", "type");
+
+    for (const vector of pbkdf2) {
+      await promise_test(async () => {
+        const params = {
+          name: "PBKDF2",
+          salt: encoder.encode("salt"),
+          iterations: vector.iterations,
+          hash: vector.hash,
+        };
+        const bits = await subtle.deriveBits(params, password, vector.derived.length * 8);
+        assert_array_equals(new Uint8Array(bits), vector.derived, "derived bits");
+      }, `PBKDF2 with ${vector.hash} and ${vector.iterations} iterations`);
+    }
+
+    for (const [i, vector] of hkdf.entries()) {
+      await promise_test(async () => {
+        const key = await subtle.importKey("raw", vector.key, "HKDF", false, ["deriveBits"]);
+        const params = { name: "HKDF", hash: "SHA-256", salt: vector.salt, info: vector.info };
+        const bits = await subtle.deriveBits(params, key, vector.derived.length * 8);
+        assert_array_equals(new Uint8Array(bits), vector.derived, "derived bits");
+      }, "HKDF known answer " + (i + 1));
+    }
+
+    await promise_test(async () => {
+      const params = {
+        name: "PBKDF2",
+        salt: encoder.encode("salt"),
+        iterations: 2,
+        hash: "SHA-256",
+      };
+      const aesKey = await subtle.deriveKey(params, password, { name: "AES-GCM", length: 256 }, true, [
+        "encrypt",
+        "decrypt",
+      ]);
+      assert_equals(aesKey.algorithm.name, "AES-GCM", "algorithm.name");
+      assert_equals(aesKey.algorithm.length, 256, "algorithm.length");
+      assert_array_equals(aesKey.usages, ["encrypt", "decrypt"], "usages");
+
+      const bits = await subtle.deriveBits(params, password, 256);
+      assert_array_equals(
+        new Uint8Array(await subtle.exportKey("raw", aesKey)),
+        new Uint8Array(bits),
+        "derived key"
+      );
+
+      const hmacKey = await subtle.deriveKey(
+        { name: "HKDF", hash: "SHA-256", salt: hkdf[0].salt, info: hkdf[0].info },
+        await subtle.importKey("raw", hkdf[0].key, "HKDF", false, ["deriveKey"]),
+        { name: "HMAC", hash: "SHA-256", length: 256 },
+        true,
+        ["sign"]
+      );
+      assert_array_equals(
+        new Uint8Array(await subtle.exportKey("raw", hmacKey)),
+        hkdf[0].derived.slice(0, 32),
+        "derived HMAC key"
+      );
+    }, "deriveKey");
+
+    await promise_test(async () => {
+      const params = { name: "PBKDF2", salt: encoder.encode("salt"), iterations: 1, hash: "SHA-256" };
+      await promise_rejects_js(subtle.deriveBits(params, password, null), "missing length");
+      await promise_rejects_js(subtle.deriveBits(params, password, 12), "length not a multiple of 8");
+      await promise_rejects_js(
+        subtle.deriveBits({ ...params, iterations: 0 }, password, 256),
+        "zero iterations"
+      );
+      await promise_rejects_js(
+        subtle.importKey("raw", encoder.encode("password"), "PBKDF2", true, ["deriveBits"]),
+        "extractable key"
+      );
+      await promise_rejects_js(
+        subtle.importKey("raw", encoder.encode("password"), "PBKDF2", false, ["sign"]),
+        "invalid usage"
+      );
+
+      const hkdfKey = await subtle.importKey("raw", hkdf[0].key, "HKDF", false, ["deriveBits"]);
+      await promise_rejects_js(
+        subtle.deriveBits(
+          { name: "HKDF", hash: "SHA-256", salt: hkdf[0].salt, info: hkdf[0].info },
+          hkdfKey,
+          255 * 32 * 8 + 8
+        ),
+        "HKDF output too long"
+      );
+    }, "Invalid key derivation parameters");
+
+    return new Response("All tests passed!");
+  } catch (e) {
+    return new Response(e.toString(), { status: 500 });
+  }
+}
+
+export { handleRequest };
@@ -0,0 +1,157 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  assert_true,
+  hex,
+  promise_rejects_js,
+  promise_test,
+} from "../test-utils";
+
+// Sections 4.1 and 4.6 of RFC 3394
+const aesKw = [
+  {
+    kek: hex("000102030405060708090a0b0c0d0e0f"),
+    key: hex("00112233445566778899aabbccddeeff"),
+    wrapped: hex("1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5"),
+  },
+  {
+    kek: hex("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"),
+    key: hex("00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f"),
+    wrapped: hex(
+      "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326cbc7f0e71a99f43bfb988b9b7a02dd21"
+    ),
+  },
+];
+
+// Encodes to "+" and "/" in standard base64, and "-" and "_" in base64url
+const hmacKeyBytes = hex("fbffbf".repeat(10) + "fbff");
+const hmacKeyBase64url = "-_-_-_-_-_-_-_-_-_-_-_-_-_-_-_-_-_-_-_-_-_8";
+
+async function handleRequest(request) {
+  try {
+    const subtle = crypto.subtle;
+    const message = new TextEncoder().encode("WinterJS");
+
+    for (const [i, vector] of aesKw.entries()) {
+      await promise_test(async () => {
+        const kek = await subtle.importKey("raw", vector.kek, "AES-KW", false, ["wrapKey", "unwrapKey"]);
+        const key = await subtle.importKey("raw", vector.key, "AES-CBC", true, ["encrypt"]);
+
+        const wrapped = await subtle.wrapKey("raw", key, kek, "AES-KW");
+        assert_array_equals(new Uint8Array(wrapped), vector.wrapped, "wrapped key");
+
+        const unwrapped = await subtle.unwrapKey("raw", vector.wrapped, kek, "AES-KW", "AES-CBC", true, [
+          "encrypt",
+        ]);
+        assert_equals(unwrapped.algorithm.name, "AES-CBC", "algorithm.name");
+        assert_array_equals(new Uint8Array(await subtle.exportKey("raw", unwrapped)), vector.key, "key");
+
+        const tampered = vector.wrapped.slice();
+        tampered[tampered.length - 1] ^= 1;
+        await promise_rejects_js(
+          subtle.unwrapKey("raw", tampered, kek, "AES-KW", "AES-CBC", true, ["encrypt"]),
+          "tampered wrapped key"
+        );
+        await promise_rejects_js(subtle.encrypt("AES-KW", kek, vector.key), "encrypt with AES-KW");
+      }, "AES-KW known answer " + (i + 1));
+    }
+
+    await promise_test(async () => {
+      const hmacParams = { name: "HMAC", hash: "SHA-256" };
+      const hmacKey = await subtle.importKey("raw", hmacKeyBytes, hmacParams, true, ["sign", "verify"]);
+
+      const jwk = await subtle.exportKey("jwk", hmacKey);
+      assert_equals(jwk.kty, "oct", "kty");
+      assert_equals(jwk.k, hmacKeyBase64url, "k is base64url without padding");
+      assert_equals(jwk.alg, "HS256", "alg");
+
+      const fromJwk = await subtle.importKey("jwk", { ...jwk, use: "sig" }, hmacParams, true, ["sign"]);
+      assert_array_equals(
+        new Uint8Array(await subtle.exportKey("raw", fromJwk)),
+        hmacKeyBytes,
+        "raw key after a JWK round trip"
+      );
+
+      await promise_rejects_js(
+        subtle.importKey("jwk", { ...jwk, use: "enc" }, hmacParams, true, ["sign"]),
+        "use must be sig"
+      );
+      await promise_rejects_js(
+        subtle.importKey("jwk", { ...jwk, alg: "HS512" }, hmacParams, true, ["sign"]),
+        "mismatched alg"
+      );
+      await promise_rejects_js(
+        subtle.importKey("jwk", { ...jwk, key_ops: ["verify"] }, hmacParams, true, ["sign"]),
+        "usages missing from key_ops"
+      );
+      await promise_rejects_js(
+        subtle.importKey("jwk", { ...jwk, ext: false }, hmacParams, true, ["sign"]),
+        "ext is false"
+      );
+      await promise_rejects_js(
+        subtle.importKey("jwk", { ...jwk, k: undefined }, hmacParams, true, ["sign"]),
+        "missing k"
+      );
+      await promise_rejects_js(
+        subtle.importKey("spki", hmacKeyBytes, hmacParams, true, ["sign"]),
+        "unsupported format"
+      );
+    }, "HMAC JWK round trip");
+
+    await promise_test(async () => {
+      const hmacParams = { name: "HMAC", hash: "SHA-256" };
+      const hmacKey = await subtle.importKey("raw", hmacKeyBytes, hmacParams, true, ["sign", "verify"]);
+      const wrappingKey = await subtle.generateKey({ name: "AES-GCM", length: 256 }, false, [
+        "wrapKey",
+        "unwrapKey",
+      ]);
+      const iv = crypto.getRandomValues(new Uint8Array(12));
+
+      const wrapped = await subtle.wrapKey("jwk", hmacKey, wrappingKey, { name: "AES-GCM", iv });
+      const unwrapped = await subtle.unwrapKey(
+        "jwk",
+        wrapped,
+        wrappingKey,
+        { name: "AES-GCM", iv },
+        hmacParams,
+        false,
+        ["verify"]
+      );
+      assert_equals(unwrapped.algorithm.name, "HMAC", "algorithm.name");
+      assert_array_equals(unwrapped.usages, ["verify"], "usages");
+
+      const signature = await subtle.sign("HMAC", hmacKey, message);
+      assert_true(await subtle.verify("HMAC", unwrapped, signature, message), "verify");
+    }, "Wrapping an HMAC key as a JWK with AES-GCM");
+
+    await promise_test(async () => {
+      const kek = await subtle.importKey("raw", aesKw[0].kek, "AES-KW", false, ["wrapKey"]);
+      const extractable = await subtle.importKey("raw", hmacKeyBytes, { name: "HMAC", hash: "SHA-1" }, true, [
+        "sign",
+      ]);
+      const notExtractable = await subtle.importKey(
+        "raw",
+        hmacKeyBytes,
+        { name: "HMAC", hash: "SHA-1" },
+        false,
+        ["sign"]
+      );
+
+      await promise_rejects_js(subtle.wrapKey("raw", notExtractable, kek, "AES-KW"), "non-extractable key");
+      await promise_rejects_js(
+        subtle.unwrapKey("raw", aesKw[0].wrapped, kek, "AES-KW", "AES-CBC", true, ["encrypt"]),
+        "missing unwrapKey usage"
+      );
+      await promise_rejects_js(
+        subtle.wrapKey("raw", extractable, kek, { name: "AES-GCM", iv: new Uint8Array(12) }),
+        "wrapping key of a different algorithm"
+      );
+    }, "Invalid wrapKey and unwrapKey calls");
+
+    return new Response("All tests passed!");
+  } catch (e) {
+    return new Response(e.toString(), { status: 500 });
+  }
+}
+
+export { handleRequest };
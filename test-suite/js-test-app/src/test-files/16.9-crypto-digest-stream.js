@@ -0,0 +1,95 @@
+import {
+  assert_array_equals,
+  assert_equals,
+  assert_throws_js,
+  assert_true,
+  hex,
+  promise_rejects_js,
+  promise_test,
+  readableStreamFromArray,
+} from "../test-utils";
+
+const encoder = new TextEncoder();
+
+// The two-block message from the FIPS 180-2 examples
+const message = "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+const digests = {
+  "SHA-1": hex("84983e441c3bd26ebaae4aa1f95129e5e54670f1"),
+  "SHA-256": hex("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"),
+  "SHA-384": hex(
+    "3391fdddfc8dc7393707a65b1b4709397cf8b1d162af05abfe8f450de5f36bc6b0455a8520bc4e6f5fe95b1fe3c8452b"
+  ),
+  "SHA-512": hex(
+    "204a8fc6dda82f0a0ced7beb8e08a41657c16ef468b228a8279be331a703c335" +
+      "96fd15c13b1b07f9aa1d3bea57789ca031ad85c7a71dd70354ec631238ca3445"
+  ),
+  MD5: hex("8215ef0796a20bcaaae116d3876c664a"),
+};
+
+// Splits the message into chunks of different sizes, so hashing has to
+// carry state across writes
+function chunks() {
+  const bytes = encoder.encode(message);
+  return [bytes.slice(0, 1), bytes.slice(1, 20), new DataView(bytes.buffer, 20, 3), bytes.slice(23).buffer];
+}
+
+async function handleRequest(request) {
+  try {
+    for (const [algorithm, expected] of Object.entries(digests)) {
+      await promise_test(async () => {
+        const stream = new crypto.DigestStream(algorithm);
+        assert_true(stream instanceof WritableStream, "DigestStream is a WritableStream");
+
+        const writer = stream.getWriter();
+        for (const chunk of chunks()) {
+          await writer.write(chunk);
+        }
+        await writer.close();
+
+        const digest = await stream.digest;
+        assert_true(digest instanceof ArrayBuffer, "digest is an ArrayBuffer");
+        assert_array_equals(new Uint8Array(digest), expected, "digest");
+      }, "DigestStream with " + algorithm);
+    }
+
+    await promise_test(async () => {
+      const stream = new crypto.DigestStream({ name: "SHA-256" });
+      await readableStreamFromArray(chunks()).pipeTo(stream);
+      assert_array_equals(new Uint8Array(await stream.digest), digests["SHA-256"], "digest");
+    }, "Piping into a DigestStream");
+
+    await promise_test(async () => {
+      const stream = new crypto.DigestStream("SHA-256");
+      await stream.getWriter().close();
+      assert_array_equals(
+        new Uint8Array(await stream.digest),
+        new Uint8Array(await crypto.subtle.digest("SHA-256", new Uint8Array())),
+        "digest of no data"
+      );
+    }, "Empty DigestStream");
+
+    await promise_test(async () => {
+      const stream = new crypto.DigestStream("SHA-256");
+      const writer = stream.getWriter();
+      await writer.write(encoder.encode("partial"));
+      const reason = new Error("aborted");
+      await writer.abort(reason);
+      await promise_rejects_js(stream.digest, "aborted stream");
+
+      const invalid = new crypto.DigestStream("SHA-256");
+      const invalidWriter = invalid.getWriter();
+      await promise_rejects_js(invalidWriter.write("not a buffer"), "string chunk");
+      await promise_rejects_js(invalid.digest, "digest after an invalid chunk");
+    }, "DigestStream errors");
+
+    assert_throws_js(() => new crypto.DigestStream("AES-GCM"), "unsupported algorithm");
+    assert_throws_js(() => new crypto.DigestStream("SHA-3"), "unknown algorithm");
+    assert_equals(typeof crypto.DigestStream, "function", "DigestStream is a constructor");
+
+    return new Response("All tests passed!");
+  } catch (e) {
+    return new Response(e.toString(), { status: 500 });
+  }
+}
+
+export { handleRequest };
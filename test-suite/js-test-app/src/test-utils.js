@@ -249,6 +249,15 @@ const flushAsyncEvents = () =>
     .then(() => delay(0))
     .then(() => delay(0));
 
+const hex = (str) => new Uint8Array(str.match(/../g).map((b) => parseInt(b, 16)));
+
+const concat = (a, b) => {
+  const result = new Uint8Array(a.length + b.length);
+  result.set(a);
+  result.set(b, a.length);
+  return result;
+};
+
 export {
   assert,
   assert_class_string,
@@ -273,4 +282,6 @@ export {
   readableStreamFromArray,
   readableStreamToArray,
   async_test,
+  concat,
+  hex,
 };
@@ -0,0 +1,41 @@
+class HttpError extends Error {
+  constructor(status, message) {
+    super(message);
+    this.status = status;
+  }
+}
+
+const thrown = new HttpError(418, "the very same error");
+
+Deno.serve({
+  handler(request) {
+    const path = new URL(request.url).pathname;
+    switch (path) {
+      case "/ok":
+        return new Response("ok");
+      case "/throw":
+        throw new HttpError(418, "thrown");
+      case "/reject":
+        return Promise.reject(new HttpError(409, "rejected"));
+      case "/throw-string":
+        throw "plain string";
+      case "/identity":
+        throw thrown;
+      case "/globals":
+        return new Response(`${typeof Deno.serve} ${typeof Bun.serve}`);
+      default:
+        return new Response("not found", { status: 404 });
+    }
+  },
+  onError(error) {
+    if (error === thrown) {
+      return new Response("same object", { status: 418 });
+    }
+    if (error instanceof HttpError) {
+      return new Response(`${error.constructor.name}: ${error.message}`, {
+        status: error.status,
+      });
+    }
+    return new Response(`${typeof error}: ${error}`, { status: 500 });
+  },
+});
@@ -0,0 +1 @@
+console.log("asset");
\ No newline at end of file
@@ -0,0 +1,7 @@
+// Everything that isn't in public/ ends up here, including requests for the
+// app's own files
+export default {
+  async fetch(request) {
+    return new Response(`dynamic ${new URL(request.url).pathname}`);
+  },
+};
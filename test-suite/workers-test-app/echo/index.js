@@ -0,0 +1,19 @@
+// Echoes requests back, so the gateway can check what arrived
+export default {
+  async fetch(request, env) {
+    const url = new URL(request.url);
+    if (url.pathname === "/throw") {
+      throw new Error("echo failed");
+    }
+
+    return new Response(request.body, {
+      status: Number(url.searchParams.get("status") ?? 200),
+      headers: {
+        "x-worker": env.WORKER_NAME,
+        "x-method": request.method,
+        "x-path": url.pathname,
+        "x-custom": request.headers.get("x-custom") ?? "",
+      },
+    });
+  },
+};
@@ -0,0 +1,83 @@
+import {
+  assert_equals,
+  assert_true,
+  promise_rejects_js,
+  promise_test,
+  readableStreamFromArray,
+} from "../../js-test-app/src/test-utils.js";
+
+const encoder = new TextEncoder();
+
+async function testServiceBindings(env) {
+  await promise_test(async () => {
+    assert_equals(env.WORKER_NAME, "gateway", "own vars");
+    assert_equals(typeof env.ECHO.fetch, "function", "fetch");
+  }, "service bindings are exposed on env");
+
+  await promise_test(async () => {
+    const response = await env.ECHO.fetch("http://echo/some/path?status=201", {
+      method: "PUT",
+      headers: { "x-custom": "value" },
+      body: "hello",
+    });
+    assert_equals(response.status, 201, "status");
+    assert_equals(response.headers.get("x-worker"), "echo", "handled by the target worker");
+    assert_equals(response.headers.get("x-method"), "PUT", "method");
+    assert_equals(response.headers.get("x-path"), "/some/path", "path");
+    assert_equals(response.headers.get("x-custom"), "value", "headers");
+    assert_equals(await response.text(), "hello", "body");
+  }, "requests are dispatched to the target worker");
+
+  await promise_test(async () => {
+    const response = await env.ECHO.fetch(new Request("http://echo/", { method: "POST", body: "from a Request" }));
+    assert_equals(await response.text(), "from a Request");
+  }, "fetch accepts Request objects");
+
+  await promise_test(async () => {
+    const chunks = [];
+    for (let i = 0; i < 64; i++) {
+      chunks.push(encoder.encode(`chunk ${i};`.padEnd(1024, ".")));
+    }
+    const response = await env.ECHO.fetch("http://echo/", {
+      method: "POST",
+      body: readableStreamFromArray(chunks),
+      duplex: "half",
+    });
+
+    let received = 0;
+    let reads = 0;
+    const reader = response.body.getReader();
+    for (;;) {
+      const { done, value } = await reader.read();
+      if (done) {
+        break;
+      }
+      received += value.byteLength;
+      reads++;
+    }
+    assert_equals(received, 64 * 1024, "all bytes are echoed");
+    assert_true(reads > 0, "body is readable as a stream");
+  }, "streaming bodies in both directions");
+
+  await promise_test(async () => {
+    await promise_rejects_js(env.ECHO.fetch("http://echo/throw"), "failed service request");
+    const response = await env.ECHO.fetch("http://echo/");
+    assert_equals(response.status, 200, "target worker keeps serving");
+  }, "errors in the target worker reject the request");
+}
+
+export default {
+  async fetch(request, env) {
+    const path = new URL(request.url).pathname.slice(1);
+    if (path !== "1-service-bindings") {
+      return new Response(`Route Not Found - ${path}`, { status: 404 });
+    }
+
+    try {
+      await testServiceBindings(env);
+      return new Response("All tests passed!");
+    } catch (e) {
+      return new Response(e.toString(), { status: 500 });
+    }
+  },
+};